@@ -0,0 +1,668 @@
+//! Incremental backups of region folders.
+//!
+//! Chunk payloads are kept in content-addressed store by hash of stored
+//! bytes, so each backup writes only chunks which changed since previous
+//! one. Every backup has manifest listing all its chunks, which makes
+//! restore of any backup independent of others.
+//!
+//! Store layout:
+//!
+//! * `objects/ab/cdef…` - compression scheme byte followed by compressed data.
+//! * `backups/<id>.tsv` - manifest of backup.
+use crate::diff::{payload_hash, PayloadHash};
+use crate::{
+    chunk_position, is_anvil_path, region_chunk_position, region_position, AnvilChunkProvider,
+    ChunkLoadError, ChunkSaveError,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Folder of chunk payloads.
+const OBJECTS_FOLDER: &str = "objects";
+/// Folder of backup manifests.
+const BACKUPS_FOLDER: &str = "backups";
+/// Backup manifest file extension.
+const MANIFEST_EXTENSION: &str = "tsv";
+
+/// Possible errors while making or restoring backup.
+#[derive(Debug)]
+pub enum BackupError {
+    /// Error while reading source chunk or region.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while writing restored chunk or region.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// I/O error while accessing backup store.
+    StoreError { io_error: io::Error },
+    /// Backup with specified id or time not found.
+    BackupNotFound,
+    /// Manifest line can not be parsed.
+    InvalidManifest { backup_id: u32, line: usize },
+    /// Stored payload does not match its hash.
+    CorruptedObject { hash: PayloadHash },
+    /// Restore destination already contains regions.
+    DestinationNotEmpty,
+    /// Region exists only as legacy McRegion file, whose chunks can not be
+    /// restored into Anvil one.
+    McRegionNotConverted { region_x: i32, region_z: i32 },
+}
+
+impl From<ChunkLoadError> for BackupError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        BackupError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for BackupError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        BackupError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(io_error: io::Error) -> Self {
+        BackupError::StoreError { io_error }
+    }
+}
+
+/// Chunk recorded in backup.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackupChunk {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// Header timestamp of chunk at backup time.
+    pub last_modified_timestamp: u32,
+    /// Hash of stored chunk payload.
+    pub hash: PayloadHash,
+}
+
+/// Backup manifest.
+#[derive(Debug)]
+pub struct Backup {
+    /// Sequential number of backup starting from 1.
+    pub id: u32,
+    /// Unix timestamp in seconds when backup was made.
+    pub created_timestamp: u64,
+    /// Unix timestamp in seconds when backup started scanning regions.
+    pub started_timestamp: u64,
+    /// All chunks of backed up folder sorted by coordinates.
+    pub chunks: Vec<BackupChunk>,
+}
+
+impl Backup {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writeln!(
+            writer,
+            "created\t{}\tstarted\t{}",
+            self.created_timestamp, self.started_timestamp
+        )?;
+        writeln!(writer, "x\tz\ttimestamp\thash")?;
+
+        for chunk in &self.chunks {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                chunk.chunk_x,
+                chunk.chunk_z,
+                chunk.last_modified_timestamp,
+                hash_hex(&chunk.hash)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn read<R: BufRead>(id: u32, reader: R) -> Result<Self, BackupError> {
+        let mut timestamps = None;
+        let mut chunks = Vec::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let invalid = || BackupError::InvalidManifest {
+                backup_id: id,
+                line: index + 1,
+            };
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            match index {
+                0 => match fields.as_slice() {
+                    ["created", created_timestamp, "started", started_timestamp] => {
+                        timestamps = Some((
+                            created_timestamp.parse().map_err(|_| invalid())?,
+                            started_timestamp.parse().map_err(|_| invalid())?,
+                        ))
+                    }
+                    _ => return Err(invalid()),
+                },
+                1 => continue,
+                _ => {
+                    let chunk = match fields.as_slice() {
+                        [chunk_x, chunk_z, timestamp, hash] => BackupChunk {
+                            chunk_x: chunk_x.parse().map_err(|_| invalid())?,
+                            chunk_z: chunk_z.parse().map_err(|_| invalid())?,
+                            last_modified_timestamp: timestamp.parse().map_err(|_| invalid())?,
+                            hash: parse_hash_hex(hash).ok_or_else(invalid)?,
+                        },
+                        _ => return Err(invalid()),
+                    };
+
+                    chunks.push(chunk);
+                }
+            }
+        }
+
+        let (created_timestamp, started_timestamp) =
+            timestamps.ok_or(BackupError::InvalidManifest {
+                backup_id: id,
+                line: 1,
+            })?;
+
+        Ok(Backup {
+            id,
+            created_timestamp,
+            started_timestamp,
+            chunks,
+        })
+    }
+}
+
+/// Result of backup.
+#[derive(Debug)]
+pub struct BackupReport {
+    /// Id of made backup.
+    pub backup_id: u32,
+    /// Amount of chunks in backup.
+    pub total_chunks: usize,
+    /// Amount of chunks which payload was read from regions.
+    pub read_chunks: usize,
+    /// Amount of payloads which were not in store before.
+    pub stored_chunks: usize,
+}
+
+/// Content-addressed store of chunk backups.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::backup::BackupStore;
+/// use anvil_region::AnvilChunkProvider;
+///
+/// let store_folder = tempfile::tempdir().unwrap();
+/// let store = BackupStore::open(store_folder.path()).unwrap();
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let report = store.backup(&chunk_provider).unwrap();
+///
+/// let restore_folder = tempfile::tempdir().unwrap();
+/// let restore_provider = AnvilChunkProvider::from_path(restore_folder.path());
+/// store.restore(report.backup_id, &restore_provider).unwrap();
+///
+/// assert_eq!(
+///     restore_provider.chunk_positions().unwrap(),
+///     chunk_provider.chunk_positions().unwrap()
+/// );
+/// ```
+pub struct BackupStore {
+    /// Folder where objects and manifests located.
+    folder_path: PathBuf,
+}
+
+impl BackupStore {
+    /// Opens store in folder, creating it when it does not exist.
+    pub fn open<P: AsRef<Path>>(folder_path: P) -> Result<Self, io::Error> {
+        let folder_path = folder_path.as_ref().to_path_buf();
+
+        fs::create_dir_all(folder_path.join(OBJECTS_FOLDER))?;
+        fs::create_dir_all(folder_path.join(BACKUPS_FOLDER))?;
+
+        Ok(BackupStore { folder_path })
+    }
+
+    /// Returns ids of all backups in ascending order.
+    pub fn backup_ids(&self) -> Result<Vec<u32>, io::Error> {
+        let mut backup_ids = Vec::new();
+
+        for entry in fs::read_dir(self.folder_path.join(BACKUPS_FOLDER))? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some(MANIFEST_EXTENSION)
+            {
+                continue;
+            }
+
+            if let Some(backup_id) = path
+                .file_stem()
+                .and_then(|file_stem| file_stem.to_str())
+                .and_then(|file_stem| file_stem.parse().ok())
+            {
+                backup_ids.push(backup_id);
+            }
+        }
+
+        backup_ids.sort_unstable();
+
+        Ok(backup_ids)
+    }
+
+    /// Reads backup manifest.
+    pub fn backup_manifest(&self, backup_id: u32) -> Result<Backup, BackupError> {
+        let file = match fs::File::open(self.manifest_path(backup_id)) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(BackupError::BackupNotFound)
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        Backup::read(backup_id, BufReader::new(file))
+    }
+
+    /// Backs up all chunks of provider with current time.
+    pub fn backup(&self, chunk_provider: &AnvilChunkProvider) -> Result<BackupReport, BackupError> {
+        self.backup_at(chunk_provider, unix_timestamp(SystemTime::now()))
+    }
+
+    /// Backs up all chunks of provider recording specified unix timestamp.
+    ///
+    /// Payload of chunk is not read when its header timestamp did not change
+    /// and its region file was not modified since previous backup.
+    pub fn backup_at(
+        &self,
+        chunk_provider: &AnvilChunkProvider,
+        created_timestamp: u64,
+    ) -> Result<BackupReport, BackupError> {
+        // Recorded before scanning, so regions saved during backup are read
+        // again by the next one.
+        let started_timestamp = unix_timestamp(SystemTime::now());
+        let backup_ids = self.backup_ids()?;
+        let mut previous_chunks = HashMap::new();
+        // Header timestamps have second precision and may be fixed, so they
+        // are trusted only for regions not modified since previous backup started.
+        let mut previous_started = None;
+
+        if let Some(&previous_backup_id) = backup_ids.last() {
+            let previous_backup = self.backup_manifest(previous_backup_id)?;
+
+            for chunk in previous_backup.chunks {
+                previous_chunks.insert((chunk.chunk_x, chunk.chunk_z), chunk);
+            }
+
+            previous_started = Some(previous_backup.started_timestamp);
+        }
+
+        let backup_id = backup_ids.last().map_or(1, |backup_id| backup_id + 1);
+        let mut backup = Backup {
+            id: backup_id,
+            created_timestamp,
+            started_timestamp,
+            chunks: Vec::new(),
+        };
+        let mut read_chunks = 0;
+        let mut stored_chunks = 0;
+
+        for (region_x, region_z) in chunk_provider.region_positions()? {
+            let region_path = match chunk_provider.existing_region_path(region_x, region_z) {
+                Some(region_path) if !is_anvil_path(&region_path) => {
+                    return Err(BackupError::McRegionNotConverted { region_x, region_z })
+                }
+                Some(region_path) => region_path,
+                None => continue,
+            };
+
+            let region_modified = fs::metadata(&region_path)
+                .and_then(|metadata| metadata.modified())
+                .map_err(ChunkLoadError::from)?;
+            let region_unchanged = previous_started
+                .is_some_and(|previous_started| unix_timestamp(region_modified) < previous_started);
+
+            let mut region = chunk_provider.open_region(region_path)?;
+
+            for (region_chunk_x, region_chunk_z, metadata) in region.chunks() {
+                let (chunk_x, chunk_z) =
+                    chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+                let last_modified_timestamp = metadata.last_modified_timestamp;
+
+                let hash = match previous_chunks.get(&(chunk_x, chunk_z)) {
+                    Some(previous_chunk)
+                        if region_unchanged
+                            && previous_chunk.last_modified_timestamp
+                                == last_modified_timestamp =>
+                    {
+                        previous_chunk.hash
+                    }
+                    _ => {
+                        let (compression_scheme, compressed_data) =
+                            region.read_chunk_bytes(region_chunk_x, region_chunk_z)?;
+                        let hash = payload_hash(compression_scheme, &compressed_data);
+                        read_chunks += 1;
+
+                        if self.store_object(&hash, compression_scheme, &compressed_data)? {
+                            stored_chunks += 1;
+                        }
+
+                        hash
+                    }
+                };
+
+                backup.chunks.push(BackupChunk {
+                    chunk_x,
+                    chunk_z,
+                    last_modified_timestamp,
+                    hash,
+                });
+            }
+        }
+
+        backup
+            .chunks
+            .sort_unstable_by_key(|chunk| (chunk.chunk_x, chunk.chunk_z));
+
+        // Manifest is written last and renamed into place, so interrupted
+        // backup leaves only unreferenced objects behind.
+        let manifest_path = self.manifest_path(backup_id);
+        let temporary_path = manifest_path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+        backup.write(&mut file)?;
+        file.sync_all()?;
+        fs::rename(temporary_path, manifest_path)?;
+
+        Ok(BackupReport {
+            backup_id,
+            total_chunks: backup.chunks.len(),
+            read_chunks,
+            stored_chunks,
+        })
+    }
+
+    /// Restores backup into provider folder without regions.
+    ///
+    /// Restored chunks keep their stored bytes and header timestamp.
+    pub fn restore(
+        &self,
+        backup_id: u32,
+        destination_provider: &AnvilChunkProvider,
+    ) -> Result<(), BackupError> {
+        if !destination_provider.region_positions()?.is_empty() {
+            return Err(BackupError::DestinationNotEmpty);
+        }
+
+        let backup = self.backup_manifest(backup_id)?;
+        let mut region_chunks = BTreeMap::new();
+
+        for chunk in &backup.chunks {
+            region_chunks
+                .entry(region_position(chunk.chunk_x, chunk.chunk_z))
+                .or_insert_with(Vec::new)
+                .push(chunk);
+        }
+
+        fs::create_dir_all(destination_provider.folder_path())?;
+
+        for ((region_x, region_z), chunks) in region_chunks {
+            let region_path = destination_provider.writable_region_path(region_x, region_z)?;
+            let mut region = destination_provider.open_region_for_write(region_path)?;
+
+            for chunk in chunks {
+                let (compression_scheme, compressed_data) = self.load_object(&chunk.hash)?;
+                let (region_chunk_x, region_chunk_z) =
+                    region_chunk_position(chunk.chunk_x, chunk.chunk_z);
+
+                region.write_chunk_bytes(
+                    region_chunk_x,
+                    region_chunk_z,
+                    compression_scheme,
+                    &compressed_data,
+                )?;
+                region
+                    .set_last_modified_timestamp(
+                        region_chunk_x,
+                        region_chunk_z,
+                        chunk.last_modified_timestamp,
+                    )
+                    .map_err(ChunkSaveError::from)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restores latest backup made not later than unix timestamp.
+    pub fn restore_at(
+        &self,
+        timestamp: u64,
+        destination_provider: &AnvilChunkProvider,
+    ) -> Result<(), BackupError> {
+        let mut found_backup_id = None;
+
+        for backup_id in self.backup_ids()? {
+            if self.backup_manifest(backup_id)?.created_timestamp <= timestamp {
+                found_backup_id = Some(backup_id);
+            }
+        }
+
+        match found_backup_id {
+            Some(backup_id) => self.restore(backup_id, destination_provider),
+            None => Err(BackupError::BackupNotFound),
+        }
+    }
+
+    fn manifest_path(&self, backup_id: u32) -> PathBuf {
+        self.folder_path
+            .join(BACKUPS_FOLDER)
+            .join(format!("{}.{}", backup_id, MANIFEST_EXTENSION))
+    }
+
+    fn object_path(&self, hash: &PayloadHash) -> PathBuf {
+        let hash_hex = hash_hex(hash);
+
+        self.folder_path
+            .join(OBJECTS_FOLDER)
+            .join(&hash_hex[..2])
+            .join(&hash_hex[2..])
+    }
+
+    /// Writes payload unless it is already stored.
+    ///
+    /// Returns whether payload was written.
+    fn store_object(
+        &self,
+        hash: &PayloadHash,
+        compression_scheme: u8,
+        compressed_data: &[u8],
+    ) -> Result<bool, io::Error> {
+        let object_path = self.object_path(hash);
+
+        if object_path.exists() {
+            return Ok(false);
+        }
+
+        if let Some(parent_path) = object_path.parent() {
+            fs::create_dir_all(parent_path)?;
+        }
+
+        let temporary_path = object_path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(&[compression_scheme])?;
+        file.write_all(compressed_data)?;
+        file.sync_all()?;
+        fs::rename(temporary_path, object_path)?;
+
+        Ok(true)
+    }
+
+    fn load_object(&self, hash: &PayloadHash) -> Result<(u8, Vec<u8>), BackupError> {
+        let mut object = fs::read(self.object_path(hash))?;
+
+        if object.is_empty() {
+            return Err(BackupError::CorruptedObject { hash: *hash });
+        }
+
+        let compressed_data = object.split_off(1);
+        let compression_scheme = object[0];
+
+        if payload_hash(compression_scheme, &compressed_data) != *hash {
+            return Err(BackupError::CorruptedObject { hash: *hash });
+        }
+
+        Ok((compression_scheme, compressed_data))
+    }
+}
+
+/// Returns unix timestamp of time in whole seconds.
+fn unix_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn hash_hex(hash: &PayloadHash) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hash_hex(text: &str) -> Option<PayloadHash> {
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 32];
+
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[index * 2..index * 2 + 2], 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backup::{BackupError, BackupStore};
+    use crate::fixtures::{chunk, load_value, save_chunk};
+    use crate::{AnvilChunkProvider, AnvilRegion};
+    use std::fs;
+    use std::time::{Duration, UNIX_EPOCH};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_incremental_backup_restore() {
+        let world_folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(world_folder.path());
+        let store_folder = TempDir::new().unwrap();
+        let store = BackupStore::open(store_folder.path()).unwrap();
+
+        save_chunk(&chunk_provider, 0, 0, 1, 1);
+        save_chunk(&chunk_provider, -40, 3, 1, 1);
+
+        let report = store.backup_at(&chunk_provider, 100).unwrap();
+        assert_eq!(report.backup_id, 1);
+        assert_eq!(report.total_chunks, 2);
+        assert_eq!(report.read_chunks, 2);
+        assert_eq!(report.stored_chunks, 1);
+
+        // Regions modified after previous backup started are read again.
+        save_chunk(&chunk_provider, 0, 0, 2, 2);
+        save_chunk(&chunk_provider, 1, 0, 1, 1);
+        chunk_provider.delete_chunk(-40, 3).unwrap();
+
+        let report = store.backup_at(&chunk_provider, 200).unwrap();
+        assert_eq!(report.backup_id, 2);
+        assert_eq!(report.total_chunks, 2);
+        assert_eq!(report.read_chunks, 2);
+        assert_eq!(report.stored_chunks, 1);
+
+        // Regions modified before previous backup started are trusted.
+        let started_timestamp = store.backup_manifest(2).unwrap().started_timestamp;
+        let modified = UNIX_EPOCH + Duration::from_secs(started_timestamp - 1);
+
+        for (region_x, region_z) in chunk_provider.region_positions().unwrap() {
+            let region_path = chunk_provider.region_path(region_x, region_z);
+            let file = fs::File::options().write(true).open(region_path).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+
+        let report = store.backup_at(&chunk_provider, 300).unwrap();
+        assert_eq!(report.read_chunks, 0);
+        assert_eq!(report.stored_chunks, 0);
+
+        let restore_folder = TempDir::new().unwrap();
+        let restore_provider = AnvilChunkProvider::from_path(restore_folder.path());
+        store.restore_at(199, &restore_provider).unwrap();
+
+        assert_eq!(
+            restore_provider.chunk_positions().unwrap(),
+            vec![(-40, 3), (0, 0)]
+        );
+        assert_eq!(load_value(&restore_provider, 0, 0), 1);
+
+        let region_path = restore_provider.region_path(0, 0);
+        let region = AnvilRegion::open(region_path).unwrap();
+        assert_eq!(region.chunk_metadata(0, 0).last_modified_timestamp, 1);
+
+        assert!(matches!(
+            store.restore(2, &restore_provider),
+            Err(BackupError::DestinationNotEmpty)
+        ));
+        assert!(matches!(
+            store.restore_at(99, &restore_provider),
+            Err(BackupError::BackupNotFound)
+        ));
+
+        let restore_folder = TempDir::new().unwrap();
+        let restore_provider = AnvilChunkProvider::from_path(restore_folder.path());
+        store.restore(2, &restore_provider).unwrap();
+
+        assert_eq!(
+            restore_provider.chunk_positions().unwrap(),
+            vec![(0, 0), (1, 0)]
+        );
+        assert_eq!(load_value(&restore_provider, 0, 0), 2);
+    }
+
+    #[test]
+    fn test_backup_fixed_timestamp() {
+        let world_folder = TempDir::new().unwrap();
+        let chunk_provider =
+            AnvilChunkProvider::from_path(world_folder.path()).with_fixed_timestamp(1);
+        let store_folder = TempDir::new().unwrap();
+        let store = BackupStore::open(store_folder.path()).unwrap();
+
+        chunk_provider.save_chunk(0, 0, chunk(1)).unwrap();
+
+        store.backup_at(&chunk_provider, 100).unwrap();
+
+        // Chunk is rewritten in place keeping its header timestamp.
+        chunk_provider.save_chunk(0, 0, chunk(2)).unwrap();
+
+        let report = store.backup_at(&chunk_provider, 100).unwrap();
+        assert_eq!(report.read_chunks, 1);
+        assert_eq!(report.stored_chunks, 1);
+
+        let restore_folder = TempDir::new().unwrap();
+        let restore_provider = AnvilChunkProvider::from_path(restore_folder.path());
+        store.restore(report.backup_id, &restore_provider).unwrap();
+
+        assert_eq!(load_value(&restore_provider, 0, 0), 2);
+    }
+
+    #[test]
+    fn test_backup_mcregion_not_converted() {
+        let world_folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(world_folder.path());
+        let store_folder = TempDir::new().unwrap();
+        let store = BackupStore::open(store_folder.path()).unwrap();
+
+        save_chunk(&chunk_provider, 0, 0, 1, 1);
+
+        let region_path = chunk_provider.region_path(0, 0);
+        fs::rename(&region_path, region_path.with_extension("mcr")).unwrap();
+
+        assert!(matches!(
+            store.backup_at(&chunk_provider, 100),
+            Err(BackupError::McRegionNotConverted {
+                region_x: 0,
+                region_z: 0
+            })
+        ));
+    }
+}
@@ -0,0 +1,327 @@
+//! Command-line tool for inspecting and manipulating region files.
+//!
+//! Chunk coordinates are relative to region, from 0 to 31.
+use anvil_region::text::{from_json, from_snbt, to_json, to_snbt};
+use anvil_region::{AnvilRegion, RegionProblem};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "Usage: anvil <command> <region file> [arguments]
+
+Commands:
+    info <region>                           Header statistics and fragmentation
+    ls <region>                             Present chunks with timestamps and sizes
+    dump <region> <x> <z> [snbt|json]       Print chunk NBT, SNBT by default
+    load <region> <x> <z> <file> [snbt|json]
+                                            Store chunk NBT parsed from text file
+    extract <region> <x> <z> <file>         Write stored chunk bytes to file
+    insert <region> <x> <z> <file>          Store chunk bytes from file
+    delete <region> <x> <z>                 Remove chunk from region
+    compact <region>                        Remove gaps between chunks
+    verify <region>...                      Check chunks can be loaded
+
+Chunk bytes are compression scheme byte followed by compressed NBT.";
+
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+
+    if let Err(message) = run(&arguments) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn run(arguments: &[String]) -> Result<(), String> {
+    let command = arguments.first().map(|command| command.as_str());
+    let arguments = arguments.get(1..).unwrap_or_default();
+
+    match command {
+        Some("info") => info(arguments),
+        Some("ls") => ls(arguments),
+        Some("dump") => dump(arguments),
+        Some("load") => load(arguments),
+        Some("extract") => extract(arguments),
+        Some("insert") => insert(arguments),
+        Some("delete") => delete(arguments),
+        Some("compact") => compact(arguments),
+        Some("verify") => verify(arguments),
+        Some("help") | Some("--help") | Some("-h") => {
+            output(USAGE);
+            output("\n");
+            Ok(())
+        }
+        _ => Err(USAGE.to_owned()),
+    }
+}
+
+/// Writes output ignoring closed pipe, for example when piped into `head`.
+fn output(text: &str) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout
+        .write_all(text.as_bytes())
+        .and_then(|_| stdout.flush());
+}
+
+fn argument<'a>(arguments: &'a [String], index: usize, name: &str) -> Result<&'a str, String> {
+    arguments
+        .get(index)
+        .map(|argument| argument.as_str())
+        .ok_or_else(|| format!("Missing argument <{}>\n\n{}", name, USAGE))
+}
+
+fn coordinate(arguments: &[String], index: usize, name: &str) -> Result<u8, String> {
+    let value = argument(arguments, index, name)?;
+
+    match value.parse() {
+        Ok(coordinate) if coordinate < 32 => Ok(coordinate),
+        _ => Err(format!(
+            "Chunk coordinate <{}> must be from 0 to 31, got `{}`",
+            name, value
+        )),
+    }
+}
+
+/// Opens region for inspection, leaving file untouched.
+fn open_region(arguments: &[String]) -> Result<AnvilRegion, String> {
+    let path = argument(arguments, 0, "region")?;
+
+    AnvilRegion::open_read_only(path)
+        .map_err(|io_error| format!("Cannot open `{}`: {}", path, io_error))
+}
+
+/// Opens existing region for change.
+fn open_region_for_write(arguments: &[String]) -> Result<AnvilRegion, String> {
+    let path = argument(arguments, 0, "region")?;
+
+    AnvilRegion::open(path).map_err(|io_error| format!("Cannot open `{}`: {}", path, io_error))
+}
+
+fn info(arguments: &[String]) -> Result<(), String> {
+    let region = open_region(arguments)?;
+    let chunks = region.chunks();
+
+    let total_sectors = region.total_sectors();
+    let used_sectors = region.used_sectors_count();
+    let free_sectors = total_sectors - used_sectors;
+    let data_sectors = total_sectors.saturating_sub(2);
+
+    let fragmentation = if data_sectors > 0 {
+        free_sectors as f64 * 100.0 / data_sectors as f64
+    } else {
+        0.0
+    };
+
+    output(&format!(
+        "chunks: {}\n\
+         sectors total: {}\n\
+         sectors used: {}\n\
+         sectors free: {}\n\
+         fragmentation: {:.1}%\n",
+        chunks.len(),
+        total_sectors,
+        used_sectors,
+        free_sectors,
+        fragmentation
+    ));
+
+    Ok(())
+}
+
+fn ls(arguments: &[String]) -> Result<(), String> {
+    let mut region = open_region(arguments)?;
+
+    let mut text = String::from("x\tz\tsector\tsectors\tbytes\ttimestamp\n");
+
+    for (chunk_x, chunk_z, metadata) in region.chunks() {
+        let bytes = match region.read_chunk_bytes(chunk_x, chunk_z) {
+            Ok((_, compressed_data)) => compressed_data.len().to_string(),
+            Err(_) => "?".to_owned(),
+        };
+
+        writeln!(
+            text,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            chunk_x,
+            chunk_z,
+            metadata.sector_index,
+            metadata.sectors,
+            bytes,
+            metadata.last_modified_timestamp
+        )
+        .unwrap();
+    }
+
+    output(&text);
+
+    Ok(())
+}
+
+fn dump(arguments: &[String]) -> Result<(), String> {
+    let mut region = open_region(arguments)?;
+    let chunk_x = coordinate(arguments, 1, "x")?;
+    let chunk_z = coordinate(arguments, 2, "z")?;
+
+    let chunk_compound_tag = region
+        .read_chunk(chunk_x, chunk_z)
+        .map_err(|chunk_load_error| format!("Cannot load chunk: {:?}", chunk_load_error))?;
+
+    let text = match arguments.get(3).map(|format| format.as_str()) {
+        None | Some("snbt") => to_snbt(&chunk_compound_tag),
+        Some("json") => to_json(&chunk_compound_tag),
+        Some(format) => return Err(format!("Unknown format `{}`", format)),
+    };
+
+    output(&text);
+    output("\n");
+
+    Ok(())
+}
+
+fn load(arguments: &[String]) -> Result<(), String> {
+    let path = argument(arguments, 0, "region")?;
+    let chunk_x = coordinate(arguments, 1, "x")?;
+    let chunk_z = coordinate(arguments, 2, "z")?;
+    let file_path = argument(arguments, 3, "file")?;
+
+    let text = fs::read_to_string(file_path)
+        .map_err(|io_error| format!("Cannot read `{}`: {}", file_path, io_error))?;
+
+    let chunk_compound_tag = match arguments.get(4).map(|format| format.as_str()) {
+        None | Some("snbt") => from_snbt(&text),
+        Some("json") => from_json(&text),
+        Some(format) => return Err(format!("Unknown format `{}`", format)),
+    }
+    .map_err(|text_parse_error| format!("Cannot parse `{}`: {:?}", file_path, text_parse_error))?;
+
+    let mut region = AnvilRegion::new(path)
+        .map_err(|io_error| format!("Cannot open `{}`: {}", path, io_error))?;
+
+    region
+        .write_chunk(chunk_x, chunk_z, chunk_compound_tag)
+        .map_err(|chunk_save_error| format!("Cannot write chunk: {:?}", chunk_save_error))
+}
+
+fn extract(arguments: &[String]) -> Result<(), String> {
+    let mut region = open_region(arguments)?;
+    let chunk_x = coordinate(arguments, 1, "x")?;
+    let chunk_z = coordinate(arguments, 2, "z")?;
+    let path = argument(arguments, 3, "file")?;
+
+    let (compression_scheme, compressed_data) = region
+        .read_chunk_bytes(chunk_x, chunk_z)
+        .map_err(|chunk_load_error| format!("Cannot read chunk: {:?}", chunk_load_error))?;
+
+    let mut bytes = Vec::with_capacity(compressed_data.len() + 1);
+    bytes.push(compression_scheme);
+    bytes.extend_from_slice(&compressed_data);
+
+    fs::write(path, bytes).map_err(|io_error| format!("Cannot write `{}`: {}", path, io_error))
+}
+
+fn insert(arguments: &[String]) -> Result<(), String> {
+    let path = argument(arguments, 0, "region")?;
+    let chunk_x = coordinate(arguments, 1, "x")?;
+    let chunk_z = coordinate(arguments, 2, "z")?;
+    let file_path = argument(arguments, 3, "file")?;
+
+    let bytes = fs::read(file_path)
+        .map_err(|io_error| format!("Cannot read `{}`: {}", file_path, io_error))?;
+
+    if bytes.is_empty() {
+        return Err(format!("`{}` are empty", file_path));
+    }
+
+    let mut region = AnvilRegion::new(path)
+        .map_err(|io_error| format!("Cannot open `{}`: {}", path, io_error))?;
+
+    region
+        .write_chunk_bytes(chunk_x, chunk_z, bytes[0], &bytes[1..])
+        .map_err(|chunk_save_error| format!("Cannot write chunk: {:?}", chunk_save_error))
+}
+
+fn delete(arguments: &[String]) -> Result<(), String> {
+    let mut region = open_region_for_write(arguments)?;
+    let chunk_x = coordinate(arguments, 1, "x")?;
+    let chunk_z = coordinate(arguments, 2, "z")?;
+
+    let deleted = region
+        .delete_chunk(chunk_x, chunk_z)
+        .map_err(|io_error| format!("Cannot delete chunk: {}", io_error))?;
+
+    if !deleted {
+        return Err(format!("There is no chunk at {} {}", chunk_x, chunk_z));
+    }
+
+    Ok(())
+}
+
+fn compact(arguments: &[String]) -> Result<(), String> {
+    let mut region = open_region_for_write(arguments)?;
+
+    let released_sectors = region
+        .compact()
+        .map_err(|io_error| format!("Cannot compact region: {}", io_error))?;
+
+    output(&format!("released sectors: {}\n", released_sectors));
+
+    Ok(())
+}
+
+fn verify(arguments: &[String]) -> Result<(), String> {
+    argument(arguments, 0, "region")?;
+    let mut problems_count = 0;
+
+    for path in arguments {
+        let mut region = AnvilRegion::open_read_only(path)
+            .map_err(|io_error| format!("Cannot open `{}`: {}", path, io_error))?;
+
+        let problems = region
+            .verify()
+            .map_err(|io_error| format!("Cannot verify `{}`: {}", path, io_error))?;
+
+        for problem in &problems {
+            output(&format!("{}: {}\n", path, describe_problem(problem)));
+        }
+
+        problems_count += problems.len();
+    }
+
+    if problems_count > 0 {
+        return Err(format!("Found {} problems", problems_count));
+    }
+
+    output("OK\n");
+
+    Ok(())
+}
+
+fn describe_problem(problem: &RegionProblem) -> String {
+    match problem {
+        RegionProblem::OverlapsHeader { chunk_x, chunk_z } => {
+            format!("chunk {} {} overlaps header", chunk_x, chunk_z)
+        }
+        RegionProblem::OutsideFile { chunk_x, chunk_z } => {
+            format!("chunk {} {} lies outside file", chunk_x, chunk_z)
+        }
+        RegionProblem::SharedSectors {
+            chunk_x,
+            chunk_z,
+            other_chunk_x,
+            other_chunk_z,
+        } => format!(
+            "chunk {} {} shares sectors with chunk {} {}",
+            chunk_x, chunk_z, other_chunk_x, other_chunk_z
+        ),
+        RegionProblem::Unreadable {
+            chunk_x,
+            chunk_z,
+            chunk_load_error,
+        } => format!(
+            "chunk {} {} cannot be loaded: {:?}",
+            chunk_x, chunk_z, chunk_load_error
+        ),
+    }
+}
@@ -0,0 +1,351 @@
+//! Sequential writing of whole region.
+//!
+//! Chunks are written back-to-back in header order, by z then by x, once
+//! region is finished, so same chunks produce identical files regardless of
+//! order they are added in. Layout is the same as `AnvilRegion::canonicalize`.
+use crate::journal::Journal;
+use crate::{
+    encode_chunk, AnvilChunkMetadata, AnvilRegion, ChunkSaveError, CHUNK_MAXIMUM_BYTES_LENGTH,
+    REGION_CHUNKS, REGION_SECTOR_BYTES_LENGTH, ZLIB_COMPRESSION_TYPE,
+};
+use byteorder::{BigEndian, WriteBytesExt};
+use nbt::CompoundTag;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Possible errors while building region.
+#[derive(Debug)]
+pub enum BuilderError {
+    /// Chunk at specified coordinates was already added.
+    DuplicateChunk {
+        chunk_x: u8,
+        chunk_z: u8,
+    },
+    ChunkSaveError {
+        chunk_save_error: ChunkSaveError,
+    },
+}
+
+impl From<ChunkSaveError> for BuilderError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        BuilderError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<io::Error> for BuilderError {
+    fn from(io_error: io::Error) -> Self {
+        BuilderError::ChunkSaveError {
+            chunk_save_error: ChunkSaveError::WriteError { io_error },
+        }
+    }
+}
+
+/// Chunk kept until region is finished.
+struct BufferedChunk {
+    compression_scheme: u8,
+    compressed_data: Vec<u8>,
+    sectors: u8,
+    last_modified_timestamp: u32,
+}
+
+/// Writer of whole region at once.
+///
+/// Chunks are buffered and written in header order when region is finished,
+/// so layout does not depend on the order they are added in.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::builder::RegionBuilder;
+/// use nbt::CompoundTag;
+/// use std::io::Cursor;
+///
+/// let mut region_builder =
+///     RegionBuilder::new(Cursor::new(Vec::new())).with_last_modified_timestamp(1_600_000_000);
+///
+/// region_builder.add_chunk(1, 0, &CompoundTag::new()).unwrap();
+/// region_builder.add_chunk(0, 0, &CompoundTag::new()).unwrap();
+///
+/// let region_bytes = region_builder.finish().unwrap().into_inner();
+///
+/// // Header followed by two chunks of one sector each.
+/// assert_eq!(region_bytes.len(), 4 * 4096);
+/// ```
+pub struct RegionBuilder<W: Write> {
+    writer: W,
+    /// Added chunks by metadata index, which is header order.
+    chunks: BTreeMap<usize, BufferedChunk>,
+    last_modified_timestamp: u32,
+}
+
+impl RegionBuilder<File> {
+    /// Creates region file, truncating existing one.
+    ///
+    /// Journal left by previous region is removed, so it is not replayed
+    /// onto built region.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let journal_path = Journal::path(path.as_ref());
+
+        if journal_path.exists() {
+            fs::remove_file(journal_path)?;
+        }
+
+        Ok(Self::new(File::create(path)?))
+    }
+
+    /// Writes region, syncs and opens built region.
+    pub fn finish_region<P: AsRef<Path>>(self, path: P) -> Result<AnvilRegion, BuilderError> {
+        self.finish()?.sync_all()?;
+
+        Ok(AnvilRegion::open(path)?)
+    }
+}
+
+impl<W: Write> RegionBuilder<W> {
+    /// Starts region which is written at current writer position on finish.
+    pub fn new(writer: W) -> Self {
+        RegionBuilder {
+            writer,
+            chunks: BTreeMap::new(),
+            last_modified_timestamp: 0,
+        }
+    }
+
+    /// Sets timestamp of following chunks, by default 0.
+    pub fn with_last_modified_timestamp(mut self, last_modified_timestamp: u32) -> Self {
+        self.last_modified_timestamp = last_modified_timestamp;
+        self
+    }
+
+    /// Sets timestamp of following chunks.
+    pub fn set_last_modified_timestamp(&mut self, last_modified_timestamp: u32) {
+        self.last_modified_timestamp = last_modified_timestamp;
+    }
+
+    /// Encodes and adds chunk.
+    pub fn add_chunk(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        chunk_compound_tag: &CompoundTag,
+    ) -> Result<(), BuilderError> {
+        let (compressed_buffer, _) = encode_chunk(chunk_compound_tag)?;
+
+        self.add_chunk_bytes(chunk_x, chunk_z, ZLIB_COMPRESSION_TYPE, &compressed_buffer)
+    }
+
+    /// Adds already compressed chunk.
+    pub fn add_chunk_bytes(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        compression_scheme: u8,
+        compressed_data: &[u8],
+    ) -> Result<(), BuilderError> {
+        let metadata_index = AnvilRegion::metadata_index(chunk_x, chunk_z);
+
+        if self.chunks.contains_key(&metadata_index) {
+            return Err(BuilderError::DuplicateChunk { chunk_x, chunk_z });
+        }
+
+        // 4 bytes for data length and 1 for compression scheme.
+        let length = compressed_data.len() as u32 + 5;
+
+        if length > CHUNK_MAXIMUM_BYTES_LENGTH {
+            return Err(ChunkSaveError::LengthExceedsMaximum { length }.into());
+        }
+
+        let sectors = length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u32) as u8;
+
+        self.chunks.insert(
+            metadata_index,
+            BufferedChunk {
+                compression_scheme,
+                compressed_data: compressed_data.to_vec(),
+                sectors,
+                last_modified_timestamp: self.last_modified_timestamp,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Returns amount of added chunks.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns true if no chunks were added.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Writes header followed by chunks and returns writer positioned after
+    /// last chunk.
+    pub fn finish(mut self) -> Result<W, BuilderError> {
+        let mut chunks_metadata = [AnvilChunkMetadata::default(); REGION_CHUNKS];
+        let mut next_sector_index = 2;
+
+        for (&metadata_index, chunk) in &self.chunks {
+            chunks_metadata[metadata_index] = AnvilChunkMetadata::new(
+                next_sector_index,
+                chunk.sectors,
+                chunk.last_modified_timestamp,
+            );
+            next_sector_index += chunk.sectors as u32;
+        }
+
+        for metadata in chunks_metadata.iter() {
+            let offset = (metadata.sector_index << 8) | metadata.sectors as u32;
+            self.writer.write_u32::<BigEndian>(offset)?;
+        }
+
+        for metadata in chunks_metadata.iter() {
+            self.writer
+                .write_u32::<BigEndian>(metadata.last_modified_timestamp)?;
+        }
+
+        for chunk in self.chunks.values() {
+            // 4 bytes for data length.
+            let length = chunk.compressed_data.len() as u32 + 5;
+            // Zeroed padding to align sector.
+            let padding = chunk.sectors as u32 * REGION_SECTOR_BYTES_LENGTH as u32 - length;
+
+            self.writer.write_u32::<BigEndian>(length - 4)?;
+            self.writer.write_u8(chunk.compression_scheme)?;
+            self.writer.write_all(&chunk.compressed_data)?;
+            self.writer.write_all(&vec![0; padding as usize])?;
+        }
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{BuilderError, RegionBuilder};
+    use crate::AnvilRegion;
+    use nbt::CompoundTag;
+    use std::fs;
+    use std::io::{self, Cursor, Write};
+    use tempfile::TempDir;
+
+    /// Writer which fails once length limit is reached.
+    struct LimitedWriter {
+        cursor: Cursor<Vec<u8>>,
+        limit: u64,
+    }
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.cursor.position() + buf.len() as u64 > self.limit {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "limit reached"));
+            }
+
+            self.cursor.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.cursor.flush()
+        }
+    }
+
+    fn chunk(value: i32) -> CompoundTag {
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32_vec("values", vec![value; 2048]);
+
+        chunk_compound_tag
+    }
+
+    fn build(chunks: &[(u8, u8, i32)]) -> Vec<u8> {
+        let mut region_builder =
+            RegionBuilder::new(Cursor::new(Vec::new())).with_last_modified_timestamp(42);
+
+        for &(chunk_x, chunk_z, value) in chunks {
+            region_builder
+                .add_chunk(chunk_x, chunk_z, &chunk(value))
+                .unwrap();
+        }
+
+        region_builder.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_build_region() {
+        let chunks = [(31, 31, 1), (0, 0, 2), (5, 7, 3)];
+        let region_bytes = build(&chunks);
+
+        assert_eq!(region_bytes, build(&[(5, 7, 3), (0, 0, 2), (31, 31, 1)]));
+        assert_eq!(region_bytes.len() % 4096, 0);
+
+        let folder = TempDir::new().unwrap();
+        let region_path = folder.path().join("r.0.0.mca");
+        let mut region_builder = RegionBuilder::create(&region_path).unwrap();
+
+        for &(chunk_x, chunk_z, value) in &chunks {
+            region_builder
+                .add_chunk(chunk_x, chunk_z, &chunk(value))
+                .unwrap();
+        }
+
+        match region_builder.add_chunk(0, 0, &CompoundTag::new()) {
+            Err(BuilderError::DuplicateChunk {
+                chunk_x: 0,
+                chunk_z: 0,
+            }) => {}
+            result => panic!("Expected `DuplicateChunk` but got `{:?}`", result),
+        }
+
+        let mut region = region_builder.finish_region(&region_path).unwrap();
+
+        // Chunks follow header in header order without gaps.
+        let chunks_metadata = region.chunks();
+        assert_eq!(chunks_metadata.len(), 3);
+        assert_eq!(region.chunk_metadata(0, 0).sector_index, 2);
+        assert_eq!(region.used_sectors_count(), region.total_sectors());
+
+        for &(chunk_x, chunk_z, value) in &chunks {
+            let chunk_compound_tag = region.read_chunk(chunk_x, chunk_z).unwrap();
+            let values = chunk_compound_tag.get_i32_vec("values").unwrap();
+
+            assert_eq!(values[0], value);
+        }
+    }
+
+    #[test]
+    fn test_build_region_matches_canonicalize() {
+        let region_bytes = build(&[(31, 31, 1), (0, 0, 2), (5, 7, 3)]);
+
+        let folder = TempDir::new().unwrap();
+        let region_path = folder.path().join("r.0.0.mca");
+        fs::write(&region_path, &region_bytes).unwrap();
+
+        let mut region = AnvilRegion::open(&region_path).unwrap();
+        assert!(region.canonicalize().unwrap().is_empty());
+        drop(region);
+
+        assert_eq!(fs::read(&region_path).unwrap(), region_bytes);
+    }
+
+    #[test]
+    fn test_finish_write_error() {
+        let writer = LimitedWriter {
+            cursor: Cursor::new(Vec::new()),
+            limit: 8192 + 100,
+        };
+        let mut region_builder = RegionBuilder::new(writer);
+        region_builder.add_chunk_bytes(0, 0, 2, &[0; 200]).unwrap();
+
+        match region_builder.finish() {
+            Err(BuilderError::ChunkSaveError { .. }) => {}
+            result => panic!(
+                "Expected `ChunkSaveError` but got `{:?}`",
+                result.map(|writer| writer.cursor)
+            ),
+        }
+    }
+}
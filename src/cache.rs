@@ -0,0 +1,431 @@
+//! Write-back cache of decoded chunks.
+//!
+//! Chunks are decoded once and kept in memory, changed chunks are marked
+//! dirty and written in batches on flush, eviction or drop.
+use crate::metrics::CountingWriter;
+use crate::{
+    region_chunk_position, region_position, AnvilChunkProvider, ChunkLoadError, ChunkSaveError,
+};
+use nbt::encode::write_compound_tag;
+use nbt::CompoundTag;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Default memory budget of 64 MiB.
+const DEFAULT_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Possible errors while accessing cached chunks.
+#[derive(Debug)]
+pub enum CacheError {
+    /// Error while loading chunk into cache.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while flushing dirty chunk.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+}
+
+impl From<ChunkLoadError> for CacheError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        CacheError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for CacheError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        CacheError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+struct CacheEntry {
+    chunk_compound_tag: CompoundTag,
+    /// Whether chunk was changed since it was loaded or flushed.
+    dirty: bool,
+    /// Estimated memory size, none if chunk could change since estimation.
+    size: Option<usize>,
+    /// Access counter value of last access.
+    last_access: u64,
+}
+
+/// Cache of decoded chunks in front of provider.
+///
+/// Dirty chunks are flushed when cache is dropped, errors of that flush are
+/// lost, so `flush` should be called explicitly to handle them.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::cache::ChunkCache;
+/// use anvil_region::AnvilChunkProvider;
+///
+/// let folder = tempfile::tempdir().unwrap();
+/// let mut chunk_cache = ChunkCache::new(AnvilChunkProvider::from_path(folder.path()));
+///
+/// chunk_cache.insert(0, 0, nbt::CompoundTag::new()).unwrap();
+///
+/// for tick in 0..20 {
+///     let chunk_compound_tag = chunk_cache.get_mut(0, 0).unwrap();
+///     chunk_compound_tag.insert_i64("InhabitedTime", tick);
+/// }
+///
+/// assert_eq!(chunk_cache.flush().unwrap(), 1);
+/// ```
+pub struct ChunkCache<'a> {
+    chunk_provider: AnvilChunkProvider<'a>,
+    entries: HashMap<(i32, i32), CacheEntry>,
+    /// Memory budget in bytes of encoded chunks.
+    memory_budget: usize,
+    flush_interval: Option<Duration>,
+    last_flush: Instant,
+    access_counter: u64,
+}
+
+impl<'a> ChunkCache<'a> {
+    /// Creates cache with 64 MiB memory budget which flushes only explicitly.
+    pub fn new(chunk_provider: AnvilChunkProvider<'a>) -> Self {
+        ChunkCache {
+            chunk_provider,
+            entries: HashMap::new(),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            flush_interval: None,
+            last_flush: Instant::now(),
+            access_counter: 0,
+        }
+    }
+
+    /// Sets budget of memory estimated by encoded chunk lengths.
+    ///
+    /// Least recently used chunks are evicted when budget is exceeded.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Flushes dirty chunks on access when interval passed since last flush.
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// Returns provider behind cache.
+    pub fn chunk_provider(&self) -> &AnvilChunkProvider<'a> {
+        &self.chunk_provider
+    }
+
+    /// Returns amount of cached chunks.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there are no cached chunks.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns whether chunk is cached and changed since last flush.
+    pub fn is_dirty(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        self.entries
+            .get(&(chunk_x, chunk_z))
+            .is_some_and(|entry| entry.dirty)
+    }
+
+    /// Returns chunk, loading it on cache miss.
+    pub fn get(&mut self, chunk_x: i32, chunk_z: i32) -> Result<&CompoundTag, CacheError> {
+        let entry = self.entry(chunk_x, chunk_z)?;
+
+        Ok(&entry.chunk_compound_tag)
+    }
+
+    /// Returns chunk for change and marks it dirty, loading it on cache miss.
+    pub fn get_mut(&mut self, chunk_x: i32, chunk_z: i32) -> Result<&mut CompoundTag, CacheError> {
+        let entry = self.entry(chunk_x, chunk_z)?;
+        entry.dirty = true;
+        entry.size = None;
+
+        Ok(&mut entry.chunk_compound_tag)
+    }
+
+    /// Puts chunk into cache replacing existing one, chunk is marked dirty.
+    pub fn insert(
+        &mut self,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk_compound_tag: CompoundTag,
+    ) -> Result<(), CacheError> {
+        self.flush_if_due()?;
+        self.access_counter += 1;
+
+        let entry = CacheEntry {
+            chunk_compound_tag,
+            dirty: true,
+            size: None,
+            last_access: self.access_counter,
+        };
+
+        self.entries.insert((chunk_x, chunk_z), entry);
+        self.evict((chunk_x, chunk_z))?;
+
+        Ok(())
+    }
+
+    /// Writes all dirty chunks keeping them cached.
+    ///
+    /// Returns amount of written chunks.
+    pub fn flush(&mut self) -> Result<usize, ChunkSaveError> {
+        self.last_flush = Instant::now();
+
+        let mut region_chunks = BTreeMap::new();
+
+        for (chunk_position, entry) in &self.entries {
+            if entry.dirty {
+                region_chunks
+                    .entry(region_position(chunk_position.0, chunk_position.1))
+                    .or_insert_with(Vec::new)
+                    .push(*chunk_position);
+            }
+        }
+
+        let mut flushed_chunks = 0;
+
+        for ((region_x, region_z), chunk_positions) in region_chunks {
+            let folder_path = self.chunk_provider.folder_path();
+
+            if !folder_path.exists() {
+                fs::create_dir(folder_path)?;
+            }
+
+            let region_path = self
+                .chunk_provider
+                .writable_region_path(region_x, region_z)?;
+            let mut region = self.chunk_provider.open_region_for_write(region_path)?;
+
+            for (chunk_x, chunk_z) in chunk_positions {
+                let entry = self.entries.get_mut(&(chunk_x, chunk_z)).unwrap();
+                let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+                region.write_chunk_tag(
+                    region_chunk_x,
+                    region_chunk_z,
+                    &entry.chunk_compound_tag,
+                )?;
+                entry.dirty = false;
+                flushed_chunks += 1;
+            }
+        }
+
+        Ok(flushed_chunks)
+    }
+
+    /// Flushes when flush interval passed.
+    fn flush_if_due(&mut self) -> Result<(), ChunkSaveError> {
+        match self.flush_interval {
+            Some(flush_interval) if self.last_flush.elapsed() >= flush_interval => {
+                self.flush()?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn entry(&mut self, chunk_x: i32, chunk_z: i32) -> Result<&mut CacheEntry, CacheError> {
+        self.flush_if_due()?;
+        self.access_counter += 1;
+
+        let hit = self.entries.contains_key(&(chunk_x, chunk_z));
+
+        if let Some(metrics) = &self.chunk_provider.metrics {
+            metrics.cache_accessed(hit);
+        }
+
+        if !hit {
+            let chunk_compound_tag = self.chunk_provider.load_chunk(chunk_x, chunk_z)?;
+
+            let entry = CacheEntry {
+                chunk_compound_tag,
+                dirty: false,
+                size: None,
+                last_access: self.access_counter,
+            };
+
+            self.entries.insert((chunk_x, chunk_z), entry);
+            self.evict((chunk_x, chunk_z))?;
+        }
+
+        let entry = self.entries.get_mut(&(chunk_x, chunk_z)).unwrap();
+        entry.last_access = self.access_counter;
+
+        Ok(entry)
+    }
+
+    /// Evicts least recently used chunks except kept one until cache fits budget.
+    fn evict(&mut self, kept_chunk_position: (i32, i32)) -> Result<(), ChunkSaveError> {
+        let mut used_memory = 0;
+
+        for entry in self.entries.values_mut() {
+            let size = match entry.size {
+                Some(size) => size,
+                None => {
+                    let size = estimate_size(&entry.chunk_compound_tag);
+                    entry.size = Some(size);
+                    size
+                }
+            };
+
+            used_memory += size;
+        }
+
+        if used_memory <= self.memory_budget {
+            return Ok(());
+        }
+
+        let mut chunk_positions: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(chunk_position, _)| **chunk_position != kept_chunk_position)
+            .map(|(chunk_position, entry)| (entry.last_access, *chunk_position))
+            .collect();
+
+        chunk_positions.sort_unstable();
+
+        for (_, (chunk_x, chunk_z)) in chunk_positions {
+            if used_memory <= self.memory_budget {
+                break;
+            }
+
+            let entry = &self.entries[&(chunk_x, chunk_z)];
+
+            // Dirty chunk stays cached when it cannot be written.
+            if entry.dirty {
+                self.chunk_provider
+                    .save_chunk_tag(chunk_x, chunk_z, &entry.chunk_compound_tag)?;
+            }
+
+            let entry = self.entries.remove(&(chunk_x, chunk_z)).unwrap();
+            used_memory -= entry.size.unwrap_or_default();
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ChunkCache<'_> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Returns length of chunk encoded without compression.
+fn estimate_size(chunk_compound_tag: &CompoundTag) -> usize {
+    let mut counting_writer = CountingWriter::new(io::sink());
+
+    match write_compound_tag(&mut counting_writer, chunk_compound_tag) {
+        Ok(()) => counting_writer.count as usize,
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{CacheError, ChunkCache};
+    use crate::fixtures::{chunk, value};
+    use crate::metrics::MetricsRecorder;
+    use crate::{AnvilChunkProvider, ChunkSaveError};
+    use nbt::CompoundTag;
+    use std::fs;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Returns chunk large enough for memory budget tests.
+    fn large_chunk(value: i32) -> CompoundTag {
+        let mut chunk_compound_tag = chunk(value);
+        chunk_compound_tag.insert_i8_vec("data", vec![0; 1000]);
+
+        chunk_compound_tag
+    }
+
+    #[test]
+    fn test_write_back() {
+        let folder = TempDir::new().unwrap();
+        let recorder = Arc::new(MetricsRecorder::default());
+        let chunk_provider =
+            AnvilChunkProvider::from_path(folder.path()).with_metrics(recorder.clone());
+        chunk_provider.save_chunk(0, 0, chunk(1)).unwrap();
+
+        let mut chunk_cache = ChunkCache::new(chunk_provider);
+
+        assert_eq!(value(chunk_cache.get(0, 0).unwrap()), 1);
+        assert!(!chunk_cache.is_dirty(0, 0));
+
+        chunk_cache.get_mut(0, 0).unwrap().insert_i32("value", 2);
+        chunk_cache.insert(40, 0, chunk(3)).unwrap();
+        assert!(chunk_cache.is_dirty(0, 0));
+
+        // Changes are not written until flush.
+        let chunk_provider = chunk_cache.chunk_provider();
+        assert_eq!(value(&chunk_provider.load_chunk(0, 0).unwrap()), 1);
+        assert!(chunk_provider.load_chunk(40, 0).is_err());
+
+        assert_eq!(chunk_cache.flush().unwrap(), 2);
+        assert_eq!(chunk_cache.flush().unwrap(), 0);
+        assert!(!chunk_cache.is_dirty(0, 0));
+
+        let chunk_provider = chunk_cache.chunk_provider();
+        assert_eq!(value(&chunk_provider.load_chunk(0, 0).unwrap()), 2);
+        assert_eq!(value(&chunk_provider.load_chunk(40, 0).unwrap()), 3);
+
+        chunk_cache.get_mut(40, 0).unwrap().insert_i32("value", 4);
+        drop(chunk_cache);
+
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        assert_eq!(value(&chunk_provider.load_chunk(40, 0).unwrap()), 4);
+
+        assert_eq!(recorder.cache_hits.load(Ordering::Relaxed), 2);
+        assert_eq!(recorder.cache_misses.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        let mut chunk_cache = ChunkCache::new(chunk_provider).with_memory_budget(2500);
+
+        chunk_cache.insert(0, 0, large_chunk(1)).unwrap();
+        chunk_cache.insert(1, 0, large_chunk(1)).unwrap();
+        chunk_cache.get(0, 0).unwrap();
+        chunk_cache.insert(2, 0, large_chunk(1)).unwrap();
+
+        // Least recently used dirty chunk is written on eviction.
+        assert_eq!(chunk_cache.len(), 2);
+        assert!(!chunk_cache.is_dirty(1, 0));
+        assert!(chunk_cache.is_dirty(0, 0));
+        assert!(chunk_cache.chunk_provider().load_chunk(1, 0).is_ok());
+        assert!(chunk_cache.chunk_provider().load_chunk(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_evict_mcregion_not_converted() {
+        let folder = TempDir::new().unwrap();
+        fs::write(folder.path().join("r.0.0.mcr"), []).unwrap();
+
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        let mut chunk_cache = ChunkCache::new(chunk_provider).with_memory_budget(2500);
+
+        chunk_cache.insert(0, 0, large_chunk(1)).unwrap();
+        chunk_cache.insert(1, 0, large_chunk(1)).unwrap();
+
+        match chunk_cache.insert(2, 0, large_chunk(1)) {
+            Err(CacheError::ChunkSaveError {
+                chunk_save_error: ChunkSaveError::McRegionNotConverted { .. },
+            }) => {}
+            result => panic!("Expected `McRegionNotConverted` but got `{:?}`", result),
+        }
+
+        // Chunk which could not be written is kept.
+        assert_eq!(chunk_cache.len(), 3);
+        assert!(chunk_cache.is_dirty(0, 0));
+        assert!(chunk_cache.flush().is_err());
+        assert!(!folder.path().join("r.0.0.mca").exists());
+    }
+}
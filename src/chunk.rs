@@ -0,0 +1,488 @@
+//! Typed access to block data stored in chunk sections.
+use crate::packed::{bits_required, pack, packed_length, unpack};
+use crate::version::ChunkVersion;
+use nbt::{CompoundTag, CompoundTagError, Tag};
+use std::collections::BTreeMap;
+
+/// Amount of blocks in section.
+pub const SECTION_BLOCKS: usize = 4096;
+/// Minimum amount of bits used to store block state index.
+const SECTION_MINIMUM_BITS_PER_BLOCK: usize = 4;
+
+/// Possible errors while accessing chunk data.
+#[derive(Debug)]
+pub enum ChunkDataError {
+    /// Required tag not found.
+    MissingTag {
+        /// Name of tag which was not found.
+        name: String,
+    },
+    /// Tag actual type not match expected.
+    WrongTagType {
+        /// Name of tag which type not matched.
+        name: String,
+    },
+    /// Array length not match expected.
+    ///
+    /// Chunk data are corrupted or was written by unsupported version.
+    InvalidLength {
+        /// Name of array tag.
+        name: String,
+        /// Actual array length.
+        length: usize,
+        /// Expected array length.
+        expected_length: usize,
+    },
+    /// Chunk layout are not supported by accessor.
+    UnsupportedVersion {
+        /// Data version of chunk, if stored.
+        data_version: Option<i32>,
+    },
+    /// Block state index points outside of section palette.
+    PaletteIndexOutOfBounds {
+        /// Block state index.
+        index: u16,
+        /// Palette length.
+        palette_length: usize,
+    },
+}
+
+impl<'a> From<CompoundTagError<'a>> for ChunkDataError {
+    fn from(compound_tag_error: CompoundTagError<'a>) -> Self {
+        match compound_tag_error {
+            CompoundTagError::TagNotFound { name } => ChunkDataError::MissingTag {
+                name: name.to_owned(),
+            },
+            CompoundTagError::TagWrongType { name, .. } => ChunkDataError::WrongTagType {
+                name: name.to_owned(),
+            },
+        }
+    }
+}
+
+/// Block state are stored in section palette.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct BlockState {
+    /// Namespaced block name, for example `minecraft:stone`.
+    pub name: String,
+    /// Block state properties, for example `facing` -> `north`.
+    pub properties: BTreeMap<String, String>,
+}
+
+impl BlockState {
+    pub fn new(name: &str) -> Self {
+        BlockState {
+            name: name.to_owned(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Returns true if block is one of the air variants.
+    pub fn is_air(&self) -> bool {
+        matches!(
+            self.name.as_str(),
+            "minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"
+        )
+    }
+
+    /// Returns property value.
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(|value| value.as_str())
+    }
+
+    fn from_tag(tag: &CompoundTag) -> Result<Self, ChunkDataError> {
+        let name = tag.get_str("Name")?.to_owned();
+        let mut properties = BTreeMap::new();
+
+        if let Ok(properties_tag) = tag.get_compound_tag("Properties") {
+            for (name, value) in properties_tag.iter() {
+                if let Tag::String(value) = value {
+                    properties.insert(name.clone(), value.clone());
+                }
+            }
+        }
+
+        Ok(BlockState { name, properties })
+    }
+
+    pub(crate) fn to_tag(&self) -> CompoundTag {
+        let mut tag = CompoundTag::new();
+        tag.insert_str("Name", &self.name);
+
+        if !self.properties.is_empty() {
+            let mut properties_tag = CompoundTag::new();
+
+            for (name, value) in &self.properties {
+                properties_tag.insert_str(name, value);
+            }
+
+            tag.insert_compound_tag("Properties", properties_tag);
+        }
+
+        tag
+    }
+}
+
+/// Section represents a 16x16x16 group of blocks.
+#[derive(Clone, Debug)]
+pub struct ChunkSection {
+    /// Section index from bottom of chunk.
+    pub y: i8,
+    /// Block states used in section.
+    pub palette: Vec<BlockState>,
+    /// Palette indexes in YZX order.
+    pub blocks: Vec<u16>,
+}
+
+impl ChunkSection {
+    /// Creates section filled with air.
+    pub fn new(y: i8) -> Self {
+        ChunkSection {
+            y,
+            palette: vec![BlockState::new("minecraft:air")],
+            blocks: vec![0; SECTION_BLOCKS],
+        }
+    }
+
+    fn block_index(x: u8, y: u8, z: u8) -> usize {
+        assert!(16 > x, "Section x coordinate out of bounds");
+        assert!(16 > y, "Section y coordinate out of bounds");
+        assert!(16 > z, "Section z coordinate out of bounds");
+
+        (y as usize) << 8 | (z as usize) << 4 | x as usize
+    }
+
+    /// Returns block state at specified section coordinates.
+    pub fn block(&self, x: u8, y: u8, z: u8) -> &BlockState {
+        &self.palette[self.blocks[Self::block_index(x, y, z)] as usize]
+    }
+
+    /// Sets block state at specified section coordinates.
+    pub fn set_block(&mut self, x: u8, y: u8, z: u8, block_state: BlockState) {
+        let palette_index = match self.palette.iter().position(|state| state == &block_state) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(block_state);
+                self.palette.len() - 1
+            }
+        };
+
+        self.blocks[Self::block_index(x, y, z)] = palette_index as u16;
+    }
+
+    /// Returns true if section contains only air.
+    pub fn is_empty(&self) -> bool {
+        self.blocks
+            .iter()
+            .all(|index| self.palette[*index as usize].is_air())
+    }
+
+    /// Reads section from tag of a sections list.
+    ///
+    /// Sections without block data are read as filled with air.
+    pub fn from_tag(
+        tag: &CompoundTag,
+        chunk_version: ChunkVersion,
+    ) -> Result<Self, ChunkDataError> {
+        let y = tag.get_i8("Y")?;
+
+        let (palette_tag, palette_name, blocks_name) = match chunk_version {
+            ChunkVersion::Unwrapped if tag.contains_key("block_states") => {
+                (tag.get_compound_tag("block_states")?, "palette", "data")
+            }
+            ChunkVersion::Unwrapped => return Ok(ChunkSection::new(y)),
+            ChunkVersion::PreFlattening => {
+                return Err(ChunkDataError::UnsupportedVersion { data_version: None })
+            }
+            _ => (tag, "Palette", "BlockStates"),
+        };
+
+        if !palette_tag.contains_key(palette_name) {
+            return Ok(ChunkSection::new(y));
+        }
+
+        let mut palette = Vec::new();
+
+        for block_state_tag in palette_tag.get_compound_tag_vec(palette_name)? {
+            palette.push(BlockState::from_tag(block_state_tag)?);
+        }
+
+        // Since 1.18 sections filled with a single block state have no data.
+        if palette.len() == 1 && !palette_tag.contains_key(blocks_name) {
+            return Ok(ChunkSection {
+                y,
+                palette,
+                blocks: vec![0; SECTION_BLOCKS],
+            });
+        }
+
+        let spanning = chunk_version.is_spanning();
+        let longs = palette_tag.get_i64_vec(blocks_name)?;
+        let bits_per_block = Self::bits_per_block(palette.len());
+        let expected_length = packed_length(bits_per_block, SECTION_BLOCKS, spanning);
+
+        if longs.len() != expected_length {
+            return Err(ChunkDataError::InvalidLength {
+                name: blocks_name.to_owned(),
+                length: longs.len(),
+                expected_length,
+            });
+        }
+
+        let blocks = unpack(longs, bits_per_block, SECTION_BLOCKS, spanning);
+
+        if let Some(index) = blocks
+            .iter()
+            .find(|index| **index as usize >= palette.len())
+        {
+            return Err(ChunkDataError::PaletteIndexOutOfBounds {
+                index: *index,
+                palette_length: palette.len(),
+            });
+        }
+
+        Ok(ChunkSection { y, palette, blocks })
+    }
+
+    /// Writes palette and block states to section tag.
+    ///
+    /// Unused palette entries are removed.
+    pub fn write_to_tag(&self, tag: &mut CompoundTag, chunk_version: ChunkVersion) {
+        let mut palette = Vec::new();
+        let mut remap = vec![None; self.palette.len()];
+        let mut blocks = Vec::with_capacity(SECTION_BLOCKS);
+
+        for index in &self.blocks {
+            let new_index = match remap[*index as usize] {
+                Some(new_index) => new_index,
+                None => {
+                    palette.push(self.palette[*index as usize].to_tag());
+                    let new_index = (palette.len() - 1) as u16;
+                    remap[*index as usize] = Some(new_index);
+
+                    new_index
+                }
+            };
+
+            blocks.push(new_index);
+        }
+
+        let bits_per_block = Self::bits_per_block(palette.len());
+        let single_state = palette.len() == 1;
+        let longs = pack(&blocks, bits_per_block, chunk_version.is_spanning());
+
+        tag.insert_i8("Y", self.y);
+
+        if chunk_version == ChunkVersion::Unwrapped {
+            let mut block_states_tag = CompoundTag::new();
+            block_states_tag.insert_compound_tag_vec("palette", palette);
+
+            if !single_state {
+                block_states_tag.insert_i64_vec("data", longs);
+            }
+
+            tag.insert_compound_tag("block_states", block_states_tag);
+        } else {
+            tag.insert_compound_tag_vec("Palette", palette);
+            tag.insert_i64_vec("BlockStates", longs);
+        }
+    }
+
+    fn bits_per_block(palette_length: usize) -> usize {
+        bits_required(palette_length.max(1) - 1).max(SECTION_MINIMUM_BITS_PER_BLOCK)
+    }
+}
+
+/// Reads all sections with block data ordered from bottom to top.
+///
+/// Sections storing only light data are skipped.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::chunk::read_sections;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// let sections = read_sections(&chunk_compound_tag).unwrap();
+/// let bedrock = sections[0].block(0, 0, 0);
+///
+/// assert_eq!(bedrock.name, "minecraft:bedrock");
+/// ```
+pub fn read_sections(
+    chunk_compound_tag: &CompoundTag,
+) -> Result<Vec<ChunkSection>, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level(chunk_compound_tag)?;
+    let sections_tag_name = chunk_version.sections_tag_name();
+    let mut sections = Vec::new();
+
+    if !level_compound_tag.contains_key(sections_tag_name) {
+        return Ok(sections);
+    }
+
+    for section_tag in level_compound_tag.get_compound_tag_vec(sections_tag_name)? {
+        if section_tag.contains_key("Palette") || section_tag.contains_key("block_states") {
+            sections.push(ChunkSection::from_tag(section_tag, chunk_version)?);
+        }
+    }
+
+    sections.sort_by_key(|section| section.y);
+
+    Ok(sections)
+}
+
+/// Replaces block data of chunk sections keeping their other tags.
+///
+/// Sections missing in chunk are added.
+pub fn write_sections(
+    chunk_compound_tag: &mut CompoundTag,
+    sections: &[ChunkSection],
+) -> Result<(), ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level_mut(chunk_compound_tag)?;
+    let sections_tag_name = chunk_version.sections_tag_name();
+
+    if !level_compound_tag.contains_key(sections_tag_name) {
+        level_compound_tag.insert(sections_tag_name, Vec::<Tag>::new());
+    }
+
+    let section_tags: &mut Vec<Tag> = level_compound_tag.get_mut(sections_tag_name)?;
+
+    for section in sections {
+        let section_tag = section_tag_mut(section_tags, section.y);
+        section.write_to_tag(section_tag, chunk_version);
+    }
+
+    Ok(())
+}
+
+/// Returns section tag with specified `Y` creating it if missing.
+pub(crate) fn section_tag_mut(section_tags: &mut Vec<Tag>, y: i8) -> &mut CompoundTag {
+    let position = section_tags.iter().position(|section| match section {
+        Tag::Compound(section_tag) => section_tag.get_i8("Y").ok() == Some(y),
+        _ => false,
+    });
+
+    let position = match position {
+        Some(position) => position,
+        None => {
+            let mut section_tag = CompoundTag::new();
+            section_tag.insert_i8("Y", y);
+            section_tags.push(Tag::Compound(section_tag));
+
+            section_tags.len() - 1
+        }
+    };
+
+    match &mut section_tags[position] {
+        Tag::Compound(section_tag) => section_tag,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{read_sections, write_sections, BlockState, ChunkSection};
+    use crate::version::ChunkVersion;
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+
+    #[test]
+    fn test_read_sections() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+
+        assert!(!sections.is_empty());
+        assert_eq!(sections[0].y, 0);
+        assert_eq!(sections[0].block(3, 0, 7).name, "minecraft:bedrock");
+    }
+
+    #[test]
+    fn test_section_write_read() {
+        let mut section = ChunkSection::new(2);
+        let mut stairs = BlockState::new("minecraft:oak_stairs");
+        stairs
+            .properties
+            .insert("facing".to_owned(), "north".to_owned());
+
+        section.set_block(1, 2, 3, BlockState::new("minecraft:stone"));
+        section.set_block(15, 15, 15, stairs.clone());
+
+        let chunk_versions = [
+            ChunkVersion::Flattened,
+            ChunkVersion::NonSpanning,
+            ChunkVersion::Unwrapped,
+        ];
+
+        for chunk_version in chunk_versions.iter() {
+            let mut tag = CompoundTag::new();
+            section.write_to_tag(&mut tag, *chunk_version);
+
+            let read_section = ChunkSection::from_tag(&tag, *chunk_version).unwrap();
+
+            assert_eq!(read_section.y, 2);
+            assert_eq!(read_section.block(1, 2, 3).name, "minecraft:stone");
+            assert_eq!(read_section.block(15, 15, 15), &stairs);
+            assert!(read_section.block(0, 0, 0).is_air());
+        }
+    }
+
+    #[test]
+    fn test_section_is_empty() {
+        let mut section = ChunkSection::new(0);
+        assert!(section.is_empty());
+
+        section.set_block(0, 0, 0, BlockState::new("minecraft:dirt"));
+        assert!(!section.is_empty());
+    }
+
+    #[test]
+    fn test_write_sections() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let mut chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+
+        let mut sections = read_sections(&chunk_compound_tag).unwrap();
+        sections[0].set_block(0, 0, 0, BlockState::new("minecraft:diamond_block"));
+
+        write_sections(&mut chunk_compound_tag, &sections).unwrap();
+        let read_sections = read_sections(&chunk_compound_tag).unwrap();
+
+        assert_eq!(read_sections.len(), sections.len());
+        assert_eq!(
+            read_sections[0].block(0, 0, 0).name,
+            "minecraft:diamond_block"
+        );
+    }
+
+    #[test]
+    fn test_read_sections_unwrapped_single_state() {
+        let mut block_states_tag = CompoundTag::new();
+        block_states_tag
+            .insert_compound_tag_vec("palette", vec![BlockState::new("minecraft:stone").to_tag()]);
+
+        let mut section_tag = CompoundTag::new();
+        section_tag.insert_i8("Y", -4);
+        section_tag.insert_compound_tag("block_states", block_states_tag);
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", 3465);
+        chunk_compound_tag.insert_compound_tag_vec("sections", vec![section_tag]);
+
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+
+        assert_eq!(sections[0].y, -4);
+        assert_eq!(sections[0].block(7, 7, 7).name, "minecraft:stone");
+    }
+
+    #[test]
+    fn test_read_sections_pre_flattening() {
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_compound_tag("Level", CompoundTag::new());
+
+        assert!(read_sections(&chunk_compound_tag).is_err());
+    }
+}
@@ -0,0 +1,466 @@
+//! Differences between two versions of chunks.
+//!
+//! Chunks are compared by header timestamps and hashes of stored payload,
+//! changed chunks can be additionally compared tag by tag and block by block.
+use crate::chunk::{read_sections, ChunkSection};
+use crate::{chunk_position, AnvilChunkProvider, AnvilRegion, ChunkLoadError};
+use nbt::{CompoundTag, Tag};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+
+/// SHA-256 hash of stored chunk payload.
+pub type PayloadHash = [u8; 32];
+
+/// Returns hash of compression scheme and compressed chunk data.
+pub fn payload_hash(compression_scheme: u8, compressed_data: &[u8]) -> PayloadHash {
+    let mut hasher = Sha256::new();
+    hasher.update([compression_scheme]);
+    hasher.update(compressed_data);
+
+    hasher.finalize().into()
+}
+
+/// Kind of chunk change.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChunkChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// Change of single tag, path consists of compound names and list indexes,
+/// for example `Level.Sections[2].BlockStates`.
+#[derive(Debug, Eq, PartialEq)]
+pub enum TagChange {
+    Added { path: String },
+    Removed { path: String },
+    Changed { path: String },
+}
+
+/// Change of chunk between old and new version.
+#[derive(Debug)]
+pub struct ChunkChange {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub kind: ChunkChangeKind,
+    /// Header timestamp of old chunk.
+    pub old_timestamp: Option<u32>,
+    /// Header timestamp of new chunk.
+    pub new_timestamp: Option<u32>,
+    /// Changed tags, filled by structural diff.
+    pub tag_changes: Vec<TagChange>,
+    /// Amount of changed blocks, filled by structural diff when block data
+    /// of both chunks are readable.
+    pub changed_blocks: Option<usize>,
+}
+
+/// Compares chunks of two providers or region files.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::diff::ChunkDiffer;
+/// use anvil_region::AnvilChunkProvider;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let changes = ChunkDiffer::new()
+///     .diff_providers(&chunk_provider, &chunk_provider)
+///     .unwrap();
+///
+/// assert!(changes.is_empty());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ChunkDiffer {
+    trust_timestamps: bool,
+    structural: bool,
+}
+
+impl ChunkDiffer {
+    /// Creates differ which compares payload hashes of all chunks.
+    pub fn new() -> Self {
+        ChunkDiffer::default()
+    }
+
+    /// Treats chunks with equal header timestamps as unchanged without
+    /// reading their payload.
+    pub fn with_trust_timestamps(mut self, trust_timestamps: bool) -> Self {
+        self.trust_timestamps = trust_timestamps;
+        self
+    }
+
+    /// Compares changed chunks tag by tag and block by block.
+    ///
+    /// Chunks which payload differs only by compression are not reported.
+    pub fn with_structural(mut self, structural: bool) -> Self {
+        self.structural = structural;
+        self
+    }
+
+    /// Returns changes from old provider chunks to new provider ones.
+    pub fn diff_providers(
+        &self,
+        old_provider: &AnvilChunkProvider,
+        new_provider: &AnvilChunkProvider,
+    ) -> Result<Vec<ChunkChange>, ChunkLoadError> {
+        let mut region_positions = old_provider.region_positions()?;
+        region_positions.extend(new_provider.region_positions()?);
+        region_positions.sort_unstable();
+        region_positions.dedup();
+
+        let mut changes = Vec::new();
+
+        for (region_x, region_z) in region_positions {
+            let mut old_region = open_region(old_provider, region_x, region_z)?;
+            let mut new_region = open_region(new_provider, region_x, region_z)?;
+
+            self.diff_region_pair(
+                old_region.as_mut(),
+                new_region.as_mut(),
+                region_x,
+                region_z,
+                &mut changes,
+            )?;
+        }
+
+        Ok(changes)
+    }
+
+    /// Returns changes between two versions of region file.
+    pub fn diff_regions(
+        &self,
+        old_region: &mut AnvilRegion,
+        new_region: &mut AnvilRegion,
+        region_x: i32,
+        region_z: i32,
+    ) -> Result<Vec<ChunkChange>, ChunkLoadError> {
+        let mut changes = Vec::new();
+        self.diff_region_pair(
+            Some(old_region),
+            Some(new_region),
+            region_x,
+            region_z,
+            &mut changes,
+        )?;
+
+        Ok(changes)
+    }
+
+    fn diff_region_pair(
+        &self,
+        mut old_region: Option<&mut AnvilRegion>,
+        mut new_region: Option<&mut AnvilRegion>,
+        region_x: i32,
+        region_z: i32,
+        changes: &mut Vec<ChunkChange>,
+    ) -> Result<(), ChunkLoadError> {
+        for region_chunk_z in 0..32 {
+            for region_chunk_x in 0..32 {
+                let old_timestamp = chunk_timestamp(&old_region, region_chunk_x, region_chunk_z);
+                let new_timestamp = chunk_timestamp(&new_region, region_chunk_x, region_chunk_z);
+
+                let (chunk_x, chunk_z) =
+                    chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+
+                let mut change = ChunkChange {
+                    chunk_x,
+                    chunk_z,
+                    kind: ChunkChangeKind::Changed,
+                    old_timestamp,
+                    new_timestamp,
+                    tag_changes: Vec::new(),
+                    changed_blocks: None,
+                };
+
+                let (old_region, new_region) = match (&mut old_region, &mut new_region) {
+                    (Some(old_region), Some(new_region))
+                        if old_timestamp.is_some() && new_timestamp.is_some() =>
+                    {
+                        (old_region, new_region)
+                    }
+                    _ => {
+                        change.kind = match (old_timestamp, new_timestamp) {
+                            (None, Some(_)) => ChunkChangeKind::Added,
+                            (Some(_), None) => ChunkChangeKind::Removed,
+                            _ => continue,
+                        };
+
+                        changes.push(change);
+                        continue;
+                    }
+                };
+
+                if self.trust_timestamps && old_timestamp == new_timestamp {
+                    continue;
+                }
+
+                let (old_scheme, old_data) =
+                    old_region.read_chunk_bytes(region_chunk_x, region_chunk_z)?;
+                let (new_scheme, new_data) =
+                    new_region.read_chunk_bytes(region_chunk_x, region_chunk_z)?;
+
+                if payload_hash(old_scheme, &old_data) == payload_hash(new_scheme, &new_data) {
+                    continue;
+                }
+
+                if self.structural {
+                    let old_compound_tag = old_region.read_chunk(region_chunk_x, region_chunk_z)?;
+                    let new_compound_tag = new_region.read_chunk(region_chunk_x, region_chunk_z)?;
+
+                    diff_compound_tags(
+                        "",
+                        &old_compound_tag,
+                        &new_compound_tag,
+                        &mut change.tag_changes,
+                    );
+
+                    if change.tag_changes.is_empty() {
+                        continue;
+                    }
+
+                    change.changed_blocks = changed_blocks(&old_compound_tag, &new_compound_tag);
+                }
+
+                changes.push(change);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn open_region(
+    chunk_provider: &AnvilChunkProvider,
+    region_x: i32,
+    region_z: i32,
+) -> Result<Option<AnvilRegion>, ChunkLoadError> {
+    match chunk_provider.existing_region_path(region_x, region_z) {
+        Some(region_path) => Ok(Some(chunk_provider.open_region(region_path)?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns header timestamp of chunk, none if there is no chunk.
+fn chunk_timestamp(region: &Option<&mut AnvilRegion>, chunk_x: u8, chunk_z: u8) -> Option<u32> {
+    let metadata = region.as_ref()?.chunk_metadata(chunk_x, chunk_z);
+
+    if metadata.is_empty() {
+        None
+    } else {
+        Some(metadata.last_modified_timestamp)
+    }
+}
+
+fn tag_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+/// Appends changes between compound tags.
+///
+/// Order of tags is not compared.
+pub fn diff_compound_tags(
+    path: &str,
+    old_compound_tag: &CompoundTag,
+    new_compound_tag: &CompoundTag,
+    changes: &mut Vec<TagChange>,
+) {
+    let new_tags: HashMap<&String, &Tag> = new_compound_tag.iter().collect();
+    let old_tags: HashMap<&String, &Tag> = old_compound_tag.iter().collect();
+
+    for (name, old_tag) in old_compound_tag.iter() {
+        let path = tag_path(path, name);
+
+        match new_tags.get(name) {
+            Some(new_tag) => diff_tags(path, old_tag, new_tag, changes),
+            None => changes.push(TagChange::Removed { path }),
+        }
+    }
+
+    for (name, _) in new_compound_tag.iter() {
+        if !old_tags.contains_key(name) {
+            changes.push(TagChange::Added {
+                path: tag_path(path, name),
+            });
+        }
+    }
+}
+
+fn diff_tags(path: String, old_tag: &Tag, new_tag: &Tag, changes: &mut Vec<TagChange>) {
+    match (old_tag, new_tag) {
+        (Tag::Compound(old_compound_tag), Tag::Compound(new_compound_tag)) => {
+            diff_compound_tags(&path, old_compound_tag, new_compound_tag, changes)
+        }
+        (Tag::List(old_tags), Tag::List(new_tags)) => {
+            for index in 0..old_tags.len().max(new_tags.len()) {
+                let path = format!("{}[{}]", path, index);
+
+                match (old_tags.get(index), new_tags.get(index)) {
+                    (Some(old_tag), Some(new_tag)) => diff_tags(path, old_tag, new_tag, changes),
+                    (Some(_), None) => changes.push(TagChange::Removed { path }),
+                    _ => changes.push(TagChange::Added { path }),
+                }
+            }
+        }
+        _ if !values_equal(old_tag, new_tag) => changes.push(TagChange::Changed { path }),
+        _ => {}
+    }
+}
+
+/// Compares values of tags other than lists and compounds.
+///
+/// Floating point numbers are compared bitwise, so equal NaN are equal.
+fn values_equal(old_tag: &Tag, new_tag: &Tag) -> bool {
+    match (old_tag, new_tag) {
+        (Tag::Byte(old_value), Tag::Byte(new_value)) => old_value == new_value,
+        (Tag::Short(old_value), Tag::Short(new_value)) => old_value == new_value,
+        (Tag::Int(old_value), Tag::Int(new_value)) => old_value == new_value,
+        (Tag::Long(old_value), Tag::Long(new_value)) => old_value == new_value,
+        (Tag::Float(old_value), Tag::Float(new_value)) => {
+            old_value.to_bits() == new_value.to_bits()
+        }
+        (Tag::Double(old_value), Tag::Double(new_value)) => {
+            old_value.to_bits() == new_value.to_bits()
+        }
+        (Tag::String(old_value), Tag::String(new_value)) => old_value == new_value,
+        (Tag::ByteArray(old_values), Tag::ByteArray(new_values)) => old_values == new_values,
+        (Tag::IntArray(old_values), Tag::IntArray(new_values)) => old_values == new_values,
+        (Tag::LongArray(old_values), Tag::LongArray(new_values)) => old_values == new_values,
+        _ => false,
+    }
+}
+
+/// Returns amount of blocks which state differs, missing sections are air.
+///
+/// Returns none if block data of any chunk are not readable.
+pub fn changed_blocks(
+    old_chunk_compound_tag: &CompoundTag,
+    new_chunk_compound_tag: &CompoundTag,
+) -> Option<usize> {
+    let old_sections = read_sections(old_chunk_compound_tag).ok()?;
+    let new_sections = read_sections(new_chunk_compound_tag).ok()?;
+
+    let section_ys: BTreeSet<i8> = old_sections
+        .iter()
+        .chain(new_sections.iter())
+        .map(|section| section.y)
+        .collect();
+
+    let mut changed_blocks = 0;
+
+    for y in section_ys {
+        let find_section = |sections: &[ChunkSection]| {
+            sections
+                .iter()
+                .find(|section| section.y == y)
+                .cloned()
+                .unwrap_or_else(|| ChunkSection::new(y))
+        };
+
+        let old_section = find_section(&old_sections);
+        let new_section = find_section(&new_sections);
+
+        changed_blocks += old_section
+            .blocks
+            .iter()
+            .zip(new_section.blocks.iter())
+            .filter(|(old_index, new_index)| {
+                old_section.palette[**old_index as usize]
+                    != new_section.palette[**new_index as usize]
+            })
+            .count();
+    }
+
+    Some(changed_blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{read_sections, write_sections, BlockState};
+    use crate::diff::{ChunkChangeKind, ChunkDiffer, TagChange};
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diff_providers() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+
+        let old_folder = TempDir::new().unwrap();
+        let old_provider = AnvilChunkProvider::from_path(old_folder.path());
+
+        let new_folder = TempDir::new().unwrap();
+        let new_provider = AnvilChunkProvider::from_path(new_folder.path());
+
+        for &(chunk_x, chunk_z) in &[(4, 2), (5, 2), (6, 2)] {
+            let (compression_scheme, compressed_data) =
+                chunk_provider.load_chunk_bytes(chunk_x, chunk_z).unwrap();
+
+            old_provider
+                .save_chunk_bytes(chunk_x, chunk_z, compression_scheme, &compressed_data)
+                .unwrap();
+        }
+
+        for &(chunk_x, chunk_z) in &[(4, 2), (5, 2), (7, 2)] {
+            let chunk_compound_tag = chunk_provider.load_chunk(chunk_x, chunk_z).unwrap();
+            new_provider
+                .save_chunk(chunk_x, chunk_z, chunk_compound_tag)
+                .unwrap();
+        }
+
+        let mut chunk_compound_tag = new_provider.load_chunk(5, 2).unwrap();
+        let mut sections = read_sections(&chunk_compound_tag).unwrap();
+        sections[1].set_block(0, 0, 0, BlockState::new("minecraft:gold_block"));
+        sections[1].set_block(1, 0, 0, BlockState::new("minecraft:gold_block"));
+        write_sections(&mut chunk_compound_tag, &sections).unwrap();
+        new_provider.save_chunk(5, 2, chunk_compound_tag).unwrap();
+
+        let changes = ChunkDiffer::new()
+            .diff_providers(&old_provider, &new_provider)
+            .unwrap();
+
+        let kinds: Vec<_> = changes
+            .iter()
+            .map(|change| (change.chunk_x, change.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (4, ChunkChangeKind::Changed),
+                (5, ChunkChangeKind::Changed),
+                (6, ChunkChangeKind::Removed),
+                (7, ChunkChangeKind::Added),
+            ]
+        );
+
+        let mut chunk_compound_tag = new_provider.load_chunk(4, 2).unwrap();
+        let level_compound_tag: &mut CompoundTag = chunk_compound_tag.get_mut("Level").unwrap();
+        level_compound_tag.insert_i64("LastUpdate", 1);
+        new_provider.save_chunk(4, 2, chunk_compound_tag).unwrap();
+
+        let changes = ChunkDiffer::new()
+            .with_structural(true)
+            .diff_providers(&old_provider, &new_provider)
+            .unwrap();
+
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[0].tag_changes,
+            vec![TagChange::Changed {
+                path: "Level.LastUpdate".to_owned()
+            }]
+        );
+        assert_eq!(changes[0].changed_blocks, Some(0));
+        assert_eq!(changes[1].changed_blocks, Some(2));
+        assert!(changes[1]
+            .tag_changes
+            .iter()
+            .all(|tag_change| match tag_change {
+                TagChange::Changed { path } => path.starts_with("Level.Sections["),
+                _ => true,
+            }));
+    }
+}
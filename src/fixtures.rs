@@ -0,0 +1,45 @@
+//! Chunks shared by tests, identified by single `value` tag.
+use crate::{region_chunk_position, region_position, AnvilChunkProvider, AnvilRegion};
+use nbt::CompoundTag;
+
+/// Returns chunk with value.
+pub(crate) fn chunk(value: i32) -> CompoundTag {
+    let mut chunk_compound_tag = CompoundTag::new();
+    chunk_compound_tag.insert_i32("value", value);
+
+    chunk_compound_tag
+}
+
+/// Returns value of chunk.
+pub(crate) fn value(chunk_compound_tag: &CompoundTag) -> i32 {
+    chunk_compound_tag.get_i32("value").unwrap()
+}
+
+/// Loads value of chunk from provider.
+pub(crate) fn load_value(chunk_provider: &AnvilChunkProvider, chunk_x: i32, chunk_z: i32) -> i32 {
+    value(&chunk_provider.load_chunk(chunk_x, chunk_z).unwrap())
+}
+
+/// Saves chunk with value and header timestamp.
+///
+/// Header timestamps have second precision, so tests set them explicitly.
+pub(crate) fn save_chunk(
+    chunk_provider: &AnvilChunkProvider,
+    chunk_x: i32,
+    chunk_z: i32,
+    value: i32,
+    last_modified_timestamp: u32,
+) {
+    chunk_provider
+        .save_chunk(chunk_x, chunk_z, chunk(value))
+        .unwrap();
+
+    let (region_x, region_z) = region_position(chunk_x, chunk_z);
+    let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+    let region_path = chunk_provider.region_path(region_x, region_z);
+    let mut region = AnvilRegion::open(region_path).unwrap();
+    region
+        .set_last_modified_timestamp(region_chunk_x, region_chunk_z, last_modified_timestamp)
+        .unwrap();
+}
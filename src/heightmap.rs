@@ -0,0 +1,529 @@
+//! Heightmaps decoding and recomputation.
+//!
+//! Heightmaps are stored in `Heightmaps` as packed long arrays of
+//! 256 columns, each column holds the height above the lowest block of
+//! the chunk of the first block matching heightmap rule.
+use crate::chunk::{read_sections, BlockState, ChunkDataError, ChunkSection};
+use crate::packed::{bits_required, pack, packed_length, unpack};
+use crate::version::ChunkVersion;
+use nbt::CompoundTag;
+
+/// Amount of columns in chunk.
+const HEIGHTMAP_COLUMNS: usize = 256;
+/// Chunk height in blocks before 1.18.
+const CHUNK_HEIGHT: usize = 256;
+/// Chunk height in blocks since 1.18.
+const EXTENDED_CHUNK_HEIGHT: usize = 384;
+
+fn chunk_height(chunk_version: ChunkVersion) -> usize {
+    match chunk_version {
+        ChunkVersion::Unwrapped => EXTENDED_CHUNK_HEIGHT,
+        _ => CHUNK_HEIGHT,
+    }
+}
+
+/// Section y of the lowest block in 1.18+ chunks without `yPos`.
+const UNWRAPPED_MINIMUM_SECTION_Y: i8 = -4;
+
+/// Returns section y of the lowest block of chunk.
+///
+/// Bottom is fixed by chunk format, not by sections present in chunk, as
+/// empty sections are usually omitted.
+pub fn minimum_section_y(chunk_compound_tag: &CompoundTag) -> Result<i8, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+
+    match chunk_version {
+        ChunkVersion::Unwrapped => Ok(chunk_compound_tag
+            .get_i32("yPos")
+            .map_or(UNWRAPPED_MINIMUM_SECTION_Y, |y| y as i8)),
+        _ => Ok(0),
+    }
+}
+
+/// Heightmap types used by the game.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum HeightmapKind {
+    /// Highest block that blocks motion or contains a fluid.
+    MotionBlocking,
+    /// Same as `MotionBlocking` except leaves are ignored.
+    MotionBlockingNoLeaves,
+    /// Highest block that blocks motion.
+    OceanFloor,
+    /// Highest block that blocks motion, used only during world generation.
+    OceanFloorWg,
+    /// Highest non-air block.
+    WorldSurface,
+    /// Highest non-air block, used only during world generation.
+    WorldSurfaceWg,
+    /// Highest light blocking block, used only before 1.15.
+    LightBlocking,
+}
+
+impl HeightmapKind {
+    /// Heightmaps which are stored in fully generated chunks.
+    pub const DEFAULT: [HeightmapKind; 4] = [
+        HeightmapKind::MotionBlocking,
+        HeightmapKind::MotionBlockingNoLeaves,
+        HeightmapKind::OceanFloor,
+        HeightmapKind::WorldSurface,
+    ];
+
+    /// Returns tag name under which heightmap are stored.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HeightmapKind::MotionBlocking => "MOTION_BLOCKING",
+            HeightmapKind::MotionBlockingNoLeaves => "MOTION_BLOCKING_NO_LEAVES",
+            HeightmapKind::OceanFloor => "OCEAN_FLOOR",
+            HeightmapKind::OceanFloorWg => "OCEAN_FLOOR_WG",
+            HeightmapKind::WorldSurface => "WORLD_SURFACE",
+            HeightmapKind::WorldSurfaceWg => "WORLD_SURFACE_WG",
+            HeightmapKind::LightBlocking => "LIGHT_BLOCKING",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MOTION_BLOCKING" => Some(HeightmapKind::MotionBlocking),
+            "MOTION_BLOCKING_NO_LEAVES" => Some(HeightmapKind::MotionBlockingNoLeaves),
+            "OCEAN_FLOOR" => Some(HeightmapKind::OceanFloor),
+            "OCEAN_FLOOR_WG" => Some(HeightmapKind::OceanFloorWg),
+            "WORLD_SURFACE" => Some(HeightmapKind::WorldSurface),
+            "WORLD_SURFACE_WG" => Some(HeightmapKind::WorldSurfaceWg),
+            "LIGHT_BLOCKING" => Some(HeightmapKind::LightBlocking),
+            _ => None,
+        }
+    }
+
+    /// Returns true if block is counted by heightmap.
+    ///
+    /// The game decides by block material which is not stored in chunk,
+    /// so this relies on block names and may differ for modded blocks.
+    pub fn matches(&self, block_state: &BlockState) -> bool {
+        match self {
+            HeightmapKind::WorldSurface | HeightmapKind::WorldSurfaceWg => !block_state.is_air(),
+            HeightmapKind::OceanFloor | HeightmapKind::OceanFloorWg => blocks_motion(block_state),
+            HeightmapKind::MotionBlocking => {
+                blocks_motion(block_state) || contains_fluid(block_state)
+            }
+            HeightmapKind::MotionBlockingNoLeaves => {
+                (blocks_motion(block_state) || contains_fluid(block_state))
+                    && !block_state.name.ends_with("_leaves")
+            }
+            HeightmapKind::LightBlocking => {
+                blocks_motion(block_state)
+                    && !block_state.name.ends_with("glass")
+                    && !block_state.name.ends_with("glass_pane")
+                    && !block_state.name.ends_with("_leaves")
+                    && block_state.name != "minecraft:ice"
+            }
+        }
+    }
+}
+
+/// Blocks without collision which does not stop motion.
+const NON_BLOCKING_MOTION: &[&str] = &[
+    "minecraft:water",
+    "minecraft:lava",
+    "minecraft:bubble_column",
+    "minecraft:grass",
+    "minecraft:short_grass",
+    "minecraft:tall_grass",
+    "minecraft:fern",
+    "minecraft:large_fern",
+    "minecraft:dead_bush",
+    "minecraft:seagrass",
+    "minecraft:tall_seagrass",
+    "minecraft:kelp",
+    "minecraft:kelp_plant",
+    "minecraft:vine",
+    "minecraft:snow",
+    "minecraft:fire",
+    "minecraft:soul_fire",
+    "minecraft:cobweb",
+    "minecraft:sugar_cane",
+    "minecraft:redstone_wire",
+    "minecraft:tripwire",
+    "minecraft:tripwire_hook",
+    "minecraft:lever",
+    "minecraft:ladder",
+    "minecraft:nether_portal",
+    "minecraft:end_portal",
+    "minecraft:structure_void",
+    "minecraft:light",
+];
+
+/// Block name suffixes of blocks without collision.
+const NON_BLOCKING_MOTION_SUFFIXES: &[&str] = &[
+    "_air",
+    "torch",
+    "_sign",
+    "_banner",
+    "_sapling",
+    "_flower",
+    "_tulip",
+    "_mushroom",
+    "_rail",
+    "rail",
+    "_button",
+    "_pressure_plate",
+    "_coral",
+    "_coral_fan",
+    "_roots",
+    "_fungus",
+    "_carpet",
+];
+
+/// Flowers which names does not end with `_flower`.
+const FLOWERS: &[&str] = &[
+    "minecraft:dandelion",
+    "minecraft:poppy",
+    "minecraft:blue_orchid",
+    "minecraft:allium",
+    "minecraft:azure_bluet",
+    "minecraft:oxeye_daisy",
+    "minecraft:lily_of_the_valley",
+    "minecraft:wither_rose",
+    "minecraft:sunflower",
+    "minecraft:lilac",
+    "minecraft:rose_bush",
+    "minecraft:peony",
+    "minecraft:wheat",
+    "minecraft:carrots",
+    "minecraft:potatoes",
+    "minecraft:beetroots",
+];
+
+pub(crate) fn blocks_motion(block_state: &BlockState) -> bool {
+    let name = block_state.name.as_str();
+
+    if block_state.is_air() || NON_BLOCKING_MOTION.contains(&name) || FLOWERS.contains(&name) {
+        return false;
+    }
+
+    !NON_BLOCKING_MOTION_SUFFIXES
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+fn contains_fluid(block_state: &BlockState) -> bool {
+    match block_state.name.as_str() {
+        "minecraft:water"
+        | "minecraft:lava"
+        | "minecraft:bubble_column"
+        | "minecraft:seagrass"
+        | "minecraft:tall_seagrass"
+        | "minecraft:kelp"
+        | "minecraft:kelp_plant" => true,
+        _ => block_state.property("waterlogged") == Some("true"),
+    }
+}
+
+/// Decoded heightmap of chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heightmap {
+    /// Heights indexed by `x + z * 16`.
+    heights: Vec<u16>,
+}
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Heightmap {
+            heights: vec![0; HEIGHTMAP_COLUMNS],
+        }
+    }
+}
+
+impl Heightmap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn column_index(x: u8, z: u8) -> usize {
+        assert!(16 > x, "Heightmap x coordinate out of bounds");
+        assert!(16 > z, "Heightmap z coordinate out of bounds");
+
+        x as usize + z as usize * 16
+    }
+
+    /// Returns column height above the lowest block of the chunk.
+    ///
+    /// Zero means that column contains no matching blocks.
+    pub fn get(&self, x: u8, z: u8) -> u16 {
+        self.heights[Self::column_index(x, z)]
+    }
+
+    pub fn set(&mut self, x: u8, z: u8, height: u16) {
+        self.heights[Self::column_index(x, z)] = height;
+    }
+
+    /// Decodes heightmap from packed long array.
+    pub fn from_packed(
+        longs: &[i64],
+        chunk_height: usize,
+        spanning: bool,
+    ) -> Result<Self, ChunkDataError> {
+        let bits_per_value = bits_required(chunk_height);
+        let expected_length = packed_length(bits_per_value, HEIGHTMAP_COLUMNS, spanning);
+
+        if longs.len() != expected_length {
+            return Err(ChunkDataError::InvalidLength {
+                name: "Heightmaps".to_owned(),
+                length: longs.len(),
+                expected_length,
+            });
+        }
+
+        let heights = unpack(longs, bits_per_value, HEIGHTMAP_COLUMNS, spanning);
+
+        Ok(Heightmap { heights })
+    }
+
+    /// Encodes heightmap to packed long array.
+    pub fn to_packed(&self, chunk_height: usize, spanning: bool) -> Vec<i64> {
+        pack(&self.heights, bits_required(chunk_height), spanning)
+    }
+
+    /// Computes heightmap from chunk sections.
+    ///
+    /// Heights are counted from section at specified y, see `minimum_section_y`.
+    pub fn compute(kind: HeightmapKind, sections: &[ChunkSection], minimum_section_y: i8) -> Self {
+        let mut heightmap = Heightmap::new();
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let height = Self::column_height(kind, sections, minimum_section_y, x, z);
+                heightmap.set(x, z, height);
+            }
+        }
+
+        heightmap
+    }
+
+    fn column_height(
+        kind: HeightmapKind,
+        sections: &[ChunkSection],
+        minimum_section_y: i8,
+        x: u8,
+        z: u8,
+    ) -> u16 {
+        let sections = sections
+            .iter()
+            .rev()
+            .filter(|section| section.y >= minimum_section_y);
+
+        for section in sections {
+            for y in (0..16).rev() {
+                if kind.matches(section.block(x, y, z)) {
+                    let section_offset = (section.y as i32 - minimum_section_y as i32) * 16;
+
+                    return (section_offset + y as i32 + 1) as u16;
+                }
+            }
+        }
+
+        0
+    }
+}
+
+/// Reads heightmap of specified kind from chunk.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::heightmap::{read_heightmap, HeightmapKind};
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// let heightmap = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+///
+/// assert!(heightmap.get(0, 0) > 0);
+/// ```
+pub fn read_heightmap(
+    chunk_compound_tag: &CompoundTag,
+    kind: HeightmapKind,
+) -> Result<Heightmap, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level(chunk_compound_tag)?;
+    let heightmaps_compound_tag = level_compound_tag.get_compound_tag("Heightmaps")?;
+    let longs = heightmaps_compound_tag.get_i64_vec(kind.name())?;
+
+    Heightmap::from_packed(
+        longs,
+        chunk_height(chunk_version),
+        chunk_version.is_spanning(),
+    )
+}
+
+/// Reads all known heightmaps stored in chunk.
+pub fn read_heightmaps(
+    chunk_compound_tag: &CompoundTag,
+) -> Result<Vec<(HeightmapKind, Heightmap)>, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level(chunk_compound_tag)?;
+    let mut heightmaps = Vec::new();
+
+    if !level_compound_tag.contains_key("Heightmaps") {
+        return Ok(heightmaps);
+    }
+
+    let heightmaps_compound_tag = level_compound_tag.get_compound_tag("Heightmaps")?;
+
+    for (name, _) in heightmaps_compound_tag.iter() {
+        if let Some(kind) = HeightmapKind::from_name(name) {
+            heightmaps.push((kind, read_heightmap(chunk_compound_tag, kind)?));
+        }
+    }
+
+    Ok(heightmaps)
+}
+
+/// Recomputes heightmaps from chunk block data.
+///
+/// Heightmaps already stored in chunk are recomputed, if chunk has no
+/// heightmaps the default ones are added. Should be called after editing
+/// blocks and before saving chunk.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::heightmap::recompute_heightmaps;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let mut chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// // Edit blocks...
+///
+/// recompute_heightmaps(&mut chunk_compound_tag).unwrap();
+/// ```
+pub fn recompute_heightmaps(chunk_compound_tag: &mut CompoundTag) -> Result<(), ChunkDataError> {
+    let sections = read_sections(chunk_compound_tag)?;
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let chunk_height = chunk_height(chunk_version);
+    let minimum_section_y = minimum_section_y(chunk_compound_tag)?;
+
+    let mut kinds: Vec<HeightmapKind> = read_heightmap_kinds(chunk_compound_tag)?;
+
+    if kinds.is_empty() {
+        kinds.extend_from_slice(&HeightmapKind::DEFAULT);
+    }
+
+    let mut heightmaps_compound_tag = CompoundTag::new();
+
+    for kind in kinds {
+        let heightmap = Heightmap::compute(kind, &sections, minimum_section_y);
+        heightmaps_compound_tag.insert_i64_vec(
+            kind.name(),
+            heightmap.to_packed(chunk_height, chunk_version.is_spanning()),
+        );
+    }
+
+    let level_compound_tag = chunk_version.level_mut(chunk_compound_tag)?;
+    level_compound_tag.insert_compound_tag("Heightmaps", heightmaps_compound_tag);
+
+    Ok(())
+}
+
+fn read_heightmap_kinds(
+    chunk_compound_tag: &CompoundTag,
+) -> Result<Vec<HeightmapKind>, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level(chunk_compound_tag)?;
+
+    match level_compound_tag.get_compound_tag("Heightmaps") {
+        Ok(heightmaps_compound_tag) => Ok(heightmaps_compound_tag
+            .iter()
+            .filter_map(|(name, _)| HeightmapKind::from_name(name))
+            .collect()),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{read_sections, write_sections, BlockState, ChunkSection};
+    use crate::heightmap::{
+        minimum_section_y, read_heightmap, recompute_heightmaps, Heightmap, HeightmapKind,
+    };
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+
+    #[test]
+    fn test_read_heightmap() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+
+        let heightmap = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+
+        // World surface is the highest non-air block.
+        for x in 0..16 {
+            for z in 0..16 {
+                let height = heightmap.get(x, z) as usize;
+                let section = &sections[(height - 1) / 16];
+
+                assert!(!section.block(x, ((height - 1) % 16) as u8, z).is_air());
+            }
+        }
+    }
+
+    #[test]
+    fn test_compute_heightmap_matches_stored() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+
+        let stored = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+        let computed = Heightmap::compute(HeightmapKind::WorldSurface, &sections, 0);
+
+        assert_eq!(stored, computed);
+    }
+
+    #[test]
+    fn test_recompute_heightmaps_after_edit() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let mut chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+
+        let mut sections = read_sections(&chunk_compound_tag).unwrap();
+        let mut top_section = ChunkSection::new(sections.last().unwrap().y + 1);
+        top_section.set_block(5, 3, 9, BlockState::new("minecraft:stone"));
+        sections.push(top_section.clone());
+
+        write_sections(&mut chunk_compound_tag, &sections).unwrap();
+        recompute_heightmaps(&mut chunk_compound_tag).unwrap();
+
+        let heightmap = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+        let expected_height = top_section.y as u16 * 16 + 3 + 1;
+
+        assert_eq!(heightmap.get(5, 9), expected_height);
+    }
+
+    #[test]
+    fn test_compute_heightmap_without_lower_sections() {
+        let mut section = ChunkSection::new(4);
+        section.set_block(2, 0, 3, BlockState::new("minecraft:stone"));
+        let sections = vec![section];
+
+        let heightmap = Heightmap::compute(HeightmapKind::WorldSurface, &sections, 0);
+        assert_eq!(heightmap.get(2, 3), 4 * 16 + 1);
+
+        let heightmap = Heightmap::compute(HeightmapKind::WorldSurface, &sections, -4);
+        assert_eq!(heightmap.get(2, 3), 8 * 16 + 1);
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", 3465);
+        assert_eq!(minimum_section_y(&chunk_compound_tag).unwrap(), -4);
+
+        chunk_compound_tag.insert_i32("yPos", -8);
+        assert_eq!(minimum_section_y(&chunk_compound_tag).unwrap(), -8);
+    }
+
+    #[test]
+    fn test_heightmap_kind_names() {
+        for kind in HeightmapKind::DEFAULT.iter() {
+            assert_eq!(HeightmapKind::from_name(kind.name()), Some(*kind));
+        }
+    }
+}
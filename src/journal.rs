@@ -0,0 +1,403 @@
+//! Write-ahead journal of region changes.
+//!
+//! Every change is appended to `r.X.Z.mca.journal` and synced before region
+//! file is touched. On open region replays complete records, so changes
+//! interrupted by crash are finished, while torn last record is discarded
+//! as its change never started. Checkpoint syncs region file and empties
+//! journal.
+//!
+//! Record layout: kind byte, chunk x and z bytes, timestamp, payload length,
+//! payload and first 4 bytes of SHA-256 of all previous fields.
+use byteorder::{BigEndian, ReadBytesExt};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Journal file extension appended to region file name.
+const JOURNAL_EXTENSION: &str = "journal";
+/// Record kind of chunk write.
+const WRITE_RECORD_KIND: u8 = 1;
+/// Record kind of chunk deletion.
+const DELETE_RECORD_KIND: u8 = 2;
+/// Record kind of timestamp change.
+const TIMESTAMP_RECORD_KIND: u8 = 3;
+
+/// Change of region recorded in journal.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum JournalRecord {
+    Write {
+        chunk_x: u8,
+        chunk_z: u8,
+        last_modified_timestamp: u32,
+        compression_scheme: u8,
+        compressed_data: Vec<u8>,
+    },
+    Delete {
+        chunk_x: u8,
+        chunk_z: u8,
+    },
+    SetTimestamp {
+        chunk_x: u8,
+        chunk_z: u8,
+        last_modified_timestamp: u32,
+    },
+}
+
+impl JournalRecord {
+    fn encode(&self) -> Vec<u8> {
+        let (kind, chunk_x, chunk_z, timestamp, payload) = match self {
+            JournalRecord::Write {
+                chunk_x,
+                chunk_z,
+                last_modified_timestamp,
+                compression_scheme,
+                compressed_data,
+            } => {
+                let mut payload = Vec::with_capacity(compressed_data.len() + 1);
+                payload.push(*compression_scheme);
+                payload.extend_from_slice(compressed_data);
+
+                (
+                    WRITE_RECORD_KIND,
+                    *chunk_x,
+                    *chunk_z,
+                    *last_modified_timestamp,
+                    payload,
+                )
+            }
+            JournalRecord::Delete { chunk_x, chunk_z } => {
+                (DELETE_RECORD_KIND, *chunk_x, *chunk_z, 0, Vec::new())
+            }
+            JournalRecord::SetTimestamp {
+                chunk_x,
+                chunk_z,
+                last_modified_timestamp,
+            } => (
+                TIMESTAMP_RECORD_KIND,
+                *chunk_x,
+                *chunk_z,
+                *last_modified_timestamp,
+                Vec::new(),
+            ),
+        };
+
+        let mut buffer = Vec::with_capacity(payload.len() + 15);
+        buffer.push(kind);
+        buffer.push(chunk_x);
+        buffer.push(chunk_z);
+        buffer.extend_from_slice(&timestamp.to_be_bytes());
+        buffer.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(&payload);
+
+        let checksum = record_checksum(&buffer);
+        buffer.extend_from_slice(&checksum.to_be_bytes());
+
+        buffer
+    }
+
+    /// Decodes record, returns none if record is torn or unknown.
+    fn decode(cursor: &mut Cursor<&[u8]>) -> Option<Self> {
+        let start = cursor.position() as usize;
+
+        let kind = cursor.read_u8().ok()?;
+        let chunk_x = cursor.read_u8().ok()?;
+        let chunk_z = cursor.read_u8().ok()?;
+        let timestamp = cursor.read_u32::<BigEndian>().ok()?;
+        let length = cursor.read_u32::<BigEndian>().ok()? as usize;
+
+        let remaining = cursor.get_ref().len() - cursor.position() as usize;
+
+        if length > remaining {
+            return None;
+        }
+
+        let mut payload = vec![0; length];
+        cursor.read_exact(&mut payload).ok()?;
+
+        let end = cursor.position() as usize;
+        let checksum = cursor.read_u32::<BigEndian>().ok()?;
+
+        if checksum != record_checksum(&cursor.get_ref()[start..end])
+            || chunk_x > 31
+            || chunk_z > 31
+        {
+            return None;
+        }
+
+        match kind {
+            WRITE_RECORD_KIND if !payload.is_empty() => {
+                let compressed_data = payload.split_off(1);
+
+                Some(JournalRecord::Write {
+                    chunk_x,
+                    chunk_z,
+                    last_modified_timestamp: timestamp,
+                    compression_scheme: payload[0],
+                    compressed_data,
+                })
+            }
+            DELETE_RECORD_KIND => Some(JournalRecord::Delete { chunk_x, chunk_z }),
+            TIMESTAMP_RECORD_KIND => Some(JournalRecord::SetTimestamp {
+                chunk_x,
+                chunk_z,
+                last_modified_timestamp: timestamp,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn record_checksum(data: &[u8]) -> u32 {
+    let hash = Sha256::digest(data);
+
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// Open journal of region.
+pub(crate) struct Journal {
+    file: File,
+    /// Amount of records since last checkpoint.
+    records: u32,
+    /// Amount of records after which checkpoint is made.
+    checkpoint_records: u32,
+}
+
+impl Journal {
+    /// Returns journal path of region file.
+    pub(crate) fn path(region_path: &Path) -> PathBuf {
+        let mut file_name = region_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(JOURNAL_EXTENSION);
+
+        region_path.with_file_name(file_name)
+    }
+
+    /// Opens empty journal, existing one must be replayed before.
+    pub(crate) fn create(journal_path: &Path, checkpoint_records: u32) -> Result<Self, io::Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(journal_path)?;
+
+        Ok(Journal {
+            file,
+            records: 0,
+            checkpoint_records: checkpoint_records.max(1),
+        })
+    }
+
+    /// Reads complete records, stopping at first torn one.
+    pub(crate) fn read_records(journal_path: &Path) -> Result<Vec<JournalRecord>, io::Error> {
+        let data = match fs::read(journal_path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+
+        let mut cursor = Cursor::new(data.as_slice());
+        let mut records = Vec::new();
+
+        while (cursor.position() as usize) < data.len() {
+            match JournalRecord::decode(&mut cursor) {
+                Some(record) => records.push(record),
+                None => break,
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Appends record and syncs it to disk.
+    pub(crate) fn append(&mut self, record: &JournalRecord) -> Result<(), io::Error> {
+        self.file.write_all(&record.encode())?;
+        self.file.sync_data()?;
+        self.records += 1;
+
+        Ok(())
+    }
+
+    /// Returns whether enough records were appended to make checkpoint.
+    pub(crate) fn checkpoint_due(&self) -> bool {
+        self.records >= self.checkpoint_records
+    }
+
+    /// Empties journal, region file must be synced before.
+    pub(crate) fn clear(&mut self) -> Result<(), io::Error> {
+        if self.records == 0 {
+            return Ok(());
+        }
+
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_data()?;
+        self.records = 0;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{chunk, value};
+    use crate::journal::{Journal, JournalRecord};
+    use crate::{AnvilChunkProvider, AnvilRegion};
+    use std::fs;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_records_round_trip() {
+        let folder = TempDir::new().unwrap();
+        let journal_path = Journal::path(&folder.path().join("r.0.0.mca"));
+        assert!(journal_path.ends_with("r.0.0.mca.journal"));
+
+        let records = vec![
+            JournalRecord::Write {
+                chunk_x: 1,
+                chunk_z: 2,
+                last_modified_timestamp: 3,
+                compression_scheme: 2,
+                compressed_data: vec![4, 5, 6],
+            },
+            JournalRecord::Delete {
+                chunk_x: 31,
+                chunk_z: 0,
+            },
+            JournalRecord::SetTimestamp {
+                chunk_x: 0,
+                chunk_z: 31,
+                last_modified_timestamp: 7,
+            },
+        ];
+
+        let mut journal = Journal::create(&journal_path, 10).unwrap();
+
+        for record in &records {
+            journal.append(record).unwrap();
+        }
+
+        assert_eq!(Journal::read_records(&journal_path).unwrap(), records);
+
+        // Torn last record is discarded.
+        let length = fs::metadata(&journal_path).unwrap().len();
+        journal.file.set_len(length - 1).unwrap();
+
+        assert_eq!(Journal::read_records(&journal_path).unwrap(), records[..2]);
+
+        journal.clear().unwrap();
+        assert!(Journal::read_records(&journal_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_crash_before_region_write() {
+        let folder = TempDir::new().unwrap();
+        let region_path = folder.path().join("r.0.0.mca");
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+        region.write_chunk(0, 0, chunk(1)).unwrap();
+        drop(region);
+
+        let region_data = fs::read(&region_path).unwrap();
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+        region.enable_journal(100).unwrap();
+        region.write_chunk(0, 0, chunk(2)).unwrap();
+        region.write_chunk(1, 0, chunk(3)).unwrap();
+        region.delete_chunk(0, 0).unwrap();
+        region.set_last_modified_timestamp(1, 0, 42).unwrap();
+        // Crash skips checkpoint on drop.
+        std::mem::forget(region);
+
+        // Region file never received journaled changes.
+        fs::write(&region_path, region_data).unwrap();
+
+        // Reading leaves journal for next write open.
+        let mut region = AnvilRegion::open_read_only(&region_path).unwrap();
+        assert_eq!(value(&region.read_chunk(0, 0).unwrap()), 1);
+        assert!(Journal::path(&region_path).exists());
+        drop(region);
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+
+        assert!(region.chunk_metadata(0, 0).is_empty());
+        assert_eq!(value(&region.read_chunk(1, 0).unwrap()), 3);
+        assert_eq!(region.chunk_metadata(1, 0).last_modified_timestamp, 42);
+        assert!(!Journal::path(&region_path).exists());
+    }
+
+    #[test]
+    fn test_crash_during_region_write() {
+        let folder = TempDir::new().unwrap();
+        let region_path = folder.path().join("r.0.0.mca");
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+        region.enable_journal(100).unwrap();
+        region.write_chunk(0, 0, chunk(1)).unwrap();
+        region.write_chunk(1, 0, chunk(2)).unwrap();
+        let metadata = region.chunk_metadata(1, 0);
+        std::mem::forget(region);
+
+        // Chunk sectors are torn and its header entry is half written.
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .open(&region_path)
+            .unwrap();
+        file.seek(SeekFrom::Start(metadata.sector_index as u64 * 4096))
+            .unwrap();
+        file.write_all(&[0xFF; 64]).unwrap();
+        file.seek(SeekFrom::Start(4096 + 4)).unwrap();
+        file.write_all(&[0; 4]).unwrap();
+        drop(file);
+
+        // Last record was torn while being appended, so its change never started.
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(Journal::path(&region_path))
+            .unwrap();
+        file.write_all(&[1, 2, 0, 0, 0]).unwrap();
+        drop(file);
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+
+        assert_eq!(value(&region.read_chunk(0, 0).unwrap()), 1);
+        assert_eq!(value(&region.read_chunk(1, 0).unwrap()), 2);
+        assert!(region.chunk_metadata(1, 0).last_modified_timestamp > 0);
+        assert!(region.chunk_metadata(2, 0).is_empty());
+        assert!(region.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let folder = TempDir::new().unwrap();
+        let region_path = folder.path().join("r.0.0.mca");
+        let journal_path = Journal::path(&region_path);
+
+        let mut region = AnvilRegion::new(&region_path).unwrap();
+        region.enable_journal(2).unwrap();
+        region.write_chunk(0, 0, chunk(1)).unwrap();
+        assert_eq!(Journal::read_records(&journal_path).unwrap().len(), 1);
+
+        region.write_chunk(1, 0, chunk(1)).unwrap();
+        assert_eq!(Journal::read_records(&journal_path).unwrap().len(), 0);
+
+        region.write_chunk(2, 0, chunk(1)).unwrap();
+        drop(region);
+
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn test_provider_journal() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path()).with_journal(true);
+
+        chunk_provider.save_chunk(40, 0, chunk(1)).unwrap();
+        assert!(chunk_provider.delete_chunk(40, 0).unwrap());
+
+        let region_path = chunk_provider.region_path(1, 0);
+        assert!(region_path.exists());
+        assert!(!Journal::path(&region_path).exists());
+    }
+}
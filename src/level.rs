@@ -0,0 +1,361 @@
+//! World `level.dat` reading and writing.
+//!
+//! `level.dat` is gzip compressed NBT with world properties in `Data`
+//! compound tag. The game keeps previous version of file in `level.dat_old`
+//! and falls back to it when `level.dat` cannot be read.
+use nbt::decode::{read_gzip_compound_tag, TagDecodeError};
+use nbt::encode::write_gzip_compound_tag;
+use nbt::{CompoundTag, CompoundTagError, Tag};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Name of world properties file.
+pub const LEVEL_DAT: &str = "level.dat";
+/// Name of previous world properties file.
+pub const LEVEL_DAT_OLD: &str = "level.dat_old";
+
+/// Possible errors while loading the level data.
+#[derive(Debug)]
+pub enum LevelLoadError {
+    /// I/O Error which happened while were reading level data file.
+    ReadError { io_error: io::Error },
+    /// Error while decoding binary data to NBT tag.
+    TagDecodeError { tag_decode_error: TagDecodeError },
+    /// Required tag are missing.
+    MissingTag { name: String },
+    /// Tag has unexpected type.
+    WrongTagType { name: String },
+}
+
+impl From<io::Error> for LevelLoadError {
+    fn from(io_error: io::Error) -> Self {
+        LevelLoadError::ReadError { io_error }
+    }
+}
+
+impl From<TagDecodeError> for LevelLoadError {
+    fn from(tag_decode_error: TagDecodeError) -> Self {
+        LevelLoadError::TagDecodeError { tag_decode_error }
+    }
+}
+
+impl<'a> From<CompoundTagError<'a>> for LevelLoadError {
+    fn from(compound_tag_error: CompoundTagError<'a>) -> Self {
+        match compound_tag_error {
+            CompoundTagError::TagNotFound { name } => LevelLoadError::MissingTag {
+                name: name.to_owned(),
+            },
+            CompoundTagError::TagWrongType { name, .. } => LevelLoadError::WrongTagType {
+                name: name.to_owned(),
+            },
+        }
+    }
+}
+
+/// Possible errors while saving the level data.
+#[derive(Debug)]
+pub enum LevelSaveError {
+    /// I/O Error which happened while were writing level data file.
+    WriteError { io_error: io::Error },
+}
+
+impl From<io::Error> for LevelSaveError {
+    fn from(io_error: io::Error) -> Self {
+        LevelSaveError::WriteError { io_error }
+    }
+}
+
+/// World properties stored in `Data` compound tag of `level.dat`.
+///
+/// Tags without typed field are kept as is and written back.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::level::LevelData;
+/// use tempfile::TempDir;
+///
+/// let folder = TempDir::new().unwrap();
+/// let path = folder.path().join("level.dat");
+///
+/// let mut level_data = LevelData::new("Test world");
+/// level_data.spawn = (10, 64, -5);
+/// level_data.write(&path).unwrap();
+///
+/// let level_data = LevelData::read(&path).unwrap();
+/// assert_eq!(level_data.level_name, "Test world");
+/// assert_eq!(level_data.spawn, (10, 64, -5));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LevelData {
+    /// World name shown in world list.
+    pub level_name: String,
+    /// Data version of game which saved world.
+    pub data_version: Option<i32>,
+    /// Name of game version which saved world, since 1.9.
+    pub version_name: Option<String>,
+    /// World spawn block coordinates.
+    pub spawn: (i32, i32, i32),
+    /// World generation seed.
+    pub seed: i64,
+    /// Default game mode: 0 survival, 1 creative, 2 adventure, 3 spectator.
+    pub game_type: i32,
+    /// True if world are in hardcore mode.
+    pub hardcore: bool,
+    /// Ticks passed since world creation.
+    pub time: i64,
+    /// Time of day in ticks.
+    pub day_time: i64,
+    /// Last time in milliseconds when world was played.
+    pub last_played: i64,
+    /// Game rules, values are stored as strings.
+    pub game_rules: BTreeMap<String, String>,
+    /// Original `Data` compound tag.
+    data_compound_tag: CompoundTag,
+}
+
+impl LevelData {
+    /// Creates level data with minimal set of tags.
+    pub fn new(level_name: &str) -> Self {
+        LevelData {
+            level_name: level_name.to_owned(),
+            data_version: None,
+            version_name: None,
+            spawn: (0, 64, 0),
+            seed: 0,
+            game_type: 0,
+            hardcore: false,
+            time: 0,
+            day_time: 0,
+            last_played: 0,
+            game_rules: BTreeMap::new(),
+            data_compound_tag: CompoundTag::new(),
+        }
+    }
+
+    /// Reads level data from root compound tag of `level.dat`.
+    pub fn from_tag(root_compound_tag: &CompoundTag) -> Result<Self, LevelLoadError> {
+        let data_compound_tag = root_compound_tag.get_compound_tag("Data")?;
+
+        // Since 1.16 seed moved to world generation settings.
+        let seed = match data_compound_tag.get_compound_tag("WorldGenSettings") {
+            Ok(world_gen_settings_compound_tag) => {
+                world_gen_settings_compound_tag.get_i64("seed")?
+            }
+            Err(_) => data_compound_tag.get_i64("RandomSeed").unwrap_or(0),
+        };
+
+        let version_name = data_compound_tag
+            .get_compound_tag("Version")
+            .and_then(|version_compound_tag| version_compound_tag.get_str("Name"))
+            .map(|name| name.to_owned())
+            .ok();
+
+        let mut game_rules = BTreeMap::new();
+
+        if let Ok(game_rules_compound_tag) = data_compound_tag.get_compound_tag("GameRules") {
+            for (name, tag) in game_rules_compound_tag.iter() {
+                if let Tag::String(value) = tag {
+                    game_rules.insert(name.to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        Ok(LevelData {
+            level_name: data_compound_tag.get_str("LevelName")?.to_owned(),
+            data_version: data_compound_tag.get_i32("DataVersion").ok(),
+            version_name,
+            spawn: (
+                data_compound_tag.get_i32("SpawnX")?,
+                data_compound_tag.get_i32("SpawnY")?,
+                data_compound_tag.get_i32("SpawnZ")?,
+            ),
+            seed,
+            game_type: data_compound_tag.get_i32("GameType").unwrap_or(0),
+            hardcore: data_compound_tag.get_bool("hardcore").unwrap_or(false),
+            time: data_compound_tag.get_i64("Time").unwrap_or(0),
+            day_time: data_compound_tag.get_i64("DayTime").unwrap_or(0),
+            last_played: data_compound_tag.get_i64("LastPlayed").unwrap_or(0),
+            game_rules,
+            data_compound_tag: data_compound_tag.clone(),
+        })
+    }
+
+    /// Writes level data to root compound tag of `level.dat`.
+    pub fn to_tag(&self) -> CompoundTag {
+        let mut data_compound_tag = self.data_compound_tag.clone();
+
+        data_compound_tag.insert_str("LevelName", &self.level_name);
+
+        if let Some(data_version) = self.data_version {
+            data_compound_tag.insert_i32("DataVersion", data_version);
+        }
+
+        if let Some(version_name) = &self.version_name {
+            let mut version_compound_tag = data_compound_tag
+                .get_compound_tag("Version")
+                .cloned()
+                .unwrap_or_else(|_| CompoundTag::new());
+
+            version_compound_tag.insert_str("Name", version_name);
+            data_compound_tag.insert_compound_tag("Version", version_compound_tag);
+        }
+
+        let (spawn_x, spawn_y, spawn_z) = self.spawn;
+        data_compound_tag.insert_i32("SpawnX", spawn_x);
+        data_compound_tag.insert_i32("SpawnY", spawn_y);
+        data_compound_tag.insert_i32("SpawnZ", spawn_z);
+
+        match data_compound_tag.get_compound_tag("WorldGenSettings") {
+            Ok(world_gen_settings_compound_tag) => {
+                let mut world_gen_settings_compound_tag = world_gen_settings_compound_tag.clone();
+                world_gen_settings_compound_tag.insert_i64("seed", self.seed);
+
+                data_compound_tag
+                    .insert_compound_tag("WorldGenSettings", world_gen_settings_compound_tag);
+            }
+            Err(_) => data_compound_tag.insert_i64("RandomSeed", self.seed),
+        }
+
+        data_compound_tag.insert_i32("GameType", self.game_type);
+        data_compound_tag.insert_bool("hardcore", self.hardcore);
+        data_compound_tag.insert_i64("Time", self.time);
+        data_compound_tag.insert_i64("DayTime", self.day_time);
+        data_compound_tag.insert_i64("LastPlayed", self.last_played);
+
+        let game_rules_compound_tag = self
+            .game_rules
+            .iter()
+            .map(|(name, value)| (name.to_owned(), Tag::String(value.to_owned())))
+            .collect();
+
+        data_compound_tag.insert_compound_tag("GameRules", game_rules_compound_tag);
+
+        let mut root_compound_tag = CompoundTag::named("");
+        root_compound_tag.insert_compound_tag("Data", data_compound_tag);
+
+        root_compound_tag
+    }
+
+    /// Reads level data from file.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, LevelLoadError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let root_compound_tag = read_gzip_compound_tag(&mut reader)?;
+
+        Self::from_tag(&root_compound_tag)
+    }
+
+    /// Writes level data to file.
+    ///
+    /// Data are written to temporary file first. Previous file are kept
+    /// with `_old` suffix as the game does.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), LevelSaveError> {
+        let path = path.as_ref();
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        let new_path = path.with_file_name(format!("{}_new", file_name));
+        let old_path = path.with_file_name(format!("{}_old", file_name));
+
+        let mut writer = BufWriter::new(File::create(&new_path)?);
+        write_gzip_compound_tag(&mut writer, &self.to_tag())?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+
+        if path.exists() {
+            if old_path.exists() {
+                fs::remove_file(&old_path)?;
+            }
+
+            fs::rename(path, &old_path)?;
+        }
+
+        fs::rename(&new_path, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level::{LevelData, LevelLoadError, LEVEL_DAT_OLD};
+    use nbt::CompoundTag;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_from_tag_world_gen_settings() {
+        let mut world_gen_settings_compound_tag = CompoundTag::new();
+        world_gen_settings_compound_tag.insert_i64("seed", 42);
+        world_gen_settings_compound_tag.insert_bool("generate_features", true);
+
+        let mut data_compound_tag = CompoundTag::new();
+        data_compound_tag.insert_str("LevelName", "Seeded");
+        data_compound_tag.insert_i32("SpawnX", 1);
+        data_compound_tag.insert_i32("SpawnY", 2);
+        data_compound_tag.insert_i32("SpawnZ", 3);
+        data_compound_tag.insert_compound_tag("WorldGenSettings", world_gen_settings_compound_tag);
+        data_compound_tag.insert_str("WanderingTraderId", "unknown");
+
+        let mut root_compound_tag = CompoundTag::named("");
+        root_compound_tag.insert_compound_tag("Data", data_compound_tag);
+
+        let mut level_data = LevelData::from_tag(&root_compound_tag).unwrap();
+        assert_eq!(level_data.seed, 42);
+
+        level_data.seed = 7;
+        level_data
+            .game_rules
+            .insert("doDaylightCycle".to_owned(), "false".to_owned());
+
+        let root_compound_tag = level_data.to_tag();
+        let data_compound_tag = root_compound_tag.get_compound_tag("Data").unwrap();
+        let world_gen_settings_compound_tag = data_compound_tag
+            .get_compound_tag("WorldGenSettings")
+            .unwrap();
+
+        assert_eq!(world_gen_settings_compound_tag.get_i64("seed").unwrap(), 7);
+        assert!(world_gen_settings_compound_tag
+            .get_bool("generate_features")
+            .unwrap());
+        assert!(!data_compound_tag.contains_key("RandomSeed"));
+        assert_eq!(
+            data_compound_tag.get_str("WanderingTraderId").unwrap(),
+            "unknown"
+        );
+
+        let game_rules_compound_tag = data_compound_tag.get_compound_tag("GameRules").unwrap();
+        assert_eq!(
+            game_rules_compound_tag.get_str("doDaylightCycle").unwrap(),
+            "false"
+        );
+    }
+
+    #[test]
+    fn test_from_tag_missing_data() {
+        match LevelData::from_tag(&CompoundTag::new()) {
+            Err(LevelLoadError::MissingTag { name }) => assert_eq!(name, "Data"),
+            result => panic!("Expected `MissingTag` but got `{:?}`", result),
+        }
+    }
+
+    #[test]
+    fn test_write_rotates_old() {
+        let folder = TempDir::new().unwrap();
+        let path = folder.path().join("level.dat");
+
+        LevelData::new("First").write(&path).unwrap();
+        assert!(!folder.path().join(LEVEL_DAT_OLD).exists());
+
+        LevelData::new("Second").write(&path).unwrap();
+
+        let level_data = LevelData::read(&path).unwrap();
+        assert_eq!(level_data.level_name, "Second");
+
+        let old_level_data = LevelData::read(folder.path().join(LEVEL_DAT_OLD)).unwrap();
+        assert_eq!(old_level_data.level_name, "First");
+
+        assert!(!folder.path().join("level.dat_new").exists());
+    }
+}
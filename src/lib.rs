@@ -37,17 +37,54 @@
 //!
 //! chunk_provider.save_chunk(31, 16, chunk_compound_tag);
 //! ```
+pub mod backup;
+pub mod builder;
+pub mod cache;
+pub mod chunk;
+pub mod diff;
+#[cfg(test)]
+mod fixtures;
+pub mod heightmap;
+mod journal;
+pub mod level;
+pub mod light;
+pub mod mcregion;
+pub mod merge;
+pub mod metrics;
+pub mod nbt_serde;
+mod packed;
+pub mod prune;
+pub mod scan;
+pub mod snapshot;
+pub mod stats;
+pub mod storage;
+pub mod text;
+pub mod transfer;
+pub mod trim;
+pub mod upgrade;
+pub mod version;
+pub mod world;
+
+use crate::journal::{Journal, JournalRecord};
+use crate::metrics::{ChunkIoMetrics, ChunkMetrics, CountingReader, CountingWriter};
+use crate::nbt_serde::{from_compound_tag, to_compound_tag, SerdeError};
 use bitvec::prelude::*;
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use nbt::decode::TagDecodeError;
-use nbt::decode::{read_gzip_compound_tag, read_zlib_compound_tag};
-use nbt::encode::write_zlib_compound_tag;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use nbt::decode::{read_compound_tag, TagDecodeError};
+use nbt::encode::write_compound_tag;
 use nbt::CompoundTag;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
 use std::fs::{File, OpenOptions};
 use std::io::{Cursor, Read, Seek, SeekFrom, Write};
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, io, mem};
 
 /// Amount of chunks in region.
 const REGION_CHUNKS: usize = 1024;
@@ -57,12 +94,18 @@ const REGION_CHUNKS_METADATA_LENGTH: usize = 2 * REGION_CHUNKS;
 const REGION_HEADER_BYTES_LENGTH: u64 = 8 * REGION_CHUNKS as u64;
 /// Region sector length in bytes.
 const REGION_SECTOR_BYTES_LENGTH: u16 = 4096;
-/// Maximum chunk length in bytes.
-const CHUNK_MAXIMUM_BYTES_LENGTH: u32 = REGION_SECTOR_BYTES_LENGTH as u32 * 256;
+/// Maximum chunk length in bytes, limited by one byte sectors count in header.
+const CHUNK_MAXIMUM_BYTES_LENGTH: u32 = REGION_SECTOR_BYTES_LENGTH as u32 * 255;
 /// Gzip compression type value.
 const GZIP_COMPRESSION_TYPE: u8 = 1;
 /// Zlib compression type value.
 const ZLIB_COMPRESSION_TYPE: u8 = 2;
+/// Zlib compression level of encoded chunks, fixed to keep output reproducible.
+const ZLIB_COMPRESSION_LEVEL: u32 = 6;
+/// Anvil region file extension.
+const ANVIL_EXTENSION: &str = "mca";
+/// Legacy McRegion file extension.
+const MCREGION_EXTENSION: &str = "mcr";
 
 /// Possible errors while loading the chunk.
 #[derive(Debug)]
@@ -125,6 +168,11 @@ pub enum ChunkSaveError {
     },
     /// I/O Error which happened while were writing chunk data to region file.
     WriteError { io_error: io::Error },
+    /// Region exists only as legacy McRegion file.
+    ///
+    /// Region must be converted to Anvil first, as new Anvil file would
+    /// hide chunks of McRegion one.
+    McRegionNotConverted { region_x: i32, region_z: i32 },
 }
 
 impl From<io::Error> for ChunkSaveError {
@@ -135,18 +183,253 @@ impl From<io::Error> for ChunkSaveError {
 
 pub struct AnvilChunkProvider<'a> {
     /// Folder where region files located.
-    folder_path: &'a Path,
+    folder_path: Cow<'a, Path>,
+    /// Whether changes are written through region journal.
+    journal: bool,
+    /// Receiver of instrumentation events.
+    metrics: Option<Arc<dyn ChunkMetrics>>,
+    /// Timestamp written for saved chunks instead of current time.
+    fixed_timestamp: Option<u32>,
 }
 
 impl<'a> AnvilChunkProvider<'a> {
     pub fn new(folder: &'a str) -> Self {
-        let folder_path = Path::new(folder);
+        let folder_path = Cow::Borrowed(Path::new(folder));
+
+        AnvilChunkProvider {
+            folder_path,
+            journal: false,
+            metrics: None,
+            fixed_timestamp: None,
+        }
+    }
+
+    pub fn from_path(folder_path: &'a Path) -> Self {
+        let folder_path = Cow::Borrowed(folder_path);
+
+        AnvilChunkProvider {
+            folder_path,
+            journal: false,
+            metrics: None,
+            fixed_timestamp: None,
+        }
+    }
+
+    /// Creates provider which owns folder path.
+    pub fn from_path_buf(folder_path: PathBuf) -> AnvilChunkProvider<'static> {
+        let folder_path = Cow::Owned(folder_path);
+
+        AnvilChunkProvider {
+            folder_path,
+            journal: false,
+            metrics: None,
+            fixed_timestamp: None,
+        }
+    }
+
+    /// Writes chunk changes through write-ahead journal of region.
+    ///
+    /// Regions are opened for single change, so journal is checkpointed
+    /// right after it.
+    pub fn with_journal(mut self, journal: bool) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Reports instrumentation events of provider and its regions.
+    pub fn with_metrics(mut self, metrics: Arc<dyn ChunkMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Writes specified timestamp for saved chunks instead of current time.
+    pub fn with_fixed_timestamp(mut self, last_modified_timestamp: u32) -> Self {
+        self.fixed_timestamp = Some(last_modified_timestamp);
+        self
+    }
+
+    /// Opens existing region for reading, passing metrics to it.
+    fn open_region(&self, region_path: PathBuf) -> Result<AnvilRegion, io::Error> {
+        let region = AnvilRegion::open_read_only(&region_path)?;
 
-        AnvilChunkProvider { folder_path }
+        Ok(self.configure_region(region, &region_path))
+    }
+
+    /// Opens region for change, passing metrics and fixed timestamp to it
+    /// and enabling its journal if required.
+    fn open_region_for_write(&self, region_path: PathBuf) -> Result<AnvilRegion, io::Error> {
+        let region = AnvilRegion::new(&region_path)?;
+        let mut region = self.configure_region(region, &region_path);
+
+        if self.journal {
+            region.enable_journal(1)?;
+        }
+
+        Ok(region)
+    }
+
+    fn configure_region(&self, mut region: AnvilRegion, region_path: &Path) -> AnvilRegion {
+        if let Some(last_modified_timestamp) = self.fixed_timestamp {
+            region = region.with_fixed_timestamp(last_modified_timestamp);
+        }
+
+        if let Some(metrics) = &self.metrics {
+            let extension = region_path
+                .extension()
+                .and_then(|extension| extension.to_str());
+            metrics.region_opened(extension.unwrap_or_default());
+
+            region = region.with_metrics(metrics.clone());
+        }
+
+        region
+    }
+
+    /// Returns folder where region files located.
+    pub fn folder_path(&self) -> &Path {
+        &self.folder_path
+    }
+
+    /// Returns path of region file at specified region coordinates.
+    pub(crate) fn region_path(&self, region_x: i32, region_z: i32) -> PathBuf {
+        let region_name = format!("r.{}.{}.{}", region_x, region_z, ANVIL_EXTENSION);
+
+        self.folder_path.join(region_name)
+    }
+
+    /// Returns path of existing region file at specified region coordinates.
+    ///
+    /// Legacy McRegion file are used when there is no Anvil one.
+    pub(crate) fn existing_region_path(&self, region_x: i32, region_z: i32) -> Option<PathBuf> {
+        let region_path = self.region_path(region_x, region_z);
+
+        if region_path.exists() {
+            return Some(region_path);
+        }
+
+        let mcregion_path = self.mcregion_path(region_x, region_z);
+
+        if mcregion_path.exists() {
+            return Some(mcregion_path);
+        }
+
+        None
+    }
+
+    /// Returns path of legacy McRegion file at specified region coordinates.
+    pub(crate) fn mcregion_path(&self, region_x: i32, region_z: i32) -> PathBuf {
+        let region_name = format!("r.{}.{}.{}", region_x, region_z, MCREGION_EXTENSION);
+
+        self.folder_path.join(region_name)
+    }
+
+    /// Returns path of region file to write at specified region coordinates.
+    ///
+    /// Fails when region exists only as legacy McRegion file.
+    pub(crate) fn writable_region_path(
+        &self,
+        region_x: i32,
+        region_z: i32,
+    ) -> Result<PathBuf, ChunkSaveError> {
+        match self.existing_region_path(region_x, region_z) {
+            Some(region_path) if !is_anvil_path(&region_path) => {
+                Err(ChunkSaveError::McRegionNotConverted { region_x, region_z })
+            }
+            _ => Ok(self.region_path(region_x, region_z)),
+        }
+    }
+
+    /// Returns coordinates of all regions in folder.
+    ///
+    /// Both `r.X.Z.mca` and legacy `r.X.Z.mcr` files are listed, other
+    /// files are ignored.
+    pub fn region_positions(&self) -> Result<Vec<(i32, i32)>, io::Error> {
+        let mut region_positions = Vec::new();
+
+        if !self.folder_path.exists() {
+            return Ok(region_positions);
+        }
+
+        for entry in fs::read_dir(&self.folder_path)? {
+            let file_name = entry?.file_name();
+            let file_name = match file_name.to_str() {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+
+            let region_position = parse_region_name(file_name, ANVIL_EXTENSION)
+                .or_else(|| parse_region_name(file_name, MCREGION_EXTENSION));
+
+            if let Some(region_position) = region_position {
+                region_positions.push(region_position);
+            }
+        }
+
+        region_positions.sort_unstable();
+        region_positions.dedup();
+
+        Ok(region_positions)
+    }
+
+    /// Returns coordinates of chunks present in region.
+    pub fn region_chunk_positions(
+        &self,
+        region_x: i32,
+        region_z: i32,
+    ) -> Result<Vec<(i32, i32)>, ChunkLoadError> {
+        let region_path = match self.existing_region_path(region_x, region_z) {
+            Some(region_path) => region_path,
+            None => return Err(ChunkLoadError::RegionNotFound { region_x, region_z }),
+        };
+
+        let region = self.open_region(region_path)?;
+        let mut chunk_positions = Vec::new();
+
+        for (index, metadata) in region.chunks_metadata.iter().enumerate() {
+            if metadata.is_empty() {
+                continue;
+            }
+
+            let region_chunk_x = (index & 31) as u8;
+            let region_chunk_z = (index >> 5) as u8;
+
+            chunk_positions.push(chunk_position(
+                region_x,
+                region_z,
+                region_chunk_x,
+                region_chunk_z,
+            ));
+        }
+
+        Ok(chunk_positions)
+    }
+
+    /// Returns coordinates of all chunks present in folder.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anvil_region::AnvilChunkProvider;
+    ///
+    /// let chunk_provider = AnvilChunkProvider::new("test/region");
+    /// let chunk_positions = chunk_provider.chunk_positions().unwrap();
+    ///
+    /// assert!(chunk_positions.contains(&(4, 2)));
+    /// ```
+    pub fn chunk_positions(&self) -> Result<Vec<(i32, i32)>, ChunkLoadError> {
+        let mut chunk_positions = Vec::new();
+
+        for (region_x, region_z) in self.region_positions()? {
+            chunk_positions.extend(self.region_chunk_positions(region_x, region_z)?);
+        }
+
+        Ok(chunk_positions)
     }
 
     /// Load chunks from the specified coordinates.
     ///
+    /// Chunks are read from legacy McRegion file when region has no Anvil file.
+    ///
     /// # Example
     ///
     /// ```
@@ -161,21 +444,16 @@ impl<'a> AnvilChunkProvider<'a> {
     /// assert_eq!(level_compound_tag.get_i32("zPos").unwrap(), 2);
     /// ```
     pub fn load_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<CompoundTag, ChunkLoadError> {
-        let region_x = chunk_x >> 5;
-        let region_z = chunk_z >> 5;
-
-        let region_chunk_x = (chunk_x & 31) as u8;
-        let region_chunk_z = (chunk_z & 31) as u8;
+        let (region_x, region_z) = region_position(chunk_x, chunk_z);
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
 
-        let region_name = format!("r.{}.{}.mca", region_x, region_z);
-        let region_path = self.folder_path.join(region_name);
-
-        if !region_path.exists() {
-            return Err(ChunkLoadError::RegionNotFound { region_x, region_z });
-        }
+        let region_path = match self.existing_region_path(region_x, region_z) {
+            Some(region_path) => region_path,
+            None => return Err(ChunkLoadError::RegionNotFound { region_x, region_z }),
+        };
 
         // TODO: Cache region files.
-        let mut region = AnvilRegion::new(region_path)?;
+        let mut region = self.open_region(region_path)?;
 
         region.read_chunk(region_chunk_x, region_chunk_z)
     }
@@ -206,46 +484,263 @@ impl<'a> AnvilChunkProvider<'a> {
         chunk_x: i32,
         chunk_z: i32,
         chunk_compound_tag: CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        self.save_chunk_tag(chunk_x, chunk_z, &chunk_compound_tag)
+    }
+
+    /// Saves borrowed chunk data to the specified coordinates.
+    pub(crate) fn save_chunk_tag(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk_compound_tag: &CompoundTag,
     ) -> Result<(), ChunkSaveError> {
         if !self.folder_path.exists() {
-            fs::create_dir(self.folder_path)?;
+            fs::create_dir(&self.folder_path)?;
         }
 
-        let region_x = chunk_x >> 5;
-        let region_z = chunk_z >> 5;
+        let (region_x, region_z) = region_position(chunk_x, chunk_z);
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
 
-        let region_chunk_x = (chunk_x & 31) as u8;
-        let region_chunk_z = (chunk_z & 31) as u8;
+        let region_path = self.writable_region_path(region_x, region_z)?;
 
-        let region_name = format!("r.{}.{}.mca", region_x, region_z);
-        let region_path = self.folder_path.join(region_name);
+        // TODO: Cache region files.
+        let mut region = self.open_region_for_write(region_path)?;
+
+        region.write_chunk_tag(region_chunk_x, region_chunk_z, chunk_compound_tag)
+    }
+
+    /// Loads compression scheme and compressed data of chunk without decoding it.
+    pub fn load_chunk_bytes(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<(u8, Vec<u8>), ChunkLoadError> {
+        let (region_x, region_z) = region_position(chunk_x, chunk_z);
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+        let region_path = match self.existing_region_path(region_x, region_z) {
+            Some(region_path) => region_path,
+            None => return Err(ChunkLoadError::RegionNotFound { region_x, region_z }),
+        };
+
+        let mut region = self.open_region(region_path)?;
+
+        region.read_chunk_bytes(region_chunk_x, region_chunk_z)
+    }
+
+    /// Saves already compressed chunk data to the specified coordinates.
+    pub fn save_chunk_bytes(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        compression_scheme: u8,
+        compressed_data: &[u8],
+    ) -> Result<(), ChunkSaveError> {
+        if !self.folder_path.exists() {
+            fs::create_dir(&self.folder_path)?;
+        }
+
+        let (region_x, region_z) = region_position(chunk_x, chunk_z);
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+        let region_path = self.writable_region_path(region_x, region_z)?;
+        let mut region = self.open_region_for_write(region_path)?;
+
+        region.write_chunk_bytes(
+            region_chunk_x,
+            region_chunk_z,
+            compression_scheme,
+            compressed_data,
+        )
+    }
+
+    /// Loads chunk from the specified coordinates and deserializes it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anvil_region::AnvilChunkProvider;
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Chunk {
+    ///     #[serde(rename = "Level")]
+    ///     level: Level,
+    /// }
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Level {
+    ///     #[serde(rename = "xPos")]
+    ///     x_pos: i32,
+    ///     #[serde(rename = "InhabitedTime")]
+    ///     inhabited_time: i64,
+    /// }
+    ///
+    /// let chunk_provider = AnvilChunkProvider::new("test/region");
+    /// let chunk: Chunk = chunk_provider.load_chunk_as(4, 2).unwrap();
+    ///
+    /// assert_eq!(chunk.level.x_pos, 4);
+    /// ```
+    pub fn load_chunk_as<T: DeserializeOwned>(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<T, SerdeError> {
+        let chunk_compound_tag = self.load_chunk(chunk_x, chunk_z)?;
+
+        from_compound_tag(chunk_compound_tag)
+    }
+
+    /// Serializes chunk and saves it to the specified coordinates.
+    pub fn save_chunk_from<T: Serialize + ?Sized>(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk: &T,
+    ) -> Result<(), SerdeError> {
+        let chunk_compound_tag = to_compound_tag(chunk)?;
+        self.save_chunk(chunk_x, chunk_z, chunk_compound_tag)?;
+
+        Ok(())
+    }
+
+    /// Deletes chunk at the specified coordinates.
+    ///
+    /// Returns false if there was no chunk.
+    pub fn delete_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<bool, ChunkSaveError> {
+        let (region_x, region_z) = region_position(chunk_x, chunk_z);
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+        let region_path = self.writable_region_path(region_x, region_z)?;
+
+        if !region_path.exists() {
+            return Ok(false);
+        }
 
         // TODO: Cache region files.
-        let mut region = AnvilRegion::new(region_path)?;
+        let mut region = self.open_region_for_write(region_path)?;
+
+        Ok(region.delete_chunk(region_chunk_x, region_chunk_z)?)
+    }
+
+    /// Canonicalizes every region, so regions with same chunks and
+    /// timestamps are byte-identical regardless of write history.
+    ///
+    /// Returns problems of regions which were left unchanged with their
+    /// coordinates.
+    pub fn canonicalize_regions(&self) -> Result<Vec<(i32, i32, RegionProblem)>, io::Error> {
+        let mut problems = Vec::new();
+
+        for (region_x, region_z) in self.region_positions()? {
+            if let Some(region_path) = self.existing_region_path(region_x, region_z) {
+                let mut region = self.open_region_for_write(region_path)?;
+
+                for problem in region.canonicalize()? {
+                    problems.push((region_x, region_z, problem));
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+/// Returns true if path has Anvil region file extension.
+fn is_anvil_path(region_path: &Path) -> bool {
+    region_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        == Some(ANVIL_EXTENSION)
+}
+
+/// Parses region coordinates from `r.X.Z.<extension>` file name.
+fn parse_region_name(file_name: &str, extension: &str) -> Option<(i32, i32)> {
+    let mut parts = file_name.split('.');
 
-        region.write_chunk(region_chunk_x, region_chunk_z, chunk_compound_tag)
+    if parts.next()? != "r" {
+        return None;
     }
+
+    let region_x = parts.next()?.parse().ok()?;
+    let region_z = parts.next()?.parse().ok()?;
+
+    if parts.next()? != extension || parts.next().is_some() {
+        return None;
+    }
+
+    Some((region_x, region_z))
+}
+
+/// Returns coordinates of region which contains chunk.
+pub fn region_position(chunk_x: i32, chunk_z: i32) -> (i32, i32) {
+    (chunk_x >> 5, chunk_z >> 5)
+}
+
+/// Returns chunk coordinates relative to its region.
+pub fn region_chunk_position(chunk_x: i32, chunk_z: i32) -> (u8, u8) {
+    ((chunk_x & 31) as u8, (chunk_z & 31) as u8)
+}
+
+/// Returns chunk coordinates from region coordinates and chunk coordinates relative to it.
+pub fn chunk_position(
+    region_x: i32,
+    region_z: i32,
+    region_chunk_x: u8,
+    region_chunk_z: u8,
+) -> (i32, i32) {
+    (
+        (region_x << 5) + region_chunk_x as i32,
+        (region_z << 5) + region_chunk_z as i32,
+    )
 }
 
 /// Region represents a 32x32 group of chunks.
-struct AnvilRegion {
+///
+/// Chunk coordinates of region methods are relative to region.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilRegion;
+///
+/// let mut region = AnvilRegion::open("test/region/r.0.0.mca").unwrap();
+/// let chunks = region.chunks();
+///
+/// assert!(chunks.iter().any(|(chunk_x, chunk_z, _)| (*chunk_x, *chunk_z) == (4, 2)));
+///
+/// let (compression_scheme, compressed_data) = region.read_chunk_bytes(4, 2).unwrap();
+/// assert_eq!(compression_scheme, 2);
+/// assert!(!compressed_data.is_empty());
+/// ```
+pub struct AnvilRegion {
     /// File in which region are stored.
     file: File,
     /// Array of chunks metadata.
     chunks_metadata: [AnvilChunkMetadata; REGION_CHUNKS],
     /// Used sectors for chunks data.
     used_sectors: BitVec,
+    /// Path of region file.
+    path: PathBuf,
+    /// Path of journal file next to region file.
+    journal_path: PathBuf,
+    /// Write-ahead journal, none if journaling is disabled.
+    journal: Option<Journal>,
+    /// Receiver of instrumentation events.
+    metrics: Option<Arc<dyn ChunkMetrics>>,
+    /// Timestamp written for chunks instead of current time.
+    fixed_timestamp: Option<u32>,
 }
 
 /// Chunk metadata are stored in header.
 #[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
-struct AnvilChunkMetadata {
+pub struct AnvilChunkMetadata {
     /// Sector index from which starts chunk data.
-    sector_index: u32,
+    pub sector_index: u32,
     /// Amount of sectors used to store chunk.
-    sectors: u8,
+    pub sectors: u8,
     /// Last time in seconds when chunk was modified.
-    last_modified_timestamp: u32,
+    pub last_modified_timestamp: u32,
 }
 
 impl AnvilChunkMetadata {
@@ -257,160 +752,787 @@ impl AnvilChunkMetadata {
         }
     }
 
-    fn update_last_modified_timestamp(&mut self) {
-        let system_time = SystemTime::now();
-        let time = system_time.duration_since(UNIX_EPOCH).unwrap();
-
-        self.last_modified_timestamp = time.as_secs() as u32
-    }
-
-    fn is_empty(&self) -> bool {
+    /// Returns true if there is no chunk.
+    pub fn is_empty(&self) -> bool {
         self.sectors == 0
     }
 }
 
+/// Problems found while verifying region.
+#[derive(Debug)]
+pub enum RegionProblem {
+    /// Chunk sectors overlap region header.
+    OverlapsHeader { chunk_x: u8, chunk_z: u8 },
+    /// Chunk sectors are beyond the end of file.
+    OutsideFile { chunk_x: u8, chunk_z: u8 },
+    /// Chunk shares sectors with another chunk.
+    SharedSectors {
+        chunk_x: u8,
+        chunk_z: u8,
+        /// Chunk which sectors are shared.
+        other_chunk_x: u8,
+        other_chunk_z: u8,
+    },
+    /// Chunk data cannot be loaded.
+    Unreadable {
+        chunk_x: u8,
+        chunk_z: u8,
+        chunk_load_error: ChunkLoadError,
+    },
+}
+
+/// Decodes compound tag returning also amount of decoded bytes.
+fn read_counted_compound_tag<R: Read>(reader: R) -> Result<(CompoundTag, u64), TagDecodeError> {
+    let mut counting_reader = CountingReader::new(reader);
+    let compound_tag = read_compound_tag(&mut counting_reader)?;
+
+    Ok((compound_tag, counting_reader.count))
+}
+
+/// Encodes and zlib compresses chunk returning also amount of encoded bytes.
+pub(crate) fn encode_chunk(chunk_compound_tag: &CompoundTag) -> Result<(Vec<u8>, u64), io::Error> {
+    let compression = Compression::new(ZLIB_COMPRESSION_LEVEL);
+    let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+    let mut counting_writer = CountingWriter::new(&mut encoder);
+    write_compound_tag(&mut counting_writer, chunk_compound_tag)?;
+
+    let uncompressed_length = counting_writer.count;
+
+    Ok((encoder.finish()?, uncompressed_length))
+}
+
+/// Returns current unix timestamp in seconds.
+fn current_timestamp() -> u32 {
+    let system_time = SystemTime::now();
+    let time = system_time.duration_since(UNIX_EPOCH).unwrap();
+
+    time.as_secs() as u32
+}
+
 impl AnvilRegion {
-    fn new<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
-        let mut file = OpenOptions::new()
+    /// Opens region file, creating empty one if there is no file.
+    ///
+    /// Changes left in journal by interrupted session are finished.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let file = OpenOptions::new()
             .write(true)
             .read(true)
             .create(true)
-            .open(path)?;
+            .truncate(false)
+            .open(path.as_ref())?;
 
         // If necessary, extend the file length to the length of the header.
         if REGION_HEADER_BYTES_LENGTH > file.metadata()?.len() {
             file.set_len(REGION_HEADER_BYTES_LENGTH)?;
         }
 
-        let chunks_metadata = Self::read_header(&mut file)?;
-        let total_sectors = file.metadata()?.len() as u32 / REGION_SECTOR_BYTES_LENGTH as u32;
+        let mut region = Self::from_file(file, path.as_ref())?;
+        region.replay_journal()?;
+
+        Ok(region)
+    }
+
+    /// Opens existing region file without changing it.
+    ///
+    /// Changes left in journal by interrupted session are not visible until
+    /// region is opened for writing. File shorter than header has no chunks.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        let file = File::open(path.as_ref())?;
+
+        Self::from_file(file, path.as_ref())
+    }
+
+    fn from_file(mut file: File, path: &Path) -> Result<Self, io::Error> {
+        let file_length = file.metadata()?.len();
+
+        let chunks_metadata = if file_length < REGION_HEADER_BYTES_LENGTH {
+            [Default::default(); REGION_CHUNKS]
+        } else {
+            Self::read_header(&mut file)?
+        };
+
+        let total_sectors =
+            file_length.max(REGION_HEADER_BYTES_LENGTH) as u32 / REGION_SECTOR_BYTES_LENGTH as u32;
         let used_sectors = Self::used_sectors(total_sectors, &chunks_metadata);
 
-        let region = AnvilRegion {
+        Ok(AnvilRegion {
             file,
             chunks_metadata,
             used_sectors,
+            path: path.to_path_buf(),
+            journal_path: Journal::path(path),
+            journal: None,
+            metrics: None,
+            fixed_timestamp: None,
+        })
+    }
+
+    /// Applies complete journal records and removes journal.
+    fn replay_journal(&mut self) -> Result<(), io::Error> {
+        if !self.journal_path.exists() {
+            return Ok(());
+        }
+
+        for record in Journal::read_records(&self.journal_path)? {
+            match record {
+                JournalRecord::Write {
+                    chunk_x,
+                    chunk_z,
+                    last_modified_timestamp,
+                    compression_scheme,
+                    compressed_data,
+                } => {
+                    let mut buffer = Vec::with_capacity(compressed_data.len() + 1);
+                    buffer.push(compression_scheme);
+                    buffer.extend_from_slice(&compressed_data);
+
+                    self.write_buffer(chunk_x, chunk_z, &buffer, last_modified_timestamp)?;
+                }
+                JournalRecord::Delete { chunk_x, chunk_z } => {
+                    self.release_chunk(chunk_x, chunk_z)?;
+                }
+                JournalRecord::SetTimestamp {
+                    chunk_x,
+                    chunk_z,
+                    last_modified_timestamp,
+                } => {
+                    let mut metadata = self.get_metadata(chunk_x, chunk_z);
+
+                    if !metadata.is_empty() {
+                        metadata.last_modified_timestamp = last_modified_timestamp;
+                        self.update_metadata(chunk_x, chunk_z, metadata)?;
+                    }
+                }
+            }
+        }
+
+        self.file.sync_all()?;
+        fs::remove_file(&self.journal_path)
+    }
+
+    /// Reports chunk loads, saves and sector allocation.
+    pub fn with_metrics(mut self, metrics: Arc<dyn ChunkMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Writes specified timestamp for chunks instead of current time.
+    pub fn with_fixed_timestamp(mut self, last_modified_timestamp: u32) -> Self {
+        self.fixed_timestamp = Some(last_modified_timestamp);
+        self
+    }
+
+    /// Records following changes in write-ahead journal before applying them.
+    ///
+    /// Journal is checkpointed after specified amount of changes and when
+    /// region is dropped. Compaction is not journaled.
+    pub fn enable_journal(&mut self, checkpoint_records: u32) -> Result<(), io::Error> {
+        self.checkpoint()?;
+        self.journal = Some(Journal::create(&self.journal_path, checkpoint_records)?);
+
+        Ok(())
+    }
+
+    /// Syncs region file and empties journal.
+    pub fn checkpoint(&mut self) -> Result<(), io::Error> {
+        if let Some(journal) = &mut self.journal {
+            self.file.sync_all()?;
+            journal.clear()?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends change to journal if it is enabled.
+    fn append_journal<F>(&mut self, record: F) -> Result<(), io::Error>
+    where
+        F: FnOnce() -> JournalRecord,
+    {
+        match &mut self.journal {
+            Some(journal) => journal.append(&record()),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes checkpoint when enough changes were journaled.
+    fn checkpoint_if_due(&mut self) -> Result<(), io::Error> {
+        match &self.journal {
+            Some(journal) if journal.checkpoint_due() => self.checkpoint(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Opens existing region file for reading and writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
+        if !path.as_ref().exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Region file not found",
+            ));
+        }
+
+        Self::new(path)
+    }
+
+    /// First 8KB of file are header of 1024 offsets and 1024 timestamps.
+    fn read_header(file: &mut File) -> Result<[AnvilChunkMetadata; REGION_CHUNKS], io::Error> {
+        let mut chunks_metadata = [Default::default(); REGION_CHUNKS];
+        let mut values = [0u32; REGION_CHUNKS_METADATA_LENGTH];
+
+        for value in values.iter_mut() {
+            *value = file.read_u32::<BigEndian>()?;
+        }
+
+        for index in 0..REGION_CHUNKS {
+            let last_modified_timestamp = values[REGION_CHUNKS + index];
+            let offset = values[index];
+
+            let sector_index = offset >> 8;
+            let sectors = (offset & 0xFF) as u8;
+
+            let metadata = AnvilChunkMetadata::new(sector_index, sectors, last_modified_timestamp);
+            chunks_metadata[index] = metadata;
+        }
+
+        Ok(chunks_metadata)
+    }
+
+    /// Calculates used sectors.
+    fn used_sectors(total_sectors: u32, chunks_metadata: &[AnvilChunkMetadata]) -> BitVec {
+        let mut used_sectors = bitvec![0; total_sectors as usize];
+        used_sectors.set(0, true);
+        used_sectors.set(1, true);
+        for metadata in chunks_metadata {
+            if metadata.is_empty() {
+                continue;
+            }
+
+            let start_index = metadata.sector_index as usize;
+            let end_index = start_index + metadata.sectors as usize;
+
+            for index in start_index..end_index {
+                if index < used_sectors.len() {
+                    used_sectors.set(index, true);
+                }
+            }
+        }
+
+        used_sectors
+    }
+
+    /// Returns coordinates and metadata of present chunks in header order.
+    pub fn chunks(&self) -> Vec<(u8, u8, AnvilChunkMetadata)> {
+        let mut chunks = Vec::new();
+
+        for (index, metadata) in self.chunks_metadata.iter().enumerate() {
+            if !metadata.is_empty() {
+                chunks.push(((index & 31) as u8, (index >> 5) as u8, *metadata));
+            }
+        }
+
+        chunks
+    }
+
+    /// Returns chunk metadata at specified coordinates.
+    pub fn chunk_metadata(&self, chunk_x: u8, chunk_z: u8) -> AnvilChunkMetadata {
+        self.get_metadata(chunk_x, chunk_z)
+    }
+
+    /// Returns amount of sectors in file including header.
+    pub fn total_sectors(&self) -> u32 {
+        self.used_sectors.len() as u32
+    }
+
+    /// Returns amount of sectors used by header and chunks.
+    pub fn used_sectors_count(&self) -> u32 {
+        self.used_sectors.count_ones() as u32
+    }
+
+    /// Loads chunk from the specified coordinates.
+    pub fn read_chunk(&mut self, chunk_x: u8, chunk_z: u8) -> Result<CompoundTag, ChunkLoadError> {
+        let started = Instant::now();
+        let (compression_scheme, compressed_buffer) = self.read_stored_chunk(chunk_x, chunk_z)?;
+        let cursor = Cursor::new(&compressed_buffer);
+
+        let (chunk_compound_tag, uncompressed_length) = match compression_scheme {
+            GZIP_COMPRESSION_TYPE => read_counted_compound_tag(GzDecoder::new(cursor))?,
+            ZLIB_COMPRESSION_TYPE => read_counted_compound_tag(ZlibDecoder::new(cursor))?,
+            _ => return Err(ChunkLoadError::UnsupportedCompressionScheme { compression_scheme }),
         };
 
-        Ok(region)
+        if let Some(metrics) = &self.metrics {
+            metrics.chunk_loaded(&ChunkIoMetrics {
+                duration: started.elapsed(),
+                compressed_length: compressed_buffer.len() as u64,
+                uncompressed_length,
+            });
+        }
+
+        Ok(chunk_compound_tag)
     }
 
-    /// First 8KB of file are header of 1024 offsets and 1024 timestamps.
-    fn read_header(file: &mut File) -> Result<[AnvilChunkMetadata; REGION_CHUNKS], io::Error> {
-        let mut chunks_metadata = [Default::default(); REGION_CHUNKS];
-        let mut values = [0u32; REGION_CHUNKS_METADATA_LENGTH];
+    /// Reads compression scheme and compressed chunk data as stored in file.
+    pub fn read_chunk_bytes(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+    ) -> Result<(u8, Vec<u8>), ChunkLoadError> {
+        let started = Instant::now();
+        let (compression_scheme, compressed_buffer) = self.read_stored_chunk(chunk_x, chunk_z)?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.chunk_bytes_loaded(started.elapsed(), compressed_buffer.len() as u64);
+        }
+
+        Ok((compression_scheme, compressed_buffer))
+    }
+
+    fn read_stored_chunk(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+    ) -> Result<(u8, Vec<u8>), ChunkLoadError> {
+        let metadata = self.get_metadata(chunk_x, chunk_z);
+
+        if metadata.is_empty() {
+            return Err(ChunkLoadError::ChunkNotFound { chunk_x, chunk_z });
+        }
+
+        let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+        let maximum_length = (metadata.sectors as u32 * REGION_SECTOR_BYTES_LENGTH as u32)
+            .min(CHUNK_MAXIMUM_BYTES_LENGTH);
+
+        self.file.seek(SeekFrom::Start(seek_offset))?;
+        let length = self.file.read_u32::<BigEndian>()?;
+
+        if length > maximum_length {
+            return Err(ChunkLoadError::LengthExceedsMaximum {
+                length,
+                maximum_length,
+            });
+        }
+
+        let compression_scheme = self.file.read_u8()?;
+        let mut compressed_buffer = vec![0u8; length.saturating_sub(1) as usize];
+        self.file.read_exact(&mut compressed_buffer)?;
+
+        Ok((compression_scheme, compressed_buffer))
+    }
+
+    /// Saves chunk data to the specified coordinates.
+    pub fn write_chunk(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        chunk_compound_tag: CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        self.write_chunk_tag(chunk_x, chunk_z, &chunk_compound_tag)
+    }
+
+    /// Saves borrowed chunk data to the specified coordinates.
+    pub(crate) fn write_chunk_tag(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        chunk_compound_tag: &CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        let started = Instant::now();
+        let (compressed_buffer, uncompressed_length) = encode_chunk(chunk_compound_tag)?;
+
+        self.write_stored_chunk(chunk_x, chunk_z, ZLIB_COMPRESSION_TYPE, &compressed_buffer)?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.chunk_saved(&ChunkIoMetrics {
+                duration: started.elapsed(),
+                compressed_length: compressed_buffer.len() as u64,
+                uncompressed_length,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Writes already compressed chunk data to the specified coordinates.
+    pub fn write_chunk_bytes(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        compression_scheme: u8,
+        compressed_data: &[u8],
+    ) -> Result<(), ChunkSaveError> {
+        let started = Instant::now();
+
+        self.write_stored_chunk(chunk_x, chunk_z, compression_scheme, compressed_data)?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.chunk_bytes_saved(started.elapsed(), compressed_data.len() as u64);
+        }
+
+        Ok(())
+    }
+
+    fn write_stored_chunk(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        compression_scheme: u8,
+        compressed_data: &[u8],
+    ) -> Result<(), ChunkSaveError> {
+        let mut buffer = Vec::with_capacity(compressed_data.len() + 1);
+
+        buffer.write_u8(compression_scheme)?;
+        buffer.extend_from_slice(compressed_data);
+
+        // 4 bytes for data length.
+        let length = (buffer.len() + 4) as u32;
+
+        if length > CHUNK_MAXIMUM_BYTES_LENGTH {
+            return Err(ChunkSaveError::LengthExceedsMaximum { length });
+        }
+
+        let last_modified_timestamp = self.fixed_timestamp.unwrap_or_else(current_timestamp);
+
+        self.append_journal(|| JournalRecord::Write {
+            chunk_x,
+            chunk_z,
+            last_modified_timestamp,
+            compression_scheme,
+            compressed_data: compressed_data.to_vec(),
+        })?;
+        self.write_buffer(chunk_x, chunk_z, &buffer, last_modified_timestamp)?;
+        self.checkpoint_if_due()?;
+
+        Ok(())
+    }
+
+    /// Writes compression scheme followed by compressed data into free sectors.
+    fn write_buffer(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        buffer: &[u8],
+        last_modified_timestamp: u32,
+    ) -> Result<(), io::Error> {
+        // 4 bytes for data length.
+        let length = (buffer.len() + 4) as u32;
+
+        let mut metadata = self.find_place(chunk_x, chunk_z, length)?;
+        let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+
+        self.file.seek(SeekFrom::Start(seek_offset))?;
+        self.file.write_u32::<BigEndian>(buffer.len() as u32)?;
+        self.file.write_all(buffer)?;
+
+        // Padding to align sector.
+        let sector_length = REGION_SECTOR_BYTES_LENGTH as u32;
+        let padding = (sector_length - length % sector_length) % sector_length;
+
+        for _ in 0..padding {
+            self.file.write_u8(0)?;
+        }
+
+        metadata.last_modified_timestamp = last_modified_timestamp;
+        self.update_metadata(chunk_x, chunk_z, metadata)
+    }
+
+    /// Sets last modified timestamp of chunk.
+    ///
+    /// Returns false if there is no chunk.
+    pub fn set_last_modified_timestamp(
+        &mut self,
+        chunk_x: u8,
+        chunk_z: u8,
+        last_modified_timestamp: u32,
+    ) -> Result<bool, io::Error> {
+        let mut metadata = self.get_metadata(chunk_x, chunk_z);
+
+        if metadata.is_empty() {
+            return Ok(false);
+        }
+
+        self.append_journal(|| JournalRecord::SetTimestamp {
+            chunk_x,
+            chunk_z,
+            last_modified_timestamp,
+        })?;
+
+        metadata.last_modified_timestamp = last_modified_timestamp;
+        self.update_metadata(chunk_x, chunk_z, metadata)?;
+        self.checkpoint_if_due()?;
+
+        Ok(true)
+    }
+
+    /// Removes chunk from header and releases its sectors.
+    ///
+    /// Returns false if there was no chunk.
+    pub fn delete_chunk(&mut self, chunk_x: u8, chunk_z: u8) -> Result<bool, io::Error> {
+        if self.get_metadata(chunk_x, chunk_z).is_empty() {
+            return Ok(false);
+        }
+
+        self.append_journal(|| JournalRecord::Delete { chunk_x, chunk_z })?;
+        self.release_chunk(chunk_x, chunk_z)?;
+        self.checkpoint_if_due()?;
+
+        Ok(true)
+    }
+
+    /// Clears chunk header entry and marks its sectors free.
+    fn release_chunk(&mut self, chunk_x: u8, chunk_z: u8) -> Result<(), io::Error> {
+        let metadata = self.get_metadata(chunk_x, chunk_z);
+
+        for i in 0..metadata.sectors {
+            let sector_index = metadata.sector_index as usize + i as usize;
+
+            if sector_index < self.used_sectors.len() {
+                self.used_sectors.set(sector_index, false);
+            }
+        }
+
+        self.update_metadata(chunk_x, chunk_z, Default::default())
+    }
+
+    /// Moves chunks to the beginning of file removing gaps between them.
+    ///
+    /// Timestamps are kept. Returns amount of released sectors.
+    pub fn compact(&mut self) -> Result<u32, io::Error> {
+        self.checkpoint()?;
+
+        let total_sectors = self.total_sectors();
+        let mut chunks = self.chunks();
+        chunks.sort_by_key(|(_, _, metadata)| metadata.sector_index);
+
+        // Sectors before this index are already compacted.
+        let mut next_sector_index = 2;
+
+        for (chunk_x, chunk_z, mut metadata) in chunks {
+            if metadata.sector_index != next_sector_index {
+                let length = metadata.sectors as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+                let mut buffer = Vec::with_capacity(length as usize);
 
-        for index in 0..REGION_CHUNKS_METADATA_LENGTH {
-            values[index] = file.read_u32::<BigEndian>()?;
-        }
+                let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+                self.file.seek(SeekFrom::Start(seek_offset))?;
+                (&mut self.file).take(length).read_to_end(&mut buffer)?;
+                buffer.resize(length as usize, 0);
 
-        for index in 0..REGION_CHUNKS {
-            let last_modified_timestamp = values[REGION_CHUNKS + index];
-            let offset = values[index];
+                let seek_offset = next_sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+                self.file.seek(SeekFrom::Start(seek_offset))?;
+                self.file.write_all(&buffer)?;
 
-            let sector_index = offset >> 8;
-            let sectors = (offset & 0xFF) as u8;
+                metadata.sector_index = next_sector_index;
+                self.update_metadata(chunk_x, chunk_z, metadata)?;
+            }
 
-            let metadata = AnvilChunkMetadata::new(sector_index, sectors, last_modified_timestamp);
-            chunks_metadata[index] = metadata;
+            next_sector_index += metadata.sectors as u32;
         }
 
-        return Ok(chunks_metadata);
+        let compacted_sectors = next_sector_index.max(2);
+        let length = compacted_sectors as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+        self.file.set_len(length)?;
+
+        self.used_sectors = Self::used_sectors(compacted_sectors, &self.chunks_metadata);
+
+        Ok(total_sectors.saturating_sub(compacted_sectors))
     }
 
-    /// Calculates used sectors.
-    fn used_sectors(total_sectors: u32, chunks_metadata: &[AnvilChunkMetadata]) -> BitVec {
-        let mut used_sectors = bitvec![0; total_sectors as usize];
-        used_sectors.set(0, true);
-        used_sectors.set(1, true);
-        for metadata in chunks_metadata {
-            if metadata.is_empty() {
+    /// Rewrites chunks back-to-back in header order with zeroed padding.
+    ///
+    /// Unlike compaction, resulting file depends only on chunks data and
+    /// timestamps. Region is written into temporary file which replaces
+    /// region file, so interrupted canonicalization leaves region intact.
+    ///
+    /// Returns chunks which stored length exceeds their sectors or which lie
+    /// beyond the end of file, region is not changed when there are any.
+    pub fn canonicalize(&mut self) -> Result<Vec<RegionProblem>, io::Error> {
+        self.checkpoint()?;
+
+        let file_length = self.file.metadata()?.len();
+        let file_sectors = file_length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u64);
+        let chunks = self.chunks();
+        let mut buffers = Vec::with_capacity(chunks.len());
+        let mut problems = Vec::new();
+
+        for (chunk_x, chunk_z, metadata) in &chunks {
+            let outside_file = RegionProblem::OutsideFile {
+                chunk_x: *chunk_x,
+                chunk_z: *chunk_z,
+            };
+
+            if metadata.sector_index as u64 + metadata.sectors as u64 > file_sectors {
+                problems.push(outside_file);
                 continue;
             }
 
-            let start_index = metadata.sector_index as usize;
-            let end_index = start_index + metadata.sectors as usize;
+            let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+            let maximum_length = metadata.sectors as u32 * REGION_SECTOR_BYTES_LENGTH as u32 - 4;
 
-            for index in start_index..end_index {
-                if index < used_sectors.len() {
-                    used_sectors.set(index, true);
+            self.file.seek(SeekFrom::Start(seek_offset))?;
+
+            // Last sector of file may be cut short.
+            let length = match self.file.read_u32::<BigEndian>() {
+                Ok(length) => length,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    problems.push(outside_file);
+                    continue;
                 }
+                Err(error) => return Err(error),
+            };
+
+            if length > maximum_length {
+                problems.push(RegionProblem::Unreadable {
+                    chunk_x: *chunk_x,
+                    chunk_z: *chunk_z,
+                    chunk_load_error: ChunkLoadError::LengthExceedsMaximum {
+                        length,
+                        maximum_length,
+                    },
+                });
+                continue;
             }
-        }
 
-        used_sectors
-    }
+            let mut buffer = Vec::with_capacity(length as usize);
+            (&mut self.file)
+                .take(length as u64)
+                .read_to_end(&mut buffer)?;
 
-    fn read_chunk(&mut self, chunk_x: u8, chunk_z: u8) -> Result<CompoundTag, ChunkLoadError> {
-        let metadata = self.get_metadata(chunk_x, chunk_z);
+            if buffer.len() < length as usize {
+                problems.push(outside_file);
+                continue;
+            }
 
-        if metadata.is_empty() {
-            return Err(ChunkLoadError::ChunkNotFound { chunk_x, chunk_z });
+            buffers.push(buffer);
         }
 
-        let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
-        let maximum_length = (metadata.sectors as u32 * REGION_SECTOR_BYTES_LENGTH as u32)
-            .min(CHUNK_MAXIMUM_BYTES_LENGTH);
+        if !problems.is_empty() {
+            return Ok(problems);
+        }
 
-        self.file.seek(SeekFrom::Start(seek_offset))?;
-        let length = self.file.read_u32::<BigEndian>()?;
+        let mut file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".tmp");
+        let temporary_path = self.path.with_file_name(file_name);
 
-        if length > maximum_length {
-            return Err(ChunkLoadError::LengthExceedsMaximum {
-                length,
-                maximum_length,
-            });
-        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .open(&temporary_path)?;
 
-        let compression_scheme = self.file.read_u8()?;
-        let mut compressed_buffer = vec![0u8; (length - 1) as usize];
-        self.file.read_exact(&mut compressed_buffer)?;
+        let mut chunks_metadata = self.chunks_metadata;
+        let mut next_sector_index = 2;
+        file.seek(SeekFrom::Start(REGION_HEADER_BYTES_LENGTH))?;
+
+        for ((chunk_x, chunk_z, mut metadata), buffer) in chunks.into_iter().zip(buffers) {
+            // 4 bytes for data length.
+            let length = buffer.len() as u32 + 4;
+            let sectors = length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u32);
+            let padding = sectors * REGION_SECTOR_BYTES_LENGTH as u32 - length;
 
-        let mut cursor = Cursor::new(&compressed_buffer);
+            file.write_u32::<BigEndian>(buffer.len() as u32)?;
+            file.write_all(&buffer)?;
+            file.write_all(&vec![0; padding as usize])?;
 
-        match compression_scheme {
-            GZIP_COMPRESSION_TYPE => Ok(read_gzip_compound_tag(&mut cursor)?),
-            ZLIB_COMPRESSION_TYPE => Ok(read_zlib_compound_tag(&mut cursor)?),
-            _ => Err(ChunkLoadError::UnsupportedCompressionScheme { compression_scheme }),
+            metadata.sector_index = next_sector_index;
+            metadata.sectors = sectors as u8;
+            chunks_metadata[Self::metadata_index(chunk_x, chunk_z)] = metadata;
+
+            next_sector_index += sectors;
         }
-    }
 
-    fn write_chunk(
-        &mut self,
-        chunk_x: u8,
-        chunk_z: u8,
-        chunk_compound_tag: CompoundTag,
-    ) -> Result<(), ChunkSaveError> {
-        let mut buffer = Vec::new();
+        file.set_len(next_sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64)?;
 
-        buffer.write_u8(ZLIB_COMPRESSION_TYPE)?;
-        write_zlib_compound_tag(&mut buffer, chunk_compound_tag)?;
+        let previous_file = mem::replace(&mut self.file, file);
+        let previous_chunks_metadata = mem::replace(&mut self.chunks_metadata, chunks_metadata);
 
-        // 4 bytes for data length.
-        let length = (buffer.len() + 4) as u32;
+        let result = self
+            .write_header()
+            .and_then(|_| self.file.sync_all())
+            .and_then(|_| fs::rename(&temporary_path, &self.path));
 
-        if length > CHUNK_MAXIMUM_BYTES_LENGTH {
-            return Err(ChunkSaveError::LengthExceedsMaximum { length });
+        // Region keeps previous file when replacement was not completed.
+        if let Err(io_error) = result {
+            self.file = previous_file;
+            self.chunks_metadata = previous_chunks_metadata;
+            let _ = fs::remove_file(&temporary_path);
+
+            return Err(io_error);
         }
 
-        let mut metadata = self.find_place(chunk_x, chunk_z, length)?;
-        let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64;
+        self.used_sectors = Self::used_sectors(next_sector_index, &self.chunks_metadata);
 
-        self.file.seek(SeekFrom::Start(seek_offset))?;
-        self.file.write_u32::<BigEndian>(buffer.len() as u32)?;
-        self.file.write_all(&buffer)?;
+        Ok(problems)
+    }
 
-        // Padding to align sector.
-        let padding = REGION_SECTOR_BYTES_LENGTH - length as u16 % REGION_SECTOR_BYTES_LENGTH;
+    /// Writes whole header from chunks metadata.
+    fn write_header(&mut self) -> Result<(), io::Error> {
+        let mut header = Vec::with_capacity(REGION_HEADER_BYTES_LENGTH as usize);
 
-        for _ in 0..padding {
-            self.file.write_u8(0)?;
+        for metadata in self.chunks_metadata.iter() {
+            header
+                .write_u32::<BigEndian>((metadata.sector_index << 8) | metadata.sectors as u32)?;
         }
 
-        metadata.update_last_modified_timestamp();
-        self.update_metadata(chunk_x, chunk_z, metadata)?;
+        for metadata in self.chunks_metadata.iter() {
+            header.write_u32::<BigEndian>(metadata.last_modified_timestamp)?;
+        }
 
-        Ok(())
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+
+    /// Checks that chunks lie inside file, does not share sectors and can be loaded.
+    pub fn verify(&mut self) -> Result<Vec<RegionProblem>, io::Error> {
+        let file_length = self.file.metadata()?.len();
+        let file_sectors = file_length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u64) as usize;
+
+        let mut sector_owners: Vec<Option<(u8, u8)>> = vec![None; file_sectors];
+        let mut problems = Vec::new();
+
+        for (chunk_x, chunk_z, metadata) in self.chunks() {
+            let start_index = metadata.sector_index as usize;
+            let end_index = start_index + metadata.sectors as usize;
+
+            if start_index < 2 {
+                problems.push(RegionProblem::OverlapsHeader { chunk_x, chunk_z });
+                continue;
+            }
+
+            if end_index > file_sectors {
+                problems.push(RegionProblem::OutsideFile { chunk_x, chunk_z });
+                continue;
+            }
+
+            let mut shared_sectors = false;
+
+            for sector_owner in &mut sector_owners[start_index..end_index] {
+                match sector_owner {
+                    Some((other_chunk_x, other_chunk_z)) => {
+                        if !shared_sectors {
+                            problems.push(RegionProblem::SharedSectors {
+                                chunk_x,
+                                chunk_z,
+                                other_chunk_x: *other_chunk_x,
+                                other_chunk_z: *other_chunk_z,
+                            });
+                        }
+
+                        shared_sectors = true;
+                    }
+                    None => *sector_owner = Some((chunk_x, chunk_z)),
+                }
+            }
+
+            if let Err(chunk_load_error) = self.read_chunk(chunk_x, chunk_z) {
+                problems.push(RegionProblem::Unreadable {
+                    chunk_x,
+                    chunk_z,
+                    chunk_load_error,
+                });
+            }
+        }
+
+        Ok(problems)
     }
 
     fn metadata_index(chunk_x: u8, chunk_z: u8) -> usize {
@@ -434,7 +1556,7 @@ impl AnvilRegion {
         chunk_z: u8,
         chunk_length: u32,
     ) -> Result<AnvilChunkMetadata, io::Error> {
-        let sectors_required = (chunk_length / REGION_SECTOR_BYTES_LENGTH as u32) as u8 + 1;
+        let sectors_required = chunk_length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u32) as u8;
         let metadata = self.get_metadata(chunk_x, chunk_z);
 
         // Can place chunk in the old sectors.
@@ -442,6 +1564,10 @@ impl AnvilRegion {
             return Ok(metadata);
         }
 
+        if let (Some(metrics), false) = (&self.metrics, metadata.is_empty()) {
+            metrics.chunk_moved(metadata.sectors, sectors_required);
+        }
+
         // Release used sectors.
         for i in 0..metadata.sectors {
             let sector_index = metadata.sector_index as usize + i as usize;
@@ -456,7 +1582,9 @@ impl AnvilRegion {
         let mut sectors_free = 0;
 
         for sector_index in 0..total_sectors {
-            if sector_index >= self.used_sectors.capacity() as u64 || self.used_sectors[sector_index as usize] {
+            if sector_index >= self.used_sectors.capacity() as u64
+                || self.used_sectors[sector_index as usize]
+            {
                 sectors_free = 0;
                 continue;
             }
@@ -465,12 +1593,12 @@ impl AnvilRegion {
 
             // Can put chunk in gap.
             if sectors_free == sectors_required {
-                let put_sector_index = sector_index as u32 - sectors_free as u32;
+                let put_sector_index = sector_index as u32 + 1 - sectors_free as u32;
 
                 // Acquire used sectors.
                 for i in 0..sectors_free {
-                    if i as usize >= self.used_sectors.capacity(){
-                        return Err(io::Error::last_os_error())
+                    if i as usize >= self.used_sectors.capacity() {
+                        return Err(io::Error::last_os_error());
                     }
                     let sector_index = put_sector_index as usize + i as usize;
                     self.used_sectors.set(sector_index, true);
@@ -482,19 +1610,23 @@ impl AnvilRegion {
 
         // Extending file because cannot find a place to put chunk data.
         let extend_sectors = sectors_required - sectors_free;
-        let extend_length = (REGION_SECTOR_BYTES_LENGTH * extend_sectors as u16) as u64;
+        let extend_length = REGION_SECTOR_BYTES_LENGTH as u64 * extend_sectors as u64;
         self.file.set_len(file_length + extend_length)?;
 
+        if let Some(metrics) = &self.metrics {
+            metrics.region_extended(extend_sectors);
+        }
+
         // Mark new sectors as used.
         for _ in 0..extend_sectors {
             self.used_sectors.push(true);
         }
 
-        return Ok(AnvilChunkMetadata::new(
+        Ok(AnvilChunkMetadata::new(
             total_sectors as u32 - sectors_free as u32,
             sectors_required,
             0,
-        ));
+        ))
     }
 
     /// Updates chunk metadata.
@@ -523,16 +1655,30 @@ impl AnvilRegion {
     }
 }
 
+impl Drop for AnvilRegion {
+    fn drop(&mut self) {
+        // Journal which failed to checkpoint is kept to be replayed on next open.
+        if self.journal.is_some() && self.checkpoint().is_ok() {
+            self.journal = None;
+            let _ = fs::remove_file(&self.journal_path);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::fixtures::{chunk, value};
     use crate::{
-        AnvilChunkMetadata, AnvilChunkProvider, AnvilRegion, ChunkLoadError,
-        REGION_HEADER_BYTES_LENGTH, REGION_SECTOR_BYTES_LENGTH,
+        current_timestamp, parse_region_name, AnvilChunkMetadata, AnvilChunkProvider, AnvilRegion,
+        ChunkLoadError, ChunkSaveError, RegionProblem, REGION_HEADER_BYTES_LENGTH,
+        REGION_SECTOR_BYTES_LENGTH,
     };
+    use byteorder::{BigEndian, WriteBytesExt};
     use nbt::CompoundTag;
-    use std::io::Read;
+    use std::fs::{self, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom};
     use std::path::Path;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
     #[test]
     fn test_empty_header_write() {
@@ -556,7 +1702,7 @@ mod tests {
 
     #[test]
     fn test_header_read() {
-        let expected_data = vec![
+        let expected_data = [
             AnvilChunkMetadata::new(61, 2, 1570215508),
             AnvilChunkMetadata::new(102, 2, 1570215511),
             AnvilChunkMetadata::new(177, 2, 1570215515),
@@ -648,13 +1794,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_region_name() {
+        assert_eq!(parse_region_name("r.0.0.mca", "mca"), Some((0, 0)));
+        assert_eq!(parse_region_name("r.-3.12.mca", "mca"), Some((-3, 12)));
+        assert_eq!(parse_region_name("r.0.0.mcr", "mca"), None);
+        assert_eq!(parse_region_name("r.0.0.mcr", "mcr"), Some((0, 0)));
+        assert_eq!(parse_region_name("r.0.mca", "mca"), None);
+        assert_eq!(parse_region_name("r.0.0.mca.tmp", "mca"), None);
+    }
+
+    #[test]
+    fn test_save_chunk_mcregion_not_converted() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("value", 1);
+        chunk_provider.save_chunk(0, 0, chunk_compound_tag).unwrap();
+
+        let region_path = chunk_provider.region_path(0, 0);
+        fs::rename(&region_path, region_path.with_extension("mcr")).unwrap();
+
+        match chunk_provider.save_chunk(1, 0, CompoundTag::new()) {
+            Err(ChunkSaveError::McRegionNotConverted {
+                region_x: 0,
+                region_z: 0,
+            }) => {}
+            result => panic!("Expected `McRegionNotConverted` but got `{:?}`", result),
+        }
+
+        assert!(chunk_provider.delete_chunk(0, 0).is_err());
+        assert!(!region_path.exists());
+
+        // Neighbouring chunk is still read from McRegion file.
+        let chunk_compound_tag = chunk_provider.load_chunk(0, 0).unwrap();
+        assert_eq!(chunk_compound_tag.get_i32("value").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_chunk_positions() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+
+        assert_eq!(chunk_provider.region_positions().unwrap(), vec![(0, 0)]);
+
+        let chunk_positions = chunk_provider.region_chunk_positions(0, 0).unwrap();
+
+        for (chunk_x, chunk_z) in chunk_positions {
+            assert!(chunk_provider.load_chunk(chunk_x, chunk_z).is_ok());
+        }
+    }
+
     #[test]
     fn test_update_metadata() {
         let mut file = NamedTempFile::new().unwrap();
         let mut region = AnvilRegion::new(file.path()).unwrap();
 
-        let mut metadata = AnvilChunkMetadata::new(500, 10, 0);
-        metadata.update_last_modified_timestamp();
+        let metadata = AnvilChunkMetadata::new(500, 10, current_timestamp());
 
         region.update_metadata(15, 15, metadata).unwrap();
         let chunks_metadata = AnvilRegion::read_header(file.as_file_mut()).unwrap();
@@ -754,6 +1950,242 @@ mod tests {
         assert_eq!(region.used_sectors.len(), 4);
     }
 
+    #[test]
+    fn test_delete_chunk() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        let mut write_compound_tag = CompoundTag::new();
+        write_compound_tag.insert_bool("test_bool", true);
+
+        region.write_chunk(15, 15, write_compound_tag).unwrap();
+
+        assert!(region.delete_chunk(15, 15).unwrap());
+        assert!(!region.delete_chunk(15, 15).unwrap());
+        assert!(!region.used_sectors.get(2).unwrap());
+
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        match region.read_chunk(15, 15) {
+            Err(ChunkLoadError::ChunkNotFound { chunk_x, chunk_z }) => {
+                assert_eq!(chunk_x, 15);
+                assert_eq!(chunk_z, 15);
+            }
+            result => panic!("Expected `ChunkNotFound` but got `{:?}`", result),
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        for chunk_x in 0..3 {
+            let mut write_compound_tag = CompoundTag::new();
+            write_compound_tag.insert_i8("chunk_x", chunk_x as i8);
+
+            region.write_chunk(chunk_x, 0, write_compound_tag).unwrap();
+        }
+
+        let timestamp = region.chunk_metadata(2, 0).last_modified_timestamp;
+        region.delete_chunk(0, 0).unwrap();
+
+        assert_eq!(region.compact().unwrap(), 1);
+        assert_eq!(region.total_sectors(), 4);
+        assert_eq!(region.used_sectors_count(), 4);
+        assert_eq!(
+            file.as_file().metadata().unwrap().len(),
+            REGION_HEADER_BYTES_LENGTH + REGION_SECTOR_BYTES_LENGTH as u64 * 2
+        );
+
+        let metadata = region.chunk_metadata(2, 0);
+        assert_eq!(metadata.sector_index, 3);
+        assert_eq!(metadata.last_modified_timestamp, timestamp);
+
+        let mut region = AnvilRegion::open(file.path()).unwrap();
+        let read_compound_tag = region.read_chunk(2, 0).unwrap();
+        assert_eq!(read_compound_tag.get_i8("chunk_x").unwrap(), 2);
+        assert!(region.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let chunk_compound_tag = |chunk_x: i32, length: usize| {
+            let mut chunk_compound_tag = CompoundTag::new();
+            chunk_compound_tag.insert_i32_vec("values", (0..length as i32).collect());
+            chunk_compound_tag.insert_i32("chunk_x", chunk_x);
+            chunk_compound_tag
+        };
+
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path()).with_fixed_timestamp(7);
+
+        for chunk_x in 0..3 {
+            chunk_provider
+                .save_chunk(chunk_x, 0, chunk_compound_tag(chunk_x, 10))
+                .unwrap();
+        }
+
+        // Same chunks written in other order with moves and deletion.
+        let other_folder = TempDir::new().unwrap();
+        let other_chunk_provider =
+            AnvilChunkProvider::from_path(other_folder.path()).with_fixed_timestamp(7);
+
+        for chunk_x in (0..5).rev() {
+            other_chunk_provider
+                .save_chunk(chunk_x, 0, chunk_compound_tag(chunk_x, 2048))
+                .unwrap();
+        }
+
+        for chunk_x in 0..3 {
+            other_chunk_provider
+                .save_chunk(chunk_x, 0, chunk_compound_tag(chunk_x, 10))
+                .unwrap();
+        }
+
+        other_chunk_provider.delete_chunk(3, 0).unwrap();
+        other_chunk_provider.delete_chunk(4, 0).unwrap();
+
+        chunk_provider.canonicalize_regions().unwrap();
+        other_chunk_provider.canonicalize_regions().unwrap();
+
+        let region_path = chunk_provider.region_path(0, 0);
+        let region_bytes = std::fs::read(&region_path).unwrap();
+        let other_region_bytes = std::fs::read(other_chunk_provider.region_path(0, 0)).unwrap();
+
+        assert_eq!(region_bytes, other_region_bytes);
+        assert_eq!(region_bytes.len(), 5 * REGION_SECTOR_BYTES_LENGTH as usize);
+
+        let mut region = AnvilRegion::open(region_path).unwrap();
+        assert_eq!(region.chunk_metadata(2, 0).sector_index, 4);
+        assert_eq!(region.chunk_metadata(2, 0).last_modified_timestamp, 7);
+        assert!(region.verify().unwrap().is_empty());
+        assert!(!folder.path().join("r.0.0.mca.tmp").exists());
+    }
+
+    #[test]
+    fn test_write_chunk_bytes_sector_aligned() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        // 4 bytes for length and 1 for compression scheme fill sector exactly.
+        let compressed_data = vec![0; REGION_SECTOR_BYTES_LENGTH as usize - 5];
+        region.write_chunk_bytes(0, 0, 2, &compressed_data).unwrap();
+
+        assert_eq!(region.chunk_metadata(0, 0).sectors, 1);
+        assert_eq!(
+            fs::metadata(file.path()).unwrap().len(),
+            3 * REGION_SECTOR_BYTES_LENGTH as u64
+        );
+        assert_eq!(region.read_chunk_bytes(0, 0).unwrap().1, compressed_data);
+    }
+
+    #[test]
+    fn test_canonicalize_length_exceeds_sectors() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        region.write_chunk(0, 0, CompoundTag::new()).unwrap();
+        region.write_chunk(1, 0, CompoundTag::new()).unwrap();
+
+        // Stored length of chunk claims more than its single sector.
+        let mut region_file = OpenOptions::new().write(true).open(file.path()).unwrap();
+        region_file
+            .seek(SeekFrom::Start(3 * REGION_SECTOR_BYTES_LENGTH as u64))
+            .unwrap();
+        region_file.write_u32::<BigEndian>(5000).unwrap();
+
+        let region_bytes = fs::read(file.path()).unwrap();
+        let problems = region.canonicalize().unwrap();
+
+        match problems.as_slice() {
+            [RegionProblem::Unreadable {
+                chunk_x: 1,
+                chunk_z: 0,
+                chunk_load_error: ChunkLoadError::LengthExceedsMaximum { length: 5000, .. },
+            }] => {}
+            problems => panic!("Expected `LengthExceedsMaximum` but got `{:?}`", problems),
+        }
+
+        assert_eq!(fs::read(file.path()).unwrap(), region_bytes);
+    }
+
+    #[test]
+    fn test_canonicalize_outside_file() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        region.write_chunk(0, 0, CompoundTag::new()).unwrap();
+        region.write_chunk(1, 0, CompoundTag::new()).unwrap();
+        region
+            .update_metadata(2, 0, AnvilChunkMetadata::new(100, 1, 0))
+            .unwrap();
+
+        // Last chunk is cut inside of its sector.
+        let region_file = OpenOptions::new().write(true).open(file.path()).unwrap();
+        region_file
+            .set_len(3 * REGION_SECTOR_BYTES_LENGTH as u64 + 2)
+            .unwrap();
+
+        let region_bytes = fs::read(file.path()).unwrap();
+        let problems = region.canonicalize().unwrap();
+
+        match problems.as_slice() {
+            [RegionProblem::OutsideFile {
+                chunk_x: 1,
+                chunk_z: 0,
+            }, RegionProblem::OutsideFile {
+                chunk_x: 2,
+                chunk_z: 0,
+            }] => {}
+            problems => panic!("Expected `OutsideFile` but got `{:?}`", problems),
+        }
+
+        assert_eq!(fs::read(file.path()).unwrap(), region_bytes);
+    }
+
+    #[test]
+    fn test_verify() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        region.write_chunk(0, 0, CompoundTag::new()).unwrap();
+        region
+            .update_metadata(1, 0, AnvilChunkMetadata::new(2, 1, 0))
+            .unwrap();
+        region
+            .update_metadata(2, 0, AnvilChunkMetadata::new(1, 1, 0))
+            .unwrap();
+        region
+            .update_metadata(3, 0, AnvilChunkMetadata::new(10, 1, 0))
+            .unwrap();
+
+        let problems = region.verify().unwrap();
+        assert_eq!(problems.len(), 3);
+
+        match &problems[0] {
+            RegionProblem::SharedSectors {
+                chunk_x,
+                other_chunk_x,
+                ..
+            } => {
+                assert_eq!(*chunk_x, 1);
+                assert_eq!(*other_chunk_x, 0);
+            }
+            problem => panic!("Expected `SharedSectors` but got `{:?}`", problem),
+        }
+
+        match &problems[1] {
+            RegionProblem::OverlapsHeader { chunk_x, .. } => assert_eq!(*chunk_x, 2),
+            problem => panic!("Expected `OverlapsHeader` but got `{:?}`", problem),
+        }
+
+        match &problems[2] {
+            RegionProblem::OutsideFile { chunk_x, .. } => assert_eq!(*chunk_x, 3),
+            problem => panic!("Expected `OutsideFile` but got `{:?}`", problem),
+        }
+    }
+
     #[test]
     fn test_write_chunk_with_insert_in_middle() {
         let file = NamedTempFile::new().unwrap();
@@ -779,6 +2211,58 @@ mod tests {
         assert_eq!(region.used_sectors.len(), 5);
     }
 
+    #[test]
+    fn test_write_chunk_into_released_gap() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+
+        for chunk_x in 0..3 {
+            region
+                .write_chunk(chunk_x, 0, chunk(chunk_x as i32))
+                .unwrap();
+        }
+
+        region.delete_chunk(1, 0).unwrap();
+        region.write_chunk(5, 0, chunk(5)).unwrap();
+
+        // Chunk takes released sector without touching its neighbours.
+        assert_eq!(region.chunk_metadata(5, 0).sector_index, 3);
+        assert_eq!(value(&region.read_chunk(0, 0).unwrap()), 0);
+        assert_eq!(value(&region.read_chunk(2, 0).unwrap()), 2);
+        assert_eq!(value(&region.read_chunk(5, 0).unwrap()), 5);
+        assert!(region.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_write_chunk_bytes_many_sectors() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+        let compressed_data = vec![7u8; 70_000];
+
+        region.write_chunk_bytes(3, 4, 2, &compressed_data).unwrap();
+
+        assert_eq!(region.chunk_metadata(3, 4).sectors, 18);
+        assert_eq!(region.read_chunk_bytes(3, 4).unwrap(), (2, compressed_data));
+    }
+
+    #[test]
+    fn test_write_chunk_bytes_exceeds_maximum_sectors() {
+        let file = NamedTempFile::new().unwrap();
+        let mut region = AnvilRegion::new(file.path()).unwrap();
+        let compressed_data = vec![7u8; 255 * REGION_SECTOR_BYTES_LENGTH as usize];
+
+        match region.write_chunk_bytes(3, 4, 2, &compressed_data) {
+            Err(ChunkSaveError::LengthExceedsMaximum { .. }) => {}
+            result => panic!("Expected `LengthExceedsMaximum` but got `{:?}`", result),
+        }
+
+        assert!(region.chunk_metadata(3, 4).is_empty());
+        assert_eq!(
+            fs::metadata(file.path()).unwrap().len(),
+            REGION_HEADER_BYTES_LENGTH
+        );
+    }
+
     #[test]
     fn test_write_chunk_not_enough_gap() {
         let file = NamedTempFile::new().unwrap();
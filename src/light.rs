@@ -0,0 +1,687 @@
+//! Block and sky light data access and relighting.
+//!
+//! Sections store light as nibble arrays of 4096 values in YZX order,
+//! two values per byte with the lower nibble first.
+use crate::chunk::{read_sections, section_tag_mut, BlockState, ChunkDataError, SECTION_BLOCKS};
+use crate::heightmap::blocks_motion;
+use crate::version::ChunkVersion;
+use crate::{AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::{CompoundTag, Tag};
+use std::collections::{HashMap, VecDeque};
+
+/// Nibble array length in bytes.
+const NIBBLE_ARRAY_BYTES_LENGTH: usize = SECTION_BLOCKS / 2;
+/// Maximum light level.
+const MAXIMUM_LIGHT: u8 = 15;
+
+/// Possible errors while relighting chunks.
+#[derive(Debug)]
+pub enum RelightError {
+    /// Error while loading chunk from provider.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while saving chunk to provider.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// Chunk data are not readable.
+    ChunkDataError { chunk_data_error: ChunkDataError },
+}
+
+impl From<ChunkLoadError> for RelightError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        RelightError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for RelightError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        RelightError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<ChunkDataError> for RelightError {
+    fn from(chunk_data_error: ChunkDataError) -> Self {
+        RelightError::ChunkDataError { chunk_data_error }
+    }
+}
+
+/// Array of 4096 light levels of section.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NibbleArray {
+    data: Vec<u8>,
+}
+
+impl Default for NibbleArray {
+    fn default() -> Self {
+        NibbleArray {
+            data: vec![0; NIBBLE_ARRAY_BYTES_LENGTH],
+        }
+    }
+}
+
+impl NibbleArray {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Reads nibble array from byte array tag value.
+    pub fn from_bytes(name: &str, bytes: &[i8]) -> Result<Self, ChunkDataError> {
+        if bytes.len() != NIBBLE_ARRAY_BYTES_LENGTH {
+            return Err(ChunkDataError::InvalidLength {
+                name: name.to_owned(),
+                length: bytes.len(),
+                expected_length: NIBBLE_ARRAY_BYTES_LENGTH,
+            });
+        }
+
+        let data = bytes.iter().map(|byte| *byte as u8).collect();
+
+        Ok(NibbleArray { data })
+    }
+
+    /// Returns byte array tag value.
+    pub fn to_bytes(&self) -> Vec<i8> {
+        self.data.iter().map(|byte| *byte as i8).collect()
+    }
+
+    fn index(x: u8, y: u8, z: u8) -> usize {
+        assert!(16 > x, "Nibble array x coordinate out of bounds");
+        assert!(16 > y, "Nibble array y coordinate out of bounds");
+        assert!(16 > z, "Nibble array z coordinate out of bounds");
+
+        (y as usize) << 8 | (z as usize) << 4 | x as usize
+    }
+
+    /// Returns light level at specified section coordinates.
+    pub fn get(&self, x: u8, y: u8, z: u8) -> u8 {
+        self.get_index(Self::index(x, y, z))
+    }
+
+    /// Sets light level at specified section coordinates.
+    pub fn set(&mut self, x: u8, y: u8, z: u8, value: u8) {
+        self.set_index(Self::index(x, y, z), value)
+    }
+
+    fn get_index(&self, index: usize) -> u8 {
+        let byte = self.data[index / 2];
+
+        if index & 1 == 0 {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        }
+    }
+
+    pub(crate) fn set_index(&mut self, index: usize, value: u8) {
+        assert!(MAXIMUM_LIGHT >= value, "Light level out of bounds");
+        let byte = &mut self.data[index / 2];
+
+        if index & 1 == 0 {
+            *byte = (*byte & 0xF0) | value;
+        } else {
+            *byte = (*byte & 0x0F) | (value << 4);
+        }
+    }
+}
+
+/// Light data of a single section.
+#[derive(Clone, Debug)]
+pub struct SectionLight {
+    /// Section index from bottom of chunk.
+    pub y: i8,
+    /// Light emitted by blocks, if stored.
+    pub block_light: Option<NibbleArray>,
+    /// Light coming from sky, if stored.
+    pub sky_light: Option<NibbleArray>,
+}
+
+/// Reads light data of all sections ordered from bottom to top.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::light::read_light;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// let sections_light = read_light(&chunk_compound_tag).unwrap();
+/// let sky_light = sections_light[4].sky_light.as_ref().unwrap();
+///
+/// assert_eq!(sky_light.get(0, 15, 0), 15);
+/// ```
+pub fn read_light(chunk_compound_tag: &CompoundTag) -> Result<Vec<SectionLight>, ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level(chunk_compound_tag)?;
+    let sections_tag_name = chunk_version.sections_tag_name();
+    let mut sections_light = Vec::new();
+
+    if !level_compound_tag.contains_key(sections_tag_name) {
+        return Ok(sections_light);
+    }
+
+    for section_tag in level_compound_tag.get_compound_tag_vec(sections_tag_name)? {
+        let y = section_tag.get_i8("Y")?;
+        let block_light = read_nibble_array(section_tag, "BlockLight")?;
+        let sky_light = read_nibble_array(section_tag, "SkyLight")?;
+
+        sections_light.push(SectionLight {
+            y,
+            block_light,
+            sky_light,
+        });
+    }
+
+    sections_light.sort_by_key(|section_light| section_light.y);
+
+    Ok(sections_light)
+}
+
+fn read_nibble_array(
+    section_tag: &CompoundTag,
+    name: &str,
+) -> Result<Option<NibbleArray>, ChunkDataError> {
+    if !section_tag.contains_key(name) {
+        return Ok(None);
+    }
+
+    let bytes = section_tag.get_i8_vec(name)?;
+
+    Ok(Some(NibbleArray::from_bytes(name, bytes)?))
+}
+
+/// Removes light data from all sections and marks chunk as not lit.
+///
+/// The game will recompute light when chunk will be loaded.
+pub fn clear_light(chunk_compound_tag: &mut CompoundTag) -> Result<(), ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level_mut(chunk_compound_tag)?;
+    let sections_tag_name = chunk_version.sections_tag_name();
+
+    if level_compound_tag.contains_key(sections_tag_name) {
+        let sections: &mut Vec<Tag> = level_compound_tag.get_mut(sections_tag_name)?;
+
+        for section in sections.iter_mut() {
+            if let Tag::Compound(section_tag) = section {
+                let light_free_tag = section_tag
+                    .clone()
+                    .into_iter()
+                    .filter(|(name, _)| name != "BlockLight" && name != "SkyLight")
+                    .collect();
+
+                *section_tag = light_free_tag;
+            }
+        }
+    }
+
+    level_compound_tag.insert_bool("isLightOn", false);
+
+    Ok(())
+}
+
+/// Returns light level emitted by block.
+pub fn light_emission(block_state: &BlockState) -> u8 {
+    // Furnaces, lamps, campfires and redstone torches emit light only when lit.
+    if let Some(lit) = block_state.property("lit") {
+        if lit != "true" {
+            return 0;
+        }
+    }
+
+    match block_state.name.as_str() {
+        "minecraft:beacon"
+        | "minecraft:conduit"
+        | "minecraft:end_gateway"
+        | "minecraft:end_portal"
+        | "minecraft:fire"
+        | "minecraft:glowstone"
+        | "minecraft:jack_o_lantern"
+        | "minecraft:lantern"
+        | "minecraft:lava"
+        | "minecraft:sea_lantern"
+        | "minecraft:shroomlight"
+        | "minecraft:campfire"
+        | "minecraft:redstone_lamp" => 15,
+        "minecraft:torch" | "minecraft:wall_torch" | "minecraft:end_rod" => 14,
+        "minecraft:furnace" | "minecraft:blast_furnace" | "minecraft:smoker" => 13,
+        "minecraft:nether_portal" => 11,
+        "minecraft:soul_torch"
+        | "minecraft:soul_wall_torch"
+        | "minecraft:soul_fire"
+        | "minecraft:soul_lantern"
+        | "minecraft:soul_campfire" => 10,
+        "minecraft:redstone_torch" | "minecraft:redstone_wall_torch" => 7,
+        "minecraft:magma_block" => 3,
+        "minecraft:brewing_stand"
+        | "minecraft:brown_mushroom"
+        | "minecraft:dragon_egg"
+        | "minecraft:end_portal_frame" => 1,
+        _ => 0,
+    }
+}
+
+/// Block name suffixes of blocks which shape does not fill whole block.
+const PARTIAL_SHAPE_SUFFIXES: &[&str] = &[
+    "_slab",
+    "_stairs",
+    "_fence",
+    "_fence_gate",
+    "_wall",
+    "_pane",
+    "glass",
+    "_door",
+    "_trapdoor",
+    "_bed",
+    "chest",
+    "_head",
+    "_skull",
+];
+
+/// Returns amount of light absorbed by block.
+///
+/// The game decides by block shape which is not stored in chunk,
+/// so this relies on block names and may differ for modded blocks.
+pub fn light_opacity(block_state: &BlockState) -> u8 {
+    let name = block_state.name.as_str();
+
+    if block_state.is_air() {
+        return 0;
+    }
+
+    match name {
+        "minecraft:water"
+        | "minecraft:bubble_column"
+        | "minecraft:ice"
+        | "minecraft:frosted_ice"
+        | "minecraft:cobweb"
+        | "minecraft:slime_block" => return 1,
+        _ => {}
+    }
+
+    if name.ends_with("_leaves") || block_state.property("waterlogged") == Some("true") {
+        return 1;
+    }
+
+    if !blocks_motion(block_state)
+        || PARTIAL_SHAPE_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+    {
+        return 0;
+    }
+
+    MAXIMUM_LIGHT
+}
+
+/// Light levels and block properties of a single chunk column.
+struct LightVolume {
+    opacity: Vec<u8>,
+    block_light: Vec<u8>,
+    sky_light: Vec<u8>,
+}
+
+/// Light levels of a group of chunks sharing the same vertical range.
+struct LightGrid {
+    volumes: HashMap<(i32, i32), LightVolume>,
+    minimum_y: i32,
+    height: i32,
+}
+
+impl LightGrid {
+    fn index(&self, x: i32, y: i32, z: i32) -> Option<((i32, i32), usize)> {
+        let local_y = y - self.minimum_y;
+
+        if local_y < 0 || local_y >= self.height {
+            return None;
+        }
+
+        let chunk_position = (x >> 4, z >> 4);
+        let index = ((local_y << 8) | ((z & 15) << 4) | (x & 15)) as usize;
+
+        if self.volumes.contains_key(&chunk_position) {
+            Some((chunk_position, index))
+        } else {
+            None
+        }
+    }
+
+    fn spread(&mut self, mut queue: VecDeque<(i32, i32, i32)>, sky: bool) {
+        const DIRECTIONS: [(i32, i32, i32); 6] = [
+            (1, 0, 0),
+            (-1, 0, 0),
+            (0, 1, 0),
+            (0, -1, 0),
+            (0, 0, 1),
+            (0, 0, -1),
+        ];
+
+        while let Some((x, y, z)) = queue.pop_front() {
+            let (chunk_position, index) = self.index(x, y, z).unwrap();
+            let level = self.light(chunk_position, index, sky);
+
+            if level <= 1 {
+                continue;
+            }
+
+            for (dx, dy, dz) in DIRECTIONS.iter() {
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+
+                if let Some((neighbour_position, neighbour_index)) = self.index(nx, ny, nz) {
+                    let volume = &self.volumes[&neighbour_position];
+                    let opacity = volume.opacity[neighbour_index].max(1);
+                    let new_level = level.saturating_sub(opacity);
+
+                    if new_level > self.light(neighbour_position, neighbour_index, sky) {
+                        self.set_light(neighbour_position, neighbour_index, sky, new_level);
+                        queue.push_back((nx, ny, nz));
+                    }
+                }
+            }
+        }
+    }
+
+    fn light(&self, chunk_position: (i32, i32), index: usize, sky: bool) -> u8 {
+        let volume = &self.volumes[&chunk_position];
+
+        if sky {
+            volume.sky_light[index]
+        } else {
+            volume.block_light[index]
+        }
+    }
+
+    fn set_light(&mut self, chunk_position: (i32, i32), index: usize, sky: bool, level: u8) {
+        let volume = self.volumes.get_mut(&chunk_position).unwrap();
+
+        if sky {
+            volume.sky_light[index] = level;
+        } else {
+            volume.block_light[index] = level;
+        }
+    }
+}
+
+/// Recomputes block light and, if requested, sky light of chunks.
+///
+/// Chunks are keyed by chunk coordinates. Light spreads between chunks
+/// of the map regardless of region they belong to, chunks outside of the
+/// map are treated as dark.
+pub fn relight(
+    chunks: &mut HashMap<(i32, i32), CompoundTag>,
+    has_sky_light: bool,
+) -> Result<(), ChunkDataError> {
+    let mut chunks_sections = HashMap::new();
+
+    for (position, chunk_compound_tag) in chunks.iter() {
+        chunks_sections.insert(*position, read_sections(chunk_compound_tag)?);
+    }
+
+    let section_ys = chunks_sections
+        .values()
+        .flat_map(|sections| sections.iter().map(|section| section.y as i32));
+
+    let minimum_section_y = section_ys.clone().min().unwrap_or(0);
+    let maximum_section_y = section_ys.max().unwrap_or(0);
+
+    let minimum_y = minimum_section_y * 16;
+    let height = (maximum_section_y - minimum_section_y + 1) * 16;
+    let volume_length = height as usize * 256;
+
+    let mut grid = LightGrid {
+        volumes: HashMap::new(),
+        minimum_y,
+        height,
+    };
+
+    let mut block_queue = VecDeque::new();
+
+    for (position, sections) in &chunks_sections {
+        let mut volume = LightVolume {
+            opacity: vec![0; volume_length],
+            block_light: vec![0; volume_length],
+            sky_light: vec![0; volume_length],
+        };
+
+        for section in sections {
+            let section_offset = (section.y as i32 - minimum_section_y) as usize * SECTION_BLOCKS;
+
+            for (block_index, palette_index) in section.blocks.iter().enumerate() {
+                let block_state = &section.palette[*palette_index as usize];
+                let index = section_offset + block_index;
+
+                volume.opacity[index] = light_opacity(block_state);
+
+                let emission = light_emission(block_state);
+
+                if emission > 0 {
+                    volume.block_light[index] = emission;
+
+                    let local_y = (index >> 8) as i32;
+                    let x = position.0 * 16 + (index & 15) as i32;
+                    let z = position.1 * 16 + ((index >> 4) & 15) as i32;
+
+                    block_queue.push_back((x, minimum_y + local_y, z));
+                }
+            }
+        }
+
+        grid.volumes.insert(*position, volume);
+    }
+
+    grid.spread(block_queue, false);
+
+    if has_sky_light {
+        let mut sky_queue = VecDeque::new();
+
+        for (position, volume) in grid.volumes.iter_mut() {
+            for column_index in 0..256 {
+                let mut level = MAXIMUM_LIGHT;
+
+                for local_y in (0..height as usize).rev() {
+                    let index = local_y << 8 | column_index;
+                    level = level.saturating_sub(volume.opacity[index]);
+
+                    if level == 0 {
+                        break;
+                    }
+
+                    volume.sky_light[index] = level;
+
+                    let x = position.0 * 16 + (column_index & 15) as i32;
+                    let z = position.1 * 16 + (column_index >> 4) as i32;
+
+                    sky_queue.push_back((x, minimum_y + local_y as i32, z));
+                }
+            }
+        }
+
+        grid.spread(sky_queue, true);
+    }
+
+    for (position, chunk_compound_tag) in chunks.iter_mut() {
+        let volume = &grid.volumes[position];
+        let mut sections_light = Vec::new();
+
+        for section_y in minimum_section_y..=maximum_section_y {
+            let section_offset = (section_y - minimum_section_y) as usize * SECTION_BLOCKS;
+            let mut block_light = NibbleArray::new();
+            let mut sky_light = NibbleArray::new();
+
+            for index in 0..SECTION_BLOCKS {
+                block_light.set_index(index, volume.block_light[section_offset + index]);
+                sky_light.set_index(index, volume.sky_light[section_offset + index]);
+            }
+
+            sections_light.push(SectionLight {
+                y: section_y as i8,
+                block_light: Some(block_light),
+                sky_light: if has_sky_light { Some(sky_light) } else { None },
+            });
+        }
+
+        write_light(chunk_compound_tag, sections_light)?;
+    }
+
+    Ok(())
+}
+
+/// Writes light data to chunk sections and marks chunk as lit.
+///
+/// Sections missing in chunk are added without block data.
+pub fn write_light(
+    chunk_compound_tag: &mut CompoundTag,
+    sections_light: Vec<SectionLight>,
+) -> Result<(), ChunkDataError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+    let level_compound_tag = chunk_version.level_mut(chunk_compound_tag)?;
+    let sections_tag_name = chunk_version.sections_tag_name();
+
+    if !level_compound_tag.contains_key(sections_tag_name) {
+        level_compound_tag.insert(sections_tag_name, Vec::<Tag>::new());
+    }
+
+    let sections: &mut Vec<Tag> = level_compound_tag.get_mut(sections_tag_name)?;
+
+    for section_light in sections_light {
+        let section_tag = section_tag_mut(sections, section_light.y);
+
+        if let Some(block_light) = section_light.block_light {
+            section_tag.insert_i8_vec("BlockLight", block_light.to_bytes());
+        }
+
+        if let Some(sky_light) = section_light.sky_light {
+            section_tag.insert_i8_vec("SkyLight", sky_light.to_bytes());
+        }
+    }
+
+    level_compound_tag.insert_bool("isLightOn", true);
+
+    Ok(())
+}
+
+/// Loads chunks from provider, recomputes their light and saves them back.
+///
+/// Light spreads only between specified chunks, so to get correct light at
+/// the edges of the edited area include the neighbour chunks as well.
+pub fn relight_chunks(
+    chunk_provider: &AnvilChunkProvider,
+    chunk_positions: &[(i32, i32)],
+    has_sky_light: bool,
+) -> Result<(), RelightError> {
+    let mut chunks = HashMap::new();
+
+    for (chunk_x, chunk_z) in chunk_positions {
+        let chunk_compound_tag = chunk_provider.load_chunk(*chunk_x, *chunk_z)?;
+        chunks.insert((*chunk_x, *chunk_z), chunk_compound_tag);
+    }
+
+    relight(&mut chunks, has_sky_light)?;
+
+    for ((chunk_x, chunk_z), chunk_compound_tag) in chunks {
+        chunk_provider.save_chunk(chunk_x, chunk_z, chunk_compound_tag)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{write_sections, BlockState, ChunkSection};
+    use crate::light::{clear_light, read_light, relight, NibbleArray};
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+    use std::collections::HashMap;
+
+    fn chunk_with_section(section: &ChunkSection) -> CompoundTag {
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", 2586);
+        chunk_compound_tag.insert_compound_tag("Level", CompoundTag::new());
+
+        write_sections(&mut chunk_compound_tag, std::slice::from_ref(section)).unwrap();
+
+        chunk_compound_tag
+    }
+
+    #[test]
+    fn test_nibble_array_get_set() {
+        let mut nibble_array = NibbleArray::new();
+        nibble_array.set(1, 2, 3, 15);
+        nibble_array.set(0, 2, 3, 7);
+
+        assert_eq!(nibble_array.get(1, 2, 3), 15);
+        assert_eq!(nibble_array.get(0, 2, 3), 7);
+        assert_eq!(nibble_array.get(2, 2, 3), 0);
+
+        let bytes = nibble_array.to_bytes();
+        assert_eq!(
+            NibbleArray::from_bytes("SkyLight", &bytes).unwrap(),
+            nibble_array
+        );
+    }
+
+    #[test]
+    fn test_nibble_array_invalid_length() {
+        assert!(NibbleArray::from_bytes("SkyLight", &[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_clear_light() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let mut chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+
+        clear_light(&mut chunk_compound_tag).unwrap();
+
+        for section_light in read_light(&chunk_compound_tag).unwrap() {
+            assert!(section_light.block_light.is_none());
+            assert!(section_light.sky_light.is_none());
+        }
+
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+        assert!(!level_compound_tag.get_bool("isLightOn").unwrap());
+    }
+
+    #[test]
+    fn test_relight_block_light_across_chunks() {
+        let mut torch_section = ChunkSection::new(0);
+        torch_section.set_block(15, 5, 8, BlockState::new("minecraft:torch"));
+
+        let mut chunks = HashMap::new();
+        chunks.insert((31, 0), chunk_with_section(&torch_section));
+        chunks.insert((32, 0), chunk_with_section(&ChunkSection::new(0)));
+
+        relight(&mut chunks, false).unwrap();
+
+        let torch_light = read_light(&chunks[&(31, 0)]).unwrap();
+        let neighbour_light = read_light(&chunks[&(32, 0)]).unwrap();
+
+        let torch_block_light = torch_light[0].block_light.as_ref().unwrap();
+        let neighbour_block_light = neighbour_light[0].block_light.as_ref().unwrap();
+
+        assert_eq!(torch_block_light.get(15, 5, 8), 14);
+        assert_eq!(torch_block_light.get(14, 5, 8), 13);
+        // Chunks 31 and 32 belong to different regions.
+        assert_eq!(neighbour_block_light.get(0, 5, 8), 13);
+        assert_eq!(neighbour_block_light.get(1, 5, 8), 12);
+        assert!(torch_light[0].sky_light.is_none());
+    }
+
+    #[test]
+    fn test_relight_sky_light() {
+        let mut section = ChunkSection::new(0);
+        section.set_block(3, 10, 3, BlockState::new("minecraft:stone"));
+
+        let mut chunks = HashMap::new();
+        chunks.insert((0, 0), chunk_with_section(&section));
+
+        relight(&mut chunks, true).unwrap();
+
+        let sections_light = read_light(&chunks[&(0, 0)]).unwrap();
+        let sky_light = sections_light[0].sky_light.as_ref().unwrap();
+
+        assert_eq!(sky_light.get(3, 11, 3), 15);
+        assert_eq!(sky_light.get(3, 10, 3), 0);
+        assert_eq!(sky_light.get(3, 9, 3), 14);
+
+        let level_compound_tag = chunks[&(0, 0)].get_compound_tag("Level").unwrap();
+        assert!(level_compound_tag.get_bool("isLightOn").unwrap());
+    }
+}
@@ -0,0 +1,247 @@
+//! Conversion of legacy McRegion chunks to Anvil.
+//!
+//! Before 1.2 chunks were 128 blocks high and stored blocks in single
+//! arrays ordered by XZY. Anvil splits them into 16 blocks high sections
+//! ordered by YZX. Converted chunks keep numeric block ids, so they can be
+//! upgraded further with [`ChunkUpgrader`](crate::upgrade::ChunkUpgrader).
+use crate::chunk::{ChunkDataError, SECTION_BLOCKS};
+use crate::light::NibbleArray;
+use crate::upgrade::{check_length, nibble};
+use crate::{AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::CompoundTag;
+
+/// Height of McRegion chunk in blocks.
+const MCREGION_CHUNK_HEIGHT: usize = 128;
+/// Amount of blocks in McRegion chunk.
+const MCREGION_CHUNK_BLOCKS: usize = 16 * 16 * MCREGION_CHUNK_HEIGHT;
+
+/// Possible errors while converting McRegion chunks.
+#[derive(Debug)]
+pub enum ConvertError {
+    /// Error while loading chunk from source provider.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while saving chunk to destination provider.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// Chunk data are not readable.
+    ChunkDataError { chunk_data_error: ChunkDataError },
+}
+
+impl From<ChunkLoadError> for ConvertError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        ConvertError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for ConvertError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        ConvertError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<ChunkDataError> for ConvertError {
+    fn from(chunk_data_error: ChunkDataError) -> Self {
+        ConvertError::ChunkDataError { chunk_data_error }
+    }
+}
+
+/// Returns true if chunk stores blocks in McRegion layout.
+pub fn is_mcregion_chunk(chunk_compound_tag: &CompoundTag) -> bool {
+    chunk_compound_tag
+        .get_compound_tag("Level")
+        .and_then(|level_compound_tag| level_compound_tag.get_i8_vec("Blocks"))
+        .map(|blocks| blocks.len() == MCREGION_CHUNK_BLOCKS)
+        .unwrap_or(false)
+}
+
+/// Converts McRegion chunk to Anvil chunk.
+///
+/// Sections without blocks are skipped as the game does. Biomes are marked
+/// as not generated so the game computes them on load.
+pub fn convert_chunk(chunk_compound_tag: &CompoundTag) -> Result<CompoundTag, ChunkDataError> {
+    let level_compound_tag = chunk_compound_tag.get_compound_tag("Level")?;
+
+    let blocks = level_compound_tag.get_i8_vec("Blocks")?;
+    let data = level_compound_tag.get_i8_vec("Data")?;
+    let sky_light = level_compound_tag.get_i8_vec("SkyLight")?;
+    let block_light = level_compound_tag.get_i8_vec("BlockLight")?;
+
+    check_length("Blocks", blocks.len(), MCREGION_CHUNK_BLOCKS)?;
+    check_length("Data", data.len(), MCREGION_CHUNK_BLOCKS / 2)?;
+    check_length("SkyLight", sky_light.len(), MCREGION_CHUNK_BLOCKS / 2)?;
+    check_length("BlockLight", block_light.len(), MCREGION_CHUNK_BLOCKS / 2)?;
+
+    let mut section_tags = Vec::new();
+
+    for section_y in 0..MCREGION_CHUNK_HEIGHT / 16 {
+        let mut section_blocks = vec![0i8; SECTION_BLOCKS];
+        let mut section_data = NibbleArray::new();
+        let mut section_sky_light = NibbleArray::new();
+        let mut section_block_light = NibbleArray::new();
+
+        for (index, section_block) in section_blocks.iter_mut().enumerate() {
+            let x = index & 15;
+            let z = (index >> 4) & 15;
+            let y = section_y * 16 + (index >> 8);
+
+            let mcregion_index = x << 11 | z << 7 | y;
+
+            *section_block = blocks[mcregion_index];
+            section_data.set_index(index, nibble(data, mcregion_index));
+            section_sky_light.set_index(index, nibble(sky_light, mcregion_index));
+            section_block_light.set_index(index, nibble(block_light, mcregion_index));
+        }
+
+        if section_blocks.iter().all(|block| *block == 0) {
+            continue;
+        }
+
+        let mut section_tag = CompoundTag::new();
+        section_tag.insert_i8("Y", section_y as i8);
+        section_tag.insert_i8_vec("Blocks", section_blocks);
+        section_tag.insert_i8_vec("Data", section_data.to_bytes());
+        section_tag.insert_i8_vec("SkyLight", section_sky_light.to_bytes());
+        section_tag.insert_i8_vec("BlockLight", section_block_light.to_bytes());
+
+        section_tags.push(section_tag);
+    }
+
+    let mut anvil_level_compound_tag: CompoundTag = level_compound_tag
+        .iter()
+        .filter(|(name, _)| {
+            !["Blocks", "Data", "SkyLight", "BlockLight", "HeightMap"].contains(&name.as_str())
+        })
+        .map(|(name, tag)| (name.clone(), tag.clone()))
+        .collect();
+
+    if let Ok(height_map) = level_compound_tag.get_i8_vec("HeightMap") {
+        let height_map = height_map
+            .iter()
+            .map(|height| *height as u8 as i32)
+            .collect();
+        anvil_level_compound_tag.insert_i32_vec("HeightMap", height_map);
+    }
+
+    anvil_level_compound_tag.insert_compound_tag_vec("Sections", section_tags);
+    anvil_level_compound_tag.insert_i8_vec("Biomes", vec![-1; 256]);
+
+    let mut anvil_chunk_compound_tag = CompoundTag::new();
+    anvil_chunk_compound_tag.insert_compound_tag("Level", anvil_level_compound_tag);
+
+    Ok(anvil_chunk_compound_tag)
+}
+
+/// Converts all McRegion chunks of source provider and saves them to destination.
+///
+/// Chunks which are already in Anvil layout are copied as is.
+/// Returns coordinates of saved chunks.
+pub fn convert_provider(
+    source_chunk_provider: &AnvilChunkProvider,
+    destination_chunk_provider: &AnvilChunkProvider,
+) -> Result<Vec<(i32, i32)>, ConvertError> {
+    let mut chunk_positions = Vec::new();
+
+    for (chunk_x, chunk_z) in source_chunk_provider.chunk_positions()? {
+        let mut chunk_compound_tag = source_chunk_provider.load_chunk(chunk_x, chunk_z)?;
+
+        if is_mcregion_chunk(&chunk_compound_tag) {
+            chunk_compound_tag = convert_chunk(&chunk_compound_tag)?;
+        }
+
+        destination_chunk_provider.save_chunk(chunk_x, chunk_z, chunk_compound_tag)?;
+        chunk_positions.push((chunk_x, chunk_z));
+    }
+
+    Ok(chunk_positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mcregion::{convert_chunk, convert_provider, is_mcregion_chunk};
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn mcregion_chunk() -> CompoundTag {
+        let mut blocks = vec![0i8; 32768];
+        let mut data = vec![0i8; 16384];
+
+        // Stone at (1, 0, 0) and orange wool at (0, 20, 3).
+        blocks[1 << 11] = 1;
+        blocks[3 << 7 | 20] = 35;
+        data[(3 << 7 | 20) / 2] = 1;
+
+        let mut level_compound_tag = CompoundTag::new();
+        level_compound_tag.insert_i32("xPos", 0);
+        level_compound_tag.insert_i32("zPos", 0);
+        level_compound_tag.insert_bool("TerrainPopulated", true);
+        level_compound_tag.insert_i8_vec("Blocks", blocks);
+        level_compound_tag.insert_i8_vec("Data", data);
+        level_compound_tag.insert_i8_vec("SkyLight", vec![-1; 16384]);
+        level_compound_tag.insert_i8_vec("BlockLight", vec![0; 16384]);
+        level_compound_tag.insert_i8_vec("HeightMap", vec![21; 256]);
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_compound_tag("Level", level_compound_tag);
+
+        chunk_compound_tag
+    }
+
+    #[test]
+    fn test_convert_chunk() {
+        let chunk_compound_tag = mcregion_chunk();
+        assert!(is_mcregion_chunk(&chunk_compound_tag));
+
+        let anvil_chunk_compound_tag = convert_chunk(&chunk_compound_tag).unwrap();
+        assert!(!is_mcregion_chunk(&anvil_chunk_compound_tag));
+
+        let level_compound_tag = anvil_chunk_compound_tag.get_compound_tag("Level").unwrap();
+        let section_tags = level_compound_tag.get_compound_tag_vec("Sections").unwrap();
+
+        assert_eq!(section_tags.len(), 2);
+        assert_eq!(section_tags[0].get_i8("Y").unwrap(), 0);
+        assert_eq!(section_tags[1].get_i8("Y").unwrap(), 1);
+
+        let blocks = section_tags[0].get_i8_vec("Blocks").unwrap();
+        assert_eq!(blocks[1], 1);
+
+        let blocks = section_tags[1].get_i8_vec("Blocks").unwrap();
+        let data = section_tags[1].get_i8_vec("Data").unwrap();
+        let index = 4 << 8 | 3 << 4;
+        assert_eq!(blocks[index], 35);
+        assert_eq!(data[index / 2], 1);
+
+        assert_eq!(section_tags[1].get_i8_vec("SkyLight").unwrap()[0], -1);
+        assert_eq!(level_compound_tag.get_i32_vec("HeightMap").unwrap()[0], 21);
+        assert!(level_compound_tag.get_bool("TerrainPopulated").unwrap());
+    }
+
+    #[test]
+    fn test_convert_provider() {
+        let source_folder = TempDir::new().unwrap();
+        let destination_folder = TempDir::new().unwrap();
+
+        let source_chunk_provider = AnvilChunkProvider::from_path(source_folder.path());
+        source_chunk_provider
+            .save_chunk(33, -1, mcregion_chunk())
+            .unwrap();
+
+        fs::rename(
+            source_folder.path().join("r.1.-1.mca"),
+            source_folder.path().join("r.1.-1.mcr"),
+        )
+        .unwrap();
+
+        assert!(source_chunk_provider.load_chunk(33, -1).is_ok());
+
+        let destination_chunk_provider = AnvilChunkProvider::from_path(destination_folder.path());
+        let chunk_positions =
+            convert_provider(&source_chunk_provider, &destination_chunk_provider).unwrap();
+
+        assert_eq!(chunk_positions, vec![(33, -1)]);
+        assert!(destination_folder.path().join("r.1.-1.mca").exists());
+
+        let chunk_compound_tag = destination_chunk_provider.load_chunk(33, -1).unwrap();
+        assert!(!is_mcregion_chunk(&chunk_compound_tag));
+    }
+}
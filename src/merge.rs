@@ -0,0 +1,535 @@
+//! Merging chunks of two worlds.
+//!
+//! Chunks present in both worlds are resolved by policy, winner is decided
+//! by `region` chunk and entities and points of interest follow it.
+use crate::storage::{ChunkStorage, StorageKind};
+use crate::world::{Dimension, World};
+use crate::{
+    chunk_position, is_anvil_path, AnvilChunkProvider, AnvilRegion, ChunkLoadError, ChunkSaveError,
+};
+use std::fs;
+use std::io::{self, Write};
+
+/// Possible errors while merging.
+#[derive(Debug)]
+pub enum MergeError {
+    /// Error while reading chunk or region.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while writing chunk or region.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+}
+
+impl From<ChunkLoadError> for MergeError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        MergeError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for MergeError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        MergeError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+/// World whose chunk is kept.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MergeSide {
+    Source,
+    Destination,
+}
+
+/// Chunk present in both worlds.
+#[derive(Debug)]
+pub struct MergeConflict {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// Header timestamp of source chunk.
+    pub source_timestamp: u32,
+    /// Header timestamp of destination chunk.
+    pub destination_timestamp: u32,
+}
+
+/// How chunks present in both worlds are resolved.
+pub enum MergePolicy<'a> {
+    /// Chunk with later header timestamp wins, destination wins ties.
+    Newest,
+    /// Source chunk always wins.
+    PreferSource,
+    /// Destination chunk always wins.
+    PreferDestination,
+    /// Callback decides.
+    Callback(&'a dyn Fn(&MergeConflict) -> MergeSide),
+}
+
+impl MergePolicy<'_> {
+    fn resolve(&self, conflict: &MergeConflict) -> MergeSide {
+        match self {
+            MergePolicy::Newest => {
+                if conflict.source_timestamp > conflict.destination_timestamp {
+                    MergeSide::Source
+                } else {
+                    MergeSide::Destination
+                }
+            }
+            MergePolicy::PreferSource => MergeSide::Source,
+            MergePolicy::PreferDestination => MergeSide::Destination,
+            MergePolicy::Callback(callback) => callback(conflict),
+        }
+    }
+}
+
+/// Outcome of merge for single chunk.
+#[derive(Debug, Eq, PartialEq)]
+pub struct MergeEntry {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    /// World whose chunk is in destination after merge.
+    pub winner: MergeSide,
+    /// Whether chunk was present in both worlds.
+    pub conflict: bool,
+}
+
+/// Outcomes of merge for all chunks of dimension.
+#[derive(Debug, Default)]
+pub struct MergeManifest {
+    pub entries: Vec<MergeEntry>,
+}
+
+impl MergeManifest {
+    /// Writes manifest as tab separated lines of chunk coordinates, winner
+    /// and whether there was conflict.
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<(), io::Error> {
+        writeln!(writer, "x\tz\twinner\tconflict")?;
+
+        for entry in &self.entries {
+            let winner = match entry.winner {
+                MergeSide::Source => "source",
+                MergeSide::Destination => "destination",
+            };
+
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                entry.chunk_x, entry.chunk_z, winner, entry.conflict
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Merges source chunks into destination provider.
+///
+/// Copied chunks keep their stored bytes and header timestamp. Regions
+/// stored only as legacy McRegion files in either world are refused.
+pub fn merge_providers(
+    source_provider: &AnvilChunkProvider,
+    destination_provider: &AnvilChunkProvider,
+    policy: &MergePolicy,
+) -> Result<MergeManifest, MergeError> {
+    merge_with_followers(source_provider, destination_provider, policy, &[])
+}
+
+/// Merges source dimension storages into destination ones.
+pub fn merge_storages(
+    source_storage: &ChunkStorage,
+    destination_storage: &ChunkStorage,
+    policy: &MergePolicy,
+) -> Result<MergeManifest, MergeError> {
+    let followers: Vec<_> = [StorageKind::Entities, StorageKind::Poi]
+        .iter()
+        .map(|kind| {
+            (
+                source_storage.provider(*kind),
+                destination_storage.provider(*kind),
+            )
+        })
+        .collect();
+
+    merge_with_followers(
+        source_storage.provider(StorageKind::Region),
+        destination_storage.provider(StorageKind::Region),
+        policy,
+        &followers,
+    )
+}
+
+/// Merges all dimensions of source world into destination world.
+///
+/// World properties of destination are kept.
+pub fn merge_worlds(
+    source_world: &World,
+    destination_world: &World,
+    policy: &MergePolicy,
+) -> Result<Vec<(Dimension, MergeManifest)>, MergeError> {
+    let mut manifests = Vec::new();
+
+    for dimension in source_world.dimensions().map_err(ChunkLoadError::from)? {
+        let manifest = merge_storages(
+            &source_world.storage(&dimension),
+            &destination_world.storage(&dimension),
+            policy,
+        )?;
+
+        manifests.push((dimension, manifest));
+    }
+
+    Ok(manifests)
+}
+
+/// Merges chunks of main providers, follower providers get chunks of the
+/// same side as main ones.
+fn merge_with_followers(
+    source_provider: &AnvilChunkProvider,
+    destination_provider: &AnvilChunkProvider,
+    policy: &MergePolicy,
+    followers: &[(&AnvilChunkProvider, &AnvilChunkProvider)],
+) -> Result<MergeManifest, MergeError> {
+    let mut region_positions = source_provider
+        .region_positions()
+        .map_err(ChunkLoadError::from)?;
+    region_positions.extend(
+        destination_provider
+            .region_positions()
+            .map_err(ChunkLoadError::from)?,
+    );
+    region_positions.sort_unstable();
+    region_positions.dedup();
+
+    let mut manifest = MergeManifest::default();
+
+    for (region_x, region_z) in region_positions {
+        let mut source_region = open_source_region(source_provider, region_x, region_z)?;
+        let mut destination_region =
+            open_destination_region(destination_provider, region_x, region_z)?;
+
+        for region_chunk_z in 0..32 {
+            for region_chunk_x in 0..32 {
+                let source_metadata = source_region
+                    .as_ref()
+                    .map(|region| region.chunk_metadata(region_chunk_x, region_chunk_z))
+                    .filter(|metadata| !metadata.is_empty());
+
+                let destination_metadata = destination_region
+                    .as_ref()
+                    .map(|region| region.chunk_metadata(region_chunk_x, region_chunk_z))
+                    .filter(|metadata| !metadata.is_empty());
+
+                let (chunk_x, chunk_z) =
+                    chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+
+                let (winner, conflict) = match (source_metadata, destination_metadata) {
+                    (None, None) => continue,
+                    (None, Some(_)) => (MergeSide::Destination, false),
+                    (Some(_), None) => (MergeSide::Source, false),
+                    (Some(source_metadata), Some(destination_metadata)) => {
+                        let conflict = MergeConflict {
+                            chunk_x,
+                            chunk_z,
+                            source_timestamp: source_metadata.last_modified_timestamp,
+                            destination_timestamp: destination_metadata.last_modified_timestamp,
+                        };
+
+                        (policy.resolve(&conflict), true)
+                    }
+                };
+
+                if let (MergeSide::Source, Some(source_region), Some(source_metadata)) =
+                    (winner, source_region.as_mut(), source_metadata)
+                {
+                    let timestamp = source_metadata.last_modified_timestamp;
+
+                    let (compression_scheme, compressed_data) =
+                        source_region.read_chunk_bytes(region_chunk_x, region_chunk_z)?;
+
+                    if destination_region.is_none() {
+                        destination_region =
+                            Some(create_region(destination_provider, region_x, region_z)?);
+                    }
+
+                    let destination_region = destination_region.as_mut().unwrap();
+
+                    destination_region.write_chunk_bytes(
+                        region_chunk_x,
+                        region_chunk_z,
+                        compression_scheme,
+                        &compressed_data,
+                    )?;
+                    destination_region
+                        .set_last_modified_timestamp(region_chunk_x, region_chunk_z, timestamp)
+                        .map_err(ChunkSaveError::from)?;
+
+                    for (source_follower, destination_follower) in followers {
+                        copy_follower(source_follower, destination_follower, chunk_x, chunk_z)?;
+                    }
+                }
+
+                manifest.entries.push(MergeEntry {
+                    chunk_x,
+                    chunk_z,
+                    winner,
+                    conflict,
+                });
+            }
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Opens source region for reading.
+///
+/// Legacy McRegion chunks are refused as they cannot be stored in Anvil
+/// destination without conversion.
+fn open_source_region(
+    chunk_provider: &AnvilChunkProvider,
+    region_x: i32,
+    region_z: i32,
+) -> Result<Option<AnvilRegion>, MergeError> {
+    match chunk_provider.existing_region_path(region_x, region_z) {
+        Some(region_path) if !is_anvil_path(&region_path) => {
+            Err(ChunkSaveError::McRegionNotConverted { region_x, region_z }.into())
+        }
+        Some(region_path) => Ok(Some(
+            chunk_provider
+                .open_region(region_path)
+                .map_err(ChunkLoadError::from)?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Opens existing destination region for writing.
+fn open_destination_region(
+    chunk_provider: &AnvilChunkProvider,
+    region_x: i32,
+    region_z: i32,
+) -> Result<Option<AnvilRegion>, ChunkSaveError> {
+    let region_path = chunk_provider.writable_region_path(region_x, region_z)?;
+
+    if !region_path.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(chunk_provider.open_region_for_write(region_path)?))
+}
+
+fn create_region(
+    chunk_provider: &AnvilChunkProvider,
+    region_x: i32,
+    region_z: i32,
+) -> Result<AnvilRegion, ChunkSaveError> {
+    fs::create_dir_all(chunk_provider.folder_path())?;
+
+    let region_path = chunk_provider.writable_region_path(region_x, region_z)?;
+
+    Ok(chunk_provider.open_region_for_write(region_path)?)
+}
+
+/// Replaces destination chunk by source one, deleting it when source has none.
+fn copy_follower(
+    source_provider: &AnvilChunkProvider,
+    destination_provider: &AnvilChunkProvider,
+    chunk_x: i32,
+    chunk_z: i32,
+) -> Result<(), MergeError> {
+    match source_provider.load_chunk_bytes(chunk_x, chunk_z) {
+        Ok((compression_scheme, compressed_data)) => destination_provider.save_chunk_bytes(
+            chunk_x,
+            chunk_z,
+            compression_scheme,
+            &compressed_data,
+        )?,
+        Err(ChunkLoadError::RegionNotFound { .. }) | Err(ChunkLoadError::ChunkNotFound { .. }) => {
+            destination_provider.delete_chunk(chunk_x, chunk_z)?;
+        }
+        Err(chunk_load_error) => return Err(chunk_load_error.into()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{self, load_value};
+    use crate::merge::{
+        merge_providers, merge_worlds, MergeConflict, MergeEntry, MergeError, MergePolicy,
+        MergeSide,
+    };
+    use crate::storage::{EntityChunk, StorageKind};
+    use crate::world::{Dimension, World};
+    use crate::{AnvilChunkProvider, AnvilRegion, ChunkSaveError};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Saves chunk with value equal to header timestamp.
+    fn save_chunk(chunk_provider: &AnvilChunkProvider, chunk_x: i32, chunk_z: i32, timestamp: u32) {
+        fixtures::save_chunk(
+            chunk_provider,
+            chunk_x,
+            chunk_z,
+            timestamp as i32,
+            timestamp,
+        );
+    }
+
+    fn entry(chunk_x: i32, chunk_z: i32, winner: MergeSide, conflict: bool) -> MergeEntry {
+        MergeEntry {
+            chunk_x,
+            chunk_z,
+            winner,
+            conflict,
+        }
+    }
+
+    #[test]
+    fn test_merge_providers() {
+        let source_folder = TempDir::new().unwrap();
+        let source_provider = AnvilChunkProvider::from_path(source_folder.path());
+
+        let destination_folder = TempDir::new().unwrap();
+        let destination_provider = AnvilChunkProvider::from_path(destination_folder.path());
+
+        save_chunk(&source_provider, 0, 0, 200);
+        save_chunk(&source_provider, 1, 0, 100);
+        save_chunk(&source_provider, -1, 0, 100);
+        save_chunk(&destination_provider, 0, 0, 100);
+        save_chunk(&destination_provider, 1, 0, 200);
+        save_chunk(&destination_provider, 2, 0, 100);
+
+        let manifest = merge_providers(
+            &source_provider,
+            &destination_provider,
+            &MergePolicy::Newest,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.entries,
+            vec![
+                entry(-1, 0, MergeSide::Source, false),
+                entry(0, 0, MergeSide::Source, true),
+                entry(1, 0, MergeSide::Destination, true),
+                entry(2, 0, MergeSide::Destination, false),
+            ]
+        );
+
+        assert_eq!(load_value(&destination_provider, -1, 0), 100);
+        assert_eq!(load_value(&destination_provider, 0, 0), 200);
+        assert_eq!(load_value(&destination_provider, 1, 0), 200);
+
+        let region_path = destination_provider.region_path(0, 0);
+        let region = AnvilRegion::open(region_path).unwrap();
+        assert_eq!(region.chunk_metadata(0, 0).last_modified_timestamp, 200);
+
+        let callback = |conflict: &MergeConflict| {
+            if conflict.chunk_x == 1 {
+                MergeSide::Source
+            } else {
+                MergeSide::Destination
+            }
+        };
+
+        merge_providers(
+            &source_provider,
+            &destination_provider,
+            &MergePolicy::Callback(&callback),
+        )
+        .unwrap();
+
+        assert_eq!(load_value(&destination_provider, 1, 0), 100);
+
+        let mut manifest_text = Vec::new();
+        manifest.write(&mut manifest_text).unwrap();
+
+        assert_eq!(
+            String::from_utf8(manifest_text).unwrap(),
+            "x\tz\twinner\tconflict\n\
+             -1\t0\tsource\tfalse\n\
+             0\t0\tsource\ttrue\n\
+             1\t0\tdestination\ttrue\n\
+             2\t0\tdestination\tfalse\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_providers_mcregion_not_converted() {
+        let source_folder = TempDir::new().unwrap();
+        let source_provider = AnvilChunkProvider::from_path(source_folder.path());
+
+        let destination_folder = TempDir::new().unwrap();
+        let destination_provider = AnvilChunkProvider::from_path(destination_folder.path());
+
+        save_chunk(&source_provider, 0, 0, 100);
+        save_chunk(&destination_provider, 0, 0, 200);
+
+        let region_path = destination_provider.region_path(0, 0);
+        fs::rename(&region_path, region_path.with_extension("mcr")).unwrap();
+
+        match merge_providers(
+            &source_provider,
+            &destination_provider,
+            &MergePolicy::PreferSource,
+        ) {
+            Err(MergeError::ChunkSaveError {
+                chunk_save_error:
+                    ChunkSaveError::McRegionNotConverted {
+                        region_x: 0,
+                        region_z: 0,
+                    },
+            }) => {}
+            result => panic!("Expected `McRegionNotConverted` but got `{:?}`", result),
+        }
+
+        assert!(!region_path.exists());
+    }
+
+    #[test]
+    fn test_merge_worlds() {
+        let source_folder = TempDir::new().unwrap();
+        let source_world = World::new(source_folder.path());
+
+        let destination_folder = TempDir::new().unwrap();
+        let destination_world = World::new(destination_folder.path());
+
+        let dimension = Dimension::Nether;
+        let source_storage = source_world.storage(&dimension);
+        let destination_storage = destination_world.storage(&dimension);
+
+        fs::create_dir_all(source_storage.dimension_path()).unwrap();
+        fs::create_dir_all(destination_storage.dimension_path()).unwrap();
+
+        save_chunk(source_storage.provider(StorageKind::Region), 0, 0, 100);
+        save_chunk(destination_storage.provider(StorageKind::Region), 0, 0, 200);
+        save_chunk(destination_storage.provider(StorageKind::Region), 1, 0, 200);
+
+        let entity_chunk = EntityChunk {
+            data_version: 2730,
+            entities: Vec::new(),
+        };
+
+        destination_storage
+            .save_entities(0, 0, &entity_chunk)
+            .unwrap();
+        destination_storage
+            .save_entities(1, 0, &entity_chunk)
+            .unwrap();
+
+        let manifests = merge_worlds(
+            &source_world,
+            &destination_world,
+            &MergePolicy::PreferSource,
+        )
+        .unwrap();
+
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].0, Dimension::Nether);
+
+        assert_eq!(
+            load_value(destination_storage.provider(StorageKind::Region), 0, 0),
+            100
+        );
+        assert!(destination_storage.load_entities(0, 0).unwrap().is_none());
+        assert!(destination_storage.load_entities(1, 0).unwrap().is_some());
+    }
+}
@@ -0,0 +1,320 @@
+//! Instrumentation of chunk loads, saves and region allocation.
+//!
+//! Provider and regions report events to `ChunkMetrics` implementation,
+//! `MetricsRecorder` collects them into counters and histograms.
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Measurements of single chunk load or save.
+#[derive(Clone, Debug)]
+pub struct ChunkIoMetrics {
+    /// Time spent reading and decoding or encoding and writing chunk.
+    pub duration: Duration,
+    /// Length of compressed data.
+    pub compressed_length: u64,
+    /// Length of encoded NBT.
+    pub uncompressed_length: u64,
+}
+
+/// Receiver of instrumentation events.
+///
+/// All methods do nothing by default, so implementations override only
+/// events they are interested in.
+pub trait ChunkMetrics: Send + Sync {
+    /// Chunk was read and decoded by region.
+    fn chunk_loaded(&self, _metrics: &ChunkIoMetrics) {}
+
+    /// Chunk was encoded and written by region.
+    fn chunk_saved(&self, _metrics: &ChunkIoMetrics) {}
+
+    /// Stored chunk bytes were read by region without decoding.
+    fn chunk_bytes_loaded(&self, _duration: Duration, _compressed_length: u64) {}
+
+    /// Already compressed chunk bytes were written by region.
+    fn chunk_bytes_saved(&self, _duration: Duration, _compressed_length: u64) {}
+
+    /// Chunk did not fit its sectors and was moved to other sectors.
+    fn chunk_moved(&self, _old_sectors: u8, _new_sectors: u8) {}
+
+    /// Region file was extended because there was no gap to put chunk.
+    fn region_extended(&self, _sectors: u8) {}
+
+    /// Provider opened region file with extension.
+    fn region_opened(&self, _extension: &str) {}
+
+    /// Cache was looked up for chunk.
+    fn cache_accessed(&self, _hit: bool) {}
+}
+
+/// Histogram with power of two buckets.
+pub struct Histogram {
+    /// Bucket at index counts values which need index bits.
+    buckets: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: (0..=64).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, value: u64) {
+        let index = (u64::BITS - value.leading_zeros()) as usize;
+
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Returns amount of recorded values.
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns sum of recorded values.
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+
+    /// Returns inclusive upper bounds of non-empty buckets with their counts.
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(index, bucket)| {
+                let upper_bound = match index {
+                    64 => u64::MAX,
+                    index => (1 << index) - 1,
+                };
+
+                (upper_bound, bucket.load(Ordering::Relaxed))
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+}
+
+/// Metrics collecting events into counters and histograms.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::metrics::MetricsRecorder;
+/// use anvil_region::AnvilChunkProvider;
+/// use std::sync::Arc;
+///
+/// let recorder = Arc::new(MetricsRecorder::default());
+/// let chunk_provider = AnvilChunkProvider::new("test/region").with_metrics(recorder.clone());
+///
+/// chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// assert_eq!(recorder.load_micros.count(), 1);
+/// assert_eq!(recorder.region_extensions(), vec![("mca".to_owned(), 1)]);
+/// ```
+#[derive(Default)]
+pub struct MetricsRecorder {
+    /// Load durations in microseconds.
+    pub load_micros: Histogram,
+    /// Save durations in microseconds.
+    pub save_micros: Histogram,
+    /// Raw bytes load durations in microseconds.
+    pub bytes_load_micros: Histogram,
+    /// Raw bytes save durations in microseconds.
+    pub bytes_save_micros: Histogram,
+    /// Compressed lengths of loaded and saved chunks, both decoded and raw.
+    pub compressed_lengths: Histogram,
+    /// Uncompressed lengths of loaded and saved chunks.
+    pub uncompressed_lengths: Histogram,
+    /// Amount of chunks moved to other sectors.
+    pub chunk_moves: AtomicU64,
+    /// Amount of sectors added by extending region files.
+    pub extended_sectors: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub cache_misses: AtomicU64,
+    region_extensions: Mutex<HashMap<String, u64>>,
+}
+
+impl MetricsRecorder {
+    /// Returns sorted extensions of opened region files with amount of opens.
+    pub fn region_extensions(&self) -> Vec<(String, u64)> {
+        let region_extensions = self.region_extensions.lock().unwrap();
+        let mut region_extensions: Vec<_> = region_extensions
+            .iter()
+            .map(|(extension, count)| (extension.clone(), *count))
+            .collect();
+
+        region_extensions.sort();
+
+        region_extensions
+    }
+
+    fn record_chunk(&self, micros: &Histogram, metrics: &ChunkIoMetrics) {
+        micros.record(metrics.duration.as_micros() as u64);
+        self.compressed_lengths.record(metrics.compressed_length);
+        self.uncompressed_lengths
+            .record(metrics.uncompressed_length);
+    }
+}
+
+impl ChunkMetrics for MetricsRecorder {
+    fn chunk_loaded(&self, metrics: &ChunkIoMetrics) {
+        self.record_chunk(&self.load_micros, metrics);
+    }
+
+    fn chunk_saved(&self, metrics: &ChunkIoMetrics) {
+        self.record_chunk(&self.save_micros, metrics);
+    }
+
+    fn chunk_bytes_loaded(&self, duration: Duration, compressed_length: u64) {
+        self.bytes_load_micros.record(duration.as_micros() as u64);
+        self.compressed_lengths.record(compressed_length);
+    }
+
+    fn chunk_bytes_saved(&self, duration: Duration, compressed_length: u64) {
+        self.bytes_save_micros.record(duration.as_micros() as u64);
+        self.compressed_lengths.record(compressed_length);
+    }
+
+    fn chunk_moved(&self, _old_sectors: u8, _new_sectors: u8) {
+        self.chunk_moves.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn region_extended(&self, sectors: u8) {
+        self.extended_sectors
+            .fetch_add(sectors as u64, Ordering::Relaxed);
+    }
+
+    fn region_opened(&self, extension: &str) {
+        let mut region_extensions = self.region_extensions.lock().unwrap();
+        *region_extensions.entry(extension.to_owned()).or_insert(0) += 1;
+    }
+
+    fn cache_accessed(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Reader which counts read bytes.
+pub(crate) struct CountingReader<R> {
+    reader: R,
+    pub(crate) count: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        CountingReader { reader, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.reader.read(buf)?;
+        self.count += length as u64;
+
+        Ok(length)
+    }
+}
+
+/// Writer which counts written bytes.
+pub(crate) struct CountingWriter<W> {
+    writer: W,
+    pub(crate) count: u64,
+}
+
+impl<W> CountingWriter<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        CountingWriter { writer, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = self.writer.write(buf)?;
+        self.count += length as u64;
+
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{Histogram, MetricsRecorder};
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::default();
+
+        for value in &[0, 1, 2, 3, 100, u64::MAX] {
+            histogram.record(*value);
+        }
+
+        assert_eq!(histogram.count(), 6);
+        assert_eq!(
+            histogram.buckets(),
+            vec![(0, 1), (1, 1), (3, 2), (127, 1), (u64::MAX, 1)]
+        );
+    }
+
+    #[test]
+    fn test_recorder() {
+        let folder = TempDir::new().unwrap();
+        let recorder = Arc::new(MetricsRecorder::default());
+        let chunk_provider =
+            AnvilChunkProvider::from_path(folder.path()).with_metrics(recorder.clone());
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32_vec("values", vec![0; 10]);
+        chunk_provider
+            .save_chunk(0, 0, chunk_compound_tag.clone())
+            .unwrap();
+        chunk_provider.save_chunk(1, 0, chunk_compound_tag).unwrap();
+
+        // Chunk grows beyond its sector and moves to end of file.
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32_vec("values", (0..4096).collect());
+        chunk_provider.save_chunk(0, 0, chunk_compound_tag).unwrap();
+
+        chunk_provider.load_chunk(0, 0).unwrap();
+
+        let (compression_scheme, compressed_data) = chunk_provider.load_chunk_bytes(1, 0).unwrap();
+        chunk_provider
+            .save_chunk_bytes(2, 0, compression_scheme, &compressed_data)
+            .unwrap();
+
+        assert_eq!(recorder.bytes_load_micros.count(), 1);
+        assert_eq!(recorder.bytes_save_micros.count(), 1);
+        assert_eq!(recorder.compressed_lengths.count(), 6);
+        assert_eq!(recorder.save_micros.count(), 3);
+        assert_eq!(recorder.load_micros.count(), 1);
+        assert_eq!(recorder.uncompressed_lengths.count(), 4);
+        assert!(recorder.uncompressed_lengths.sum() > 2 * 4 * 4096);
+        assert_eq!(recorder.chunk_moves.load(Ordering::Relaxed), 1);
+        assert_eq!(recorder.extended_sectors.load(Ordering::Relaxed), 4);
+        assert_eq!(recorder.region_extensions(), vec![("mca".to_owned(), 6)]);
+    }
+}
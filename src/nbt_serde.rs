@@ -0,0 +1,1007 @@
+//! Serde serializer and deserializer for NBT.
+//!
+//! Numbers and strings are stored in tags of the same width, booleans are
+//! bytes and unsigned integers are stored in signed tags of the same width.
+//! Sequences of bytes, ints or longs become byte, int or long arrays, other
+//! sequences become lists, so all elements must map to the same tag type.
+//! Empty sequence has no elements to choose array type by and becomes empty
+//! list, so arrays which may be empty are wrapped into `ByteArray`,
+//! `IntArray` or `LongArray`. Fields with `None` value are omitted.
+//!
+//! # Example
+//!
+//! ```
+//! use anvil_region::nbt_serde::{from_compound_tag, to_compound_tag};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Heightmaps {
+//!     #[serde(rename = "WORLD_SURFACE")]
+//!     world_surface: Vec<i64>,
+//! }
+//!
+//! let heightmaps = Heightmaps {
+//!     world_surface: vec![0; 37],
+//! };
+//!
+//! let compound_tag = to_compound_tag(&heightmaps).unwrap();
+//! assert_eq!(compound_tag.get_i64_vec("WORLD_SURFACE").unwrap().len(), 37);
+//!
+//! let heightmaps: Heightmaps = from_compound_tag(compound_tag).unwrap();
+//! assert_eq!(heightmaps.world_surface.len(), 37);
+//! ```
+use crate::{ChunkLoadError, ChunkSaveError};
+use nbt::{CompoundTag, Tag};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize, Serializer};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::mem;
+
+/// Possible errors while mapping NBT to types and back.
+#[derive(Debug)]
+pub enum SerdeError {
+    ChunkLoadError {
+        chunk_load_error: ChunkLoadError,
+    },
+    ChunkSaveError {
+        chunk_save_error: ChunkSaveError,
+    },
+    /// Error reported by serialized or deserialized type.
+    Custom {
+        message: String,
+    },
+    /// Top level value is not a struct or map.
+    ExpectedCompound,
+    /// Map key is not a string.
+    KeyMustBeString,
+    /// Sequence element or enum variant content serializes to nothing,
+    /// for example `None`.
+    MissingValue,
+    /// Sequence elements have different tag types.
+    MixedList,
+}
+
+impl From<ChunkLoadError> for SerdeError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        SerdeError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for SerdeError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        SerdeError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+// Serde requires errors to be displayable.
+impl fmt::Display for SerdeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerdeError::Custom { message } => formatter.write_str(message),
+            serde_error => write!(formatter, "{:?}", serde_error),
+        }
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SerdeError::Custom {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SerdeError::Custom {
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Serializes value which must be a struct or map into compound tag.
+pub fn to_compound_tag<T: Serialize + ?Sized>(value: &T) -> Result<CompoundTag, SerdeError> {
+    match value.serialize(TagSerializer)? {
+        Some(Tag::Compound(compound_tag)) => Ok(compound_tag),
+        _ => Err(SerdeError::ExpectedCompound),
+    }
+}
+
+/// Deserializes value from compound tag.
+pub fn from_compound_tag<T: DeserializeOwned>(compound_tag: CompoundTag) -> Result<T, SerdeError> {
+    T::deserialize(TagDeserializer(Tag::Compound(compound_tag)))
+}
+
+/// Newtype names by which serializer recognizes array wrappers.
+const BYTE_ARRAY_NAME: &str = "$anvil_region::ByteArray";
+const INT_ARRAY_NAME: &str = "$anvil_region::IntArray";
+const LONG_ARRAY_NAME: &str = "$anvil_region::LongArray";
+
+/// Bytes which are stored as byte array even when empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ByteArray(pub Vec<i8>);
+
+/// Ints which are stored as int array even when empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IntArray(pub Vec<i32>);
+
+/// Longs which are stored as long array even when empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LongArray(pub Vec<i64>);
+
+impl Serialize for ByteArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(BYTE_ARRAY_NAME, &self.0)
+    }
+}
+
+impl Serialize for IntArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(INT_ARRAY_NAME, &self.0)
+    }
+}
+
+impl Serialize for LongArray {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(LONG_ARRAY_NAME, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(ByteArray)
+    }
+}
+
+impl<'de> Deserialize<'de> for IntArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(IntArray)
+    }
+}
+
+impl<'de> Deserialize<'de> for LongArray {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(LongArray)
+    }
+}
+
+/// Wraps tag into compound with variant name as the only key.
+fn variant_tag(variant: &'static str, tag: Tag) -> Tag {
+    let mut compound_tag = CompoundTag::new();
+    compound_tag.insert(variant, tag);
+
+    Tag::Compound(compound_tag)
+}
+
+/// Returns array tag when all elements are bytes, ints or longs and list otherwise.
+fn sequence_tag(tags: Vec<Tag>) -> Result<Tag, SerdeError> {
+    let first = match tags.first() {
+        Some(first) => mem::discriminant(first),
+        None => return Ok(Tag::List(tags)),
+    };
+
+    if tags.iter().any(|tag| mem::discriminant(tag) != first) {
+        return Err(SerdeError::MixedList);
+    }
+
+    let tag = match tags[0] {
+        Tag::Byte(_) => Tag::ByteArray(
+            tags.into_iter()
+                .filter_map(|tag| match tag {
+                    Tag::Byte(value) => Some(value),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        Tag::Int(_) => Tag::IntArray(
+            tags.into_iter()
+                .filter_map(|tag| match tag {
+                    Tag::Int(value) => Some(value),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        Tag::Long(_) => Tag::LongArray(
+            tags.into_iter()
+                .filter_map(|tag| match tag {
+                    Tag::Long(value) => Some(value),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => Tag::List(tags),
+    };
+
+    Ok(tag)
+}
+
+/// Serializes value into tag, values without content such as `None` give no tag.
+struct TagSerializer;
+
+impl ser::Serializer for TagSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+    type SerializeSeq = SequenceSerializer;
+    type SerializeTuple = SequenceSerializer;
+    type SerializeTupleStruct = SequenceSerializer;
+    type SerializeTupleVariant = SequenceSerializer;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = CompoundSerializer;
+
+    fn serialize_bool(self, value: bool) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Byte(value as i8)))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Byte(value)))
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Short(value)))
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Int(value)))
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Long(value)))
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Byte(value as i8)))
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Short(value as i16)))
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Int(value as i32)))
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Long(value as i64)))
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Float(value)))
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Double(value)))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::String(value.to_string())))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::String(value.to_owned())))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::ByteArray(
+            value.iter().map(|byte| *byte as i8).collect(),
+        )))
+    }
+
+    fn serialize_none(self) -> Result<Option<Tag>, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<Tag>, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Tag>, SerdeError> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::Compound(CompoundTag::new())))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<Tag>, SerdeError> {
+        Ok(Some(Tag::String(variant.to_owned())))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Option<Tag>, SerdeError> {
+        let tag = match (name, value.serialize(self)?) {
+            (BYTE_ARRAY_NAME, Some(Tag::List(tags))) if tags.is_empty() => {
+                Tag::ByteArray(Vec::new())
+            }
+            (INT_ARRAY_NAME, Some(Tag::List(tags))) if tags.is_empty() => Tag::IntArray(Vec::new()),
+            (LONG_ARRAY_NAME, Some(Tag::List(tags))) if tags.is_empty() => {
+                Tag::LongArray(Vec::new())
+            }
+            (_, tag) => return Ok(tag),
+        };
+
+        Ok(Some(tag))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Option<Tag>, SerdeError> {
+        let tag = value.serialize(self)?.ok_or(SerdeError::MissingValue)?;
+
+        Ok(Some(variant_tag(variant, tag)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SequenceSerializer, SerdeError> {
+        Ok(SequenceSerializer {
+            tags: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SequenceSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SequenceSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SequenceSerializer, SerdeError> {
+        Ok(SequenceSerializer {
+            tags: Vec::with_capacity(len),
+            variant: Some(variant),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<CompoundSerializer, SerdeError> {
+        Ok(CompoundSerializer {
+            compound_tag: CompoundTag::new(),
+            key: None,
+            variant: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<CompoundSerializer, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<CompoundSerializer, SerdeError> {
+        Ok(CompoundSerializer {
+            compound_tag: CompoundTag::new(),
+            key: None,
+            variant: Some(variant),
+        })
+    }
+}
+
+struct SequenceSerializer {
+    tags: Vec<Tag>,
+    /// Enum variant name of tuple variant.
+    variant: Option<&'static str>,
+}
+
+impl SequenceSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let tag = value
+            .serialize(TagSerializer)?
+            .ok_or(SerdeError::MissingValue)?;
+
+        self.tags.push(tag);
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Tag>, SerdeError> {
+        let tag = sequence_tag(self.tags)?;
+
+        match self.variant {
+            Some(variant) => Ok(Some(variant_tag(variant, tag))),
+            None => Ok(Some(tag)),
+        }
+    }
+}
+
+impl ser::SerializeSeq for SequenceSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SequenceSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SequenceSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SequenceSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+struct CompoundSerializer {
+    compound_tag: CompoundTag,
+    /// Map key waiting for its value.
+    key: Option<String>,
+    /// Enum variant name of struct variant.
+    variant: Option<&'static str>,
+}
+
+impl CompoundSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> Result<(), SerdeError> {
+        if let Some(tag) = value.serialize(TagSerializer)? {
+            self.compound_tag.insert(name, tag);
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Tag>, SerdeError> {
+        let tag = Tag::Compound(self.compound_tag);
+
+        match self.variant {
+            Some(variant) => Ok(Some(variant_tag(variant, tag))),
+            None => Ok(Some(tag)),
+        }
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        match key.serialize(TagSerializer)? {
+            Some(Tag::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(SerdeError::KeyMustBeString),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self.key.take().ok_or(SerdeError::KeyMustBeString)?;
+
+        self.insert(&key, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Option<Tag>, SerdeError> {
+        self.finish()
+    }
+}
+
+/// Deserializes value from owned tag.
+struct TagDeserializer(Tag);
+
+impl<'de> de::Deserializer<'de> for TagDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_i8(value),
+            Tag::Short(value) => visitor.visit_i16(value),
+            Tag::Int(value) => visitor.visit_i32(value),
+            Tag::Long(value) => visitor.visit_i64(value),
+            Tag::Float(value) => visitor.visit_f32(value),
+            Tag::Double(value) => visitor.visit_f64(value),
+            Tag::String(value) => visitor.visit_string(value),
+            Tag::ByteArray(values) => {
+                visitor.visit_seq(SequenceDeserializer(values.into_iter().map(Tag::Byte)))
+            }
+            Tag::IntArray(values) => {
+                visitor.visit_seq(SequenceDeserializer(values.into_iter().map(Tag::Int)))
+            }
+            Tag::LongArray(values) => {
+                visitor.visit_seq(SequenceDeserializer(values.into_iter().map(Tag::Long)))
+            }
+            Tag::List(tags) => visitor.visit_seq(SequenceDeserializer(tags.into_iter())),
+            Tag::Compound(compound_tag) => visitor.visit_map(CompoundDeserializer {
+                tags: compound_tag.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_bool(value != 0),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Byte(value) => visitor.visit_u8(value as u8),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Short(value) => visitor.visit_u16(value as u16),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Int(value) => visitor.visit_u32(value as u32),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::Long(value) => visitor.visit_u64(value as u64),
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::ByteArray(values) => {
+                visitor.visit_byte_buf(values.into_iter().map(|value| value as u8).collect())
+            }
+            tag => TagDeserializer(tag).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Tag::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Tag::Compound(compound_tag) => {
+                let mut tags = compound_tag.into_iter();
+
+                match (tags.next(), tags.next()) {
+                    (Some((variant, tag)), None) => {
+                        visitor.visit_enum(EnumDeserializer { variant, tag })
+                    }
+                    _ => Err(de::Error::invalid_value(
+                        Unexpected::Map,
+                        &"compound with single tag",
+                    )),
+                }
+            }
+            _ => Err(de::Error::invalid_type(
+                Unexpected::Other("tag"),
+                &"string or compound",
+            )),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string
+        seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct SequenceDeserializer<I>(I);
+
+impl<'de, I: ExactSizeIterator<Item = Tag>> SeqAccess<'de> for SequenceDeserializer<I> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        match self.0.next() {
+            Some(tag) => seed.deserialize(TagDeserializer(tag)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct CompoundDeserializer {
+    tags: <CompoundTag as IntoIterator>::IntoIter,
+    /// Tag of last returned key.
+    value: Option<Tag>,
+}
+
+impl<'de> MapAccess<'de> for CompoundDeserializer {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.tags.next() {
+            Some((name, tag)) => {
+                self.value = Some(tag);
+                seed.deserialize(name.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let tag = self
+            .value
+            .take()
+            .ok_or_else(|| <SerdeError as de::Error>::custom("value requested before key"))?;
+
+        seed.deserialize(TagDeserializer(tag))
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    tag: Tag,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = SerdeError;
+    type Variant = TagDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, TagDeserializer), SerdeError> {
+        let variant_deserializer: de::value::StringDeserializer<SerdeError> =
+            self.variant.into_deserializer();
+        let variant = seed.deserialize(variant_deserializer)?;
+
+        Ok((variant, TagDeserializer(self.tag)))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TagDeserializer {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nbt_serde::{
+        from_compound_tag, to_compound_tag, ByteArray, IntArray, LongArray, SerdeError,
+    };
+    use crate::AnvilChunkProvider;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Point,
+        Circle(f32),
+        Line(i32, i32),
+        Rectangle { width: u16, height: u16 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Values {
+        flag: bool,
+        byte: i8,
+        unsigned: u32,
+        long: i64,
+        name: String,
+        missing: Option<i32>,
+        bytes: Vec<i8>,
+        longs: Vec<i64>,
+        empty_bytes: ByteArray,
+        empty_ints: IntArray,
+        empty_longs: LongArray,
+        floats: Vec<f32>,
+        position: (f64, f64, f64),
+        shape: Shape,
+        shapes: Vec<Shape>,
+        properties: BTreeMap<String, String>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Chunk {
+        #[serde(rename = "DataVersion")]
+        data_version: i32,
+        #[serde(rename = "Level")]
+        level: Level,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Level {
+        #[serde(rename = "xPos")]
+        x_pos: i32,
+        #[serde(rename = "zPos")]
+        z_pos: i32,
+        inhabited_time: i64,
+        status: String,
+        sections: Vec<Section>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Section {
+        y: i8,
+        block_states: Option<Vec<i64>>,
+        block_light: Option<Vec<i8>>,
+    }
+
+    #[test]
+    fn test_values_round_trip() {
+        let mut properties = BTreeMap::new();
+        properties.insert("facing".to_owned(), "north".to_owned());
+
+        let values = Values {
+            flag: true,
+            byte: -3,
+            unsigned: u32::MAX,
+            long: 1 << 40,
+            name: "stone".to_owned(),
+            missing: None,
+            bytes: vec![1, 2],
+            longs: vec![3],
+            empty_bytes: ByteArray::default(),
+            empty_ints: IntArray::default(),
+            empty_longs: LongArray::default(),
+            floats: vec![0.5],
+            position: (1.0, 2.0, 3.0),
+            shape: Shape::Point,
+            shapes: vec![
+                Shape::Circle(1.5),
+                Shape::Line(1, 2),
+                Shape::Rectangle {
+                    width: 4,
+                    height: 5,
+                },
+            ],
+            properties,
+        };
+
+        let compound_tag = to_compound_tag(&values).unwrap();
+
+        assert_eq!(compound_tag.get_i8("flag").unwrap(), 1);
+        assert_eq!(compound_tag.get_str("shape").unwrap(), "Point");
+        assert_eq!(compound_tag.get_i32("unsigned").unwrap(), -1);
+        assert!(compound_tag.get_i32("missing").is_err());
+        assert_eq!(compound_tag.get_i8_vec("bytes").unwrap(), &vec![1, 2]);
+        assert_eq!(compound_tag.get_i64_vec("longs").unwrap(), &vec![3]);
+        assert!(compound_tag.get_i8_vec("empty_bytes").unwrap().is_empty());
+        assert!(compound_tag.get_i32_vec("empty_ints").unwrap().is_empty());
+        assert!(compound_tag.get_i64_vec("empty_longs").unwrap().is_empty());
+        assert_eq!(compound_tag.get_f32_vec("floats").unwrap(), vec![0.5]);
+        assert_eq!(
+            compound_tag.get_f64_vec("position").unwrap(),
+            vec![1.0, 2.0, 3.0]
+        );
+
+        assert_eq!(from_compound_tag::<Values>(compound_tag).unwrap(), values);
+    }
+
+    #[test]
+    fn test_serialize_errors() {
+        match to_compound_tag(&5) {
+            Err(SerdeError::ExpectedCompound) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert(1, 2);
+
+        match to_compound_tag(&map) {
+            Err(SerdeError::KeyMustBeString) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        let mut map = BTreeMap::new();
+        map.insert("values", vec![None, Some(1)]);
+
+        match to_compound_tag(&map) {
+            Err(SerdeError::MissingValue) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let chunk: Chunk = chunk_provider.load_chunk_as(4, 2).unwrap();
+
+        assert_eq!(chunk.data_version, 1631);
+        assert_eq!(chunk.level.x_pos, 4);
+        assert_eq!(chunk.level.z_pos, 2);
+        assert!(!chunk.level.sections.is_empty());
+
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        chunk_provider.save_chunk_from(4, 2, &chunk).unwrap();
+
+        let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+        let section_compound_tags = level_compound_tag.get_compound_tag_vec("Sections").unwrap();
+
+        let block_states_count = section_compound_tags
+            .iter()
+            .filter(|section| section.get_i64_vec("BlockStates").is_ok())
+            .count();
+
+        assert_eq!(
+            level_compound_tag.get_str("Status").unwrap(),
+            chunk.level.status
+        );
+        assert_eq!(
+            block_states_count,
+            chunk
+                .level
+                .sections
+                .iter()
+                .filter(|section| section.block_states.is_some())
+                .count()
+        );
+    }
+}
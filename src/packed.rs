@@ -0,0 +1,135 @@
+//! Packed long arrays used by block states and heightmaps.
+//!
+//! Before 20w17a values were packed tightly and could span two longs.
+//! Since then every long holds a whole number of values and the unused
+//! high bits are left zeroed.
+
+/// Returns amount of longs required to pack values.
+pub(crate) fn packed_length(bits_per_value: usize, values_length: usize, spanning: bool) -> usize {
+    if spanning {
+        (values_length * bits_per_value).div_ceil(64)
+    } else {
+        let values_per_long = 64 / bits_per_value;
+
+        values_length.div_ceil(values_per_long)
+    }
+}
+
+/// Returns minimum amount of bits required to store values up to `maximum_value`.
+pub(crate) fn bits_required(maximum_value: usize) -> usize {
+    let mut bits = 0;
+
+    while (1 << bits) <= maximum_value {
+        bits += 1;
+    }
+
+    bits.max(1)
+}
+
+/// Unpacks `values_length` values of `bits_per_value` width.
+///
+/// Caller must check that `longs` length matches `packed_length`.
+pub(crate) fn unpack(
+    longs: &[i64],
+    bits_per_value: usize,
+    values_length: usize,
+    spanning: bool,
+) -> Vec<u16> {
+    let mask = (1u64 << bits_per_value) - 1;
+    let mut values = Vec::with_capacity(values_length);
+
+    if spanning {
+        for index in 0..values_length {
+            let bit_index = index * bits_per_value;
+            let long_index = bit_index / 64;
+            let bit_offset = bit_index % 64;
+
+            let mut value = longs[long_index] as u64 >> bit_offset;
+
+            // Value continues in the next long.
+            if bit_offset + bits_per_value > 64 {
+                value |= (longs[long_index + 1] as u64) << (64 - bit_offset);
+            }
+
+            values.push((value & mask) as u16);
+        }
+    } else {
+        let values_per_long = 64 / bits_per_value;
+
+        for index in 0..values_length {
+            let long_index = index / values_per_long;
+            let bit_offset = (index % values_per_long) * bits_per_value;
+            let value = longs[long_index] as u64 >> bit_offset;
+
+            values.push((value & mask) as u16);
+        }
+    }
+
+    values
+}
+
+/// Packs values of `bits_per_value` width into longs.
+pub(crate) fn pack(values: &[u16], bits_per_value: usize, spanning: bool) -> Vec<i64> {
+    let mask = (1u64 << bits_per_value) - 1;
+    let mut longs = vec![0u64; packed_length(bits_per_value, values.len(), spanning)];
+
+    if spanning {
+        for (index, value) in values.iter().enumerate() {
+            let value = *value as u64 & mask;
+            let bit_index = index * bits_per_value;
+            let long_index = bit_index / 64;
+            let bit_offset = bit_index % 64;
+
+            longs[long_index] |= value << bit_offset;
+
+            if bit_offset + bits_per_value > 64 {
+                longs[long_index + 1] |= value >> (64 - bit_offset);
+            }
+        }
+    } else {
+        let values_per_long = 64 / bits_per_value;
+
+        for (index, value) in values.iter().enumerate() {
+            let value = *value as u64 & mask;
+            let long_index = index / values_per_long;
+            let bit_offset = (index % values_per_long) * bits_per_value;
+
+            longs[long_index] |= value << bit_offset;
+        }
+    }
+
+    longs.into_iter().map(|long| long as i64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::packed::{bits_required, pack, packed_length, unpack};
+
+    #[test]
+    fn test_packed_length() {
+        assert_eq!(packed_length(9, 256, true), 36);
+        assert_eq!(packed_length(9, 256, false), 37);
+        assert_eq!(packed_length(4, 4096, false), 256);
+    }
+
+    #[test]
+    fn test_bits_required() {
+        assert_eq!(bits_required(0), 1);
+        assert_eq!(bits_required(1), 1);
+        assert_eq!(bits_required(15), 4);
+        assert_eq!(bits_required(16), 5);
+        assert_eq!(bits_required(256), 9);
+    }
+
+    #[test]
+    fn test_pack_unpack_round_trip() {
+        let values: Vec<u16> = (0..256).map(|value| (value * 7 % 300) as u16).collect();
+
+        for spanning in [true, false].iter() {
+            let longs = pack(&values, 9, *spanning);
+
+            assert_eq!(longs.len(), packed_length(9, values.len(), *spanning));
+            assert_eq!(unpack(&longs, 9, values.len(), *spanning), values);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+//! Deleting chunks which are not worth keeping.
+//!
+//! Typical use is removing chunks players only passed through, which have
+//! low `InhabitedTime`, or chunks beyond world border.
+use crate::version::ChunkVersion;
+use crate::{chunk_position, is_anvil_path, AnvilChunkProvider, AnvilRegion};
+use nbt::CompoundTag;
+use std::io;
+
+/// Built-in condition of chunk removal.
+#[derive(Clone, Debug)]
+pub enum PruneCriterion {
+    /// Chunk `InhabitedTime` is lower than ticks.
+    InhabitedTimeBelow { ticks: i64 },
+    /// Chunk generation status is not `full`.
+    StatusNotFull,
+    /// Chunk was last modified before unix timestamp in seconds.
+    ModifiedBefore { timestamp: u32 },
+    /// Chunk lies outside of inclusive rectangle in chunk coordinates.
+    OutsideRectangle {
+        min_chunk_x: i32,
+        min_chunk_z: i32,
+        max_chunk_x: i32,
+        max_chunk_z: i32,
+    },
+    /// Chunk lies further than radius in chunks from center chunk.
+    OutsideRadius {
+        center_chunk_x: i32,
+        center_chunk_z: i32,
+        radius: u32,
+    },
+}
+
+impl PruneCriterion {
+    /// Returns whether chunk matches by its position and timestamp.
+    ///
+    /// Returns None if criterion requires chunk data.
+    fn matches_metadata(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        last_modified_timestamp: u32,
+    ) -> Option<bool> {
+        match *self {
+            PruneCriterion::InhabitedTimeBelow { .. } | PruneCriterion::StatusNotFull => None,
+            PruneCriterion::ModifiedBefore { timestamp } => {
+                Some(last_modified_timestamp < timestamp)
+            }
+            PruneCriterion::OutsideRectangle {
+                min_chunk_x,
+                min_chunk_z,
+                max_chunk_x,
+                max_chunk_z,
+            } => Some(
+                chunk_x < min_chunk_x
+                    || chunk_x > max_chunk_x
+                    || chunk_z < min_chunk_z
+                    || chunk_z > max_chunk_z,
+            ),
+            PruneCriterion::OutsideRadius {
+                center_chunk_x,
+                center_chunk_z,
+                radius,
+            } => {
+                let delta_x = chunk_x as i64 - center_chunk_x as i64;
+                let delta_z = chunk_z as i64 - center_chunk_z as i64;
+
+                Some(delta_x * delta_x + delta_z * delta_z > radius as i64 * radius as i64)
+            }
+        }
+    }
+
+    /// Returns whether chunk data matches criterion.
+    ///
+    /// Chunks without required tags never match to not lose them by mistake.
+    fn matches_data(&self, chunk_compound_tag: &CompoundTag) -> bool {
+        let level_compound_tag =
+            match ChunkVersion::detect(chunk_compound_tag).level(chunk_compound_tag) {
+                Ok(level_compound_tag) => level_compound_tag,
+                Err(_) => return false,
+            };
+
+        match *self {
+            PruneCriterion::InhabitedTimeBelow { ticks } => {
+                match level_compound_tag.get_i64("InhabitedTime") {
+                    Ok(inhabited_time) => inhabited_time < ticks,
+                    Err(_) => false,
+                }
+            }
+            PruneCriterion::StatusNotFull => match level_compound_tag.get_str("Status") {
+                Ok(status) => status != "full" && status != "minecraft:full",
+                Err(_) => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+/// Custom condition of chunk removal.
+type PrunePredicate = Box<dyn Fn(&PruneChunk) -> bool>;
+
+/// Chunk passed to custom prune predicate.
+pub struct PruneChunk<'a> {
+    pub chunk_x: i32,
+    pub chunk_z: i32,
+    pub last_modified_timestamp: u32,
+    pub chunk_compound_tag: &'a CompoundTag,
+}
+
+/// Result of prune.
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    /// Amount of chunks checked.
+    pub scanned_chunks: usize,
+    /// Chunks which matched criteria, deleted unless it was dry run.
+    pub pruned_chunks: Vec<(i32, i32)>,
+    /// Chunks which could not be loaded to check, they are kept.
+    pub unreadable_chunks: Vec<(i32, i32)>,
+    /// Sectors released by compaction of regions with pruned chunks.
+    pub released_sectors: u32,
+    /// Legacy McRegion regions, which are not pruned until converted.
+    pub skipped_regions: Vec<(i32, i32)>,
+}
+
+/// Deletes chunks matching all criteria and predicates.
+///
+/// Pruner without criteria and predicates matches no chunks.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::prune::{ChunkPruner, PruneCriterion};
+/// use anvil_region::AnvilChunkProvider;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+///
+/// let report = ChunkPruner::new()
+///     .with_criterion(PruneCriterion::InhabitedTimeBelow { ticks: 20 * 60 })
+///     .with_dry_run(true)
+///     .prune(&chunk_provider)
+///     .unwrap();
+///
+/// assert!(report.scanned_chunks > 0);
+/// ```
+#[derive(Default)]
+pub struct ChunkPruner {
+    criteria: Vec<PruneCriterion>,
+    predicates: Vec<PrunePredicate>,
+    dry_run: bool,
+    compact: bool,
+}
+
+impl ChunkPruner {
+    /// Creates pruner which matches no chunks until criteria are added.
+    pub fn new() -> Self {
+        ChunkPruner::default()
+    }
+
+    /// Adds built-in criterion chunk must match to be deleted.
+    pub fn with_criterion(mut self, criterion: PruneCriterion) -> Self {
+        self.criteria.push(criterion);
+        self
+    }
+
+    /// Adds custom predicate chunk must match to be deleted.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&PruneChunk) -> bool + 'static,
+    {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// Only reports matching chunks without deleting them.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Compacts regions from which chunks were deleted.
+    pub fn with_compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+
+    /// Prunes all regions of provider.
+    pub fn prune(&self, chunk_provider: &AnvilChunkProvider) -> Result<PruneReport, io::Error> {
+        let mut report = PruneReport::default();
+
+        for (region_x, region_z) in chunk_provider.region_positions()? {
+            let region_path = match chunk_provider.existing_region_path(region_x, region_z) {
+                Some(region_path) if !is_anvil_path(&region_path) => {
+                    report.skipped_regions.push((region_x, region_z));
+                    continue;
+                }
+                Some(region_path) => region_path,
+                None => continue,
+            };
+
+            let mut region = if self.dry_run {
+                chunk_provider.open_region(region_path)?
+            } else {
+                chunk_provider.open_region_for_write(region_path)?
+            };
+            self.prune_region(&mut region, region_x, region_z, &mut report)?;
+        }
+
+        Ok(report)
+    }
+
+    fn prune_region(
+        &self,
+        region: &mut AnvilRegion,
+        region_x: i32,
+        region_z: i32,
+        report: &mut PruneReport,
+    ) -> Result<(), io::Error> {
+        let mut pruned = false;
+
+        for (region_chunk_x, region_chunk_z, metadata) in region.chunks() {
+            let (chunk_x, chunk_z) =
+                chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+
+            report.scanned_chunks += 1;
+
+            // Empty pruner would otherwise delete whole world.
+            if self.criteria.is_empty() && self.predicates.is_empty() {
+                continue;
+            }
+
+            let mut requires_data = !self.predicates.is_empty();
+            let mut matches = true;
+
+            for criterion in &self.criteria {
+                match criterion.matches_metadata(chunk_x, chunk_z, metadata.last_modified_timestamp)
+                {
+                    Some(criterion_matches) => matches &= criterion_matches,
+                    None => requires_data = true,
+                }
+            }
+
+            if !matches {
+                continue;
+            }
+
+            if requires_data {
+                let chunk_compound_tag = match region.read_chunk(region_chunk_x, region_chunk_z) {
+                    Ok(chunk_compound_tag) => chunk_compound_tag,
+                    Err(_) => {
+                        report.unreadable_chunks.push((chunk_x, chunk_z));
+                        continue;
+                    }
+                };
+
+                let chunk = PruneChunk {
+                    chunk_x,
+                    chunk_z,
+                    last_modified_timestamp: metadata.last_modified_timestamp,
+                    chunk_compound_tag: &chunk_compound_tag,
+                };
+
+                let matches = self
+                    .criteria
+                    .iter()
+                    .all(|criterion| criterion.matches_data(&chunk_compound_tag))
+                    && self.predicates.iter().all(|predicate| predicate(&chunk));
+
+                if !matches {
+                    continue;
+                }
+            }
+
+            if !self.dry_run {
+                region.delete_chunk(region_chunk_x, region_chunk_z)?;
+                pruned = true;
+            }
+
+            report.pruned_chunks.push((chunk_x, chunk_z));
+        }
+
+        if pruned && self.compact {
+            report.released_sectors += region.compact()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prune::{ChunkPruner, PruneCriterion};
+    use crate::AnvilChunkProvider;
+    use nbt::CompoundTag;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn save_chunks(chunk_provider: &AnvilChunkProvider) {
+        for chunk_x in -2..2 {
+            for chunk_z in -2..2 {
+                let mut level_compound_tag = CompoundTag::new();
+                level_compound_tag.insert_i32("xPos", chunk_x);
+                level_compound_tag.insert_i32("zPos", chunk_z);
+                level_compound_tag.insert_i64("InhabitedTime", (chunk_x * 100) as i64);
+                level_compound_tag
+                    .insert_str("Status", if chunk_z < 0 { "full" } else { "features" });
+
+                let mut chunk_compound_tag = CompoundTag::new();
+                chunk_compound_tag.insert_i32("DataVersion", 1631);
+                chunk_compound_tag.insert_compound_tag("Level", level_compound_tag);
+
+                chunk_provider
+                    .save_chunk(chunk_x, chunk_z, chunk_compound_tag)
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_empty() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        save_chunks(&chunk_provider);
+
+        let report = ChunkPruner::new().prune(&chunk_provider).unwrap();
+
+        assert_eq!(report.scanned_chunks, 16);
+        assert!(report.pruned_chunks.is_empty());
+        assert_eq!(chunk_provider.chunk_positions().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_outside_radius_far_chunk() {
+        let criterion = PruneCriterion::OutsideRadius {
+            center_chunk_x: -1_200_000_000,
+            center_chunk_z: 0,
+            radius: 1_000_000,
+        };
+
+        // Difference of coordinates does not fit into i32.
+        assert_eq!(criterion.matches_metadata(1_200_000_000, 0, 0), Some(true));
+        assert_eq!(
+            criterion.matches_metadata(-1_199_500_000, 0, 0),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn test_prune_criteria() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        save_chunks(&chunk_provider);
+
+        let report = ChunkPruner::new()
+            .with_criterion(PruneCriterion::InhabitedTimeBelow { ticks: 50 })
+            .with_criterion(PruneCriterion::StatusNotFull)
+            .with_dry_run(true)
+            .prune(&chunk_provider)
+            .unwrap();
+
+        assert_eq!(report.scanned_chunks, 16);
+        assert_eq!(
+            report.pruned_chunks,
+            vec![(-2, 0), (-1, 0), (-2, 1), (-1, 1), (0, 0), (0, 1)]
+        );
+        assert_eq!(chunk_provider.chunk_positions().unwrap().len(), 16);
+
+        let report = ChunkPruner::new()
+            .with_criterion(PruneCriterion::OutsideRadius {
+                center_chunk_x: 0,
+                center_chunk_z: 0,
+                radius: 1,
+            })
+            .with_predicate(|chunk| chunk.chunk_x < 0)
+            .prune(&chunk_provider)
+            .unwrap();
+
+        assert_eq!(
+            report.pruned_chunks,
+            vec![
+                (-2, -2),
+                (-1, -2),
+                (-2, -1),
+                (-1, -1),
+                (-2, 0),
+                (-2, 1),
+                (-1, 1)
+            ]
+        );
+        assert_eq!(chunk_provider.chunk_positions().unwrap().len(), 9);
+        assert!(chunk_provider.load_chunk(-2, 1).is_err());
+    }
+
+    #[test]
+    fn test_prune_compact() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        save_chunks(&chunk_provider);
+
+        let report = ChunkPruner::new()
+            .with_criterion(PruneCriterion::OutsideRectangle {
+                min_chunk_x: -1,
+                min_chunk_z: -1,
+                max_chunk_x: 0,
+                max_chunk_z: 0,
+            })
+            .with_criterion(PruneCriterion::ModifiedBefore {
+                timestamp: u32::MAX,
+            })
+            .with_compact(true)
+            .prune(&chunk_provider)
+            .unwrap();
+
+        assert_eq!(report.pruned_chunks.len(), 12);
+        assert_eq!(report.released_sectors, 12);
+        assert_eq!(
+            chunk_provider.chunk_positions().unwrap(),
+            vec![(-1, -1), (-1, 0), (0, -1), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_prune_dry_run_read_only() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        save_chunks(&chunk_provider);
+
+        // Unaligned region would be padded if opened for write.
+        let region_path = chunk_provider.region_path(0, 0);
+        let mut file = OpenOptions::new().append(true).open(&region_path).unwrap();
+        file.write_all(&[0; 10]).unwrap();
+        let length = fs::metadata(&region_path).unwrap().len();
+
+        let report = ChunkPruner::new()
+            .with_criterion(PruneCriterion::StatusNotFull)
+            .with_dry_run(true)
+            .prune(&chunk_provider)
+            .unwrap();
+
+        assert_eq!(report.pruned_chunks.len(), 8);
+        assert_eq!(fs::metadata(&region_path).unwrap().len(), length);
+    }
+
+    #[test]
+    fn test_prune_skips_mcregion() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        save_chunks(&chunk_provider);
+
+        let region_path = chunk_provider.region_path(0, 0);
+        fs::rename(&region_path, region_path.with_extension("mcr")).unwrap();
+
+        let report = ChunkPruner::new()
+            .with_criterion(PruneCriterion::StatusNotFull)
+            .prune(&chunk_provider)
+            .unwrap();
+
+        assert_eq!(report.scanned_chunks, 12);
+        assert_eq!(report.skipped_regions, vec![(0, 0)]);
+        assert!(!region_path.exists());
+        assert!(chunk_provider.load_chunk(1, 1).is_ok());
+        assert!(chunk_provider.load_chunk(-1, 1).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+//! Whole-world scan decoding every chunk once.
+//!
+//! Regions are distributed across threads when `parallel` feature is enabled,
+//! otherwise they are scanned one by one.
+use crate::{chunk_position, AnvilChunkProvider, ChunkLoadError};
+use nbt::CompoundTag;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Possible errors while scanning.
+#[derive(Debug)]
+pub enum ScanError {
+    /// Regions of provider folder can not be listed.
+    ReadFolderError { io_error: io::Error },
+    /// Chunk or region could not be loaded and errors are not skipped.
+    ScanFailure { scan_failure: ScanFailure },
+}
+
+impl From<ScanFailure> for ScanError {
+    fn from(scan_failure: ScanFailure) -> Self {
+        ScanError::ScanFailure { scan_failure }
+    }
+}
+
+/// Chunk or region which could not be loaded.
+#[derive(Debug)]
+pub struct ScanFailure {
+    pub region_x: i32,
+    pub region_z: i32,
+    /// Chunk coordinates, none if whole region could not be opened.
+    pub chunk_position: Option<(i32, i32)>,
+    pub chunk_load_error: ChunkLoadError,
+}
+
+/// Progress of scan reported after each region.
+#[derive(Debug)]
+pub struct ScanProgress {
+    pub scanned_regions: usize,
+    pub total_regions: usize,
+    pub scanned_chunks: usize,
+}
+
+/// Result of scan.
+#[derive(Debug)]
+pub struct ScanReport<T> {
+    /// Reduced result of all mapped chunks.
+    pub result: T,
+    pub scanned_chunks: usize,
+    /// Skipped chunks and regions, empty unless errors are skipped.
+    pub failures: Vec<ScanFailure>,
+}
+
+/// Callback receiving scan progress.
+type ProgressCallback = Box<dyn Fn(&ScanProgress) + Send + Sync>;
+
+/// Result of single region scan.
+struct RegionScan<T> {
+    result: T,
+    scanned_chunks: usize,
+    failures: Vec<ScanFailure>,
+}
+
+/// Scan which maps every chunk and reduces results.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::scan::WorldScan;
+/// use anvil_region::AnvilChunkProvider;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+///
+/// // Sum of inhabited time of all chunks.
+/// let report = WorldScan::new()
+///     .with_skip_errors(true)
+///     .scan(
+///         &chunk_provider,
+///         || 0,
+///         |_, _, chunk_compound_tag| {
+///             chunk_compound_tag
+///                 .get_compound_tag("Level")
+///                 .and_then(|level_compound_tag| level_compound_tag.get_i64("InhabitedTime"))
+///                 .unwrap_or(0)
+///         },
+///         |first, second| first + second,
+///     )
+///     .unwrap();
+///
+/// assert!(report.scanned_chunks > 0);
+/// ```
+#[derive(Default)]
+pub struct WorldScan {
+    skip_errors: bool,
+    progress: Option<ProgressCallback>,
+}
+
+impl WorldScan {
+    /// Creates scan which stops at first error.
+    pub fn new() -> Self {
+        WorldScan::default()
+    }
+
+    /// Records chunks and regions which can not be loaded instead of stopping.
+    pub fn with_skip_errors(mut self, skip_errors: bool) -> Self {
+        self.skip_errors = skip_errors;
+        self
+    }
+
+    /// Calls callback after each scanned region, possibly from several threads.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+    where
+        F: Fn(&ScanProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Maps every chunk of provider and reduces mapped values.
+    ///
+    /// Reduce must be associative and identity must produce neutral value,
+    /// as chunks are reduced in unspecified order.
+    pub fn scan<T, I, M, R>(
+        &self,
+        chunk_provider: &AnvilChunkProvider,
+        identity: I,
+        map: M,
+        reduce: R,
+    ) -> Result<ScanReport<T>, ScanError>
+    where
+        T: Send,
+        I: Fn() -> T + Send + Sync,
+        M: Fn(i32, i32, CompoundTag) -> T + Send + Sync,
+        R: Fn(T, T) -> T + Send + Sync,
+    {
+        let region_positions = chunk_provider
+            .region_positions()
+            .map_err(|io_error| ScanError::ReadFolderError { io_error })?;
+
+        let total_regions = region_positions.len();
+        let scanned_regions = AtomicUsize::new(0);
+        let scanned_chunks = AtomicUsize::new(0);
+
+        let scan_region = |&(region_x, region_z): &(i32, i32)| {
+            let region_scan =
+                self.scan_region(chunk_provider, region_x, region_z, &identity, &map, &reduce)?;
+
+            let scanned_regions = scanned_regions.fetch_add(1, Ordering::Relaxed) + 1;
+            let scanned_chunks = scanned_chunks
+                .fetch_add(region_scan.scanned_chunks, Ordering::Relaxed)
+                + region_scan.scanned_chunks;
+
+            if let Some(progress) = &self.progress {
+                progress(&ScanProgress {
+                    scanned_regions,
+                    total_regions,
+                    scanned_chunks,
+                });
+            }
+
+            Ok(region_scan)
+        };
+
+        #[cfg(feature = "parallel")]
+        let region_scans: Result<Vec<_>, ScanFailure> =
+            region_positions.par_iter().map(scan_region).collect();
+        #[cfg(not(feature = "parallel"))]
+        let region_scans: Result<Vec<_>, ScanFailure> =
+            region_positions.iter().map(scan_region).collect();
+
+        let mut report = ScanReport {
+            result: identity(),
+            scanned_chunks: 0,
+            failures: Vec::new(),
+        };
+
+        for region_scan in region_scans? {
+            report.result = reduce(report.result, region_scan.result);
+            report.scanned_chunks += region_scan.scanned_chunks;
+            report.failures.extend(region_scan.failures);
+        }
+
+        Ok(report)
+    }
+
+    fn scan_region<T, I, M, R>(
+        &self,
+        chunk_provider: &AnvilChunkProvider,
+        region_x: i32,
+        region_z: i32,
+        identity: &I,
+        map: &M,
+        reduce: &R,
+    ) -> Result<RegionScan<T>, ScanFailure>
+    where
+        I: Fn() -> T,
+        M: Fn(i32, i32, CompoundTag) -> T,
+        R: Fn(T, T) -> T,
+    {
+        let mut region_scan = RegionScan {
+            result: identity(),
+            scanned_chunks: 0,
+            failures: Vec::new(),
+        };
+
+        let region = chunk_provider
+            .existing_region_path(region_x, region_z)
+            .ok_or(ChunkLoadError::RegionNotFound { region_x, region_z })
+            .and_then(|region_path| Ok(chunk_provider.open_region(region_path)?));
+
+        let mut region = match region {
+            Ok(region) => region,
+            Err(chunk_load_error) => {
+                let failure = ScanFailure {
+                    region_x,
+                    region_z,
+                    chunk_position: None,
+                    chunk_load_error,
+                };
+
+                return self.skip(failure, region_scan);
+            }
+        };
+
+        for (region_chunk_x, region_chunk_z, _) in region.chunks() {
+            let (chunk_x, chunk_z) =
+                chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+
+            match region.read_chunk(region_chunk_x, region_chunk_z) {
+                Ok(chunk_compound_tag) => {
+                    let value = map(chunk_x, chunk_z, chunk_compound_tag);
+
+                    region_scan.result = reduce(region_scan.result, value);
+                    region_scan.scanned_chunks += 1;
+                }
+                Err(chunk_load_error) => {
+                    let failure = ScanFailure {
+                        region_x,
+                        region_z,
+                        chunk_position: Some((chunk_x, chunk_z)),
+                        chunk_load_error,
+                    };
+
+                    region_scan = self.skip(failure, region_scan)?;
+                }
+            }
+        }
+
+        Ok(region_scan)
+    }
+
+    /// Records failure when errors are skipped, returns it otherwise.
+    fn skip<T>(
+        &self,
+        failure: ScanFailure,
+        mut region_scan: RegionScan<T>,
+    ) -> Result<RegionScan<T>, ScanFailure> {
+        if !self.skip_errors {
+            return Err(failure);
+        }
+
+        region_scan.failures.push(failure);
+
+        Ok(region_scan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scan::{ScanError, WorldScan};
+    use crate::{AnvilChunkProvider, AnvilRegion, ChunkLoadError};
+    use nbt::CompoundTag;
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        for &chunk_x in &[0, 1, 2, 40, 80, -1] {
+            let mut chunk_compound_tag = CompoundTag::new();
+            chunk_compound_tag.insert_i32("value", chunk_x);
+
+            chunk_provider
+                .save_chunk(chunk_x, 0, chunk_compound_tag)
+                .unwrap();
+        }
+
+        // Chunk 2 0 gets unsupported compression scheme.
+        let region_path = chunk_provider.region_path(0, 0);
+        let region = AnvilRegion::open(&region_path).unwrap();
+        let sector_index = region.chunk_metadata(2, 0).sector_index;
+        drop(region);
+
+        let mut file = OpenOptions::new().write(true).open(&region_path).unwrap();
+        file.seek(SeekFrom::Start(sector_index as u64 * 4096 + 4))
+            .unwrap();
+        file.write_all(&[9]).unwrap();
+        drop(file);
+
+        let sum = |_: i32, _: i32, chunk_compound_tag: CompoundTag| {
+            chunk_compound_tag.get_i32("value").unwrap()
+        };
+
+        match WorldScan::new().scan(&chunk_provider, || 0, sum, |first, second| first + second) {
+            Err(ScanError::ScanFailure { scan_failure }) => {
+                assert_eq!(scan_failure.chunk_position, Some((2, 0)))
+            }
+            result => panic!("Expected `ScanFailure` but got `{:?}`", result),
+        }
+
+        let progress_calls = Arc::new(AtomicUsize::new(0));
+        let progress_counter = progress_calls.clone();
+
+        let report = WorldScan::new()
+            .with_skip_errors(true)
+            .with_progress(move |progress| {
+                assert_eq!(progress.total_regions, 4);
+                progress_counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .scan(&chunk_provider, || 0, sum, |first, second| first + second)
+            .unwrap();
+
+        assert_eq!(report.result, 120);
+        assert_eq!(report.scanned_chunks, 5);
+        assert_eq!(progress_calls.load(Ordering::Relaxed), 4);
+        assert_eq!(report.failures.len(), 1);
+        assert!(matches!(
+            report.failures[0].chunk_load_error,
+            ChunkLoadError::UnsupportedCompressionScheme {
+                compression_scheme: 9
+            }
+        ));
+    }
+
+    #[test]
+    fn test_scan_unreadable_folder() {
+        let folder = TempDir::new().unwrap();
+        let file_path = folder.path().join("region");
+        fs::write(&file_path, []).unwrap();
+
+        let chunk_provider = AnvilChunkProvider::from_path(&file_path);
+
+        match WorldScan::new().scan(&chunk_provider, || 0, |_, _, _| 0, |first, _| first) {
+            Err(ScanError::ReadFolderError { .. }) => {}
+            result => panic!("Expected `ReadFolderError` but got `{:?}`", result),
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Named snapshots of provider folder with copy-on-write layers.
+//!
+//! Region files of provider folder form base layer. Taking snapshot freezes
+//! all existing layers and starts new delta layer, later saves and deletes
+//! change only the delta. Deleted chunks are recorded as tombstones which
+//! hide chunks of lower layers.
+//!
+//! Base layer is not copied, so once folder has snapshots all writes must go
+//! through `SnapshotProvider`. Saving chunk with `AnvilChunkProvider` or
+//! other tools on the same folder changes base layer of every snapshot.
+//!
+//! Folder layout:
+//!
+//! * `r.X.Z.mca` - base layer regions.
+//! * `snapshots/index.tsv` - snapshot names with amount of frozen layers.
+//! * `snapshots/layers/<n>/` - regions of delta layer and `deleted.tsv` with tombstones.
+use crate::{region_chunk_position, AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::CompoundTag;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Folder of snapshots data inside provider folder.
+const SNAPSHOTS_FOLDER: &str = "snapshots";
+/// Folder of delta layers inside snapshots folder.
+const LAYERS_FOLDER: &str = "layers";
+/// Snapshot index file name.
+const INDEX_FILE_NAME: &str = "index.tsv";
+/// Tombstones file name inside layer folder.
+const TOMBSTONES_FILE_NAME: &str = "deleted.tsv";
+
+/// Possible errors while managing snapshots.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Snapshot with same name already exists.
+    SnapshotExists { name: String },
+    /// Snapshot with specified name not found.
+    SnapshotNotFound { name: String },
+    /// Snapshot names can not contain tabs and line breaks.
+    InvalidName { name: String },
+    /// I/O error while accessing snapshots data.
+    IoError { io_error: io::Error },
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(io_error: io::Error) -> Self {
+        SnapshotError::IoError { io_error }
+    }
+}
+
+/// Ordered layers from base to top.
+struct Layers {
+    layer_paths: Vec<PathBuf>,
+}
+
+impl Layers {
+    fn load_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<CompoundTag, ChunkLoadError> {
+        let (region_chunk_x, region_chunk_z) = region_chunk_position(chunk_x, chunk_z);
+
+        for layer_path in self.layer_paths.iter().rev() {
+            let chunk_provider = AnvilChunkProvider::from_path(layer_path);
+
+            match chunk_provider.load_chunk(chunk_x, chunk_z) {
+                Err(ChunkLoadError::RegionNotFound { .. })
+                | Err(ChunkLoadError::ChunkNotFound { .. }) => {}
+                result => return result,
+            }
+
+            if read_tombstones(layer_path)?.contains(&(chunk_x, chunk_z)) {
+                break;
+            }
+        }
+
+        Err(ChunkLoadError::ChunkNotFound {
+            chunk_x: region_chunk_x,
+            chunk_z: region_chunk_z,
+        })
+    }
+
+    fn chunk_positions(&self) -> Result<Vec<(i32, i32)>, ChunkLoadError> {
+        let mut chunk_positions = BTreeSet::new();
+
+        for layer_path in &self.layer_paths {
+            for chunk_position in read_tombstones(layer_path)? {
+                chunk_positions.remove(&chunk_position);
+            }
+
+            let chunk_provider = AnvilChunkProvider::from_path(layer_path);
+            chunk_positions.extend(chunk_provider.chunk_positions()?);
+        }
+
+        Ok(chunk_positions.into_iter().collect())
+    }
+}
+
+/// Provider folder with named snapshots.
+///
+/// Region files of folder must not be changed by other providers while
+/// snapshots exist, as they are shared base layer of all snapshots.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::snapshot::SnapshotProvider;
+/// use nbt::CompoundTag;
+///
+/// let folder = tempfile::tempdir().unwrap();
+/// let snapshot_provider = SnapshotProvider::open(folder.path()).unwrap();
+///
+/// snapshot_provider.save_chunk(0, 0, CompoundTag::new()).unwrap();
+/// snapshot_provider.create_snapshot("before-raid").unwrap();
+/// snapshot_provider.delete_chunk(0, 0).unwrap();
+///
+/// let snapshot_view = snapshot_provider.snapshot_view("before-raid").unwrap();
+///
+/// assert!(snapshot_view.load_chunk(0, 0).is_ok());
+/// assert!(snapshot_provider.load_chunk(0, 0).is_err());
+/// ```
+pub struct SnapshotProvider {
+    /// Provider folder with base layer regions.
+    folder_path: PathBuf,
+}
+
+impl SnapshotProvider {
+    /// Opens provider folder, creating it when it does not exist.
+    pub fn open<P: AsRef<Path>>(folder_path: P) -> Result<Self, io::Error> {
+        let folder_path = folder_path.as_ref().to_path_buf();
+        fs::create_dir_all(&folder_path)?;
+
+        Ok(SnapshotProvider { folder_path })
+    }
+
+    /// Returns snapshot names with amount of frozen layers in creation order.
+    fn read_index(&self) -> Result<Vec<(String, usize)>, io::Error> {
+        let index_path = self.snapshots_path().join(INDEX_FILE_NAME);
+        let mut snapshots = Vec::new();
+
+        if !index_path.exists() {
+            return Ok(snapshots);
+        }
+
+        let reader = BufReader::new(fs::File::open(&index_path)?);
+
+        for line in reader.lines() {
+            let line = line?;
+            let snapshot = line
+                .split_once('\t')
+                .and_then(|(name, layers)| Some((name.to_owned(), layers.parse().ok()?)));
+
+            match snapshot {
+                Some(snapshot) => snapshots.push(snapshot),
+                None => return Err(invalid_line(&line)),
+            }
+        }
+
+        Ok(snapshots)
+    }
+
+    fn write_index(&self, snapshots: &[(String, usize)]) -> Result<(), io::Error> {
+        let index_path = self.snapshots_path().join(INDEX_FILE_NAME);
+        let temporary_path = index_path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+
+        for (name, layers) in snapshots {
+            writeln!(file, "{}\t{}", name, layers)?;
+        }
+
+        file.sync_all()?;
+        fs::rename(temporary_path, index_path)
+    }
+
+    fn snapshots_path(&self) -> PathBuf {
+        self.folder_path.join(SNAPSHOTS_FOLDER)
+    }
+
+    fn layer_path(&self, layer: usize) -> PathBuf {
+        if layer == 0 {
+            self.folder_path.clone()
+        } else {
+            self.snapshots_path()
+                .join(LAYERS_FOLDER)
+                .join(layer.to_string())
+        }
+    }
+
+    /// Returns amount of layers, top one receives writes.
+    fn layer_count(&self) -> usize {
+        let mut layer_count = 1;
+
+        while self.layer_path(layer_count).exists() {
+            layer_count += 1;
+        }
+
+        layer_count
+    }
+
+    fn layers(&self, layer_count: usize) -> Layers {
+        Layers {
+            layer_paths: (0..layer_count)
+                .map(|layer| self.layer_path(layer))
+                .collect(),
+        }
+    }
+
+    /// Returns snapshot names in creation order.
+    pub fn snapshots(&self) -> Result<Vec<String>, SnapshotError> {
+        Ok(self
+            .read_index()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Freezes current state under name and starts new delta layer.
+    pub fn create_snapshot(&self, name: &str) -> Result<(), SnapshotError> {
+        if name.contains(['\t', '\n', '\r']) {
+            return Err(SnapshotError::InvalidName {
+                name: name.to_owned(),
+            });
+        }
+
+        let mut snapshots = self.read_index()?;
+
+        if snapshots
+            .iter()
+            .any(|(snapshot_name, _)| snapshot_name == name)
+        {
+            return Err(SnapshotError::SnapshotExists {
+                name: name.to_owned(),
+            });
+        }
+
+        let layer_count = self.layer_count();
+        fs::create_dir_all(self.layer_path(layer_count))?;
+
+        snapshots.push((name.to_owned(), layer_count));
+        self.write_index(&snapshots)?;
+
+        Ok(())
+    }
+
+    /// Removes snapshot name, its layers stay shared with later state.
+    pub fn delete_snapshot(&self, name: &str) -> Result<(), SnapshotError> {
+        let mut snapshots = self.read_index()?;
+        let snapshots_count = snapshots.len();
+
+        snapshots.retain(|(snapshot_name, _)| snapshot_name != name);
+
+        if snapshots.len() == snapshots_count {
+            return Err(SnapshotError::SnapshotNotFound {
+                name: name.to_owned(),
+            });
+        }
+
+        self.write_index(&snapshots)?;
+
+        Ok(())
+    }
+
+    /// Returns read-only view of frozen snapshot state.
+    pub fn snapshot_view(&self, name: &str) -> Result<SnapshotView, SnapshotError> {
+        let layer_count = self.snapshot_layer_count(name)?;
+
+        Ok(SnapshotView {
+            layers: self.layers(layer_count),
+        })
+    }
+
+    fn snapshot_layer_count(&self, name: &str) -> Result<usize, SnapshotError> {
+        self.read_index()?
+            .into_iter()
+            .find(|(snapshot_name, _)| snapshot_name == name)
+            .map(|(_, layers)| layers)
+            .ok_or_else(|| SnapshotError::SnapshotNotFound {
+                name: name.to_owned(),
+            })
+    }
+
+    /// Discards all changes made after snapshot.
+    ///
+    /// Later snapshots are removed, rolled back snapshot stays available.
+    pub fn rollback(&self, name: &str) -> Result<(), SnapshotError> {
+        let layer_count = self.snapshot_layer_count(name)?;
+        let top_layer_count = self.layer_count();
+
+        let mut snapshots = self.read_index()?;
+        snapshots.retain(|(_, layers)| *layers <= layer_count);
+        self.write_index(&snapshots)?;
+
+        for layer in (layer_count + 1)..top_layer_count {
+            fs::remove_dir_all(self.layer_path(layer))?;
+        }
+
+        // Top layer is emptied rather than removed to keep receiving writes.
+        let layer_path = self.layer_path(layer_count);
+        fs::remove_dir_all(&layer_path)?;
+        fs::create_dir_all(&layer_path)?;
+
+        Ok(())
+    }
+
+    /// Loads chunk from latest layer which has it.
+    pub fn load_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<CompoundTag, ChunkLoadError> {
+        self.layers(self.layer_count()).load_chunk(chunk_x, chunk_z)
+    }
+
+    /// Returns sorted coordinates of all current chunks.
+    pub fn chunk_positions(&self) -> Result<Vec<(i32, i32)>, ChunkLoadError> {
+        self.layers(self.layer_count()).chunk_positions()
+    }
+
+    /// Saves chunk into top layer.
+    pub fn save_chunk(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk_compound_tag: CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        let layer_path = self.layer_path(self.layer_count() - 1);
+        let chunk_provider = AnvilChunkProvider::from_path(&layer_path);
+
+        chunk_provider.save_chunk(chunk_x, chunk_z, chunk_compound_tag)?;
+
+        let mut tombstones = read_tombstones(&layer_path)?;
+
+        if tombstones.remove(&(chunk_x, chunk_z)) {
+            write_tombstones(&layer_path, &tombstones)?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes chunk from top layer and hides it in lower layers.
+    ///
+    /// Returns whether chunk existed.
+    pub fn delete_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<bool, ChunkSaveError> {
+        let layer_count = self.layer_count();
+        let layer_path = self.layer_path(layer_count - 1);
+        let chunk_provider = AnvilChunkProvider::from_path(&layer_path);
+        let deleted = chunk_provider.delete_chunk(chunk_x, chunk_z)?;
+
+        if layer_count == 1 {
+            return Ok(deleted);
+        }
+
+        let lower_layers = self.layers(layer_count - 1);
+
+        match lower_layers.load_chunk(chunk_x, chunk_z) {
+            Err(ChunkLoadError::ChunkNotFound { .. }) => Ok(deleted),
+            _ => {
+                let mut tombstones = read_tombstones(&layer_path)?;
+                let hidden = tombstones.insert((chunk_x, chunk_z));
+
+                if hidden {
+                    write_tombstones(&layer_path, &tombstones)?;
+                }
+
+                Ok(deleted || hidden)
+            }
+        }
+    }
+}
+
+/// Read-only frozen state of snapshot.
+pub struct SnapshotView {
+    layers: Layers,
+}
+
+impl SnapshotView {
+    /// Loads chunk as it was when snapshot was taken.
+    pub fn load_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<CompoundTag, ChunkLoadError> {
+        self.layers.load_chunk(chunk_x, chunk_z)
+    }
+
+    /// Returns sorted coordinates of chunks which existed when snapshot was taken.
+    pub fn chunk_positions(&self) -> Result<Vec<(i32, i32)>, ChunkLoadError> {
+        self.layers.chunk_positions()
+    }
+}
+
+fn invalid_line(line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid snapshots data line \"{}\"", line),
+    )
+}
+
+/// Reads coordinates of chunks deleted in layer.
+fn read_tombstones(layer_path: &Path) -> Result<BTreeSet<(i32, i32)>, io::Error> {
+    let tombstones_path = layer_path.join(TOMBSTONES_FILE_NAME);
+    let mut tombstones = BTreeSet::new();
+
+    if !tombstones_path.exists() {
+        return Ok(tombstones);
+    }
+
+    for line in BufReader::new(fs::File::open(&tombstones_path)?).lines() {
+        let line = line?;
+        let chunk_position = line
+            .split_once('\t')
+            .and_then(|(chunk_x, chunk_z)| Some((chunk_x.parse().ok()?, chunk_z.parse().ok()?)));
+
+        match chunk_position {
+            Some(chunk_position) => tombstones.insert(chunk_position),
+            None => return Err(invalid_line(&line)),
+        };
+    }
+
+    Ok(tombstones)
+}
+
+fn write_tombstones(layer_path: &Path, tombstones: &BTreeSet<(i32, i32)>) -> Result<(), io::Error> {
+    let tombstones_path = layer_path.join(TOMBSTONES_FILE_NAME);
+    let temporary_path = tombstones_path.with_extension("tmp");
+    let mut file = fs::File::create(&temporary_path)?;
+
+    for (chunk_x, chunk_z) in tombstones {
+        writeln!(file, "{}\t{}", chunk_x, chunk_z)?;
+    }
+
+    file.sync_all()?;
+    fs::rename(temporary_path, tombstones_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{chunk, value};
+    use crate::snapshot::{SnapshotError, SnapshotProvider};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshots() {
+        let folder = TempDir::new().unwrap();
+        let snapshot_provider = SnapshotProvider::open(folder.path()).unwrap();
+
+        snapshot_provider.save_chunk(0, 0, chunk(1)).unwrap();
+        snapshot_provider.save_chunk(1, 0, chunk(1)).unwrap();
+        snapshot_provider.create_snapshot("first").unwrap();
+
+        snapshot_provider.save_chunk(0, 0, chunk(2)).unwrap();
+        snapshot_provider.save_chunk(40, 0, chunk(2)).unwrap();
+        assert!(snapshot_provider.delete_chunk(1, 0).unwrap());
+        snapshot_provider.create_snapshot("second").unwrap();
+
+        snapshot_provider.save_chunk(1, 0, chunk(3)).unwrap();
+        snapshot_provider.save_chunk(0, 0, chunk(3)).unwrap();
+
+        assert!(matches!(
+            snapshot_provider.create_snapshot("first"),
+            Err(SnapshotError::SnapshotExists { .. })
+        ));
+        assert_eq!(
+            snapshot_provider.snapshots().unwrap(),
+            vec!["first", "second"]
+        );
+
+        let first_view = snapshot_provider.snapshot_view("first").unwrap();
+        assert_eq!(first_view.chunk_positions().unwrap(), vec![(0, 0), (1, 0)]);
+        assert_eq!(value(&first_view.load_chunk(0, 0).unwrap()), 1);
+
+        let second_view = snapshot_provider.snapshot_view("second").unwrap();
+        assert_eq!(
+            second_view.chunk_positions().unwrap(),
+            vec![(0, 0), (40, 0)]
+        );
+        assert_eq!(value(&second_view.load_chunk(0, 0).unwrap()), 2);
+        assert!(second_view.load_chunk(1, 0).is_err());
+
+        assert_eq!(
+            snapshot_provider.chunk_positions().unwrap(),
+            vec![(0, 0), (1, 0), (40, 0)]
+        );
+        assert_eq!(value(&snapshot_provider.load_chunk(1, 0).unwrap()), 3);
+
+        snapshot_provider.rollback("first").unwrap();
+
+        assert_eq!(snapshot_provider.snapshots().unwrap(), vec!["first"]);
+        assert_eq!(
+            snapshot_provider.chunk_positions().unwrap(),
+            vec![(0, 0), (1, 0)]
+        );
+        assert_eq!(value(&snapshot_provider.load_chunk(0, 0).unwrap()), 1);
+
+        snapshot_provider.save_chunk(0, 0, chunk(4)).unwrap();
+        let first_view = snapshot_provider.snapshot_view("first").unwrap();
+        assert_eq!(value(&first_view.load_chunk(0, 0).unwrap()), 1);
+    }
+}
@@ -0,0 +1,290 @@
+//! Statistics of region space usage.
+//!
+//! Statistics are computed from region header and used sectors, only
+//! compression scheme byte of each chunk is read.
+use crate::{AnvilChunkProvider, AnvilRegion, REGION_SECTOR_BYTES_LENGTH};
+use byteorder::ReadBytesExt;
+use std::collections::BTreeMap;
+use std::io::{self, Seek, SeekFrom};
+
+/// Amount of header sectors in region.
+const HEADER_SECTORS: u32 = 2;
+
+/// Space usage of region or several regions.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RegionStats {
+    /// Amount of regions statistics are computed from.
+    pub region_count: usize,
+    pub chunk_count: usize,
+    /// Amount of sectors in files including headers.
+    pub total_sectors: u32,
+    /// Amount of sectors used by headers and chunks.
+    pub used_sectors: u32,
+    /// Amount of unused sectors between and after chunks.
+    pub free_sectors: u32,
+    /// Longest run of unused sectors in single region.
+    pub largest_free_gap: u32,
+    /// Amount of chunks by amount of sectors they occupy.
+    pub chunk_sectors: BTreeMap<u8, usize>,
+    /// Amount of chunks by compression scheme.
+    pub compression_schemes: BTreeMap<u8, usize>,
+    /// Amount of chunks which sectors are beyond the end of file.
+    pub outside_file_chunks: usize,
+    /// Earliest chunk header timestamp.
+    pub oldest_timestamp: Option<u32>,
+    /// Latest chunk header timestamp.
+    pub newest_timestamp: Option<u32>,
+}
+
+impl RegionStats {
+    /// Computes statistics of region.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anvil_region::stats::RegionStats;
+    /// use anvil_region::AnvilRegion;
+    ///
+    /// let mut region = AnvilRegion::open_read_only("test/region/r.0.0.mca").unwrap();
+    /// let region_stats = RegionStats::from_region(&mut region).unwrap();
+    ///
+    /// assert_eq!(region_stats.chunk_count, region.chunks().len());
+    /// assert_eq!(region_stats.compression_schemes.get(&2), Some(&region_stats.chunk_count));
+    /// ```
+    pub fn from_region(region: &mut AnvilRegion) -> Result<Self, io::Error> {
+        let mut region_stats = RegionStats {
+            region_count: 1,
+            total_sectors: region.total_sectors(),
+            used_sectors: region.used_sectors_count(),
+            ..Default::default()
+        };
+        region_stats.free_sectors = region_stats.total_sectors - region_stats.used_sectors;
+
+        let file_length = region.file.metadata()?.len();
+        let file_sectors = file_length.div_ceil(REGION_SECTOR_BYTES_LENGTH as u64);
+        let mut free_gap = 0;
+
+        for used in region.used_sectors.iter() {
+            if *used {
+                free_gap = 0;
+            } else {
+                free_gap += 1;
+                region_stats.largest_free_gap = region_stats.largest_free_gap.max(free_gap);
+            }
+        }
+
+        for (_, _, metadata) in region.chunks() {
+            region_stats.chunk_count += 1;
+            *region_stats
+                .chunk_sectors
+                .entry(metadata.sectors)
+                .or_insert(0) += 1;
+
+            let timestamp = metadata.last_modified_timestamp;
+            region_stats.oldest_timestamp = Some(
+                region_stats
+                    .oldest_timestamp
+                    .map_or(timestamp, |oldest| oldest.min(timestamp)),
+            );
+            region_stats.newest_timestamp = Some(
+                region_stats
+                    .newest_timestamp
+                    .map_or(timestamp, |newest| newest.max(timestamp)),
+            );
+
+            // Chunk is counted, but its compression scheme cannot be read.
+            if metadata.sector_index as u64 + metadata.sectors as u64 > file_sectors {
+                region_stats.outside_file_chunks += 1;
+                continue;
+            }
+
+            // Compression scheme follows 4 bytes of chunk length.
+            let seek_offset = metadata.sector_index as u64 * REGION_SECTOR_BYTES_LENGTH as u64 + 4;
+            region.file.seek(SeekFrom::Start(seek_offset))?;
+            let compression_scheme = region.file.read_u8()?;
+
+            *region_stats
+                .compression_schemes
+                .entry(compression_scheme)
+                .or_insert(0) += 1;
+        }
+
+        Ok(region_stats)
+    }
+
+    /// Returns share of free sectors among sectors available for chunks.
+    pub fn fragmentation_ratio(&self) -> f64 {
+        let chunk_sectors = self
+            .total_sectors
+            .saturating_sub(HEADER_SECTORS * self.region_count as u32);
+
+        if chunk_sectors == 0 {
+            return 0.0;
+        }
+
+        self.free_sectors as f64 / chunk_sectors as f64
+    }
+
+    /// Adds statistics of other regions.
+    pub fn merge(&mut self, other: &RegionStats) {
+        self.region_count += other.region_count;
+        self.chunk_count += other.chunk_count;
+        self.total_sectors += other.total_sectors;
+        self.used_sectors += other.used_sectors;
+        self.free_sectors += other.free_sectors;
+        self.largest_free_gap = self.largest_free_gap.max(other.largest_free_gap);
+        self.outside_file_chunks += other.outside_file_chunks;
+
+        for (sectors, count) in &other.chunk_sectors {
+            *self.chunk_sectors.entry(*sectors).or_insert(0) += count;
+        }
+
+        for (compression_scheme, count) in &other.compression_schemes {
+            *self
+                .compression_schemes
+                .entry(*compression_scheme)
+                .or_insert(0) += count;
+        }
+
+        self.oldest_timestamp = match (self.oldest_timestamp, other.oldest_timestamp) {
+            (Some(oldest), Some(other_oldest)) => Some(oldest.min(other_oldest)),
+            (oldest, other_oldest) => oldest.or(other_oldest),
+        };
+        self.newest_timestamp = match (self.newest_timestamp, other.newest_timestamp) {
+            (Some(newest), Some(other_newest)) => Some(newest.max(other_newest)),
+            (newest, other_newest) => newest.or(other_newest),
+        };
+    }
+}
+
+/// Statistics of all regions of provider.
+#[derive(Debug, Default)]
+pub struct ProviderStats {
+    /// Region coordinates with their statistics.
+    pub regions: Vec<((i32, i32), RegionStats)>,
+    /// Statistics of all regions together.
+    pub total: RegionStats,
+}
+
+impl ProviderStats {
+    /// Computes statistics of every region of provider.
+    pub fn from_provider(chunk_provider: &AnvilChunkProvider) -> Result<Self, io::Error> {
+        let mut provider_stats = ProviderStats::default();
+
+        for (region_x, region_z) in chunk_provider.region_positions()? {
+            let region_path = match chunk_provider.existing_region_path(region_x, region_z) {
+                Some(region_path) => region_path,
+                None => continue,
+            };
+
+            let mut region = chunk_provider.open_region(region_path)?;
+            let region_stats = RegionStats::from_region(&mut region)?;
+
+            provider_stats.total.merge(&region_stats);
+            provider_stats
+                .regions
+                .push(((region_x, region_z), region_stats));
+        }
+
+        Ok(provider_stats)
+    }
+
+    /// Returns regions with at least specified amount of free sectors and
+    /// fragmentation ratio, most fragmented first.
+    pub fn compaction_candidates(&self, min_free_sectors: u32, min_ratio: f64) -> Vec<(i32, i32)> {
+        let mut candidates: Vec<_> = self
+            .regions
+            .iter()
+            .filter(|(_, region_stats)| {
+                region_stats.free_sectors >= min_free_sectors
+                    && region_stats.fragmentation_ratio() >= min_ratio
+            })
+            .collect();
+
+        candidates.sort_by(|(_, stats), (_, other_stats)| {
+            other_stats
+                .fragmentation_ratio()
+                .total_cmp(&stats.fragmentation_ratio())
+        });
+
+        candidates
+            .into_iter()
+            .map(|(region_position, _)| *region_position)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{ProviderStats, RegionStats};
+    use crate::{AnvilChunkProvider, AnvilRegion};
+    use byteorder::{BigEndian, WriteBytesExt};
+    use nbt::CompoundTag;
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_provider_stats() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        for chunk_x in 0..4 {
+            chunk_provider
+                .save_chunk(chunk_x, 0, CompoundTag::new())
+                .unwrap();
+        }
+
+        chunk_provider
+            .save_chunk(40, 0, CompoundTag::new())
+            .unwrap();
+        chunk_provider.delete_chunk(1, 0).unwrap();
+        chunk_provider.delete_chunk(2, 0).unwrap();
+
+        let region_path = chunk_provider.region_path(0, 0);
+        let mut region = AnvilRegion::open(region_path).unwrap();
+        region.set_last_modified_timestamp(0, 0, 10).unwrap();
+        region.set_last_modified_timestamp(3, 0, 20).unwrap();
+
+        let region_stats = RegionStats::from_region(&mut region).unwrap();
+        assert_eq!(region_stats.chunk_count, 2);
+        assert_eq!(region_stats.total_sectors, 6);
+        assert_eq!(region_stats.used_sectors, 4);
+        assert_eq!(region_stats.free_sectors, 2);
+        assert_eq!(region_stats.largest_free_gap, 2);
+        assert_eq!(region_stats.fragmentation_ratio(), 0.5);
+        assert_eq!(region_stats.oldest_timestamp, Some(10));
+        assert_eq!(region_stats.newest_timestamp, Some(20));
+        drop(region);
+
+        let provider_stats = ProviderStats::from_provider(&chunk_provider).unwrap();
+        assert_eq!(provider_stats.regions.len(), 2);
+        assert_eq!(provider_stats.total.region_count, 2);
+        assert_eq!(provider_stats.total.chunk_count, 3);
+        assert_eq!(provider_stats.total.chunk_sectors.get(&1), Some(&3));
+        assert_eq!(provider_stats.total.compression_schemes.get(&2), Some(&3));
+        assert_eq!(provider_stats.total.oldest_timestamp, Some(10));
+        assert_eq!(provider_stats.compaction_candidates(1, 0.25), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_provider_stats_chunk_outside_file() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        chunk_provider.save_chunk(0, 0, CompoundTag::new()).unwrap();
+        chunk_provider.save_chunk(1, 0, CompoundTag::new()).unwrap();
+
+        // Header entry of chunk 1:0 points to sector 100.
+        let region_path = chunk_provider.region_path(0, 0);
+        let mut file = OpenOptions::new().write(true).open(region_path).unwrap();
+        file.seek(SeekFrom::Start(4)).unwrap();
+        file.write_u32::<BigEndian>(100 << 8 | 1).unwrap();
+        drop(file);
+
+        let provider_stats = ProviderStats::from_provider(&chunk_provider).unwrap();
+        assert_eq!(provider_stats.total.chunk_count, 2);
+        assert_eq!(provider_stats.total.outside_file_chunks, 1);
+        assert_eq!(provider_stats.total.compression_schemes.get(&2), Some(&1));
+    }
+}
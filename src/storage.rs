@@ -0,0 +1,572 @@
+//! Chunk storages of dimension.
+//!
+//! Since 1.17 chunk data are split between three folders of region files:
+//! `region` with blocks, `entities` with entities and `poi` with points of
+//! interest. Each folder has its own chunk layout.
+use crate::chunk::ChunkDataError;
+use crate::transfer::translate_chunk;
+use crate::{AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::{CompoundTag, Tag};
+use std::path::{Path, PathBuf};
+
+/// Possible errors while accessing chunk storages.
+#[derive(Debug)]
+pub enum StorageError {
+    /// Error while loading chunk.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while saving or deleting chunk.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// Chunk data are not readable.
+    ChunkDataError { chunk_data_error: ChunkDataError },
+}
+
+impl From<ChunkLoadError> for StorageError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        StorageError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for StorageError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        StorageError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<ChunkDataError> for StorageError {
+    fn from(chunk_data_error: ChunkDataError) -> Self {
+        StorageError::ChunkDataError { chunk_data_error }
+    }
+}
+
+impl<'a> From<nbt::CompoundTagError<'a>> for StorageError {
+    fn from(compound_tag_error: nbt::CompoundTagError<'a>) -> Self {
+        StorageError::ChunkDataError {
+            chunk_data_error: compound_tag_error.into(),
+        }
+    }
+}
+
+/// Kinds of region folders in dimension.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum StorageKind {
+    /// Blocks, block entities and ticks.
+    Region,
+    /// Entities, since 1.17.
+    Entities,
+    /// Points of interest, since 1.14.
+    Poi,
+}
+
+impl StorageKind {
+    /// All storage kinds.
+    pub const ALL: [StorageKind; 3] =
+        [StorageKind::Region, StorageKind::Entities, StorageKind::Poi];
+
+    /// Returns name of folder with region files.
+    pub fn folder_name(&self) -> &'static str {
+        match self {
+            StorageKind::Region => "region",
+            StorageKind::Entities => "entities",
+            StorageKind::Poi => "poi",
+        }
+    }
+}
+
+/// Entities of chunk stored in `entities` folder.
+#[derive(Clone, Debug, Default)]
+pub struct EntityChunk {
+    /// Data version of game which saved entities.
+    pub data_version: i32,
+    /// Entity compound tags.
+    pub entities: Vec<CompoundTag>,
+}
+
+impl EntityChunk {
+    /// Reads entities from chunk compound tag.
+    pub fn from_tag(chunk_compound_tag: &CompoundTag) -> Result<Self, ChunkDataError> {
+        let data_version = chunk_compound_tag.get_i32("DataVersion")?;
+        let entities = chunk_compound_tag
+            .get_compound_tag_vec("Entities")?
+            .into_iter()
+            .cloned()
+            .collect();
+
+        Ok(EntityChunk {
+            data_version,
+            entities,
+        })
+    }
+
+    /// Writes entities to chunk compound tag at specified coordinates.
+    pub fn to_tag(&self, chunk_x: i32, chunk_z: i32) -> CompoundTag {
+        let mut chunk_compound_tag = CompoundTag::new();
+
+        chunk_compound_tag.insert_i32("DataVersion", self.data_version);
+        chunk_compound_tag.insert_i32_vec("Position", vec![chunk_x, chunk_z]);
+        chunk_compound_tag.insert_compound_tag_vec("Entities", self.entities.clone());
+
+        chunk_compound_tag
+    }
+}
+
+/// Point of interest such as bed, bell or workstation.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PoiRecord {
+    /// Point of interest type, for example `minecraft:home`.
+    pub kind: String,
+    /// Block coordinates.
+    pub position: (i32, i32, i32),
+    /// Amount of villagers which can still claim point.
+    pub free_tickets: i32,
+}
+
+/// Points of interest in 16 blocks high section.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PoiSection {
+    /// Section y coordinate.
+    pub y: i8,
+    /// False if the game should rescan section blocks.
+    pub valid: bool,
+    /// Points of interest in section.
+    pub records: Vec<PoiRecord>,
+}
+
+/// Points of interest of chunk stored in `poi` folder.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PoiChunk {
+    /// Data version of game which saved points of interest.
+    pub data_version: i32,
+    /// Sections sorted by y coordinate.
+    pub sections: Vec<PoiSection>,
+}
+
+impl PoiChunk {
+    /// Reads points of interest from chunk compound tag.
+    pub fn from_tag(chunk_compound_tag: &CompoundTag) -> Result<Self, ChunkDataError> {
+        let data_version = chunk_compound_tag.get_i32("DataVersion")?;
+        let sections_compound_tag = chunk_compound_tag.get_compound_tag("Sections")?;
+        let mut sections = Vec::new();
+
+        for (name, tag) in sections_compound_tag.iter() {
+            let section_compound_tag = match tag {
+                Tag::Compound(section_compound_tag) => section_compound_tag,
+                _ => {
+                    return Err(ChunkDataError::WrongTagType {
+                        name: name.to_owned(),
+                    })
+                }
+            };
+
+            let y = name.parse().map_err(|_| ChunkDataError::WrongTagType {
+                name: name.to_owned(),
+            })?;
+
+            let valid = section_compound_tag.get_bool("Valid").unwrap_or(false);
+            let mut records = Vec::new();
+
+            for record_compound_tag in section_compound_tag.get_compound_tag_vec("Records")? {
+                let position = record_compound_tag.get_i32_vec("pos")?;
+
+                if position.len() != 3 {
+                    return Err(ChunkDataError::InvalidLength {
+                        name: "pos".to_owned(),
+                        length: position.len(),
+                        expected_length: 3,
+                    });
+                }
+
+                records.push(PoiRecord {
+                    kind: record_compound_tag.get_str("type")?.to_owned(),
+                    position: (position[0], position[1], position[2]),
+                    free_tickets: record_compound_tag.get_i32("free_tickets").unwrap_or(0),
+                });
+            }
+
+            sections.push(PoiSection { y, valid, records });
+        }
+
+        sections.sort_by_key(|section| section.y);
+
+        Ok(PoiChunk {
+            data_version,
+            sections,
+        })
+    }
+
+    /// Writes points of interest to chunk compound tag.
+    pub fn to_tag(&self) -> CompoundTag {
+        let mut sections_compound_tag = CompoundTag::new();
+
+        for section in &self.sections {
+            let mut section_compound_tag = CompoundTag::new();
+            let mut record_compound_tags = Vec::new();
+
+            for record in &section.records {
+                let (x, y, z) = record.position;
+                let mut record_compound_tag = CompoundTag::new();
+
+                record_compound_tag.insert_str("type", &record.kind);
+                record_compound_tag.insert_i32_vec("pos", vec![x, y, z]);
+                record_compound_tag.insert_i32("free_tickets", record.free_tickets);
+
+                record_compound_tags.push(record_compound_tag);
+            }
+
+            section_compound_tag.insert_bool("Valid", section.valid);
+            section_compound_tag.insert_compound_tag_vec("Records", record_compound_tags);
+
+            sections_compound_tag.insert_compound_tag(section.y.to_string(), section_compound_tag);
+        }
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", self.data_version);
+        chunk_compound_tag.insert_compound_tag("Sections", sections_compound_tag);
+
+        chunk_compound_tag
+    }
+}
+
+/// Region, entities and poi folders of single dimension.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::storage::{ChunkStorage, StorageKind};
+///
+/// // Test data contains only `region` folder.
+/// let chunk_storage = ChunkStorage::new("test");
+/// let chunk_compound_tag = chunk_storage.load_chunk(4, 2).unwrap();
+///
+/// assert!(chunk_storage.load_entities(4, 2).unwrap().is_none());
+/// assert!(chunk_storage.provider(StorageKind::Poi).folder_path().ends_with("poi"));
+/// ```
+pub struct ChunkStorage {
+    /// Dimension folder which contains region folders.
+    dimension_path: PathBuf,
+    /// Provider of `region` folder.
+    region_provider: AnvilChunkProvider<'static>,
+    /// Provider of `entities` folder.
+    entities_provider: AnvilChunkProvider<'static>,
+    /// Provider of `poi` folder.
+    poi_provider: AnvilChunkProvider<'static>,
+}
+
+impl ChunkStorage {
+    pub fn new<P: AsRef<Path>>(dimension_path: P) -> Self {
+        let dimension_path = dimension_path.as_ref().to_path_buf();
+        let provider = |kind: StorageKind| {
+            AnvilChunkProvider::from_path_buf(dimension_path.join(kind.folder_name()))
+        };
+
+        ChunkStorage {
+            region_provider: provider(StorageKind::Region),
+            entities_provider: provider(StorageKind::Entities),
+            poi_provider: provider(StorageKind::Poi),
+            dimension_path,
+        }
+    }
+
+    /// Returns dimension folder.
+    pub fn dimension_path(&self) -> &Path {
+        &self.dimension_path
+    }
+
+    /// Returns chunk provider of specified kind.
+    pub fn provider(&self, kind: StorageKind) -> &AnvilChunkProvider<'static> {
+        match kind {
+            StorageKind::Region => &self.region_provider,
+            StorageKind::Entities => &self.entities_provider,
+            StorageKind::Poi => &self.poi_provider,
+        }
+    }
+
+    /// Loads raw chunk compound tag of specified kind.
+    ///
+    /// Returns none if there is no chunk.
+    pub fn load_raw(
+        &self,
+        kind: StorageKind,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<Option<CompoundTag>, ChunkLoadError> {
+        match self.provider(kind).load_chunk(chunk_x, chunk_z) {
+            Ok(chunk_compound_tag) => Ok(Some(chunk_compound_tag)),
+            Err(ChunkLoadError::RegionNotFound { .. }) => Ok(None),
+            Err(ChunkLoadError::ChunkNotFound { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Saves raw chunk compound tag of specified kind.
+    pub fn save_raw(
+        &self,
+        kind: StorageKind,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk_compound_tag: CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        self.provider(kind)
+            .save_chunk(chunk_x, chunk_z, chunk_compound_tag)
+    }
+
+    /// Loads chunk from `region` folder.
+    pub fn load_chunk(&self, chunk_x: i32, chunk_z: i32) -> Result<CompoundTag, ChunkLoadError> {
+        self.region_provider.load_chunk(chunk_x, chunk_z)
+    }
+
+    /// Saves chunk to `region` folder.
+    pub fn save_chunk(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        chunk_compound_tag: CompoundTag,
+    ) -> Result<(), ChunkSaveError> {
+        self.save_raw(StorageKind::Region, chunk_x, chunk_z, chunk_compound_tag)
+    }
+
+    /// Loads entities of chunk.
+    ///
+    /// Returns none if chunk has no entities file entry.
+    pub fn load_entities(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<Option<EntityChunk>, StorageError> {
+        match self.load_raw(StorageKind::Entities, chunk_x, chunk_z)? {
+            Some(chunk_compound_tag) => Ok(Some(EntityChunk::from_tag(&chunk_compound_tag)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves entities of chunk.
+    pub fn save_entities(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        entity_chunk: &EntityChunk,
+    ) -> Result<(), ChunkSaveError> {
+        let chunk_compound_tag = entity_chunk.to_tag(chunk_x, chunk_z);
+
+        self.save_raw(StorageKind::Entities, chunk_x, chunk_z, chunk_compound_tag)
+    }
+
+    /// Loads points of interest of chunk.
+    ///
+    /// Returns none if chunk has no poi file entry.
+    pub fn load_poi(&self, chunk_x: i32, chunk_z: i32) -> Result<Option<PoiChunk>, StorageError> {
+        match self.load_raw(StorageKind::Poi, chunk_x, chunk_z)? {
+            Some(chunk_compound_tag) => Ok(Some(PoiChunk::from_tag(&chunk_compound_tag)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Saves points of interest of chunk.
+    pub fn save_poi(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        poi_chunk: &PoiChunk,
+    ) -> Result<(), ChunkSaveError> {
+        self.save_raw(StorageKind::Poi, chunk_x, chunk_z, poi_chunk.to_tag())
+    }
+
+    /// Deletes chunk from all storages.
+    ///
+    /// Returns kinds of storages which contained chunk.
+    pub fn delete_chunk(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+    ) -> Result<Vec<StorageKind>, ChunkSaveError> {
+        let mut deleted_kinds = Vec::new();
+
+        for kind in StorageKind::ALL.iter() {
+            if self.provider(*kind).delete_chunk(chunk_x, chunk_z)? {
+                deleted_kinds.push(*kind);
+            }
+        }
+
+        Ok(deleted_kinds)
+    }
+
+    /// Moves chunk to other coordinates in all storages.
+    ///
+    /// Chunk coordinates and positions of entities, block entities, ticks
+    /// and points of interest are shifted. Chunk existing at destination
+    /// coordinates are replaced. Source chunks are deleted only after all
+    /// destination chunks were written.
+    ///
+    /// Returns kinds of storages which contained chunk.
+    pub fn move_chunk(
+        &self,
+        chunk_x: i32,
+        chunk_z: i32,
+        to_chunk_x: i32,
+        to_chunk_z: i32,
+    ) -> Result<Vec<StorageKind>, StorageError> {
+        let mut chunk_compound_tags = Vec::new();
+
+        for kind in StorageKind::ALL.iter() {
+            chunk_compound_tags.push((*kind, self.load_raw(*kind, chunk_x, chunk_z)?));
+        }
+
+        let moved_kinds: Vec<StorageKind> = chunk_compound_tags
+            .iter()
+            .filter(|(_, chunk_compound_tag)| chunk_compound_tag.is_some())
+            .map(|(kind, _)| *kind)
+            .collect();
+
+        if (chunk_x, chunk_z) == (to_chunk_x, to_chunk_z) {
+            return Ok(moved_kinds);
+        }
+
+        for (kind, chunk_compound_tag) in chunk_compound_tags {
+            let mut chunk_compound_tag = match chunk_compound_tag {
+                Some(chunk_compound_tag) => chunk_compound_tag,
+                None => {
+                    self.provider(kind).delete_chunk(to_chunk_x, to_chunk_z)?;
+                    continue;
+                }
+            };
+
+            translate_chunk(
+                &mut chunk_compound_tag,
+                to_chunk_x - chunk_x,
+                to_chunk_z - chunk_z,
+            )?;
+
+            self.save_raw(kind, to_chunk_x, to_chunk_z, chunk_compound_tag)?;
+        }
+
+        for kind in &moved_kinds {
+            self.provider(*kind).delete_chunk(chunk_x, chunk_z)?;
+        }
+
+        Ok(moved_kinds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::{ChunkStorage, EntityChunk, PoiChunk, PoiRecord, PoiSection, StorageKind};
+    use nbt::CompoundTag;
+    use tempfile::TempDir;
+
+    fn entity_chunk() -> EntityChunk {
+        let mut entity_compound_tag = CompoundTag::new();
+        entity_compound_tag.insert_str("id", "minecraft:cow");
+        entity_compound_tag.insert_f64_vec("Pos", vec![20.5, 64.0, 20.5]);
+
+        EntityChunk {
+            data_version: 2730,
+            entities: vec![entity_compound_tag],
+        }
+    }
+
+    fn poi_chunk() -> PoiChunk {
+        let record = PoiRecord {
+            kind: "minecraft:home".to_owned(),
+            position: (1, 70, 2),
+            free_tickets: 1,
+        };
+
+        let section = PoiSection {
+            y: 4,
+            valid: true,
+            records: vec![record],
+        };
+
+        PoiChunk {
+            data_version: 2730,
+            sections: vec![section],
+        }
+    }
+
+    fn region_chunk(chunk_x: i32, chunk_z: i32) -> CompoundTag {
+        let mut chunk_compound_tag = CompoundTag::new();
+        let mut level_compound_tag = CompoundTag::new();
+
+        level_compound_tag.insert_i32("xPos", chunk_x);
+        level_compound_tag.insert_i32("zPos", chunk_z);
+
+        chunk_compound_tag.insert_i32("DataVersion", 2730);
+        chunk_compound_tag.insert_compound_tag("Level", level_compound_tag);
+
+        chunk_compound_tag
+    }
+
+    #[test]
+    fn test_entities_and_poi_roundtrip() {
+        let folder = TempDir::new().unwrap();
+        let chunk_storage = ChunkStorage::new(folder.path());
+
+        chunk_storage.save_entities(3, -7, &entity_chunk()).unwrap();
+        chunk_storage.save_poi(3, -7, &poi_chunk()).unwrap();
+
+        let raw_compound_tag = chunk_storage
+            .load_raw(StorageKind::Entities, 3, -7)
+            .unwrap()
+            .unwrap();
+        assert_eq!(raw_compound_tag.get_i32_vec("Position").unwrap(), &[3, -7]);
+
+        let entity_chunk = chunk_storage.load_entities(3, -7).unwrap().unwrap();
+        assert_eq!(entity_chunk.data_version, 2730);
+        assert_eq!(
+            entity_chunk.entities[0].get_str("id").unwrap(),
+            "minecraft:cow"
+        );
+        assert_eq!(chunk_storage.load_poi(3, -7).unwrap(), Some(poi_chunk()));
+        assert_eq!(chunk_storage.load_poi(3, -6).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_chunk() {
+        let folder = TempDir::new().unwrap();
+        let chunk_storage = ChunkStorage::new(folder.path());
+
+        chunk_storage.save_chunk(1, 1, region_chunk(1, 1)).unwrap();
+        chunk_storage.save_entities(1, 1, &entity_chunk()).unwrap();
+
+        assert_eq!(
+            chunk_storage.delete_chunk(1, 1).unwrap(),
+            vec![StorageKind::Region, StorageKind::Entities]
+        );
+        assert!(chunk_storage
+            .load_raw(StorageKind::Region, 1, 1)
+            .unwrap()
+            .is_none());
+        assert!(chunk_storage.load_entities(1, 1).unwrap().is_none());
+        assert!(chunk_storage.delete_chunk(1, 1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_move_chunk() {
+        let folder = TempDir::new().unwrap();
+        let chunk_storage = ChunkStorage::new(folder.path());
+
+        chunk_storage.save_chunk(1, 1, region_chunk(1, 1)).unwrap();
+        chunk_storage.save_entities(1, 1, &entity_chunk()).unwrap();
+        chunk_storage.save_poi(40, 40, &poi_chunk()).unwrap();
+
+        let moved_kinds = chunk_storage.move_chunk(1, 1, 40, 40).unwrap();
+        assert_eq!(
+            moved_kinds,
+            vec![StorageKind::Region, StorageKind::Entities]
+        );
+
+        let chunk_compound_tag = chunk_storage.load_chunk(40, 40).unwrap();
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+        assert_eq!(level_compound_tag.get_i32("xPos").unwrap(), 40);
+
+        assert!(chunk_storage.load_entities(1, 1).unwrap().is_none());
+
+        let entity_chunk = chunk_storage.load_entities(40, 40).unwrap().unwrap();
+        assert_eq!(
+            entity_chunk.entities[0].get_f64_vec("Pos").unwrap(),
+            vec![644.5, 64.0, 644.5]
+        );
+
+        // Stale poi at destination are removed to keep storages in sync.
+        assert!(chunk_storage.load_poi(40, 40).unwrap().is_none());
+    }
+}
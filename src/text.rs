@@ -0,0 +1,929 @@
+//! Text representations of chunk NBT.
+//!
+//! SNBT is the stringified NBT used by the game commands. JSON keeps type
+//! of every tag by wrapping value into object with type name as the only
+//! key, for example `{"int": 4}` or `{"long_array": [1, 2]}`.
+//!
+//! Both representations can be parsed back without losing tag types.
+use nbt::{CompoundTag, Tag};
+use std::fmt::Write;
+use std::mem;
+use std::str::FromStr;
+
+/// Indentation of nested tags.
+const INDENT: &str = "    ";
+
+/// Returns pretty printed SNBT of compound tag.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::text::to_snbt;
+/// use nbt::CompoundTag;
+///
+/// let mut compound_tag = CompoundTag::new();
+/// compound_tag.insert_i32("xPos", 4);
+/// compound_tag.insert_i64_vec("data", vec![1, 2]);
+///
+/// assert_eq!(to_snbt(&compound_tag), "{\n    xPos: 4,\n    data: [L; 1L, 2L]\n}");
+/// ```
+pub fn to_snbt(compound_tag: &CompoundTag) -> String {
+    let mut snbt = String::new();
+    write_snbt_compound(&mut snbt, compound_tag, 0);
+
+    snbt
+}
+
+fn write_indent(text: &mut String, depth: usize) {
+    for _ in 0..depth {
+        text.push_str(INDENT);
+    }
+}
+
+fn write_snbt_compound(snbt: &mut String, compound_tag: &CompoundTag, depth: usize) {
+    let mut tags = compound_tag.iter().peekable();
+
+    if tags.peek().is_none() {
+        snbt.push_str("{}");
+        return;
+    }
+
+    snbt.push_str("{\n");
+
+    while let Some((name, tag)) = tags.next() {
+        write_indent(snbt, depth + 1);
+        write_snbt_name(snbt, name);
+        snbt.push_str(": ");
+        write_snbt_tag(snbt, tag, depth + 1);
+
+        if tags.peek().is_some() {
+            snbt.push(',');
+        }
+
+        snbt.push('\n');
+    }
+
+    write_indent(snbt, depth);
+    snbt.push('}');
+}
+
+fn write_snbt_name(snbt: &mut String, name: &str) {
+    let is_plain = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c));
+
+    if is_plain {
+        snbt.push_str(name);
+    } else {
+        write_quoted(snbt, name);
+    }
+}
+
+pub(crate) fn write_quoted(text: &mut String, value: &str) {
+    text.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if c.is_control() => write!(text, "\\u{:04x}", c as u32).unwrap(),
+            c => text.push(c),
+        }
+    }
+
+    text.push('"');
+}
+
+fn write_snbt_array<T, F>(snbt: &mut String, prefix: &str, values: &[T], write_value: F)
+where
+    F: Fn(&mut String, &T),
+{
+    snbt.push('[');
+    snbt.push_str(prefix);
+    snbt.push(';');
+
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            snbt.push(',');
+        }
+
+        snbt.push(' ');
+        write_value(snbt, value);
+    }
+
+    snbt.push(']');
+}
+
+fn write_snbt_tag(snbt: &mut String, tag: &Tag, depth: usize) {
+    match tag {
+        Tag::Byte(value) => write!(snbt, "{}b", value).unwrap(),
+        Tag::Short(value) => write!(snbt, "{}s", value).unwrap(),
+        Tag::Int(value) => write!(snbt, "{}", value).unwrap(),
+        Tag::Long(value) => write!(snbt, "{}L", value).unwrap(),
+        Tag::Float(value) => write!(snbt, "{}f", value).unwrap(),
+        Tag::Double(value) => write!(snbt, "{}d", value).unwrap(),
+        Tag::String(value) => write_quoted(snbt, value),
+        Tag::ByteArray(values) => write_snbt_array(snbt, "B", values, |snbt, value| {
+            write!(snbt, "{}b", value).unwrap()
+        }),
+        Tag::IntArray(values) => write_snbt_array(snbt, "I", values, |snbt, value| {
+            write!(snbt, "{}", value).unwrap()
+        }),
+        Tag::LongArray(values) => write_snbt_array(snbt, "L", values, |snbt, value| {
+            write!(snbt, "{}L", value).unwrap()
+        }),
+        Tag::List(tags) => {
+            if tags.is_empty() {
+                snbt.push_str("[]");
+                return;
+            }
+
+            snbt.push_str("[\n");
+
+            for (index, tag) in tags.iter().enumerate() {
+                write_indent(snbt, depth + 1);
+                write_snbt_tag(snbt, tag, depth + 1);
+
+                if index + 1 < tags.len() {
+                    snbt.push(',');
+                }
+
+                snbt.push('\n');
+            }
+
+            write_indent(snbt, depth);
+            snbt.push(']');
+        }
+        Tag::Compound(compound_tag) => write_snbt_compound(snbt, compound_tag, depth),
+    }
+}
+
+/// Returns pretty printed JSON of compound tag with tag types.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::text::to_json;
+/// use nbt::CompoundTag;
+///
+/// let mut compound_tag = CompoundTag::new();
+/// compound_tag.insert_i8("isLightOn", 1);
+///
+/// assert_eq!(to_json(&compound_tag), "{\n    \"isLightOn\": {\"byte\": 1}\n}");
+/// ```
+pub fn to_json(compound_tag: &CompoundTag) -> String {
+    let mut json = String::new();
+    write_json_compound(&mut json, compound_tag, 0);
+
+    json
+}
+
+/// Returns type name used as JSON key.
+fn json_type_name(tag: &Tag) -> &'static str {
+    match tag {
+        Tag::Byte(_) => "byte",
+        Tag::Short(_) => "short",
+        Tag::Int(_) => "int",
+        Tag::Long(_) => "long",
+        Tag::Float(_) => "float",
+        Tag::Double(_) => "double",
+        Tag::ByteArray(_) => "byte_array",
+        Tag::String(_) => "string",
+        Tag::List(_) => "list",
+        Tag::Compound(_) => "compound",
+        Tag::IntArray(_) => "int_array",
+        Tag::LongArray(_) => "long_array",
+    }
+}
+
+fn write_json_compound(json: &mut String, compound_tag: &CompoundTag, depth: usize) {
+    let mut tags = compound_tag.iter().peekable();
+
+    if tags.peek().is_none() {
+        json.push_str("{}");
+        return;
+    }
+
+    json.push_str("{\n");
+
+    while let Some((name, tag)) = tags.next() {
+        write_indent(json, depth + 1);
+        write_quoted(json, name);
+        json.push_str(": ");
+        write_json_tag(json, tag, depth + 1);
+
+        if tags.peek().is_some() {
+            json.push(',');
+        }
+
+        json.push('\n');
+    }
+
+    write_indent(json, depth);
+    json.push('}');
+}
+
+/// Writes floating point number, non finite values are written as strings.
+fn write_json_float(json: &mut String, value: f64, text: String) {
+    if value.is_finite() {
+        json.push_str(&text);
+    } else {
+        write_quoted(json, &text);
+    }
+}
+
+fn write_json_numbers<T: ToString>(json: &mut String, values: &[T]) {
+    json.push('[');
+
+    for (index, value) in values.iter().enumerate() {
+        if index > 0 {
+            json.push_str(", ");
+        }
+
+        json.push_str(&value.to_string());
+    }
+
+    json.push(']');
+}
+
+fn write_json_tag(json: &mut String, tag: &Tag, depth: usize) {
+    json.push_str("{\"");
+    json.push_str(json_type_name(tag));
+    json.push_str("\": ");
+
+    match tag {
+        Tag::Byte(value) => write!(json, "{}", value).unwrap(),
+        Tag::Short(value) => write!(json, "{}", value).unwrap(),
+        Tag::Int(value) => write!(json, "{}", value).unwrap(),
+        Tag::Long(value) => write!(json, "{}", value).unwrap(),
+        Tag::Float(value) => write_json_float(json, *value as f64, value.to_string()),
+        Tag::Double(value) => write_json_float(json, *value, value.to_string()),
+        Tag::String(value) => write_quoted(json, value),
+        Tag::ByteArray(values) => write_json_numbers(json, values),
+        Tag::IntArray(values) => write_json_numbers(json, values),
+        Tag::LongArray(values) => write_json_numbers(json, values),
+        Tag::List(tags) => {
+            if tags.is_empty() {
+                json.push_str("[]");
+            } else {
+                json.push_str("[\n");
+
+                for (index, tag) in tags.iter().enumerate() {
+                    write_indent(json, depth + 1);
+                    write_json_tag(json, tag, depth + 1);
+
+                    if index + 1 < tags.len() {
+                        json.push(',');
+                    }
+
+                    json.push('\n');
+                }
+
+                write_indent(json, depth);
+                json.push(']');
+            }
+        }
+        Tag::Compound(compound_tag) => write_json_compound(json, compound_tag, depth),
+    }
+
+    json.push('}');
+}
+
+/// Possible errors while parsing SNBT or JSON.
+///
+/// Positions are byte offsets in parsed text.
+#[derive(Debug)]
+pub enum TextParseError {
+    /// Text ended before value was complete.
+    UnexpectedEnd,
+    /// Character is not allowed at position.
+    UnexpectedCharacter { character: char, position: usize },
+    /// Number is malformed or does not fit into its tag type.
+    InvalidNumber { text: String, position: usize },
+    /// JSON tag type name is not known.
+    UnknownType { name: String, position: usize },
+    /// List elements have different tag types.
+    MixedList { position: usize },
+}
+
+/// Parses SNBT written by `to_snbt` or by hand.
+///
+/// Numbers without suffix are ints when integer and doubles otherwise,
+/// unquoted text which is not a number is a string.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::text::from_snbt;
+///
+/// let compound_tag = from_snbt("{xPos: 4, data: [L; 1L, 2L]}").unwrap();
+///
+/// assert_eq!(compound_tag.get_i32("xPos").unwrap(), 4);
+/// assert_eq!(compound_tag.get_i64_vec("data").unwrap(), &vec![1, 2]);
+/// ```
+pub fn from_snbt(snbt: &str) -> Result<CompoundTag, TextParseError> {
+    let mut parser = Parser::new(snbt);
+    parser.expect('{')?;
+
+    let compound_tag = parser.snbt_compound()?;
+    parser.finish()?;
+
+    Ok(compound_tag)
+}
+
+/// Parses JSON with tag types written by `to_json`.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::text::from_json;
+///
+/// let compound_tag = from_json(r#"{"isLightOn": {"byte": 1}}"#).unwrap();
+///
+/// assert_eq!(compound_tag.get_i8("isLightOn").unwrap(), 1);
+/// ```
+pub fn from_json(json: &str) -> Result<CompoundTag, TextParseError> {
+    let mut parser = Parser::new(json);
+    parser.expect('{')?;
+
+    let compound_tag = parser.json_compound()?;
+    parser.finish()?;
+
+    Ok(compound_tag)
+}
+
+/// Returns whether character can be part of unquoted SNBT string.
+fn is_unquoted_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-.+".contains(c)
+}
+
+/// Returns whether text is integer with optional sign.
+fn is_integer(text: &str) -> bool {
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Returns tag of unquoted SNBT value.
+fn snbt_value(token: &str, position: usize) -> Result<Tag, TextParseError> {
+    let invalid_number = || TextParseError::InvalidNumber {
+        text: token.to_owned(),
+        position,
+    };
+
+    match token {
+        "true" => return Ok(Tag::Byte(1)),
+        "false" => return Ok(Tag::Byte(0)),
+        _ => {}
+    }
+
+    if is_integer(token) {
+        return token.parse().map(Tag::Int).map_err(|_| invalid_number());
+    }
+
+    let suffix = token.chars().last().unwrap_or_default();
+    let number = &token[..token.len() - suffix.len_utf8()];
+
+    match suffix.to_ascii_lowercase() {
+        'b' if is_integer(number) => number.parse().map(Tag::Byte).map_err(|_| invalid_number()),
+        's' if is_integer(number) => number.parse().map(Tag::Short).map_err(|_| invalid_number()),
+        'l' if is_integer(number) => number.parse().map(Tag::Long).map_err(|_| invalid_number()),
+        'f' => match number.parse() {
+            Ok(value) => Ok(Tag::Float(value)),
+            Err(_) => Ok(Tag::String(token.to_owned())),
+        },
+        'd' => match number.parse() {
+            Ok(value) => Ok(Tag::Double(value)),
+            Err(_) => Ok(Tag::String(token.to_owned())),
+        },
+        _ => {
+            let has_digit = token.chars().any(|c| c.is_ascii_digit());
+
+            match token.parse() {
+                Ok(value) if has_digit => Ok(Tag::Double(value)),
+                _ => Ok(Tag::String(token.to_owned())),
+            }
+        }
+    }
+}
+
+/// Checks that all list elements have the same tag type.
+fn check_list(tags: &[Tag], position: usize) -> Result<(), TextParseError> {
+    let mut discriminants = tags.iter().map(mem::discriminant);
+
+    match discriminants.next() {
+        Some(first) if discriminants.any(|discriminant| discriminant != first) => {
+            Err(TextParseError::MixedList { position })
+        }
+        _ => Ok(()),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Parser { text, position: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn next_char(&mut self) -> Result<char, TextParseError> {
+        let c = self.peek().ok_or(TextParseError::UnexpectedEnd)?;
+        self.position += c.len_utf8();
+
+        Ok(c)
+    }
+
+    /// Returns error for character at current position.
+    fn unexpected(&self) -> TextParseError {
+        match self.peek() {
+            Some(character) => TextParseError::UnexpectedCharacter {
+                character,
+                position: self.position,
+            },
+            None => TextParseError::UnexpectedEnd,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+
+            self.position += c.len_utf8();
+        }
+    }
+
+    /// Consumes character if it is next after whitespace.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), TextParseError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Checks that only whitespace is left.
+    fn finish(&mut self) -> Result<(), TextParseError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some(_) => Err(self.unexpected()),
+            None => Ok(()),
+        }
+    }
+
+    /// Parses comma separated elements until closing character.
+    ///
+    /// Opening character must be already consumed.
+    fn sequence<T, F>(&mut self, close: char, mut element: F) -> Result<Vec<T>, TextParseError>
+    where
+        F: FnMut(&mut Self) -> Result<T, TextParseError>,
+    {
+        let mut elements = Vec::new();
+
+        if self.eat(close) {
+            return Ok(elements);
+        }
+
+        loop {
+            self.skip_whitespace();
+            elements.push(element(self)?);
+
+            if !self.eat(',') {
+                self.expect(close)?;
+                return Ok(elements);
+            }
+        }
+    }
+
+    /// Reads string in single or double quotes.
+    fn quoted(&mut self) -> Result<String, TextParseError> {
+        let quote = self.next_char()?;
+        let mut value = String::new();
+
+        loop {
+            let position = self.position;
+
+            match self.next_char()? {
+                c if c == quote => return Ok(value),
+                '\\' => {
+                    let escape_position = self.position;
+
+                    match self.next_char()? {
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'u' => value.push(self.unicode_escape(escape_position)?),
+                        c @ ('"' | '\'' | '\\' | '/') => value.push(c),
+                        character => {
+                            return Err(TextParseError::UnexpectedCharacter {
+                                character,
+                                position: escape_position,
+                            })
+                        }
+                    }
+                }
+                c if c.is_control() && c != '\t' => {
+                    return Err(TextParseError::UnexpectedCharacter {
+                        character: c,
+                        position,
+                    })
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self, position: usize) -> Result<char, TextParseError> {
+        let digits = self
+            .text
+            .get(self.position..self.position + 4)
+            .ok_or(TextParseError::UnexpectedEnd)?;
+
+        let character = u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| TextParseError::InvalidNumber {
+                text: digits.to_owned(),
+                position,
+            })?;
+
+        self.position += 4;
+
+        Ok(character)
+    }
+
+    /// Reads characters allowed in unquoted SNBT string.
+    fn unquoted(&mut self) -> &'a str {
+        let start = self.position;
+
+        while let Some(c) = self.peek() {
+            if !is_unquoted_character(c) {
+                break;
+            }
+
+            self.position += c.len_utf8();
+        }
+
+        &self.text[start..self.position]
+    }
+
+    fn snbt_compound(&mut self) -> Result<CompoundTag, TextParseError> {
+        let tags = self.sequence('}', |parser| {
+            let name = parser.snbt_name()?;
+            parser.expect(':')?;
+
+            Ok((name, parser.snbt_tag()?))
+        })?;
+
+        Ok(tags.into_iter().collect())
+    }
+
+    fn snbt_name(&mut self) -> Result<String, TextParseError> {
+        match self.peek() {
+            Some('"') | Some('\'') => self.quoted(),
+            _ => match self.unquoted() {
+                "" => Err(self.unexpected()),
+                name => Ok(name.to_owned()),
+            },
+        }
+    }
+
+    fn snbt_tag(&mut self) -> Result<Tag, TextParseError> {
+        self.skip_whitespace();
+        let position = self.position;
+
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                Ok(Tag::Compound(self.snbt_compound()?))
+            }
+            Some('[') => {
+                self.position += 1;
+                self.snbt_list(position)
+            }
+            Some('"') | Some('\'') => Ok(Tag::String(self.quoted()?)),
+            _ => match self.unquoted() {
+                "" => Err(self.unexpected()),
+                token => snbt_value(token, position),
+            },
+        }
+    }
+
+    /// Parses list or typed array, opening bracket must be already consumed.
+    fn snbt_list(&mut self, position: usize) -> Result<Tag, TextParseError> {
+        let rest = &self.text[self.position..];
+
+        let array_type = match rest.get(..2) {
+            Some("B;") | Some("I;") | Some("L;") => &rest[..1],
+            _ => {
+                let tags = self.sequence(']', Self::snbt_tag)?;
+                check_list(&tags, position)?;
+
+                return Ok(Tag::List(tags));
+            }
+        };
+
+        self.position += 2;
+
+        let tag = match array_type {
+            "B" => Tag::ByteArray(self.snbt_array(|tag| match tag {
+                Tag::Byte(value) => Some(value),
+                _ => None,
+            })?),
+            "I" => Tag::IntArray(self.snbt_array(|tag| match tag {
+                Tag::Int(value) => Some(value),
+                _ => None,
+            })?),
+            _ => Tag::LongArray(self.snbt_array(|tag| match tag {
+                Tag::Long(value) => Some(value),
+                _ => None,
+            })?),
+        };
+
+        Ok(tag)
+    }
+
+    /// Parses typed array values, element tag must match array type.
+    fn snbt_array<T>(&mut self, value: fn(Tag) -> Option<T>) -> Result<Vec<T>, TextParseError> {
+        self.sequence(']', |parser| {
+            let position = parser.position;
+
+            match parser.unquoted() {
+                "" => Err(parser.unexpected()),
+                token => value(snbt_value(token, position)?).ok_or_else(|| {
+                    TextParseError::InvalidNumber {
+                        text: token.to_owned(),
+                        position,
+                    }
+                }),
+            }
+        })
+    }
+
+    fn json_string(&mut self) -> Result<String, TextParseError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('"') => self.quoted(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn json_compound(&mut self) -> Result<CompoundTag, TextParseError> {
+        let tags = self.sequence('}', |parser| {
+            let name = parser.json_string()?;
+            parser.expect(':')?;
+
+            Ok((name, parser.json_tag()?))
+        })?;
+
+        Ok(tags.into_iter().collect())
+    }
+
+    fn json_number<T: FromStr>(&mut self) -> Result<T, TextParseError> {
+        self.skip_whitespace();
+        let start = self.position;
+
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || "-+.eE".contains(c)) {
+                break;
+            }
+
+            self.position += 1;
+        }
+
+        let text = &self.text[start..self.position];
+
+        if text.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        text.parse().map_err(|_| TextParseError::InvalidNumber {
+            text: text.to_owned(),
+            position: start,
+        })
+    }
+
+    /// Parses floating point number which is written as string when not finite.
+    fn json_float<T: FromStr>(&mut self) -> Result<T, TextParseError> {
+        self.skip_whitespace();
+        let position = self.position;
+
+        if self.peek() != Some('"') {
+            return self.json_number();
+        }
+
+        let text = self.quoted()?;
+
+        text.parse()
+            .map_err(|_| TextParseError::InvalidNumber { text, position })
+    }
+
+    fn json_numbers<T: FromStr>(&mut self) -> Result<Vec<T>, TextParseError> {
+        self.expect('[')?;
+        self.sequence(']', Self::json_number)
+    }
+
+    fn json_tag(&mut self) -> Result<Tag, TextParseError> {
+        self.expect('{')?;
+        self.skip_whitespace();
+
+        let position = self.position;
+        let name = self.json_string()?;
+        self.expect(':')?;
+
+        let tag = match name.as_str() {
+            "byte" => Tag::Byte(self.json_number()?),
+            "short" => Tag::Short(self.json_number()?),
+            "int" => Tag::Int(self.json_number()?),
+            "long" => Tag::Long(self.json_number()?),
+            "float" => Tag::Float(self.json_float()?),
+            "double" => Tag::Double(self.json_float()?),
+            "string" => Tag::String(self.json_string()?),
+            "byte_array" => Tag::ByteArray(self.json_numbers()?),
+            "int_array" => Tag::IntArray(self.json_numbers()?),
+            "long_array" => Tag::LongArray(self.json_numbers()?),
+            "list" => {
+                self.expect('[')?;
+                let tags = self.sequence(']', Self::json_tag)?;
+                check_list(&tags, position)?;
+
+                Tag::List(tags)
+            }
+            "compound" => {
+                self.expect('{')?;
+                Tag::Compound(self.json_compound()?)
+            }
+            _ => return Err(TextParseError::UnknownType { name, position }),
+        };
+
+        self.expect('}')?;
+
+        Ok(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::text::{from_json, from_snbt, to_json, to_snbt, TextParseError};
+    use crate::AnvilChunkProvider;
+    use nbt::{CompoundTag, Tag};
+
+    fn compound_tag() -> CompoundTag {
+        let mut section_compound_tag = CompoundTag::new();
+        section_compound_tag.insert_i8("Y", -1);
+        section_compound_tag.insert_i8_vec("BlockLight", vec![0, 15]);
+
+        let mut compound_tag = CompoundTag::new();
+        compound_tag.insert_str("Status", "full");
+        compound_tag.insert_f32("speed", 0.5);
+        compound_tag.insert_f64("nan", f64::NAN);
+        compound_tag.insert_str("odd key", "quote \" and \\");
+        compound_tag.insert_compound_tag_vec("sections", vec![section_compound_tag]);
+        compound_tag.insert("empty", Tag::List(Vec::new()));
+
+        compound_tag
+    }
+
+    #[test]
+    fn test_to_snbt() {
+        let expected_snbt = r#"{
+    Status: "full",
+    speed: 0.5f,
+    nan: NaNd,
+    "odd key": "quote \" and \\",
+    sections: [
+        {
+            Y: -1b,
+            BlockLight: [B; 0b, 15b]
+        }
+    ],
+    empty: []
+}"#;
+
+        assert_eq!(to_snbt(&compound_tag()), expected_snbt);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let expected_json = r#"{
+    "Status": {"string": "full"},
+    "speed": {"float": 0.5},
+    "nan": {"double": "NaN"},
+    "odd key": {"string": "quote \" and \\"},
+    "sections": {"list": [
+        {"compound": {
+            "Y": {"byte": -1},
+            "BlockLight": {"byte_array": [0, 15]}
+        }}
+    ]},
+    "empty": {"list": []}
+}"#;
+
+        assert_eq!(to_json(&compound_tag()), expected_json);
+    }
+
+    #[test]
+    fn test_snbt_round_trip() {
+        let snbt = to_snbt(&compound_tag());
+
+        assert_eq!(to_snbt(&from_snbt(&snbt).unwrap()), snbt);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let json = to_json(&compound_tag());
+
+        assert_eq!(to_json(&from_json(&json).unwrap()), json);
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+        let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+        let snbt = to_snbt(&chunk_compound_tag);
+
+        let snbt_compound_tag = from_snbt(&snbt).unwrap();
+        let json_compound_tag = from_json(&to_json(&chunk_compound_tag)).unwrap();
+
+        assert_eq!(to_snbt(&snbt_compound_tag), snbt);
+        assert_eq!(to_snbt(&json_compound_tag), snbt);
+    }
+
+    #[test]
+    fn test_from_snbt_types() {
+        let compound_tag = from_snbt(
+            "{a: [B; 1b, -2B], b: [I;], c: [L; 3L], d: 1b, e: 2, f: 1.5, g: abc, 'h i': true}",
+        )
+        .unwrap();
+
+        assert_eq!(compound_tag.get_i8_vec("a").unwrap(), &vec![1, -2]);
+        assert!(compound_tag.get_i32_vec("b").unwrap().is_empty());
+        assert_eq!(compound_tag.get_i64_vec("c").unwrap(), &vec![3]);
+        assert_eq!(compound_tag.get_i8("d").unwrap(), 1);
+        assert_eq!(compound_tag.get_i32("e").unwrap(), 2);
+        assert_eq!(compound_tag.get_f64("f").unwrap(), 1.5);
+        assert_eq!(compound_tag.get_str("g").unwrap(), "abc");
+        assert_eq!(compound_tag.get_i8("h i").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match from_snbt("{a: 300b}") {
+            Err(TextParseError::InvalidNumber { text, position }) => {
+                assert_eq!(text, "300b");
+                assert_eq!(position, 4);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        match from_snbt("{a: [1, 2b]}") {
+            Err(TextParseError::MixedList { position: 4 }) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        match from_snbt("{a: 1") {
+            Err(TextParseError::UnexpectedEnd) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        match from_json(r#"{"a": {"integer": 1}}"#) {
+            Err(TextParseError::UnknownType { name, position: 7 }) => assert_eq!(name, "integer"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+
+        match from_json(r#"{"a": {"int": 1}} x"#) {
+            Err(TextParseError::UnexpectedCharacter {
+                character: 'x',
+                position: 18,
+            }) => {}
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+//! Copying and moving chunks between providers.
+//!
+//! Chunks placed at other coordinates have their coordinates and positions
+//! of entities, block entities, scheduled ticks and points of interest
+//! shifted. Structure references are left as is.
+use crate::chunk::ChunkDataError;
+use crate::version::ChunkVersion;
+use crate::{AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::{CompoundTag, Tag};
+use std::path::Path;
+
+/// Possible errors while transferring chunks.
+#[derive(Debug)]
+pub enum TransferError {
+    /// Error while loading chunk.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while saving or deleting chunk.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// Chunk data are not readable.
+    ChunkDataError { chunk_data_error: ChunkDataError },
+    /// Source and destination providers use the same folder.
+    SameFolder,
+}
+
+impl From<ChunkLoadError> for TransferError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        TransferError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for TransferError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        TransferError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<ChunkDataError> for TransferError {
+    fn from(chunk_data_error: ChunkDataError) -> Self {
+        TransferError::ChunkDataError { chunk_data_error }
+    }
+}
+
+/// Names of block entity and tick lists of all chunk layouts.
+const BLOCK_POSITION_LISTS: [&str; 6] = [
+    "TileEntities",
+    "TileTicks",
+    "LiquidTicks",
+    "block_entities",
+    "block_ticks",
+    "fluid_ticks",
+];
+
+/// Shifts chunk by offset in chunks.
+///
+/// Region chunks, entity chunks stored since 1.17 and point of interest
+/// chunks are supported.
+pub fn translate_chunk(
+    chunk_compound_tag: &mut CompoundTag,
+    offset_x: i32,
+    offset_z: i32,
+) -> Result<(), ChunkDataError> {
+    let block_offset_x = offset_x * 16;
+    let block_offset_z = offset_z * 16;
+
+    if chunk_compound_tag.contains_key("Position") {
+        let position: &mut Vec<i32> = chunk_compound_tag.get_mut("Position")?;
+
+        if position.len() != 2 {
+            return Err(ChunkDataError::InvalidLength {
+                name: "Position".to_owned(),
+                length: position.len(),
+                expected_length: 2,
+            });
+        }
+
+        position[0] += offset_x;
+        position[1] += offset_z;
+
+        return translate_entities(chunk_compound_tag, block_offset_x, block_offset_z);
+    }
+
+    // Only point of interest chunks have sections compound at top level.
+    if let Ok(sections_compound_tag) = chunk_compound_tag.get_mut::<&mut CompoundTag>("Sections") {
+        return translate_poi_sections(sections_compound_tag, block_offset_x, block_offset_z);
+    }
+
+    let chunk_version = ChunkVersion::detect(chunk_compound_tag);
+    let level_compound_tag = chunk_version.level_mut(chunk_compound_tag)?;
+
+    let chunk_x: &mut i32 = level_compound_tag.get_mut("xPos")?;
+    *chunk_x += offset_x;
+
+    let chunk_z: &mut i32 = level_compound_tag.get_mut("zPos")?;
+    *chunk_z += offset_z;
+
+    translate_entities(level_compound_tag, block_offset_x, block_offset_z)?;
+
+    for name in BLOCK_POSITION_LISTS.iter() {
+        if !level_compound_tag.contains_key(name) {
+            continue;
+        }
+
+        let tags: &mut Vec<Tag> = level_compound_tag.get_mut(name)?;
+
+        for tag in tags.iter_mut() {
+            if let Tag::Compound(compound_tag) = tag {
+                translate_block_position(compound_tag, block_offset_x, block_offset_z)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shifts `x` and `z` block coordinates.
+fn translate_block_position(
+    compound_tag: &mut CompoundTag,
+    block_offset_x: i32,
+    block_offset_z: i32,
+) -> Result<(), ChunkDataError> {
+    let x: &mut i32 = compound_tag.get_mut("x")?;
+    *x += block_offset_x;
+
+    let z: &mut i32 = compound_tag.get_mut("z")?;
+    *z += block_offset_z;
+
+    Ok(())
+}
+
+/// Shifts positions of point of interest records.
+fn translate_poi_sections(
+    sections_compound_tag: &mut CompoundTag,
+    block_offset_x: i32,
+    block_offset_z: i32,
+) -> Result<(), ChunkDataError> {
+    for (_, tag) in sections_compound_tag.iter_mut() {
+        let section_compound_tag = match tag {
+            Tag::Compound(section_compound_tag) => section_compound_tag,
+            _ => continue,
+        };
+
+        if !section_compound_tag.contains_key("Records") {
+            continue;
+        }
+
+        let tags: &mut Vec<Tag> = section_compound_tag.get_mut("Records")?;
+
+        for tag in tags.iter_mut() {
+            if let Tag::Compound(record_compound_tag) = tag {
+                let position: &mut Vec<i32> = record_compound_tag.get_mut("pos")?;
+
+                if position.len() != 3 {
+                    return Err(ChunkDataError::InvalidLength {
+                        name: "pos".to_owned(),
+                        length: position.len(),
+                        expected_length: 3,
+                    });
+                }
+
+                position[0] += block_offset_x;
+                position[2] += block_offset_z;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Shifts entities of `Entities` list if present.
+fn translate_entities(
+    compound_tag: &mut CompoundTag,
+    block_offset_x: i32,
+    block_offset_z: i32,
+) -> Result<(), ChunkDataError> {
+    if !compound_tag.contains_key("Entities") {
+        return Ok(());
+    }
+
+    let tags: &mut Vec<Tag> = compound_tag.get_mut("Entities")?;
+
+    for tag in tags.iter_mut() {
+        if let Tag::Compound(entity_compound_tag) = tag {
+            translate_entity(entity_compound_tag, block_offset_x, block_offset_z)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Shifts entity position, hanging entity block position and passengers.
+fn translate_entity(
+    entity_compound_tag: &mut CompoundTag,
+    block_offset_x: i32,
+    block_offset_z: i32,
+) -> Result<(), ChunkDataError> {
+    if entity_compound_tag.contains_key("Pos") {
+        let position: &mut Vec<Tag> = entity_compound_tag.get_mut("Pos")?;
+
+        if let [Tag::Double(x), _, Tag::Double(z)] = position.as_mut_slice() {
+            *x += block_offset_x as f64;
+            *z += block_offset_z as f64;
+        }
+    }
+
+    if entity_compound_tag.contains_key("TileX") {
+        let tile_x: &mut i32 = entity_compound_tag.get_mut("TileX")?;
+        *tile_x += block_offset_x;
+    }
+
+    if entity_compound_tag.contains_key("TileZ") {
+        let tile_z: &mut i32 = entity_compound_tag.get_mut("TileZ")?;
+        *tile_z += block_offset_z;
+    }
+
+    if entity_compound_tag.contains_key("Passengers") {
+        let tags: &mut Vec<Tag> = entity_compound_tag.get_mut("Passengers")?;
+
+        for tag in tags.iter_mut() {
+            if let Tag::Compound(passenger_compound_tag) = tag {
+                translate_entity(passenger_compound_tag, block_offset_x, block_offset_z)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies or moves chunks from one provider to another.
+///
+/// Providers must use different folders.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::transfer::ChunkTransfer;
+/// use anvil_region::AnvilChunkProvider;
+/// use tempfile::TempDir;
+///
+/// let source_provider = AnvilChunkProvider::new("test/region");
+///
+/// let folder = TempDir::new().unwrap();
+/// let destination_provider = AnvilChunkProvider::from_path(folder.path());
+///
+/// let transferred_chunk_positions = ChunkTransfer::new()
+///     .with_range(4, 2, 5, 3)
+///     .with_offset(100, -100)
+///     .transfer(&source_provider, &destination_provider)
+///     .unwrap();
+///
+/// assert!(transferred_chunk_positions.contains(&(4, 2)));
+/// assert!(destination_provider.load_chunk(104, -98).is_ok());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ChunkTransfer {
+    /// Inclusive minimum and maximum chunk coordinates.
+    range: Option<(i32, i32, i32, i32)>,
+    offset_x: i32,
+    offset_z: i32,
+    remove_source: bool,
+}
+
+impl ChunkTransfer {
+    /// Creates transfer which copies all chunks at the same coordinates.
+    pub fn new() -> Self {
+        ChunkTransfer::default()
+    }
+
+    /// Limits transfer to inclusive rectangle in chunk coordinates.
+    pub fn with_range(
+        mut self,
+        min_chunk_x: i32,
+        min_chunk_z: i32,
+        max_chunk_x: i32,
+        max_chunk_z: i32,
+    ) -> Self {
+        self.range = Some((min_chunk_x, min_chunk_z, max_chunk_x, max_chunk_z));
+        self
+    }
+
+    /// Sets offset in chunks added to coordinates of transferred chunks.
+    pub fn with_offset(mut self, offset_x: i32, offset_z: i32) -> Self {
+        self.offset_x = offset_x;
+        self.offset_z = offset_z;
+        self
+    }
+
+    /// Deletes chunks from source provider after they were saved to destination.
+    pub fn with_move(mut self, remove_source: bool) -> Self {
+        self.remove_source = remove_source;
+        self
+    }
+
+    fn in_range(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        match self.range {
+            Some((min_chunk_x, min_chunk_z, max_chunk_x, max_chunk_z)) => {
+                chunk_x >= min_chunk_x
+                    && chunk_x <= max_chunk_x
+                    && chunk_z >= min_chunk_z
+                    && chunk_z <= max_chunk_z
+            }
+            None => true,
+        }
+    }
+
+    /// Transfers chunks and returns their source coordinates.
+    ///
+    /// Chunks without offset are copied as stored bytes without decoding.
+    pub fn transfer(
+        &self,
+        source_provider: &AnvilChunkProvider,
+        destination_provider: &AnvilChunkProvider,
+    ) -> Result<Vec<(i32, i32)>, TransferError> {
+        if is_same_folder(
+            source_provider.folder_path(),
+            destination_provider.folder_path(),
+        ) {
+            return Err(TransferError::SameFolder);
+        }
+
+        let mut transferred_chunk_positions = Vec::new();
+
+        for (chunk_x, chunk_z) in source_provider.chunk_positions()? {
+            if !self.in_range(chunk_x, chunk_z) {
+                continue;
+            }
+
+            let to_chunk_x = chunk_x + self.offset_x;
+            let to_chunk_z = chunk_z + self.offset_z;
+
+            if self.offset_x == 0 && self.offset_z == 0 {
+                let (compression_scheme, compressed_data) =
+                    source_provider.load_chunk_bytes(chunk_x, chunk_z)?;
+
+                destination_provider.save_chunk_bytes(
+                    to_chunk_x,
+                    to_chunk_z,
+                    compression_scheme,
+                    &compressed_data,
+                )?;
+            } else {
+                let mut chunk_compound_tag = source_provider.load_chunk(chunk_x, chunk_z)?;
+                translate_chunk(&mut chunk_compound_tag, self.offset_x, self.offset_z)?;
+
+                destination_provider.save_chunk(to_chunk_x, to_chunk_z, chunk_compound_tag)?;
+            }
+
+            if self.remove_source {
+                source_provider.delete_chunk(chunk_x, chunk_z)?;
+            }
+
+            transferred_chunk_positions.push((chunk_x, chunk_z));
+        }
+
+        Ok(transferred_chunk_positions)
+    }
+}
+
+/// Returns true if both paths lead to the same folder.
+fn is_same_folder(first_path: &Path, second_path: &Path) -> bool {
+    match (first_path.canonicalize(), second_path.canonicalize()) {
+        (Ok(first_path), Ok(second_path)) => first_path == second_path,
+        _ => first_path == second_path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transfer::{translate_chunk, ChunkTransfer, TransferError};
+    use crate::AnvilChunkProvider;
+    use nbt::{CompoundTag, Tag};
+    use tempfile::TempDir;
+
+    fn entity(x: f64, z: f64) -> CompoundTag {
+        let mut entity_compound_tag = CompoundTag::new();
+        entity_compound_tag.insert_f64_vec("Pos", vec![x, 64.0, z]);
+
+        entity_compound_tag
+    }
+
+    fn block_tick(x: i32, z: i32) -> CompoundTag {
+        let mut tick_compound_tag = CompoundTag::new();
+        tick_compound_tag.insert_i32("x", x);
+        tick_compound_tag.insert_i32("y", 64);
+        tick_compound_tag.insert_i32("z", z);
+
+        tick_compound_tag
+    }
+
+    #[test]
+    fn test_translate_chunk() {
+        let mut passenger_compound_tag = entity(1.5, 2.5);
+        passenger_compound_tag.insert_i32("TileX", 1);
+        passenger_compound_tag.insert_i32("TileZ", 2);
+
+        let mut entity_compound_tag = entity(1.5, 2.5);
+        entity_compound_tag.insert_compound_tag_vec("Passengers", vec![passenger_compound_tag]);
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", 2860);
+        chunk_compound_tag.insert_i32("xPos", 0);
+        chunk_compound_tag.insert_i32("zPos", 0);
+        chunk_compound_tag.insert_compound_tag_vec("Entities", vec![entity_compound_tag]);
+        chunk_compound_tag.insert_compound_tag_vec("block_ticks", vec![block_tick(3, 4)]);
+
+        translate_chunk(&mut chunk_compound_tag, 2, -1).unwrap();
+
+        assert_eq!(chunk_compound_tag.get_i32("xPos").unwrap(), 2);
+        assert_eq!(chunk_compound_tag.get_i32("zPos").unwrap(), -1);
+
+        let entity_compound_tag = &chunk_compound_tag.get_compound_tag_vec("Entities").unwrap()[0];
+        assert_eq!(
+            entity_compound_tag.get_f64_vec("Pos").unwrap(),
+            vec![33.5, 64.0, -13.5]
+        );
+
+        let passenger_compound_tag = &entity_compound_tag
+            .get_compound_tag_vec("Passengers")
+            .unwrap()[0];
+        assert_eq!(passenger_compound_tag.get_i32("TileX").unwrap(), 33);
+        assert_eq!(passenger_compound_tag.get_i32("TileZ").unwrap(), -14);
+
+        let tick_compound_tag = &chunk_compound_tag
+            .get_compound_tag_vec("block_ticks")
+            .unwrap()[0];
+        assert_eq!(tick_compound_tag.get_i32("x").unwrap(), 35);
+        assert_eq!(tick_compound_tag.get_i32("z").unwrap(), -12);
+
+        let mut entity_chunk_compound_tag = CompoundTag::new();
+        entity_chunk_compound_tag.insert_i32_vec("Position", vec![0, 0]);
+        entity_chunk_compound_tag
+            .insert("Entities", Tag::List(vec![Tag::Compound(entity(0.0, 0.0))]));
+
+        translate_chunk(&mut entity_chunk_compound_tag, 1, 1).unwrap();
+
+        assert_eq!(
+            entity_chunk_compound_tag.get_i32_vec("Position").unwrap(),
+            &vec![1, 1]
+        );
+
+        let mut record_compound_tag = CompoundTag::new();
+        record_compound_tag.insert_str("type", "minecraft:home");
+        record_compound_tag.insert_i32_vec("pos", vec![1, 70, 2]);
+
+        let mut section_compound_tag = CompoundTag::new();
+        section_compound_tag.insert_compound_tag_vec("Records", vec![record_compound_tag]);
+
+        let mut sections_compound_tag = CompoundTag::new();
+        sections_compound_tag.insert_compound_tag("4", section_compound_tag);
+
+        let mut poi_chunk_compound_tag = CompoundTag::new();
+        poi_chunk_compound_tag.insert_i32("DataVersion", 2730);
+        poi_chunk_compound_tag.insert_compound_tag("Sections", sections_compound_tag);
+
+        translate_chunk(&mut poi_chunk_compound_tag, 1, -1).unwrap();
+
+        let record_compound_tag = &poi_chunk_compound_tag
+            .get_compound_tag("Sections")
+            .and_then(|sections_compound_tag| sections_compound_tag.get_compound_tag("4"))
+            .and_then(|section_compound_tag| section_compound_tag.get_compound_tag_vec("Records"))
+            .unwrap()[0];
+        assert_eq!(
+            record_compound_tag.get_i32_vec("pos").unwrap(),
+            &vec![17, 70, -14]
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let source_folder = TempDir::new().unwrap();
+        let source_provider = AnvilChunkProvider::from_path(source_folder.path());
+
+        let destination_folder = TempDir::new().unwrap();
+        let destination_provider = AnvilChunkProvider::from_path(destination_folder.path());
+
+        let chunk_provider = AnvilChunkProvider::new("test/region");
+
+        for &(chunk_x, chunk_z) in &[(4, 2), (5, 2), (12, 0)] {
+            let (compression_scheme, compressed_data) =
+                chunk_provider.load_chunk_bytes(chunk_x, chunk_z).unwrap();
+
+            source_provider
+                .save_chunk_bytes(chunk_x, chunk_z, compression_scheme, &compressed_data)
+                .unwrap();
+        }
+
+        let copied_chunk_positions = ChunkTransfer::new()
+            .with_range(0, 0, 10, 10)
+            .transfer(&source_provider, &destination_provider)
+            .unwrap();
+
+        assert_eq!(copied_chunk_positions, vec![(4, 2), (5, 2)]);
+        assert_eq!(
+            destination_provider.load_chunk_bytes(4, 2).unwrap(),
+            source_provider.load_chunk_bytes(4, 2).unwrap()
+        );
+
+        let moved_chunk_positions = ChunkTransfer::new()
+            .with_offset(-32, 0)
+            .with_move(true)
+            .transfer(&source_provider, &destination_provider)
+            .unwrap();
+
+        assert_eq!(moved_chunk_positions.len(), 3);
+        assert!(source_provider.chunk_positions().unwrap().is_empty());
+
+        let chunk_compound_tag = destination_provider.load_chunk(-20, 0).unwrap();
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+
+        assert_eq!(level_compound_tag.get_i32("xPos").unwrap(), -20);
+        assert_eq!(level_compound_tag.get_i32("zPos").unwrap(), 0);
+
+        // Same folder reached through other path.
+        let same_provider = AnvilChunkProvider::from_path_buf(destination_folder.path().join("."));
+
+        match ChunkTransfer::new()
+            .with_offset(1, 0)
+            .transfer(&destination_provider, &same_provider)
+        {
+            Err(TransferError::SameFolder) => {}
+            result => panic!("Expected `SameFolder` but got `{:?}`", result),
+        }
+    }
+}
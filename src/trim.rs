@@ -0,0 +1,261 @@
+//! Cutting world down to a border around its center.
+use crate::{chunk_position, AnvilChunkProvider};
+use std::fs;
+use std::io;
+
+/// Shape of kept area in chunk coordinates.
+#[derive(Clone, Debug)]
+pub enum Border {
+    /// Chunks at most radius chunks away on both axes are kept.
+    Square {
+        center_chunk_x: i32,
+        center_chunk_z: i32,
+        radius: u32,
+    },
+    /// Chunks at most radius chunks away from center are kept.
+    Circle {
+        center_chunk_x: i32,
+        center_chunk_z: i32,
+        radius: u32,
+    },
+}
+
+/// Position of region relative to border.
+#[derive(Debug, Eq, PartialEq)]
+enum RegionOverlap {
+    Inside,
+    Partial,
+    Outside,
+}
+
+impl Border {
+    /// Returns whether chunk is inside border.
+    pub fn contains_chunk(&self, chunk_x: i32, chunk_z: i32) -> bool {
+        match *self {
+            Border::Square {
+                center_chunk_x,
+                center_chunk_z,
+                radius,
+            } => {
+                (chunk_x as i64 - center_chunk_x as i64).unsigned_abs() <= radius as u64
+                    && (chunk_z as i64 - center_chunk_z as i64).unsigned_abs() <= radius as u64
+            }
+            Border::Circle {
+                center_chunk_x,
+                center_chunk_z,
+                radius,
+            } => {
+                let delta_x = chunk_x as i64 - center_chunk_x as i64;
+                let delta_z = chunk_z as i64 - center_chunk_z as i64;
+
+                delta_x * delta_x + delta_z * delta_z <= radius as i64 * radius as i64
+            }
+        }
+    }
+
+    /// Returns whether region lies inside, outside or crosses border.
+    fn region_overlap(&self, region_x: i32, region_z: i32) -> RegionOverlap {
+        let (min_chunk_x, min_chunk_z) = chunk_position(region_x, region_z, 0, 0);
+        let (max_chunk_x, max_chunk_z) = chunk_position(region_x, region_z, 31, 31);
+
+        let (center_chunk_x, center_chunk_z) = match *self {
+            Border::Square {
+                center_chunk_x,
+                center_chunk_z,
+                ..
+            }
+            | Border::Circle {
+                center_chunk_x,
+                center_chunk_z,
+                ..
+            } => (center_chunk_x, center_chunk_z),
+        };
+
+        // Both shapes are convex, so checking nearest and farthest chunks is enough.
+        let nearest_chunk_x = center_chunk_x.clamp(min_chunk_x, max_chunk_x);
+        let nearest_chunk_z = center_chunk_z.clamp(min_chunk_z, max_chunk_z);
+
+        if !self.contains_chunk(nearest_chunk_x, nearest_chunk_z) {
+            return RegionOverlap::Outside;
+        }
+
+        let corners = [
+            (min_chunk_x, min_chunk_z),
+            (min_chunk_x, max_chunk_z),
+            (max_chunk_x, min_chunk_z),
+            (max_chunk_x, max_chunk_z),
+        ];
+
+        if corners
+            .iter()
+            .all(|(chunk_x, chunk_z)| self.contains_chunk(*chunk_x, *chunk_z))
+        {
+            RegionOverlap::Inside
+        } else {
+            RegionOverlap::Partial
+        }
+    }
+}
+
+/// Deletes chunks outside of border.
+///
+/// Region files entirely outside of border are removed, chunks outside of
+/// border are deleted from regions which cross it. Legacy McRegion files
+/// are trimmed alongside Anvil ones.
+///
+/// Returns sorted coordinates of deleted chunks.
+pub fn trim(
+    chunk_provider: &AnvilChunkProvider,
+    border: &Border,
+) -> Result<Vec<(i32, i32)>, io::Error> {
+    let mut deleted_chunk_positions = Vec::new();
+
+    for (region_x, region_z) in chunk_provider.region_positions()? {
+        let overlap = border.region_overlap(region_x, region_z);
+
+        if overlap == RegionOverlap::Inside {
+            continue;
+        }
+
+        // Legacy McRegion file is trimmed the same way as Anvil one next to it.
+        let region_paths = [
+            chunk_provider.region_path(region_x, region_z),
+            chunk_provider.mcregion_path(region_x, region_z),
+        ];
+
+        for region_path in region_paths
+            .iter()
+            .filter(|region_path| region_path.exists())
+        {
+            let mut region = match overlap {
+                RegionOverlap::Partial => {
+                    chunk_provider.open_region_for_write(region_path.clone())?
+                }
+                _ => chunk_provider.open_region(region_path.clone())?,
+            };
+
+            for (region_chunk_x, region_chunk_z, _) in region.chunks() {
+                let (chunk_x, chunk_z) =
+                    chunk_position(region_x, region_z, region_chunk_x, region_chunk_z);
+
+                if overlap == RegionOverlap::Outside {
+                    deleted_chunk_positions.push((chunk_x, chunk_z));
+                } else if !border.contains_chunk(chunk_x, chunk_z) {
+                    region.delete_chunk(region_chunk_x, region_chunk_z)?;
+                    deleted_chunk_positions.push((chunk_x, chunk_z));
+                }
+            }
+
+            if overlap == RegionOverlap::Outside {
+                drop(region);
+                fs::remove_file(region_path)?;
+            }
+        }
+    }
+
+    deleted_chunk_positions.sort_unstable();
+    deleted_chunk_positions.dedup();
+
+    Ok(deleted_chunk_positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::trim::{trim, Border, RegionOverlap};
+    use crate::{AnvilChunkProvider, AnvilRegion};
+    use nbt::CompoundTag;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_region_overlap() {
+        let border = Border::Circle {
+            center_chunk_x: 0,
+            center_chunk_z: 0,
+            radius: 50,
+        };
+
+        assert_eq!(border.region_overlap(0, 0), RegionOverlap::Inside);
+        assert_eq!(border.region_overlap(-1, 0), RegionOverlap::Inside);
+        assert_eq!(border.region_overlap(1, 1), RegionOverlap::Partial);
+        assert_eq!(border.region_overlap(2, 0), RegionOverlap::Outside);
+        assert_eq!(border.region_overlap(-3, -3), RegionOverlap::Outside);
+
+        let border = Border::Square {
+            center_chunk_x: 16,
+            center_chunk_z: 16,
+            radius: 16,
+        };
+
+        assert_eq!(border.region_overlap(0, 0), RegionOverlap::Inside);
+        assert_eq!(border.region_overlap(1, 0), RegionOverlap::Partial);
+        assert_eq!(border.region_overlap(0, -1), RegionOverlap::Outside);
+    }
+
+    #[test]
+    fn test_trim() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        for &(chunk_x, chunk_z) in &[(0, 0), (2, 2), (3, 0), (-1, -1), (40, 40)] {
+            chunk_provider
+                .save_chunk(chunk_x, chunk_z, CompoundTag::new())
+                .unwrap();
+        }
+
+        let border = Border::Square {
+            center_chunk_x: 0,
+            center_chunk_z: 0,
+            radius: 2,
+        };
+
+        let deleted_chunk_positions = trim(&chunk_provider, &border).unwrap();
+
+        assert_eq!(deleted_chunk_positions, vec![(3, 0), (40, 40)]);
+        assert_eq!(
+            chunk_provider.chunk_positions().unwrap(),
+            vec![(-1, -1), (0, 0), (2, 2)]
+        );
+        assert_eq!(
+            chunk_provider.region_positions().unwrap(),
+            vec![(-1, -1), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn test_trim_mcregion() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+        let border = Border::Square {
+            center_chunk_x: 0,
+            center_chunk_z: 0,
+            radius: 2,
+        };
+
+        for &(chunk_x, chunk_z) in &[(0, 0), (3, 0), (40, 40)] {
+            chunk_provider
+                .save_chunk(chunk_x, chunk_z, CompoundTag::new())
+                .unwrap();
+        }
+
+        for &(region_x, region_z) in &[(0, 0), (1, 1)] {
+            let region_path = chunk_provider.region_path(region_x, region_z);
+            fs::rename(&region_path, region_path.with_extension("mcr")).unwrap();
+        }
+
+        // Anvil region next to McRegion one.
+        let mut region = AnvilRegion::new(chunk_provider.region_path(0, 0)).unwrap();
+        region.write_chunk(4, 0, CompoundTag::new()).unwrap();
+        drop(region);
+
+        let deleted_chunk_positions = trim(&chunk_provider, &border).unwrap();
+
+        assert_eq!(deleted_chunk_positions, vec![(3, 0), (4, 0), (40, 40)]);
+        assert_eq!(chunk_provider.region_positions().unwrap(), vec![(0, 0)]);
+        assert!(chunk_provider.mcregion_path(0, 0).exists());
+        assert!(!chunk_provider.mcregion_path(1, 1).exists());
+
+        fs::remove_file(chunk_provider.region_path(0, 0)).unwrap();
+        assert_eq!(chunk_provider.chunk_positions().unwrap(), vec![(0, 0)]);
+    }
+}
@@ -0,0 +1,2488 @@
+//! Chunk format upgrades between data versions.
+//!
+//! Chunks are migrated step by step through every layout between their
+//! own and the target one. Each step sets `DataVersion` to the first version
+//! of the new layout, so the game skips its own fixes for all versions in
+//! between, and each step converts the data those fixes would have changed:
+//!
+//! - Flattening converts numeric block ids to palettes and merges flower
+//!   pot, skull, note block, bed and banner block entities into block
+//!   states. Item ids, damage, enchantments and names, renamed entities,
+//!   custom names and block states stored in entities and block entities
+//!   are converted to 1.13.
+//! - Unspanning renames blocks and items changed by 1.14 and 1.16, fixes
+//!   wall sides and converts entity UUIDs.
+//! - Unwrapping renames chunk tags, blocks and items changed by 1.17 and
+//!   1.18 and converts proto chunk tick lists to scheduled ticks.
+//!
+//! Other entity data changed between these versions, such as villager
+//! professions or attribute names, is left as it is.
+use crate::chunk::{read_sections, write_sections, BlockState, ChunkDataError, ChunkSection};
+use crate::heightmap::{read_heightmaps, recompute_heightmaps, HeightmapKind};
+use crate::light::clear_light;
+use crate::packed::{bits_required, pack};
+use crate::text::write_quoted;
+use crate::version::{data_version, ChunkVersion};
+use crate::version::{NON_SPANNING_DATA_VERSION, UNWRAPPED_DATA_VERSION};
+use crate::{AnvilChunkProvider, ChunkLoadError, ChunkSaveError};
+use nbt::{CompoundTag, Tag};
+use std::collections::HashMap;
+
+/// Data version of 1.13 release which is set after blocks flattening.
+const FLATTENED_RELEASE_DATA_VERSION: i32 = 1519;
+/// Amount of biome entries in chunk since 1.15.
+const BIOMES_3D_LENGTH: usize = 1024;
+/// Amount of biome entries in section since 1.18.
+const SECTION_BIOMES: usize = 64;
+
+/// Possible errors while upgrading chunks.
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// Error while loading chunk from provider.
+    ChunkLoadError { chunk_load_error: ChunkLoadError },
+    /// Error while saving chunk to provider.
+    ChunkSaveError { chunk_save_error: ChunkSaveError },
+    /// Chunk data are not readable.
+    ChunkDataError { chunk_data_error: ChunkDataError },
+    /// Chunk are newer than target version.
+    DowngradeNotSupported {
+        /// Data version of chunk.
+        data_version: i32,
+        /// Requested data version.
+        target_data_version: i32,
+    },
+}
+
+impl From<ChunkLoadError> for UpgradeError {
+    fn from(chunk_load_error: ChunkLoadError) -> Self {
+        UpgradeError::ChunkLoadError { chunk_load_error }
+    }
+}
+
+impl From<ChunkSaveError> for UpgradeError {
+    fn from(chunk_save_error: ChunkSaveError) -> Self {
+        UpgradeError::ChunkSaveError { chunk_save_error }
+    }
+}
+
+impl From<ChunkDataError> for UpgradeError {
+    fn from(chunk_data_error: ChunkDataError) -> Self {
+        UpgradeError::ChunkDataError { chunk_data_error }
+    }
+}
+
+impl<'a> From<nbt::CompoundTagError<'a>> for UpgradeError {
+    fn from(compound_tag_error: nbt::CompoundTagError<'a>) -> Self {
+        UpgradeError::ChunkDataError {
+            chunk_data_error: compound_tag_error.into(),
+        }
+    }
+}
+
+/// Upgrades chunks to the layout of target data version.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::upgrade::ChunkUpgrader;
+/// use anvil_region::version::{ChunkVersion, UNWRAPPED_DATA_VERSION};
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let mut chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// let chunk_upgrader = ChunkUpgrader::new(UNWRAPPED_DATA_VERSION);
+/// chunk_upgrader.upgrade(&mut chunk_compound_tag).unwrap();
+///
+/// assert_eq!(ChunkVersion::detect(&chunk_compound_tag), ChunkVersion::Unwrapped);
+/// assert_eq!(chunk_compound_tag.get_i32("xPos").unwrap(), 4);
+/// ```
+pub struct ChunkUpgrader {
+    /// Requested data version.
+    target_data_version: i32,
+    /// Lowest section after upgrade to `Unwrapped` layout.
+    minimum_section_y: i8,
+    /// Highest section after upgrade to `Unwrapped` layout.
+    maximum_section_y: i8,
+    /// Maps numeric block id and data value to block state.
+    legacy_block_mapper: fn(u16, u8) -> BlockState,
+}
+
+impl ChunkUpgrader {
+    /// Creates upgrader with overworld section range of 1.18.
+    pub fn new(target_data_version: i32) -> Self {
+        ChunkUpgrader {
+            target_data_version,
+            minimum_section_y: -4,
+            maximum_section_y: 19,
+            legacy_block_mapper: legacy_block_state,
+        }
+    }
+
+    /// Sets section range of chunks upgraded to `Unwrapped` layout.
+    ///
+    /// For nether and end dimensions range should stay from 0 to 15.
+    pub fn with_section_range(mut self, minimum_section_y: i8, maximum_section_y: i8) -> Self {
+        self.minimum_section_y = minimum_section_y;
+        self.maximum_section_y = maximum_section_y;
+        self
+    }
+
+    /// Sets mapping of numeric block ids used instead of vanilla one.
+    ///
+    /// Useful for modded worlds which registered their own block ids.
+    pub fn with_legacy_block_mapper(
+        mut self,
+        legacy_block_mapper: fn(u16, u8) -> BlockState,
+    ) -> Self {
+        self.legacy_block_mapper = legacy_block_mapper;
+        self
+    }
+
+    /// Upgrades chunk to layout of target data version.
+    ///
+    /// Returns true if chunk was modified.
+    pub fn upgrade(&self, chunk_compound_tag: &mut CompoundTag) -> Result<bool, UpgradeError> {
+        let target_version = ChunkVersion::from_data_version(Some(self.target_data_version));
+
+        if let Some(data_version) = data_version(chunk_compound_tag) {
+            if data_version > self.target_data_version {
+                return Err(UpgradeError::DowngradeNotSupported {
+                    data_version,
+                    target_data_version: self.target_data_version,
+                });
+            }
+        }
+
+        let mut upgraded = false;
+
+        loop {
+            let chunk_version = ChunkVersion::detect(chunk_compound_tag);
+
+            if chunk_version >= target_version {
+                return Ok(upgraded);
+            }
+
+            match chunk_version {
+                ChunkVersion::PreFlattening => self.flatten(chunk_compound_tag)?,
+                ChunkVersion::Flattened => self.unspan(chunk_compound_tag)?,
+                ChunkVersion::NonSpanning => self.unwrap_level(chunk_compound_tag)?,
+                ChunkVersion::Unwrapped => unreachable!(),
+            }
+
+            upgraded = true;
+        }
+    }
+
+    /// Upgrades all chunks of provider folder.
+    ///
+    /// Returns coordinates of upgraded chunks.
+    pub fn upgrade_provider(
+        &self,
+        chunk_provider: &AnvilChunkProvider,
+    ) -> Result<Vec<(i32, i32)>, UpgradeError> {
+        let mut upgraded_chunk_positions = Vec::new();
+
+        for (chunk_x, chunk_z) in chunk_provider.chunk_positions()? {
+            let mut chunk_compound_tag = chunk_provider.load_chunk(chunk_x, chunk_z)?;
+
+            if self.upgrade(&mut chunk_compound_tag)? {
+                chunk_provider.save_chunk(chunk_x, chunk_z, chunk_compound_tag)?;
+                upgraded_chunk_positions.push((chunk_x, chunk_z));
+            }
+        }
+
+        Ok(upgraded_chunk_positions)
+    }
+
+    /// Converts numeric block ids to palettes, items, entities and block
+    /// entities to 1.13.
+    fn flatten(&self, chunk_compound_tag: &mut CompoundTag) -> Result<(), UpgradeError> {
+        let level_compound_tag: &mut CompoundTag = chunk_compound_tag.get_mut("Level")?;
+        let mut sections = Vec::new();
+
+        let block_entities: Vec<CompoundTag> = level_compound_tag
+            .get_compound_tag_vec("TileEntities")
+            .unwrap_or_default()
+            .into_iter()
+            .cloned()
+            .collect();
+
+        // Block entities by section coordinates x and z and absolute y.
+        let block_entities_by_position: HashMap<(i32, i32, i32), &CompoundTag> = block_entities
+            .iter()
+            .filter_map(|block_entity| {
+                let x = block_entity.get_i32("x").ok()?;
+                let y = block_entity.get_i32("y").ok()?;
+                let z = block_entity.get_i32("z").ok()?;
+
+                Some(((x & 15, y, z & 15), block_entity))
+            })
+            .collect();
+
+        if level_compound_tag.contains_key("Sections") {
+            let section_tags: &mut Vec<Tag> = level_compound_tag.get_mut("Sections")?;
+
+            for section_tag in section_tags.iter_mut() {
+                if let Tag::Compound(section_tag) = section_tag {
+                    if section_tag.contains_key("Blocks") {
+                        sections
+                            .push(self.flatten_section(section_tag, &block_entities_by_position)?);
+                    }
+
+                    *section_tag = without_tags(section_tag, &["Blocks", "Data", "Add"]);
+                }
+            }
+        }
+
+        if level_compound_tag.contains_key("TileEntities") {
+            let block_entities: Vec<CompoundTag> = block_entities
+                .iter()
+                .filter_map(flatten_block_entity)
+                .collect();
+
+            level_compound_tag.insert_compound_tag_vec("TileEntities", block_entities);
+        }
+
+        if let Ok(entities) = level_compound_tag.get_mut::<&mut Vec<Tag>>("Entities") {
+            for entity in entities.iter_mut() {
+                if let Tag::Compound(entity) = entity {
+                    flatten_entity(entity);
+                }
+            }
+        }
+
+        if let Ok(biomes) = level_compound_tag.get_i8_vec("Biomes") {
+            let biomes = biomes.iter().map(|biome| *biome as u8 as i32).collect();
+            level_compound_tag.insert_i32_vec("Biomes", biomes);
+        }
+
+        let terrain_populated = level_compound_tag
+            .get_bool("TerrainPopulated")
+            .unwrap_or(false);
+        let status = if terrain_populated {
+            "postprocessed"
+        } else {
+            "carved"
+        };
+
+        let mut level_compound_tag = without_tags(
+            level_compound_tag,
+            &["TerrainPopulated", "LightPopulated", "HeightMap", "V"],
+        );
+        level_compound_tag.insert_str("Status", status);
+
+        chunk_compound_tag.insert_compound_tag("Level", level_compound_tag);
+        chunk_compound_tag.insert_i32("DataVersion", FLATTENED_RELEASE_DATA_VERSION);
+
+        write_sections(chunk_compound_tag, &sections)?;
+        recompute_heightmaps(chunk_compound_tag)?;
+
+        Ok(())
+    }
+
+    fn flatten_section(
+        &self,
+        section_tag: &CompoundTag,
+        block_entities: &HashMap<(i32, i32, i32), &CompoundTag>,
+    ) -> Result<ChunkSection, UpgradeError> {
+        let y = section_tag.get_i8("Y")?;
+        let blocks = section_tag.get_i8_vec("Blocks")?;
+        let data = section_tag.get_i8_vec("Data")?;
+        let add = section_tag.get_i8_vec("Add").ok();
+
+        check_length("Blocks", blocks.len(), 4096)?;
+        check_length("Data", data.len(), 2048)?;
+
+        if let Some(add) = add {
+            check_length("Add", add.len(), 2048)?;
+        }
+
+        let mut section = ChunkSection::new(y);
+        section.palette.clear();
+        let mut legacy_indexes = HashMap::new();
+        let mut state_indexes = HashMap::new();
+
+        for (index, block) in blocks.iter().enumerate() {
+            let add_value = add.map(|add| nibble(add, index)).unwrap_or(0) as u16;
+            let block_id = (add_value << 8) | *block as u8 as u16;
+            let block_data = nibble(data, index);
+
+            let position = (
+                (index & 15) as i32,
+                y as i32 * 16 + (index >> 8) as i32,
+                ((index >> 4) & 15) as i32,
+            );
+            let merged_block_state = block_entities
+                .get(&position)
+                .and_then(|block_entity| merged_block_state(block_id, block_data, block_entity));
+
+            let palette_index = match merged_block_state {
+                Some(block_state) => {
+                    palette_index(&mut section.palette, &mut state_indexes, block_state)
+                }
+                None => match legacy_indexes.get(&(block_id, block_data)) {
+                    Some(palette_index) => *palette_index,
+                    None => {
+                        let block_state = (self.legacy_block_mapper)(block_id, block_data);
+                        let palette_index =
+                            palette_index(&mut section.palette, &mut state_indexes, block_state);
+                        legacy_indexes.insert((block_id, block_data), palette_index);
+
+                        palette_index
+                    }
+                },
+            };
+
+            section.blocks[index] = palette_index;
+        }
+
+        Ok(section)
+    }
+
+    /// Repacks packed arrays so values no longer span longs and renames
+    /// data changed up to 1.16.
+    fn unspan(&self, chunk_compound_tag: &mut CompoundTag) -> Result<(), UpgradeError> {
+        let mut sections = read_sections(chunk_compound_tag)?;
+        let heightmaps = read_heightmaps(chunk_compound_tag)?;
+
+        for section in sections.iter_mut() {
+            for block_state in section.palette.iter_mut() {
+                rename_nether_update_block(block_state);
+            }
+        }
+
+        let level_compound_tag: &mut CompoundTag = chunk_compound_tag.get_mut("Level")?;
+
+        for name in &["TileEntities", "Entities"] {
+            if let Ok(tags) = level_compound_tag.get_mut::<&mut Vec<Tag>>(name) {
+                for tag in tags.iter_mut() {
+                    rename_items(tag, rename_nether_update_item);
+                }
+            }
+        }
+
+        if let Ok(entities) = level_compound_tag.get_mut::<&mut Vec<Tag>>("Entities") {
+            for entity in entities.iter_mut() {
+                if let Tag::Compound(entity) = entity {
+                    unspan_entity(entity);
+                }
+            }
+        }
+
+        if let Ok(status) = level_compound_tag.get_str("Status") {
+            let status = renamed_status(status).to_owned();
+            level_compound_tag.insert_str("Status", status);
+        }
+
+        if let Ok(biomes) = level_compound_tag.get_i32_vec("Biomes") {
+            if biomes.len() == 256 {
+                let biomes = expand_biomes(biomes);
+                level_compound_tag.insert_i32_vec("Biomes", biomes);
+            }
+        }
+
+        let mut heightmaps_compound_tag = CompoundTag::new();
+
+        for (kind, heightmap) in heightmaps {
+            if kind != HeightmapKind::LightBlocking {
+                heightmaps_compound_tag
+                    .insert_i64_vec(kind.name(), heightmap.to_packed(256, false));
+            }
+        }
+
+        level_compound_tag.insert_compound_tag("Heightmaps", heightmaps_compound_tag);
+        chunk_compound_tag.insert_i32("DataVersion", NON_SPANNING_DATA_VERSION);
+
+        write_sections(chunk_compound_tag, &sections)?;
+
+        Ok(())
+    }
+
+    /// Moves chunk data out of `Level`, renames data changed up to 1.18 and
+    /// extends section range.
+    fn unwrap_level(&self, chunk_compound_tag: &mut CompoundTag) -> Result<(), UpgradeError> {
+        let mut sections = read_sections(chunk_compound_tag)?;
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level")?.clone();
+
+        for section in sections.iter_mut() {
+            for block_state in section.palette.iter_mut() {
+                rename_cave_update_block(block_state);
+            }
+        }
+
+        let block_ticks = proto_ticks(&level_compound_tag, "ToBeTicked", &sections, |block| {
+            Some(block.name.clone())
+        });
+        let fluid_ticks = proto_ticks(
+            &level_compound_tag,
+            "LiquidsToBeTicked",
+            &sections,
+            fluid_name,
+        );
+
+        let biomes = match level_compound_tag.get_i32_vec("Biomes") {
+            Ok(biomes) if biomes.len() == BIOMES_3D_LENGTH => biomes.clone(),
+            Ok(biomes) if biomes.len() == 256 => expand_biomes(biomes),
+            _ => vec![1; BIOMES_3D_LENGTH],
+        };
+
+        let mut unwrapped_compound_tag = CompoundTag::new();
+        unwrapped_compound_tag.insert_i32("DataVersion", UNWRAPPED_DATA_VERSION);
+
+        for (name, tag) in level_compound_tag {
+            let name = match name.as_str() {
+                "Sections" | "Biomes" | "ToBeTicked" | "LiquidsToBeTicked" => continue,
+                "TileEntities" => "block_entities".to_owned(),
+                "Entities" => "entities".to_owned(),
+                "TileTicks" => "block_ticks".to_owned(),
+                "LiquidTicks" => "fluid_ticks".to_owned(),
+                "CarvingMasks" => "carving_masks".to_owned(),
+                "Structures" => {
+                    let structures = match tag {
+                        Tag::Compound(structures) => structures,
+                        _ => continue,
+                    };
+
+                    let structures: CompoundTag = structures
+                        .into_iter()
+                        .map(|(name, tag)| match name.as_str() {
+                            "Starts" => ("starts".to_owned(), tag),
+                            _ => (name, tag),
+                        })
+                        .collect();
+
+                    unwrapped_compound_tag.insert_compound_tag("structures", structures);
+                    continue;
+                }
+                _ => name,
+            };
+
+            unwrapped_compound_tag.insert(name, tag);
+        }
+
+        for name in &["block_entities", "entities"] {
+            if let Ok(tags) = unwrapped_compound_tag.get_mut::<&mut Vec<Tag>>(name) {
+                for tag in tags.iter_mut() {
+                    rename_items(tag, rename_cave_update_item);
+                }
+            }
+        }
+
+        for (name, ticks) in &[("block_ticks", block_ticks), ("fluid_ticks", fluid_ticks)] {
+            if ticks.is_empty() {
+                continue;
+            }
+
+            let mut tick_tags: Vec<CompoundTag> = unwrapped_compound_tag
+                .get_compound_tag_vec(name)
+                .unwrap_or_default()
+                .into_iter()
+                .cloned()
+                .collect();
+            tick_tags.extend(ticks.iter().cloned());
+
+            unwrapped_compound_tag.insert_compound_tag_vec(name, tick_tags);
+        }
+
+        let mut section_tags = Vec::new();
+
+        for section in &sections {
+            let mut section_tag = CompoundTag::new();
+            section.write_to_tag(&mut section_tag, ChunkVersion::Unwrapped);
+            section_tag.insert_compound_tag("biomes", section_biomes(&biomes, section.y));
+            section_tags.push(section_tag);
+        }
+
+        unwrapped_compound_tag.insert_compound_tag_vec("sections", section_tags);
+        *chunk_compound_tag = unwrapped_compound_tag;
+
+        extend_section_range(
+            chunk_compound_tag,
+            self.minimum_section_y,
+            self.maximum_section_y,
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Adds air sections so chunk covers specified section range.
+///
+/// Only for chunks with `Unwrapped` layout. Heightmaps are recomputed for
+/// the new lowest section and light is cleared so the game relights chunk.
+pub fn extend_section_range(
+    chunk_compound_tag: &mut CompoundTag,
+    minimum_section_y: i8,
+    maximum_section_y: i8,
+) -> Result<(), UpgradeError> {
+    let chunk_version = ChunkVersion::detect_supported(chunk_compound_tag)?;
+
+    if chunk_version != ChunkVersion::Unwrapped {
+        return Err(UpgradeError::ChunkDataError {
+            chunk_data_error: ChunkDataError::UnsupportedVersion {
+                data_version: data_version(chunk_compound_tag),
+            },
+        });
+    }
+
+    let mut section_tags: Vec<CompoundTag> = chunk_compound_tag
+        .get_compound_tag_vec("sections")
+        .unwrap_or_default()
+        .into_iter()
+        .cloned()
+        .collect();
+
+    let existing_ys: Vec<i8> = section_tags
+        .iter()
+        .filter_map(|section_tag| section_tag.get_i8("Y").ok())
+        .collect();
+
+    let lowest_existing_y = existing_ys
+        .iter()
+        .min()
+        .cloned()
+        .unwrap_or(minimum_section_y);
+    let highest_existing_y = existing_ys
+        .iter()
+        .max()
+        .cloned()
+        .unwrap_or(maximum_section_y);
+
+    for y in minimum_section_y..=maximum_section_y {
+        if existing_ys.contains(&y) {
+            continue;
+        }
+
+        // New sections take biomes of the nearest existing section.
+        let nearest_y = y.clamp(lowest_existing_y, highest_existing_y);
+        let biomes_tag = section_tags
+            .iter()
+            .find(|section_tag| section_tag.get_i8("Y").ok() == Some(nearest_y))
+            .and_then(|section_tag| section_tag.get_compound_tag("biomes").ok())
+            .cloned();
+
+        let mut section_tag = CompoundTag::new();
+        ChunkSection::new(y).write_to_tag(&mut section_tag, ChunkVersion::Unwrapped);
+
+        if let Some(biomes_tag) = biomes_tag {
+            section_tag.insert_compound_tag("biomes", biomes_tag);
+        }
+
+        section_tags.push(section_tag);
+    }
+
+    section_tags.sort_by_key(|section_tag| section_tag.get_i8("Y").unwrap_or(0));
+
+    if let Ok(post_processing) = chunk_compound_tag.get::<&Vec<Tag>>("PostProcessing") {
+        let mut post_processing = post_processing.clone();
+        let sections_below = (lowest_existing_y as i32 - minimum_section_y as i32).max(0);
+        let sections_count = (maximum_section_y as i32 - minimum_section_y as i32 + 1) as usize;
+
+        for _ in 0..sections_below {
+            post_processing.insert(0, Tag::List(Vec::new()));
+        }
+
+        post_processing.resize(
+            sections_count.max(post_processing.len()),
+            Tag::List(Vec::new()),
+        );
+        chunk_compound_tag.insert("PostProcessing", post_processing);
+    }
+
+    chunk_compound_tag.insert_compound_tag_vec("sections", section_tags);
+    chunk_compound_tag.insert_i32("yPos", minimum_section_y as i32);
+
+    recompute_heightmaps(chunk_compound_tag)?;
+    clear_light(chunk_compound_tag)?;
+
+    Ok(())
+}
+
+pub(crate) fn check_length(
+    name: &str,
+    length: usize,
+    expected_length: usize,
+) -> Result<(), ChunkDataError> {
+    if length != expected_length {
+        return Err(ChunkDataError::InvalidLength {
+            name: name.to_owned(),
+            length,
+            expected_length,
+        });
+    }
+
+    Ok(())
+}
+
+pub(crate) fn nibble(bytes: &[i8], index: usize) -> u8 {
+    let byte = bytes[index / 2] as u8;
+
+    if index & 1 == 0 {
+        byte & 0x0F
+    } else {
+        byte >> 4
+    }
+}
+
+/// Returns compound tag copy without specified tags.
+fn without_tags(compound_tag: &CompoundTag, names: &[&str]) -> CompoundTag {
+    compound_tag
+        .iter()
+        .filter(|(name, _)| !names.contains(&name.as_str()))
+        .map(|(name, tag)| (name.clone(), tag.clone()))
+        .collect()
+}
+
+/// Maps chunk statuses of 1.13 to the ones used since 1.14.
+fn renamed_status(status: &str) -> &str {
+    match status {
+        "base" => "structure_starts",
+        "carved" => "carvers",
+        "liquid_carved" => "liquid_carvers",
+        "decorated" => "features",
+        "lighted" => "light",
+        "mobs_spawned" => "spawn",
+        "finalized" => "heightmaps",
+        "fullchunk" | "postprocessed" => "full",
+        status => status,
+    }
+}
+
+/// Expands 16x16 biome columns to 4x64x4 biome volume used since 1.15.
+fn expand_biomes(biomes: &[i32]) -> Vec<i32> {
+    let mut expanded_biomes = Vec::with_capacity(BIOMES_3D_LENGTH);
+
+    for _ in 0..64 {
+        for quart_z in 0..4 {
+            for quart_x in 0..4 {
+                let column_index = (quart_z * 4 + 2) * 16 + quart_x * 4 + 2;
+                expanded_biomes.push(biomes[column_index]);
+            }
+        }
+    }
+
+    expanded_biomes
+}
+
+/// Builds biome palette container of section used since 1.18.
+fn section_biomes(biomes: &[i32], section_y: i8) -> CompoundTag {
+    let mut palette: Vec<String> = Vec::new();
+    let mut values = Vec::with_capacity(SECTION_BIOMES);
+
+    for index in 0..SECTION_BIOMES {
+        let quart_y = (section_y as i32 * 4 + (index >> 4) as i32).clamp(0, 63) as usize;
+        let biome_name = biome_name(biomes[quart_y << 4 | (index & 15)]);
+
+        let palette_index = match palette.iter().position(|name| name == biome_name) {
+            Some(palette_index) => palette_index,
+            None => {
+                palette.push(biome_name.to_owned());
+                palette.len() - 1
+            }
+        };
+
+        values.push(palette_index as u16);
+    }
+
+    let mut biomes_tag = CompoundTag::new();
+
+    if palette.len() > 1 {
+        let bits_per_biome = bits_required(palette.len() - 1);
+        biomes_tag.insert_i64_vec("data", pack(&values, bits_per_biome, false));
+    }
+
+    biomes_tag.insert_str_vec("palette", palette);
+
+    biomes_tag
+}
+
+/// Maps numeric biome id to biome name used since 1.18.
+pub fn biome_name(biome_id: i32) -> &'static str {
+    match biome_id {
+        0 => "minecraft:ocean",
+        1 => "minecraft:plains",
+        2 | 17 | 130 => "minecraft:desert",
+        3 | 20 => "minecraft:windswept_hills",
+        4 | 18 => "minecraft:forest",
+        5 | 19 | 133 => "minecraft:taiga",
+        6 | 134 => "minecraft:swamp",
+        7 => "minecraft:river",
+        8 => "minecraft:nether_wastes",
+        9 => "minecraft:the_end",
+        10 => "minecraft:frozen_ocean",
+        11 => "minecraft:frozen_river",
+        12 | 13 => "minecraft:snowy_plains",
+        14 | 15 => "minecraft:mushroom_fields",
+        16 => "minecraft:beach",
+        21 | 22 | 149 => "minecraft:jungle",
+        23 | 151 => "minecraft:sparse_jungle",
+        24 => "minecraft:deep_ocean",
+        25 => "minecraft:stony_shore",
+        26 => "minecraft:snowy_beach",
+        27 | 28 => "minecraft:birch_forest",
+        29 | 157 => "minecraft:dark_forest",
+        30 | 31 | 158 => "minecraft:snowy_taiga",
+        32 | 33 => "minecraft:old_growth_pine_taiga",
+        34 => "minecraft:windswept_forest",
+        35 => "minecraft:savanna",
+        36 => "minecraft:savanna_plateau",
+        37 | 39 | 167 => "minecraft:badlands",
+        38 | 166 => "minecraft:wooded_badlands",
+        40 => "minecraft:small_end_islands",
+        41 => "minecraft:end_midlands",
+        42 => "minecraft:end_highlands",
+        43 => "minecraft:end_barrens",
+        44 => "minecraft:warm_ocean",
+        45 => "minecraft:lukewarm_ocean",
+        46 => "minecraft:cold_ocean",
+        47 | 48 => "minecraft:deep_lukewarm_ocean",
+        49 => "minecraft:deep_cold_ocean",
+        50 => "minecraft:deep_frozen_ocean",
+        127 => "minecraft:the_void",
+        129 => "minecraft:sunflower_plains",
+        131 | 162 => "minecraft:windswept_gravelly_hills",
+        132 => "minecraft:flower_forest",
+        140 => "minecraft:ice_spikes",
+        155 | 156 => "minecraft:old_growth_birch_forest",
+        160 | 161 => "minecraft:old_growth_spruce_taiga",
+        163 | 164 => "minecraft:windswept_savanna",
+        165 => "minecraft:eroded_badlands",
+        168 | 169 => "minecraft:bamboo_jungle",
+        170 => "minecraft:soul_sand_valley",
+        171 => "minecraft:crimson_forest",
+        172 => "minecraft:warped_forest",
+        173 => "minecraft:basalt_deltas",
+        174 => "minecraft:dripstone_caves",
+        175 => "minecraft:lush_caves",
+        _ => "minecraft:plains",
+    }
+}
+
+/// Dye colors in order of legacy data values.
+const COLORS: [&str; 16] = [
+    "white",
+    "orange",
+    "magenta",
+    "light_blue",
+    "yellow",
+    "lime",
+    "pink",
+    "gray",
+    "light_gray",
+    "cyan",
+    "purple",
+    "blue",
+    "brown",
+    "green",
+    "red",
+    "black",
+];
+
+/// Wood types in order of legacy data values.
+const WOODS: [&str; 6] = ["oak", "spruce", "birch", "jungle", "acacia", "dark_oak"];
+
+fn variant(variants: &[&str], block_data: u8) -> String {
+    let index = block_data as usize;
+
+    variants.get(index).unwrap_or(&variants[0]).to_string()
+}
+
+fn with_property(mut block_state: BlockState, name: &str, value: &str) -> BlockState {
+    block_state
+        .properties
+        .insert(name.to_owned(), value.to_owned());
+
+    block_state
+}
+
+fn slab(name: &str, block_data: u8, double: bool) -> BlockState {
+    let slab_type = if double {
+        "double"
+    } else if block_data & 8 != 0 {
+        "top"
+    } else {
+        "bottom"
+    };
+
+    with_property(
+        BlockState::new(&format!("minecraft:{}_slab", name)),
+        "type",
+        slab_type,
+    )
+}
+
+fn log(wood: &str, block_data: u8) -> BlockState {
+    let axis = match block_data >> 2 {
+        1 => "x",
+        2 => "z",
+        _ => "y",
+    };
+
+    with_property(
+        BlockState::new(&format!("minecraft:{}_log", wood)),
+        "axis",
+        axis,
+    )
+}
+
+/// Maps numeric block id and data value of 1.12 to block state.
+///
+/// Block variants such as colors, wood types and stone kinds are kept,
+/// while orientation of most blocks are lost. Unknown ids are mapped to air.
+pub fn legacy_block_state(block_id: u16, block_data: u8) -> BlockState {
+    let name = match block_id {
+        1 => variant(
+            &[
+                "stone",
+                "granite",
+                "polished_granite",
+                "diorite",
+                "polished_diorite",
+                "andesite",
+                "polished_andesite",
+            ],
+            block_data,
+        ),
+        3 => variant(&["dirt", "coarse_dirt", "podzol"], block_data),
+        5 => format!("{}_planks", variant(&WOODS, block_data)),
+        6 => format!("{}_sapling", variant(&WOODS, block_data & 7)),
+        8..=11 => {
+            let fluid = if block_id < 10 { "water" } else { "lava" };
+            let block_state = BlockState::new(&format!("minecraft:{}", fluid));
+
+            return with_property(block_state, "level", &(block_data & 15).to_string());
+        }
+        12 => variant(&["sand", "red_sand"], block_data),
+        17 => return log(&variant(&WOODS[..4], block_data & 3), block_data),
+        18 => format!("{}_leaves", variant(&WOODS[..4], block_data & 3)),
+        19 => variant(&["sponge", "wet_sponge"], block_data),
+        24 => variant(
+            &["sandstone", "chiseled_sandstone", "cut_sandstone"],
+            block_data,
+        ),
+        31 => variant(&["dead_bush", "grass", "fern"], block_data),
+        35 => format!("{}_wool", variant(&COLORS, block_data)),
+        38 => variant(
+            &[
+                "poppy",
+                "blue_orchid",
+                "allium",
+                "azure_bluet",
+                "red_tulip",
+                "orange_tulip",
+                "white_tulip",
+                "pink_tulip",
+                "oxeye_daisy",
+            ],
+            block_data,
+        ),
+        43 | 44 => {
+            let name = variant(
+                &[
+                    "stone",
+                    "sandstone",
+                    "petrified_oak",
+                    "cobblestone",
+                    "brick",
+                    "stone_brick",
+                    "nether_brick",
+                    "quartz",
+                ],
+                block_data & 7,
+            );
+
+            return slab(&name, block_data, block_id == 43);
+        }
+        50 => {
+            if block_data == 0 || block_data == 5 {
+                "torch".to_owned()
+            } else {
+                "wall_torch".to_owned()
+            }
+        }
+        59 | 141 | 142 | 207 => {
+            let name = match block_id {
+                59 => "wheat",
+                141 => "carrots",
+                142 => "potatoes",
+                _ => "beetroots",
+            };
+            let block_state = BlockState::new(&format!("minecraft:{}", name));
+
+            return with_property(block_state, "age", &block_data.to_string());
+        }
+        62 => return with_property(BlockState::new("minecraft:furnace"), "lit", "true"),
+        74 => return with_property(BlockState::new("minecraft:redstone_ore"), "lit", "true"),
+        124 => return with_property(BlockState::new("minecraft:redstone_lamp"), "lit", "true"),
+        75 | 76 => {
+            let name = if block_data == 0 || block_data == 5 {
+                "minecraft:redstone_torch"
+            } else {
+                "minecraft:redstone_wall_torch"
+            };
+            let lit = if block_id == 76 { "true" } else { "false" };
+
+            return with_property(BlockState::new(name), "lit", lit);
+        }
+        95 => format!("{}_stained_glass", variant(&COLORS, block_data)),
+        97 => variant(
+            &[
+                "infested_stone",
+                "infested_cobblestone",
+                "infested_stone_bricks",
+                "infested_mossy_stone_bricks",
+                "infested_cracked_stone_bricks",
+                "infested_chiseled_stone_bricks",
+            ],
+            block_data,
+        ),
+        98 => variant(
+            &[
+                "stone_bricks",
+                "mossy_stone_bricks",
+                "cracked_stone_bricks",
+                "chiseled_stone_bricks",
+            ],
+            block_data,
+        ),
+        125 | 126 => {
+            return slab(
+                &variant(&WOODS, block_data & 7),
+                block_data,
+                block_id == 125,
+            )
+        }
+        155 => variant(
+            &["quartz_block", "chiseled_quartz_block", "quartz_pillar"],
+            block_data,
+        ),
+        159 => format!("{}_terracotta", variant(&COLORS, block_data)),
+        160 => format!("{}_stained_glass_pane", variant(&COLORS, block_data)),
+        161 => format!("{}_leaves", variant(&WOODS[4..], block_data & 1)),
+        162 => return log(&variant(&WOODS[4..], block_data & 1), block_data),
+        168 => variant(
+            &["prismarine", "prismarine_bricks", "dark_prismarine"],
+            block_data,
+        ),
+        171 => format!("{}_carpet", variant(&COLORS, block_data)),
+        175 => {
+            let name = variant(
+                &[
+                    "sunflower",
+                    "lilac",
+                    "tall_grass",
+                    "large_fern",
+                    "rose_bush",
+                    "peony",
+                ],
+                block_data & 7,
+            );
+            let half = if block_data & 8 != 0 {
+                "upper"
+            } else {
+                "lower"
+            };
+            let block_state = BlockState::new(&format!("minecraft:{}", name));
+
+            return with_property(block_state, "half", half);
+        }
+        179 => variant(
+            &[
+                "red_sandstone",
+                "chiseled_red_sandstone",
+                "cut_red_sandstone",
+            ],
+            block_data,
+        ),
+        181 | 182 => return slab("red_sandstone", block_data, block_id == 181),
+        204 | 205 => return slab("purpur", block_data, block_id == 204),
+        219..=234 => format!("{}_shulker_box", COLORS[(block_id - 219) as usize]),
+        235..=250 => format!("{}_glazed_terracotta", COLORS[(block_id - 235) as usize]),
+        251 => format!("{}_concrete", variant(&COLORS, block_data)),
+        252 => format!("{}_concrete_powder", variant(&COLORS, block_data)),
+        block_id => legacy_block_name(block_id).to_owned(),
+    };
+
+    BlockState::new(&format!("minecraft:{}", name))
+}
+
+/// Returns block name of numeric id ignoring data value.
+fn legacy_block_name(block_id: u16) -> &'static str {
+    match block_id {
+        2 => "grass_block",
+        4 => "cobblestone",
+        7 => "bedrock",
+        13 => "gravel",
+        14 => "gold_ore",
+        15 => "iron_ore",
+        16 => "coal_ore",
+        20 => "glass",
+        21 => "lapis_ore",
+        22 => "lapis_block",
+        23 => "dispenser",
+        25 => "note_block",
+        26 => "red_bed",
+        27 => "powered_rail",
+        28 => "detector_rail",
+        29 => "sticky_piston",
+        30 => "cobweb",
+        32 => "dead_bush",
+        33 => "piston",
+        34 => "piston_head",
+        36 => "moving_piston",
+        37 => "dandelion",
+        39 => "brown_mushroom",
+        40 => "red_mushroom",
+        41 => "gold_block",
+        42 => "iron_block",
+        45 => "bricks",
+        46 => "tnt",
+        47 => "bookshelf",
+        48 => "mossy_cobblestone",
+        49 => "obsidian",
+        51 => "fire",
+        52 => "spawner",
+        53 => "oak_stairs",
+        54 => "chest",
+        55 => "redstone_wire",
+        56 => "diamond_ore",
+        57 => "diamond_block",
+        58 => "crafting_table",
+        60 => "farmland",
+        61 => "furnace",
+        63 => "sign",
+        64 => "oak_door",
+        65 => "ladder",
+        66 => "rail",
+        67 => "cobblestone_stairs",
+        68 => "wall_sign",
+        69 => "lever",
+        70 => "stone_pressure_plate",
+        71 => "iron_door",
+        72 => "oak_pressure_plate",
+        73 => "redstone_ore",
+        77 => "stone_button",
+        78 => "snow",
+        79 => "ice",
+        80 => "snow_block",
+        81 => "cactus",
+        82 => "clay",
+        83 => "sugar_cane",
+        84 => "jukebox",
+        85 => "oak_fence",
+        86 => "carved_pumpkin",
+        87 => "netherrack",
+        88 => "soul_sand",
+        89 => "glowstone",
+        90 => "nether_portal",
+        91 => "jack_o_lantern",
+        92 => "cake",
+        93 | 94 => "repeater",
+        96 => "oak_trapdoor",
+        99 => "brown_mushroom_block",
+        100 => "red_mushroom_block",
+        101 => "iron_bars",
+        102 => "glass_pane",
+        103 => "melon",
+        104 => "pumpkin_stem",
+        105 => "melon_stem",
+        106 => "vine",
+        107 => "oak_fence_gate",
+        108 => "brick_stairs",
+        109 => "stone_brick_stairs",
+        110 => "mycelium",
+        111 => "lily_pad",
+        112 => "nether_bricks",
+        113 => "nether_brick_fence",
+        114 => "nether_brick_stairs",
+        115 => "nether_wart",
+        116 => "enchanting_table",
+        117 => "brewing_stand",
+        118 => "cauldron",
+        119 => "end_portal",
+        120 => "end_portal_frame",
+        121 => "end_stone",
+        122 => "dragon_egg",
+        123 => "redstone_lamp",
+        127 => "cocoa",
+        128 => "sandstone_stairs",
+        129 => "emerald_ore",
+        130 => "ender_chest",
+        131 => "tripwire_hook",
+        132 => "tripwire",
+        133 => "emerald_block",
+        134 => "spruce_stairs",
+        135 => "birch_stairs",
+        136 => "jungle_stairs",
+        137 => "command_block",
+        138 => "beacon",
+        139 => "cobblestone_wall",
+        140 => "flower_pot",
+        143 => "oak_button",
+        144 => "skeleton_skull",
+        145 => "anvil",
+        146 => "trapped_chest",
+        147 => "light_weighted_pressure_plate",
+        148 => "heavy_weighted_pressure_plate",
+        149 | 150 => "comparator",
+        151 | 178 => "daylight_detector",
+        152 => "redstone_block",
+        153 => "nether_quartz_ore",
+        154 => "hopper",
+        156 => "quartz_stairs",
+        157 => "activator_rail",
+        158 => "dropper",
+        163 => "acacia_stairs",
+        164 => "dark_oak_stairs",
+        165 => "slime_block",
+        166 => "barrier",
+        167 => "iron_trapdoor",
+        169 => "sea_lantern",
+        170 => "hay_block",
+        172 => "terracotta",
+        173 => "coal_block",
+        174 => "packed_ice",
+        176 => "white_banner",
+        177 => "white_wall_banner",
+        180 => "red_sandstone_stairs",
+        183 => "spruce_fence_gate",
+        184 => "birch_fence_gate",
+        185 => "jungle_fence_gate",
+        186 => "dark_oak_fence_gate",
+        187 => "acacia_fence_gate",
+        188 => "spruce_fence",
+        189 => "birch_fence",
+        190 => "jungle_fence",
+        191 => "dark_oak_fence",
+        192 => "acacia_fence",
+        193 => "spruce_door",
+        194 => "birch_door",
+        195 => "jungle_door",
+        196 => "acacia_door",
+        197 => "dark_oak_door",
+        198 => "end_rod",
+        199 => "chorus_plant",
+        200 => "chorus_flower",
+        201 => "purpur_block",
+        202 => "purpur_pillar",
+        203 => "purpur_stairs",
+        206 => "end_stone_bricks",
+        208 => "grass_path",
+        209 => "end_gateway",
+        210 => "repeating_command_block",
+        211 => "chain_command_block",
+        212 => "frosted_ice",
+        213 => "magma_block",
+        214 => "nether_wart_block",
+        215 => "red_nether_bricks",
+        216 => "bone_block",
+        217 => "structure_void",
+        218 => "observer",
+        255 => "structure_block",
+        _ => "air",
+    }
+}
+
+/// Numeric block ids of 1.12 in order, used to resolve block item names.
+const LEGACY_BLOCK_NAMES: [&str; 256] = [
+    "air",
+    "stone",
+    "grass",
+    "dirt",
+    "cobblestone",
+    "planks",
+    "sapling",
+    "bedrock",
+    "flowing_water",
+    "water",
+    "flowing_lava",
+    "lava",
+    "sand",
+    "gravel",
+    "gold_ore",
+    "iron_ore",
+    "coal_ore",
+    "log",
+    "leaves",
+    "sponge",
+    "glass",
+    "lapis_ore",
+    "lapis_block",
+    "dispenser",
+    "sandstone",
+    "noteblock",
+    "bed",
+    "golden_rail",
+    "detector_rail",
+    "sticky_piston",
+    "web",
+    "tallgrass",
+    "deadbush",
+    "piston",
+    "piston_head",
+    "wool",
+    "piston_extension",
+    "yellow_flower",
+    "red_flower",
+    "brown_mushroom",
+    "red_mushroom",
+    "gold_block",
+    "iron_block",
+    "double_stone_slab",
+    "stone_slab",
+    "brick_block",
+    "tnt",
+    "bookshelf",
+    "mossy_cobblestone",
+    "obsidian",
+    "torch",
+    "fire",
+    "mob_spawner",
+    "oak_stairs",
+    "chest",
+    "redstone_wire",
+    "diamond_ore",
+    "diamond_block",
+    "crafting_table",
+    "wheat",
+    "farmland",
+    "furnace",
+    "lit_furnace",
+    "standing_sign",
+    "wooden_door",
+    "ladder",
+    "rail",
+    "stone_stairs",
+    "wall_sign",
+    "lever",
+    "stone_pressure_plate",
+    "iron_door",
+    "wooden_pressure_plate",
+    "redstone_ore",
+    "lit_redstone_ore",
+    "unlit_redstone_torch",
+    "redstone_torch",
+    "stone_button",
+    "snow_layer",
+    "ice",
+    "snow",
+    "cactus",
+    "clay",
+    "reeds",
+    "jukebox",
+    "fence",
+    "pumpkin",
+    "netherrack",
+    "soul_sand",
+    "glowstone",
+    "portal",
+    "lit_pumpkin",
+    "cake",
+    "unpowered_repeater",
+    "powered_repeater",
+    "stained_glass",
+    "trapdoor",
+    "monster_egg",
+    "stonebrick",
+    "brown_mushroom_block",
+    "red_mushroom_block",
+    "iron_bars",
+    "glass_pane",
+    "melon_block",
+    "pumpkin_stem",
+    "melon_stem",
+    "vine",
+    "fence_gate",
+    "brick_stairs",
+    "stone_brick_stairs",
+    "mycelium",
+    "waterlily",
+    "nether_brick",
+    "nether_brick_fence",
+    "nether_brick_stairs",
+    "nether_wart",
+    "enchanting_table",
+    "brewing_stand",
+    "cauldron",
+    "end_portal",
+    "end_portal_frame",
+    "end_stone",
+    "dragon_egg",
+    "redstone_lamp",
+    "lit_redstone_lamp",
+    "double_wooden_slab",
+    "wooden_slab",
+    "cocoa",
+    "sandstone_stairs",
+    "emerald_ore",
+    "ender_chest",
+    "tripwire_hook",
+    "tripwire",
+    "emerald_block",
+    "spruce_stairs",
+    "birch_stairs",
+    "jungle_stairs",
+    "command_block",
+    "beacon",
+    "cobblestone_wall",
+    "flower_pot",
+    "carrots",
+    "potatoes",
+    "wooden_button",
+    "skull",
+    "anvil",
+    "trapped_chest",
+    "light_weighted_pressure_plate",
+    "heavy_weighted_pressure_plate",
+    "unpowered_comparator",
+    "powered_comparator",
+    "daylight_detector",
+    "redstone_block",
+    "quartz_ore",
+    "hopper",
+    "quartz_block",
+    "quartz_stairs",
+    "activator_rail",
+    "dropper",
+    "stained_hardened_clay",
+    "stained_glass_pane",
+    "leaves2",
+    "log2",
+    "acacia_stairs",
+    "dark_oak_stairs",
+    "slime",
+    "barrier",
+    "iron_trapdoor",
+    "prismarine",
+    "sea_lantern",
+    "hay_block",
+    "carpet",
+    "hardened_clay",
+    "coal_block",
+    "packed_ice",
+    "double_plant",
+    "standing_banner",
+    "wall_banner",
+    "daylight_detector_inverted",
+    "red_sandstone",
+    "red_sandstone_stairs",
+    "double_stone_slab2",
+    "stone_slab2",
+    "spruce_fence_gate",
+    "birch_fence_gate",
+    "jungle_fence_gate",
+    "dark_oak_fence_gate",
+    "acacia_fence_gate",
+    "spruce_fence",
+    "birch_fence",
+    "jungle_fence",
+    "dark_oak_fence",
+    "acacia_fence",
+    "spruce_door",
+    "birch_door",
+    "jungle_door",
+    "acacia_door",
+    "dark_oak_door",
+    "end_rod",
+    "chorus_plant",
+    "chorus_flower",
+    "purpur_block",
+    "purpur_pillar",
+    "purpur_stairs",
+    "purpur_double_slab",
+    "purpur_slab",
+    "end_bricks",
+    "beetroots",
+    "grass_path",
+    "end_gateway",
+    "repeating_command_block",
+    "chain_command_block",
+    "frosted_ice",
+    "magma",
+    "nether_wart_block",
+    "red_nether_brick",
+    "bone_block",
+    "structure_void",
+    "observer",
+    "white_shulker_box",
+    "orange_shulker_box",
+    "magenta_shulker_box",
+    "light_blue_shulker_box",
+    "yellow_shulker_box",
+    "lime_shulker_box",
+    "pink_shulker_box",
+    "gray_shulker_box",
+    "silver_shulker_box",
+    "cyan_shulker_box",
+    "purple_shulker_box",
+    "blue_shulker_box",
+    "brown_shulker_box",
+    "green_shulker_box",
+    "red_shulker_box",
+    "black_shulker_box",
+    "white_glazed_terracotta",
+    "orange_glazed_terracotta",
+    "magenta_glazed_terracotta",
+    "light_blue_glazed_terracotta",
+    "yellow_glazed_terracotta",
+    "lime_glazed_terracotta",
+    "pink_glazed_terracotta",
+    "gray_glazed_terracotta",
+    "silver_glazed_terracotta",
+    "cyan_glazed_terracotta",
+    "purple_glazed_terracotta",
+    "blue_glazed_terracotta",
+    "brown_glazed_terracotta",
+    "green_glazed_terracotta",
+    "red_glazed_terracotta",
+    "black_glazed_terracotta",
+    "concrete",
+    "concrete_powder",
+    "",
+    "",
+    "structure_block",
+];
+
+/// Dye items in order of legacy damage values.
+const DYES: [&str; 16] = [
+    "ink_sac",
+    "rose_red",
+    "cactus_green",
+    "cocoa_beans",
+    "lapis_lazuli",
+    "purple_dye",
+    "cyan_dye",
+    "light_gray_dye",
+    "gray_dye",
+    "pink_dye",
+    "lime_dye",
+    "dandelion_yellow",
+    "light_blue_dye",
+    "magenta_dye",
+    "orange_dye",
+    "bone_meal",
+];
+
+/// Skull and head names in order of legacy skull types.
+const SKULLS: [&str; 6] = [
+    "skeleton_skull",
+    "wither_skeleton_skull",
+    "zombie_head",
+    "player_head",
+    "creeper_head",
+    "dragon_head",
+];
+
+/// Wall skull and head names in order of legacy skull types.
+const WALL_SKULLS: [&str; 6] = [
+    "skeleton_wall_skull",
+    "wither_skeleton_wall_skull",
+    "zombie_wall_head",
+    "player_wall_head",
+    "creeper_wall_head",
+    "dragon_wall_head",
+];
+
+/// Music discs in order of legacy numeric item ids starting at 2256.
+const RECORDS: [&str; 12] = [
+    "13", "cat", "blocks", "chirp", "far", "mall", "mellohi", "stal", "strad", "ward", "11", "wait",
+];
+
+/// Returns numeric id of 1.12 block name.
+fn legacy_block_id(name: &str) -> Option<u16> {
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+
+    if name.is_empty() {
+        return None;
+    }
+
+    LEGACY_BLOCK_NAMES
+        .iter()
+        .position(|legacy_name| *legacy_name == name)
+        .map(|block_id| block_id as u16)
+}
+
+/// Returns horizontal facing of legacy data values 2 to 5.
+fn legacy_facing(block_data: u8) -> &'static str {
+    match block_data {
+        3 => "south",
+        4 => "west",
+        5 => "east",
+        _ => "north",
+    }
+}
+
+/// Returns block state of blocks whose variant was stored in block entity.
+fn merged_block_state(
+    block_id: u16,
+    block_data: u8,
+    block_entity: &CompoundTag,
+) -> Option<BlockState> {
+    let block_entity_id = block_entity.get_str("id").ok()?;
+
+    let block_state = match (block_id, block_entity_id) {
+        (25, "minecraft:noteblock") => {
+            let note = block_entity.get_i8("note").unwrap_or(0).clamp(0, 24);
+            let powered = block_entity.get_bool("powered").unwrap_or(false);
+            let block_state = with_property(
+                BlockState::new("minecraft:note_block"),
+                "note",
+                &note.to_string(),
+            );
+
+            with_property(block_state, "powered", &powered.to_string())
+        }
+        (26, "minecraft:bed") => {
+            let color = block_entity.get_i32("color").unwrap_or(14) as u8;
+            let facing = ["south", "west", "north", "east"][(block_data & 3) as usize];
+            let part = if block_data & 8 != 0 { "head" } else { "foot" };
+            let block_state =
+                BlockState::new(&format!("minecraft:{}_bed", variant(&COLORS, color)));
+
+            let block_state = with_property(block_state, "facing", facing);
+            let block_state = with_property(block_state, "occupied", "false");
+
+            with_property(block_state, "part", part)
+        }
+        (140, "minecraft:flower_pot") => {
+            let plant_id = match block_entity.get::<&Tag>("Item").ok()? {
+                Tag::String(name) => legacy_block_id(name)?,
+                Tag::Int(plant_id) => *plant_id as u16,
+                _ => return None,
+            };
+
+            if plant_id == 0 {
+                return None;
+            }
+
+            let plant_data = block_entity.get_i32("Data").unwrap_or(0) as u8;
+            let plant = legacy_block_state(plant_id, plant_data);
+
+            if plant.is_air() {
+                return None;
+            }
+
+            let plant_name = plant.name.trim_start_matches("minecraft:");
+
+            BlockState::new(&format!("minecraft:potted_{}", plant_name))
+        }
+        (144, "minecraft:skull") => {
+            let skull_type = block_entity.get_i8("SkullType").unwrap_or(0) as u8;
+
+            if block_data & 7 == 1 {
+                let rotation = block_entity.get_i8("Rot").unwrap_or(0) & 15;
+                let name = format!("minecraft:{}", variant(&SKULLS, skull_type));
+
+                with_property(BlockState::new(&name), "rotation", &rotation.to_string())
+            } else {
+                let name = format!("minecraft:{}", variant(&WALL_SKULLS, skull_type));
+
+                with_property(
+                    BlockState::new(&name),
+                    "facing",
+                    legacy_facing(block_data & 7),
+                )
+            }
+        }
+        (176, "minecraft:banner") | (177, "minecraft:banner") => {
+            let base = block_entity.get_i32("Base").unwrap_or(0) & 15;
+            let color = COLORS[15 - base as usize];
+
+            if block_id == 176 {
+                let name = format!("minecraft:{}_banner", color);
+                let rotation = (block_data & 15).to_string();
+
+                with_property(BlockState::new(&name), "rotation", &rotation)
+            } else {
+                let name = format!("minecraft:{}_wall_banner", color);
+
+                with_property(BlockState::new(&name), "facing", legacy_facing(block_data))
+            }
+        }
+        _ => return None,
+    };
+
+    Some(block_state)
+}
+
+/// Returns palette index of block state, adding it to palette if missing.
+fn palette_index(
+    palette: &mut Vec<BlockState>,
+    state_indexes: &mut HashMap<BlockState, u16>,
+    block_state: BlockState,
+) -> u16 {
+    if let Some(palette_index) = state_indexes.get(&block_state) {
+        return *palette_index;
+    }
+
+    let palette_index = palette.len() as u16;
+    palette.push(block_state.clone());
+    state_indexes.insert(block_state, palette_index);
+
+    palette_index
+}
+
+/// Converts block entity of 1.12, none if it was merged into block state.
+fn flatten_block_entity(block_entity: &CompoundTag) -> Option<CompoundTag> {
+    let block_entity_id = block_entity.get_str("id").unwrap_or("");
+
+    let mut block_entity = match block_entity_id {
+        "minecraft:flower_pot" | "minecraft:noteblock" => return None,
+        "minecraft:skull" => without_tags(block_entity, &["SkullType", "Rot"]),
+        "minecraft:bed" => without_tags(block_entity, &["color"]),
+        "minecraft:banner" => without_tags(block_entity, &["Base"]),
+        "minecraft:piston" => {
+            let block_id = block_entity.get_i32("blockId").unwrap_or(0) as u16;
+            let block_data = block_entity.get_i32("blockData").unwrap_or(0) as u8;
+            let mut block_entity = without_tags(block_entity, &["blockId", "blockData"]);
+
+            block_entity.insert_compound_tag(
+                "blockState",
+                legacy_block_state(block_id, block_data & 15).to_tag(),
+            );
+
+            block_entity
+        }
+        "minecraft:jukebox" => {
+            let record = block_entity.get_i32("Record").unwrap_or(0);
+            let mut block_entity = without_tags(block_entity, &["Record"]);
+
+            if !block_entity.contains_key("RecordItem") && record >= 2256 {
+                if let Some(record_name) = RECORDS.get(record as usize - 2256) {
+                    let mut record_item = CompoundTag::new();
+                    record_item.insert_str("id", format!("minecraft:music_disc_{}", record_name));
+                    record_item.insert_i8("Count", 1);
+
+                    block_entity.insert_compound_tag("RecordItem", record_item);
+                }
+            }
+
+            block_entity
+        }
+        "minecraft:mob_spawner" => {
+            let mut block_entity = block_entity.clone();
+
+            if let Ok(spawn_data) = block_entity.get_mut::<&mut CompoundTag>("SpawnData") {
+                rename_entity(spawn_data);
+            }
+
+            if let Ok(spawn_potentials) = block_entity.get_mut::<&mut Vec<Tag>>("SpawnPotentials") {
+                for spawn_potential in spawn_potentials.iter_mut() {
+                    if let Tag::Compound(spawn_potential) = spawn_potential {
+                        if let Ok(entity) = spawn_potential.get_mut::<&mut CompoundTag>("Entity") {
+                            rename_entity(entity);
+                        }
+                    }
+                }
+            }
+
+            block_entity
+        }
+        _ => block_entity.clone(),
+    };
+
+    convert_custom_name(&mut block_entity);
+
+    for (_, tag) in block_entity.iter_mut() {
+        flatten_items(tag);
+    }
+
+    Some(block_entity)
+}
+
+/// Converts entity of 1.12 together with its passengers and items.
+fn flatten_entity(entity: &mut CompoundTag) {
+    rename_entity(entity);
+
+    let entity_id = entity.get_str("id").unwrap_or("").to_owned();
+
+    match entity_id.as_str() {
+        "minecraft:falling_block" => {
+            let block_id = entity
+                .get_str("Block")
+                .ok()
+                .and_then(legacy_block_id)
+                .or_else(|| {
+                    entity
+                        .get_i32("TileID")
+                        .ok()
+                        .map(|block_id| block_id as u16)
+                })
+                .or_else(|| {
+                    entity
+                        .get_i8("Tile")
+                        .ok()
+                        .map(|block_id| block_id as u8 as u16)
+                });
+
+            if let Some(block_id) = block_id {
+                let block_data = entity.get_i8("Data").unwrap_or(0) as u8 & 15;
+
+                *entity = without_tags(entity, &["Block", "TileID", "Tile", "Data"]);
+                entity.insert_compound_tag(
+                    "BlockState",
+                    legacy_block_state(block_id, block_data).to_tag(),
+                );
+            }
+        }
+        "minecraft:enderman" => {
+            let block_id = entity.get_i16("carried").unwrap_or(0) as u16;
+            let block_data = entity.get_i16("carriedData").unwrap_or(0) as u8 & 15;
+
+            *entity = without_tags(entity, &["carried", "carriedData"]);
+
+            if block_id != 0 {
+                entity.insert_compound_tag(
+                    "carriedBlockState",
+                    legacy_block_state(block_id, block_data).to_tag(),
+                );
+            }
+        }
+        "minecraft:minecart"
+        | "minecraft:chest_minecart"
+        | "minecraft:furnace_minecart"
+        | "minecraft:tnt_minecart"
+        | "minecraft:hopper_minecart"
+        | "minecraft:spawner_minecart"
+        | "minecraft:command_block_minecart" => {
+            let block_id = match entity.get::<&Tag>("DisplayTile") {
+                Ok(Tag::String(name)) => legacy_block_id(name),
+                Ok(Tag::Int(block_id)) => Some(*block_id as u16),
+                _ => None,
+            };
+
+            if let Some(block_id) = block_id {
+                let block_data = entity.get_i32("DisplayData").unwrap_or(0) as u8 & 15;
+
+                *entity = without_tags(entity, &["DisplayTile", "DisplayData"]);
+                entity.insert_compound_tag(
+                    "DisplayState",
+                    legacy_block_state(block_id, block_data).to_tag(),
+                );
+            }
+        }
+        "minecraft:item_frame" => {
+            // Horizontal direction index became three-dimensional facing.
+            if let Ok(facing) = entity.get_i8("Facing") {
+                let facing = [3, 4, 2, 5][(facing & 3) as usize];
+                entity.insert_i8("Facing", facing);
+            }
+        }
+        "minecraft:painting" => {
+            if let Ok(motive) = entity.get_str("Motive") {
+                let motive = format!("minecraft:{}", snake_case(motive));
+                entity.insert_str("Motive", motive);
+            }
+        }
+        _ => {}
+    }
+
+    convert_custom_name(entity);
+
+    for (name, tag) in entity.iter_mut() {
+        if name == "Passengers" {
+            if let Tag::List(passengers) = tag {
+                for passenger in passengers.iter_mut() {
+                    if let Tag::Compound(passenger) = passenger {
+                        flatten_entity(passenger);
+                    }
+                }
+            }
+        } else {
+            flatten_items(tag);
+        }
+    }
+}
+
+/// Renames entity ids changed in 1.13.
+fn rename_entity(entity: &mut CompoundTag) {
+    let renamed_id = match entity.get_str("id") {
+        Ok(entity_id) => renamed_entity_id(entity_id),
+        Err(_) => return,
+    };
+
+    if let Some(renamed_id) = renamed_id {
+        entity.insert_str("id", renamed_id);
+    }
+}
+
+fn renamed_entity_id(entity_id: &str) -> Option<&'static str> {
+    let renamed_id = match entity_id {
+        "minecraft:commandblock_minecart" => "minecraft:command_block_minecart",
+        "minecraft:ender_crystal" => "minecraft:end_crystal",
+        "minecraft:snowman" => "minecraft:snow_golem",
+        "minecraft:evocation_illager" => "minecraft:evoker",
+        "minecraft:evocation_fangs" => "minecraft:evoker_fangs",
+        "minecraft:illusion_illager" => "minecraft:illusioner",
+        "minecraft:vindication_illager" => "minecraft:vindicator",
+        "minecraft:villager_golem" => "minecraft:iron_golem",
+        "minecraft:xp_orb" => "minecraft:experience_orb",
+        "minecraft:xp_bottle" => "minecraft:experience_bottle",
+        "minecraft:eye_of_ender_signal" => "minecraft:eye_of_ender",
+        "minecraft:fireworks_rocket" => "minecraft:firework_rocket",
+        _ => return None,
+    };
+
+    Some(renamed_id)
+}
+
+/// Converts plain text custom name to JSON text component.
+fn convert_custom_name(compound_tag: &mut CompoundTag) {
+    let custom_name = match compound_tag.get_str("CustomName") {
+        Ok(custom_name) => custom_name.to_owned(),
+        Err(_) => return,
+    };
+
+    if custom_name.is_empty() {
+        *compound_tag = without_tags(compound_tag, &["CustomName"]);
+    } else {
+        compound_tag.insert_str("CustomName", text_component(&custom_name));
+    }
+}
+
+fn text_component(text: &str) -> String {
+    let mut text_component = String::from("{\"text\":");
+    write_quoted(&mut text_component, text);
+    text_component.push('}');
+
+    text_component
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake_case = String::with_capacity(name.len() + 4);
+
+    for (index, character) in name.chars().enumerate() {
+        if character.is_ascii_uppercase() && index > 0 {
+            snake_case.push('_');
+        }
+
+        snake_case.push(character.to_ascii_lowercase());
+    }
+
+    snake_case
+}
+
+/// Returns true if compound tag is an item stack.
+fn is_item(compound_tag: &CompoundTag) -> bool {
+    compound_tag.get_str("id").is_ok() && compound_tag.get_i8("Count").is_ok()
+}
+
+/// Converts every item stack nested in tag.
+fn flatten_items(tag: &mut Tag) {
+    match tag {
+        Tag::Compound(compound_tag) => {
+            if is_item(compound_tag) {
+                flatten_item(compound_tag);
+            }
+
+            for (_, tag) in compound_tag.iter_mut() {
+                flatten_items(tag);
+            }
+        }
+        Tag::List(tags) => {
+            for tag in tags.iter_mut() {
+                flatten_items(tag);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Converts item stack id, damage, enchantments and name of 1.12.
+fn flatten_item(item: &mut CompoundTag) {
+    let item_id = item.get_str("id").unwrap_or("").to_owned();
+    let damage = item.get_i16("Damage").unwrap_or(0);
+    let mut item_tag = item
+        .get_compound_tag("tag")
+        .cloned()
+        .unwrap_or_else(|_| CompoundTag::new());
+
+    let flattened_id = match item_id.as_str() {
+        "minecraft:filled_map" => {
+            item_tag.insert_i32("map", damage as i32);
+            item_id
+        }
+        "minecraft:spawn_egg" => {
+            let entity_id = item_tag
+                .get_compound_tag("EntityTag")
+                .and_then(|entity_tag| entity_tag.get_str("id"))
+                .unwrap_or("minecraft:pig");
+            let entity_id = renamed_entity_id(entity_id).unwrap_or(entity_id);
+
+            format!("{}_spawn_egg", entity_id)
+        }
+        _ => {
+            let (flattened_id, is_variant) = flattened_item_id(&item_id, damage);
+
+            if !is_variant && damage != 0 {
+                item_tag.insert_i32("Damage", damage as i32);
+            }
+
+            flattened_id
+        }
+    };
+
+    for (legacy_name, name) in &[
+        ("ench", "Enchantments"),
+        ("StoredEnchantments", "StoredEnchantments"),
+    ] {
+        if let Ok(enchantments) = item_tag.get_compound_tag_vec(legacy_name) {
+            let enchantments: Vec<CompoundTag> = enchantments
+                .into_iter()
+                .map(|enchantment| {
+                    let mut enchantment = enchantment.clone();
+
+                    if let Ok(id) = enchantment.get_i16("id") {
+                        if let Some(enchantment_name) = legacy_enchantment_name(id) {
+                            enchantment.insert_str("id", format!("minecraft:{}", enchantment_name));
+                        }
+                    }
+
+                    enchantment
+                })
+                .collect();
+
+            item_tag = without_tags(&item_tag, &[legacy_name]);
+            item_tag.insert_compound_tag_vec(name, enchantments);
+        }
+    }
+
+    if let Ok(display) = item_tag.get_mut::<&mut CompoundTag>("display") {
+        if let Ok(name) = display.get_str("Name") {
+            let name = text_component(name);
+            display.insert_str("Name", name);
+        }
+    }
+
+    *item = without_tags(item, &["Damage", "tag"]);
+    item.insert_str("id", flattened_id);
+
+    if !item_tag.is_empty() {
+        item.insert_compound_tag("tag", item_tag);
+    }
+}
+
+/// Returns 1.13 item id of 1.12 item id and damage, with true if damage
+/// selected item variant instead of durability.
+fn flattened_item_id(item_id: &str, damage: i16) -> (String, bool) {
+    let name = match item_id.strip_prefix("minecraft:") {
+        Some(name) => name,
+        // Items of other namespaces are kept as they are.
+        None if item_id.contains(':') => return (item_id.to_owned(), false),
+        None => item_id,
+    };
+
+    let data = damage.clamp(0, 255) as u8;
+
+    let flattened_name = match name {
+        "bed" => Some(format!("{}_bed", variant(&COLORS, data))),
+        "banner" => Some(format!("{}_banner", COLORS[15 - (data & 15) as usize])),
+        "skull" => Some(variant(&SKULLS, data)),
+        "dye" => Some(variant(&DYES, data)),
+        "coal" => Some(variant(&["coal", "charcoal"], data)),
+        "golden_apple" => Some(variant(&["golden_apple", "enchanted_golden_apple"], data)),
+        "fish" => Some(variant(
+            &["cod", "salmon", "tropical_fish", "pufferfish"],
+            data,
+        )),
+        "cooked_fish" => Some(variant(&["cooked_cod", "cooked_salmon"], data)),
+        "anvil" => Some(variant(&["anvil", "chipped_anvil", "damaged_anvil"], data)),
+        name => match legacy_block_id(name) {
+            Some(block_id) if block_id != 0 => {
+                let block_state = legacy_block_state(block_id, data & 15);
+
+                Some(block_state.name.trim_start_matches("minecraft:").to_owned())
+            }
+            _ => None,
+        },
+    };
+
+    if let Some(flattened_name) = flattened_name {
+        return (format!("minecraft:{}", flattened_name), true);
+    }
+
+    let renamed = match name {
+        "melon" => "melon_slice",
+        "speckled_melon" => "glistering_melon_slice",
+        "fireworks" => "firework_rocket",
+        "firework_charge" => "firework_star",
+        "netherbrick" => "nether_brick",
+        "chorus_fruit_popped" => "popped_chorus_fruit",
+        "boat" => "oak_boat",
+        name => match name.strip_prefix("record_") {
+            Some(record_name) => return (format!("minecraft:music_disc_{}", record_name), false),
+            None => name,
+        },
+    };
+
+    (format!("minecraft:{}", renamed), false)
+}
+
+/// Maps numeric enchantment id of 1.12 to its name.
+fn legacy_enchantment_name(id: i16) -> Option<&'static str> {
+    let name = match id {
+        0 => "protection",
+        1 => "fire_protection",
+        2 => "feather_falling",
+        3 => "blast_protection",
+        4 => "projectile_protection",
+        5 => "respiration",
+        6 => "aqua_affinity",
+        7 => "thorns",
+        8 => "depth_strider",
+        9 => "frost_walker",
+        10 => "binding_curse",
+        16 => "sharpness",
+        17 => "smite",
+        18 => "bane_of_arthropods",
+        19 => "knockback",
+        20 => "fire_aspect",
+        21 => "looting",
+        22 => "sweeping",
+        32 => "efficiency",
+        33 => "silk_touch",
+        34 => "unbreaking",
+        35 => "fortune",
+        48 => "power",
+        49 => "punch",
+        50 => "flame",
+        51 => "infinity",
+        61 => "luck_of_the_sea",
+        62 => "lure",
+        70 => "mending",
+        71 => "vanishing_curse",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Renames blocks changed by 1.14 and 1.16 which are not fixed after
+/// `NonSpanning` layout.
+fn rename_nether_update_block(block_state: &mut BlockState) {
+    let renamed_name = match block_state.name.as_str() {
+        "minecraft:sign" => "minecraft:oak_sign",
+        "minecraft:wall_sign" => "minecraft:oak_wall_sign",
+        "minecraft:stone_slab" => "minecraft:smooth_stone_slab",
+        name if name.ends_with("_wall") => {
+            // Wall sides became heights in 1.16.
+            for side in &["north", "east", "south", "west"] {
+                if let Some(value) = block_state.properties.get_mut(*side) {
+                    match value.as_str() {
+                        "true" => *value = "low".to_owned(),
+                        "false" => *value = "none".to_owned(),
+                        _ => {}
+                    }
+                }
+            }
+
+            return;
+        }
+        _ => return,
+    };
+
+    block_state.name = renamed_name.to_owned();
+}
+
+fn rename_nether_update_item(item_id: &str) -> Option<&'static str> {
+    let renamed_id = match item_id {
+        "minecraft:sign" => "minecraft:oak_sign",
+        "minecraft:stone_slab" => "minecraft:smooth_stone_slab",
+        "minecraft:rose_red" => "minecraft:red_dye",
+        "minecraft:cactus_green" => "minecraft:green_dye",
+        "minecraft:dandelion_yellow" => "minecraft:yellow_dye",
+        "minecraft:zombie_pigman_spawn_egg" => "minecraft:zombified_piglin_spawn_egg",
+        _ => return None,
+    };
+
+    Some(renamed_id)
+}
+
+/// Converts entity id and UUID changed by 1.16 together with its passengers.
+fn unspan_entity(entity: &mut CompoundTag) {
+    if entity.get_str("id").ok() == Some("minecraft:zombie_pigman") {
+        entity.insert_str("id", "minecraft:zombified_piglin");
+    }
+
+    if let (Ok(most), Ok(least)) = (entity.get_i64("UUIDMost"), entity.get_i64("UUIDLeast")) {
+        let uuid = vec![
+            (most >> 32) as i32,
+            most as i32,
+            (least >> 32) as i32,
+            least as i32,
+        ];
+
+        *entity = without_tags(entity, &["UUIDMost", "UUIDLeast"]);
+        entity.insert_i32_vec("UUID", uuid);
+    }
+
+    if let Ok(passengers) = entity.get_mut::<&mut Vec<Tag>>("Passengers") {
+        for passenger in passengers.iter_mut() {
+            if let Tag::Compound(passenger) = passenger {
+                unspan_entity(passenger);
+            }
+        }
+    }
+}
+
+/// Renames blocks changed by 1.17 which are not fixed after `Unwrapped` layout.
+fn rename_cave_update_block(block_state: &mut BlockState) {
+    match block_state.name.as_str() {
+        "minecraft:grass_path" => block_state.name = "minecraft:dirt_path".to_owned(),
+        "minecraft:cauldron" => match block_state.properties.remove("level") {
+            Some(level) if level != "0" => {
+                block_state.name = "minecraft:water_cauldron".to_owned();
+                block_state.properties.insert("level".to_owned(), level);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+fn rename_cave_update_item(item_id: &str) -> Option<&'static str> {
+    match item_id {
+        "minecraft:grass_path" => Some("minecraft:dirt_path"),
+        _ => None,
+    }
+}
+
+/// Renames every item stack nested in tag.
+fn rename_items(tag: &mut Tag, renamed_item_id: fn(&str) -> Option<&'static str>) {
+    match tag {
+        Tag::Compound(compound_tag) => {
+            if is_item(compound_tag) {
+                let renamed_id = compound_tag.get_str("id").ok().and_then(renamed_item_id);
+
+                if let Some(renamed_id) = renamed_id {
+                    compound_tag.insert_str("id", renamed_id);
+                }
+            }
+
+            for (_, tag) in compound_tag.iter_mut() {
+                rename_items(tag, renamed_item_id);
+            }
+        }
+        Tag::List(tags) => {
+            for tag in tags.iter_mut() {
+                rename_items(tag, renamed_item_id);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns fluid name of block, none for blocks without fluid.
+fn fluid_name(block_state: &BlockState) -> Option<String> {
+    match block_state.name.as_str() {
+        "minecraft:water" | "minecraft:lava" => Some(block_state.name.clone()),
+        _ if block_state.property("waterlogged") == Some("true") => {
+            Some("minecraft:water".to_owned())
+        }
+        _ => None,
+    }
+}
+
+/// Converts positions packed per section of proto chunk to scheduled ticks.
+fn proto_ticks<F>(
+    level_compound_tag: &CompoundTag,
+    name: &str,
+    sections: &[ChunkSection],
+    tick_name: F,
+) -> Vec<CompoundTag>
+where
+    F: Fn(&BlockState) -> Option<String>,
+{
+    let mut ticks = Vec::new();
+
+    let section_positions = match level_compound_tag.get::<&Vec<Tag>>(name) {
+        Ok(section_positions) => section_positions,
+        Err(_) => return ticks,
+    };
+
+    let chunk_x = level_compound_tag.get_i32("xPos").unwrap_or(0);
+    let chunk_z = level_compound_tag.get_i32("zPos").unwrap_or(0);
+
+    for (section_y, positions) in section_positions.iter().enumerate() {
+        let positions = match positions {
+            Tag::List(positions) => positions,
+            _ => continue,
+        };
+
+        let section = sections
+            .iter()
+            .find(|section| section.y as usize == section_y);
+
+        for position in positions {
+            let position = match position {
+                Tag::Short(position) => *position as u16,
+                _ => continue,
+            };
+
+            let x = (position & 15) as u8;
+            let y = ((position >> 4) & 15) as u8;
+            let z = ((position >> 8) & 15) as u8;
+
+            let tick_name = match section.and_then(|section| tick_name(section.block(x, y, z))) {
+                Some(tick_name) => tick_name,
+                None => continue,
+            };
+
+            let mut tick = CompoundTag::new();
+            tick.insert_str("i", tick_name);
+            tick.insert_i32("x", chunk_x * 16 + x as i32);
+            tick.insert_i32("y", section_y as i32 * 16 + y as i32);
+            tick.insert_i32("z", chunk_z * 16 + z as i32);
+            tick.insert_i32("t", 0);
+            tick.insert_i32("p", 0);
+
+            ticks.push(tick);
+        }
+    }
+
+    ticks
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::read_sections;
+    use crate::heightmap::{read_heightmap, HeightmapKind};
+    use crate::upgrade::{legacy_block_state, ChunkUpgrader, UpgradeError};
+    use crate::version::{ChunkVersion, NON_SPANNING_DATA_VERSION, UNWRAPPED_DATA_VERSION};
+    use crate::AnvilChunkProvider;
+    use nbt::{CompoundTag, Tag};
+    use tempfile::TempDir;
+
+    fn legacy_chunk() -> CompoundTag {
+        let mut blocks = vec![0i8; 4096];
+        let mut data = vec![0i8; 2048];
+
+        // Bedrock floor and a single orange wool block above it.
+        for block in blocks.iter_mut().take(256) {
+            *block = 7;
+        }
+
+        blocks[256] = 35;
+        data[128] = 1;
+
+        let mut section_tag = CompoundTag::new();
+        section_tag.insert_i8("Y", 0);
+        section_tag.insert_i8_vec("Blocks", blocks);
+        section_tag.insert_i8_vec("Data", data);
+        section_tag.insert_i8_vec("BlockLight", vec![0; 2048]);
+        section_tag.insert_i8_vec("SkyLight", vec![0; 2048]);
+
+        let mut level_compound_tag = CompoundTag::new();
+        level_compound_tag.insert_i32("xPos", 1);
+        level_compound_tag.insert_i32("zPos", 2);
+        level_compound_tag.insert_bool("TerrainPopulated", true);
+        level_compound_tag.insert_i8_vec("Biomes", vec![4; 256]);
+        level_compound_tag.insert_compound_tag_vec("Sections", vec![section_tag]);
+
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("DataVersion", 1343);
+        chunk_compound_tag.insert_compound_tag("Level", level_compound_tag);
+
+        chunk_compound_tag
+    }
+
+    #[test]
+    fn test_legacy_block_state() {
+        assert_eq!(legacy_block_state(1, 3).name, "minecraft:diorite");
+        assert_eq!(legacy_block_state(35, 14).name, "minecraft:red_wool");
+        assert_eq!(legacy_block_state(162, 5).name, "minecraft:dark_oak_log");
+        assert_eq!(legacy_block_state(162, 5).property("axis"), Some("x"));
+        assert_eq!(legacy_block_state(44, 8).property("type"), Some("top"));
+        assert!(legacy_block_state(4000, 0).is_air());
+    }
+
+    #[test]
+    fn test_upgrade_flattening() {
+        let mut chunk_compound_tag = legacy_chunk();
+        let chunk_upgrader = ChunkUpgrader::new(NON_SPANNING_DATA_VERSION);
+
+        assert!(chunk_upgrader.upgrade(&mut chunk_compound_tag).unwrap());
+        assert_eq!(
+            ChunkVersion::detect(&chunk_compound_tag),
+            ChunkVersion::NonSpanning
+        );
+
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+        assert_eq!(sections[0].block(0, 0, 0).name, "minecraft:bedrock");
+        assert_eq!(sections[0].block(0, 1, 0).name, "minecraft:orange_wool");
+
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+        assert_eq!(level_compound_tag.get_str("Status").unwrap(), "full");
+        assert_eq!(
+            level_compound_tag.get_i32_vec("Biomes").unwrap().len(),
+            1024
+        );
+        assert!(!level_compound_tag.contains_key("TerrainPopulated"));
+
+        let heightmap = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+        assert_eq!(heightmap.get(0, 0), 2);
+        assert_eq!(heightmap.get(1, 0), 1);
+
+        // Upgrading again does nothing.
+        assert!(!chunk_upgrader.upgrade(&mut chunk_compound_tag).unwrap());
+    }
+
+    #[test]
+    fn test_upgrade_flattening_entities() {
+        let mut chunk_compound_tag = legacy_chunk();
+        let level_compound_tag: &mut CompoundTag = chunk_compound_tag.get_mut("Level").unwrap();
+        let section_tags: &mut Vec<Tag> = level_compound_tag.get_mut("Sections").unwrap();
+
+        if let Tag::Compound(section_tag) = &mut section_tags[0] {
+            let mut blocks = section_tag.get_i8_vec("Blocks").unwrap().clone();
+            blocks[257] = 140u8 as i8;
+            section_tag.insert_i8_vec("Blocks", blocks);
+        }
+
+        let mut flower_pot = CompoundTag::new();
+        flower_pot.insert_str("id", "minecraft:flower_pot");
+        flower_pot.insert_i32("x", 17);
+        flower_pot.insert_i32("y", 1);
+        flower_pot.insert_i32("z", 32);
+        flower_pot.insert_str("Item", "minecraft:red_flower");
+        flower_pot.insert_i32("Data", 2);
+
+        let mut sword = CompoundTag::new();
+        sword.insert_str("id", "minecraft:diamond_sword");
+        sword.insert_i8("Count", 1);
+        sword.insert_i16("Damage", 5);
+
+        let mut sharpness = CompoundTag::new();
+        sharpness.insert_i16("id", 16);
+        sharpness.insert_i16("lvl", 2);
+
+        let mut sword_tag = CompoundTag::new();
+        sword_tag.insert_compound_tag_vec("ench", vec![sharpness]);
+        sword.insert_compound_tag("tag", sword_tag);
+
+        let mut wool = CompoundTag::new();
+        wool.insert_str("id", "minecraft:wool");
+        wool.insert_i8("Count", 3);
+        wool.insert_i16("Damage", 14);
+
+        let mut chest = CompoundTag::new();
+        chest.insert_str("id", "minecraft:chest");
+        chest.insert_i32("x", 18);
+        chest.insert_i32("y", 1);
+        chest.insert_i32("z", 32);
+        chest.insert_compound_tag_vec("Items", vec![sword, wool]);
+
+        let mut falling_block = CompoundTag::new();
+        falling_block.insert_str("id", "minecraft:falling_block");
+        falling_block.insert_str("Block", "minecraft:sand");
+        falling_block.insert_i8("Data", 1);
+
+        let mut snowman = CompoundTag::new();
+        snowman.insert_str("id", "minecraft:snowman");
+        snowman.insert_str("CustomName", "Frosty");
+        snowman.insert_compound_tag_vec("Passengers", vec![falling_block]);
+
+        level_compound_tag.insert_compound_tag_vec("TileEntities", vec![flower_pot, chest]);
+        level_compound_tag.insert_compound_tag_vec("Entities", vec![snowman]);
+
+        ChunkUpgrader::new(NON_SPANNING_DATA_VERSION)
+            .upgrade(&mut chunk_compound_tag)
+            .unwrap();
+
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+        assert_eq!(sections[0].block(1, 1, 0).name, "minecraft:potted_allium");
+
+        let level_compound_tag = chunk_compound_tag.get_compound_tag("Level").unwrap();
+        let block_entities = level_compound_tag
+            .get_compound_tag_vec("TileEntities")
+            .unwrap();
+
+        // Flower pot was merged into block state.
+        assert_eq!(block_entities.len(), 1);
+
+        let items = block_entities[0].get_compound_tag_vec("Items").unwrap();
+        let sword_tag = items[0].get_compound_tag("tag").unwrap();
+        let enchantments = sword_tag.get_compound_tag_vec("Enchantments").unwrap();
+        assert!(!items[0].contains_key("Damage"));
+        assert_eq!(sword_tag.get_i32("Damage").unwrap(), 5);
+        assert_eq!(
+            enchantments[0].get_str("id").unwrap(),
+            "minecraft:sharpness"
+        );
+        assert_eq!(items[1].get_str("id").unwrap(), "minecraft:red_wool");
+        assert!(!items[1].contains_key("tag"));
+
+        let entities = level_compound_tag.get_compound_tag_vec("Entities").unwrap();
+        assert_eq!(entities[0].get_str("id").unwrap(), "minecraft:snow_golem");
+        assert_eq!(
+            entities[0].get_str("CustomName").unwrap(),
+            r#"{"text":"Frosty"}"#
+        );
+
+        let passengers = entities[0].get_compound_tag_vec("Passengers").unwrap();
+        let block_state = passengers[0].get_compound_tag("BlockState").unwrap();
+        assert_eq!(block_state.get_str("Name").unwrap(), "minecraft:red_sand");
+    }
+
+    #[test]
+    fn test_upgrade_unwrap_level() {
+        let mut chunk_compound_tag = legacy_chunk();
+        let chunk_upgrader = ChunkUpgrader::new(UNWRAPPED_DATA_VERSION);
+
+        chunk_upgrader.upgrade(&mut chunk_compound_tag).unwrap();
+
+        assert!(!chunk_compound_tag.contains_key("Level"));
+        assert_eq!(chunk_compound_tag.get_i32("xPos").unwrap(), 1);
+        assert_eq!(chunk_compound_tag.get_i32("yPos").unwrap(), -4);
+
+        let sections = read_sections(&chunk_compound_tag).unwrap();
+        assert_eq!(sections.len(), 24);
+        assert_eq!(sections[0].y, -4);
+        assert!(sections[0].is_empty());
+        assert_eq!(sections[4].block(0, 1, 0).name, "minecraft:orange_wool");
+
+        let section_tags = chunk_compound_tag.get_compound_tag_vec("sections").unwrap();
+        let biomes_tag = section_tags[4].get_compound_tag("biomes").unwrap();
+        assert_eq!(
+            biomes_tag.get_str_vec("palette").unwrap(),
+            vec!["minecraft:forest"]
+        );
+
+        // Section range was extended by 4 sections below.
+        let heightmap = read_heightmap(&chunk_compound_tag, HeightmapKind::WorldSurface).unwrap();
+        assert_eq!(heightmap.get(0, 0), 64 + 2);
+    }
+
+    #[test]
+    fn test_upgrade_downgrade_not_supported() {
+        let mut chunk_compound_tag = legacy_chunk();
+        chunk_compound_tag.insert_i32("DataVersion", 3000);
+
+        match ChunkUpgrader::new(NON_SPANNING_DATA_VERSION).upgrade(&mut chunk_compound_tag) {
+            Err(UpgradeError::DowngradeNotSupported { data_version, .. }) => {
+                assert_eq!(data_version, 3000)
+            }
+            result => panic!("Expected `DowngradeNotSupported` but got `{:?}`", result),
+        }
+    }
+
+    #[test]
+    fn test_upgrade_provider() {
+        let folder = TempDir::new().unwrap();
+        let chunk_provider = AnvilChunkProvider::from_path(folder.path());
+
+        chunk_provider.save_chunk(1, 2, legacy_chunk()).unwrap();
+
+        let chunk_upgrader = ChunkUpgrader::new(UNWRAPPED_DATA_VERSION);
+        let upgraded = chunk_upgrader.upgrade_provider(&chunk_provider).unwrap();
+
+        assert_eq!(upgraded, vec![(1, 2)]);
+
+        let chunk_compound_tag = chunk_provider.load_chunk(1, 2).unwrap();
+        assert_eq!(
+            ChunkVersion::detect(&chunk_compound_tag),
+            ChunkVersion::Unwrapped
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Chunk format versions.
+//!
+//! Since 1.9 every chunk stores `DataVersion` of the game which saved it.
+//! Chunk layout changed several times since then, this module classifies
+//! chunks by layout so accessors can read them in the right way.
+use crate::chunk::ChunkDataError;
+use nbt::CompoundTag;
+
+/// Data version of 17w47a which replaced numeric block ids with palettes.
+pub const FLATTENING_DATA_VERSION: i32 = 1451;
+/// Data version of 20w17a since which packed values no longer span two longs.
+pub const NON_SPANNING_DATA_VERSION: i32 = 2529;
+/// Data version of 21w43a which removed `Level` wrapper.
+pub const UNWRAPPED_DATA_VERSION: i32 = 2844;
+
+/// Returns data version stored in chunk.
+///
+/// Chunks saved before 1.9 does not have data version.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::AnvilChunkProvider;
+/// use anvil_region::version::data_version;
+///
+/// let chunk_provider = AnvilChunkProvider::new("test/region");
+/// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+///
+/// assert!(data_version(&chunk_compound_tag).is_some());
+/// ```
+pub fn data_version(chunk_compound_tag: &CompoundTag) -> Option<i32> {
+    chunk_compound_tag.get_i32("DataVersion").ok()
+}
+
+/// Chunk layouts used by different game versions.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ChunkVersion {
+    /// Before 1.13, blocks are stored as numeric ids in `Blocks`, `Data`
+    /// and `Add` arrays.
+    PreFlattening,
+    /// From 1.13 to 1.15, blocks are stored in `Palette` and `BlockStates`
+    /// packed with values spanning two longs.
+    Flattened,
+    /// 1.16 and 1.17, same as `Flattened` but values does not span longs.
+    NonSpanning,
+    /// Since 1.18, `Level` wrapper are removed and sections are stored in
+    /// `sections` with `block_states` palette containers.
+    Unwrapped,
+}
+
+impl ChunkVersion {
+    /// Classifies chunk layout by data version.
+    pub fn from_data_version(data_version: Option<i32>) -> Self {
+        match data_version {
+            Some(data_version) if data_version >= UNWRAPPED_DATA_VERSION => ChunkVersion::Unwrapped,
+            Some(data_version) if data_version >= NON_SPANNING_DATA_VERSION => {
+                ChunkVersion::NonSpanning
+            }
+            Some(data_version) if data_version >= FLATTENING_DATA_VERSION => {
+                ChunkVersion::Flattened
+            }
+            _ => ChunkVersion::PreFlattening,
+        }
+    }
+
+    /// Classifies layout of loaded chunk.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anvil_region::AnvilChunkProvider;
+    /// use anvil_region::version::ChunkVersion;
+    ///
+    /// let chunk_provider = AnvilChunkProvider::new("test/region");
+    /// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+    ///
+    /// assert_eq!(ChunkVersion::detect(&chunk_compound_tag), ChunkVersion::Flattened);
+    /// ```
+    pub fn detect(chunk_compound_tag: &CompoundTag) -> Self {
+        Self::from_data_version(data_version(chunk_compound_tag))
+    }
+
+    /// Classifies layout of loaded chunk rejecting layouts without block palettes.
+    ///
+    /// Section, heightmap and light accessors support only palette based layouts.
+    pub fn detect_supported(chunk_compound_tag: &CompoundTag) -> Result<Self, ChunkDataError> {
+        let chunk_version = Self::detect(chunk_compound_tag);
+
+        if chunk_version == ChunkVersion::PreFlattening {
+            return Err(ChunkDataError::UnsupportedVersion {
+                data_version: data_version(chunk_compound_tag),
+            });
+        }
+
+        Ok(chunk_version)
+    }
+
+    /// Returns true if packed arrays values span two longs.
+    pub fn is_spanning(&self) -> bool {
+        *self < ChunkVersion::NonSpanning
+    }
+
+    /// Returns true if chunk data are wrapped into `Level` compound tag.
+    pub fn has_level(&self) -> bool {
+        *self != ChunkVersion::Unwrapped
+    }
+
+    /// Returns name of list tag with sections.
+    pub fn sections_tag_name(&self) -> &'static str {
+        match self {
+            ChunkVersion::Unwrapped => "sections",
+            _ => "Sections",
+        }
+    }
+
+    /// Returns compound tag which holds chunk data.
+    pub fn level<'a>(
+        &self,
+        chunk_compound_tag: &'a CompoundTag,
+    ) -> Result<&'a CompoundTag, ChunkDataError> {
+        if self.has_level() {
+            Ok(chunk_compound_tag.get_compound_tag("Level")?)
+        } else {
+            Ok(chunk_compound_tag)
+        }
+    }
+
+    /// Returns mutable compound tag which holds chunk data.
+    pub fn level_mut<'a>(
+        &self,
+        chunk_compound_tag: &'a mut CompoundTag,
+    ) -> Result<&'a mut CompoundTag, ChunkDataError> {
+        if self.has_level() {
+            Ok(chunk_compound_tag.get_mut("Level")?)
+        } else {
+            Ok(chunk_compound_tag)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::ChunkDataError;
+    use crate::version::ChunkVersion;
+    use nbt::CompoundTag;
+
+    #[test]
+    fn test_from_data_version() {
+        assert_eq!(
+            ChunkVersion::from_data_version(None),
+            ChunkVersion::PreFlattening
+        );
+        assert_eq!(
+            ChunkVersion::from_data_version(Some(1343)),
+            ChunkVersion::PreFlattening
+        );
+        assert_eq!(
+            ChunkVersion::from_data_version(Some(1976)),
+            ChunkVersion::Flattened
+        );
+        assert_eq!(
+            ChunkVersion::from_data_version(Some(2586)),
+            ChunkVersion::NonSpanning
+        );
+        assert_eq!(
+            ChunkVersion::from_data_version(Some(3465)),
+            ChunkVersion::Unwrapped
+        );
+    }
+
+    #[test]
+    fn test_detect_supported() {
+        let mut chunk_compound_tag = CompoundTag::new();
+
+        match ChunkVersion::detect_supported(&chunk_compound_tag) {
+            Err(ChunkDataError::UnsupportedVersion { data_version }) => {
+                assert_eq!(data_version, None)
+            }
+            result => panic!("Expected `UnsupportedVersion` but got `{:?}`", result),
+        }
+
+        chunk_compound_tag.insert_i32("DataVersion", 2730);
+
+        assert_eq!(
+            ChunkVersion::detect_supported(&chunk_compound_tag).unwrap(),
+            ChunkVersion::NonSpanning
+        );
+    }
+
+    #[test]
+    fn test_level() {
+        let mut chunk_compound_tag = CompoundTag::new();
+        chunk_compound_tag.insert_i32("xPos", 1);
+
+        let level = ChunkVersion::Unwrapped.level(&chunk_compound_tag).unwrap();
+        assert_eq!(level.get_i32("xPos").unwrap(), 1);
+
+        assert!(ChunkVersion::Flattened.level(&chunk_compound_tag).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! World save directory with its dimensions.
+//!
+//! Vanilla dimensions are stored in the save directory itself (overworld),
+//! `DIM-1` (nether) and `DIM1` (end). Since 1.16 custom dimensions are
+//! stored in `dimensions/<namespace>/<path>`, where path of identifier such
+//! as `mymod:caves/deep` spans several folders.
+use crate::level::{LevelData, LevelLoadError, LevelSaveError, LEVEL_DAT, LEVEL_DAT_OLD};
+use crate::storage::{ChunkStorage, StorageKind};
+use crate::AnvilChunkProvider;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Dimension of world.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Dimension {
+    /// `minecraft:overworld`.
+    Overworld,
+    /// `minecraft:the_nether`.
+    Nether,
+    /// `minecraft:the_end`.
+    End,
+    /// Dimension added by data pack or mod.
+    Custom { namespace: String, name: String },
+}
+
+impl Dimension {
+    /// Parses dimension identifier.
+    ///
+    /// Identifier without namespace belongs to `minecraft` namespace.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use anvil_region::world::Dimension;
+    ///
+    /// assert_eq!(Dimension::from_id("the_nether"), Dimension::Nether);
+    /// assert_eq!(Dimension::from_id("mymod:mining").id(), "mymod:mining");
+    /// ```
+    pub fn from_id(id: &str) -> Self {
+        let (namespace, name) = match id.find(':') {
+            Some(index) => (&id[..index], &id[index + 1..]),
+            None => ("minecraft", id),
+        };
+
+        match (namespace, name) {
+            ("minecraft", "overworld") => Dimension::Overworld,
+            ("minecraft", "the_nether") => Dimension::Nether,
+            ("minecraft", "the_end") => Dimension::End,
+            (namespace, name) => Dimension::Custom {
+                namespace: namespace.to_owned(),
+                name: name.to_owned(),
+            },
+        }
+    }
+
+    /// Returns namespaced dimension identifier.
+    pub fn id(&self) -> String {
+        match self {
+            Dimension::Overworld => "minecraft:overworld".to_owned(),
+            Dimension::Nether => "minecraft:the_nether".to_owned(),
+            Dimension::End => "minecraft:the_end".to_owned(),
+            Dimension::Custom { namespace, name } => format!("{}:{}", namespace, name),
+        }
+    }
+
+    /// Returns dimension folder relative to save directory.
+    pub fn relative_path(&self) -> PathBuf {
+        match self {
+            Dimension::Overworld => PathBuf::new(),
+            Dimension::Nether => PathBuf::from("DIM-1"),
+            Dimension::End => PathBuf::from("DIM1"),
+            Dimension::Custom { namespace, name } => {
+                Path::new("dimensions").join(namespace).join(name)
+            }
+        }
+    }
+}
+
+/// World save directory.
+///
+/// # Example
+///
+/// ```
+/// use anvil_region::world::{Dimension, World};
+///
+/// // Test data has overworld region folder only.
+/// let world = World::new("test");
+///
+/// assert_eq!(world.dimensions().unwrap(), vec![Dimension::Overworld]);
+///
+/// let chunk_provider = world.provider(&Dimension::Overworld);
+/// let chunk_compound_tag = chunk_provider.load_chunk(4, 2).unwrap();
+/// ```
+pub struct World {
+    /// Save directory.
+    path: PathBuf,
+}
+
+impl World {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        World {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Returns save directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads world properties from `level.dat`.
+    ///
+    /// Falls back to `level.dat_old` when `level.dat` cannot be read,
+    /// returning error of `level.dat` if both fail.
+    pub fn level_data(&self) -> Result<LevelData, LevelLoadError> {
+        match LevelData::read(self.path.join(LEVEL_DAT)) {
+            Ok(level_data) => Ok(level_data),
+            Err(level_load_error) => {
+                LevelData::read(self.path.join(LEVEL_DAT_OLD)).map_err(|_| level_load_error)
+            }
+        }
+    }
+
+    /// Writes world properties to `level.dat` keeping previous one in `level.dat_old`.
+    pub fn write_level_data(&self, level_data: &LevelData) -> Result<(), LevelSaveError> {
+        level_data.write(self.path.join(LEVEL_DAT))
+    }
+
+    /// Returns folder of specified dimension.
+    pub fn dimension_path(&self, dimension: &Dimension) -> PathBuf {
+        self.path.join(dimension.relative_path())
+    }
+
+    /// Returns dimensions which have region files.
+    ///
+    /// Vanilla dimensions go first, custom are sorted by identifier.
+    pub fn dimensions(&self) -> Result<Vec<Dimension>, io::Error> {
+        let mut dimensions = Vec::new();
+
+        for dimension in &[Dimension::Overworld, Dimension::Nether, Dimension::End] {
+            if self.has_regions(dimension) {
+                dimensions.push(dimension.clone());
+            }
+        }
+
+        let dimensions_path = self.path.join("dimensions");
+
+        if !dimensions_path.is_dir() {
+            return Ok(dimensions);
+        }
+
+        let mut custom_dimensions = Vec::new();
+
+        for namespace_entry in fs::read_dir(dimensions_path)? {
+            let namespace_entry = namespace_entry?;
+
+            if !namespace_entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let namespace = match namespace_entry.file_name().to_str() {
+                Some(namespace) => namespace.to_owned(),
+                None => continue,
+            };
+
+            let mut names = Vec::new();
+            find_dimension_names(&namespace_entry.path(), "", &mut names)?;
+
+            for name in names {
+                custom_dimensions.push(Dimension::Custom {
+                    namespace: namespace.clone(),
+                    name,
+                });
+            }
+        }
+
+        custom_dimensions.sort();
+        dimensions.extend(custom_dimensions);
+
+        Ok(dimensions)
+    }
+
+    fn has_regions(&self, dimension: &Dimension) -> bool {
+        has_storage_folder(&self.dimension_path(dimension))
+    }
+
+    /// Returns region, entities and poi storages of dimension.
+    pub fn storage(&self, dimension: &Dimension) -> ChunkStorage {
+        ChunkStorage::new(self.dimension_path(dimension))
+    }
+
+    /// Returns chunk provider of dimension `region` folder.
+    pub fn provider(&self, dimension: &Dimension) -> AnvilChunkProvider<'static> {
+        let folder_path = self
+            .dimension_path(dimension)
+            .join(StorageKind::Region.folder_name());
+
+        AnvilChunkProvider::from_path_buf(folder_path)
+    }
+}
+
+/// Returns true if folder contains any of region, entities or poi folders.
+fn has_storage_folder(dimension_path: &Path) -> bool {
+    StorageKind::ALL
+        .iter()
+        .any(|kind| dimension_path.join(kind.folder_name()).is_dir())
+}
+
+/// Collects paths of dimension folders, descending into subfolders until
+/// folder with storage folders is found.
+fn find_dimension_names(
+    folder_path: &Path,
+    prefix: &str,
+    names: &mut Vec<String>,
+) -> Result<(), io::Error> {
+    for entry in fs::read_dir(folder_path)? {
+        let entry = entry?;
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let file_name = entry.file_name();
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => continue,
+        };
+
+        let name = if prefix.is_empty() {
+            file_name.to_owned()
+        } else {
+            format!("{}/{}", prefix, file_name)
+        };
+
+        if has_storage_folder(&entry.path()) {
+            names.push(name);
+        } else {
+            find_dimension_names(&entry.path(), &name, names)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::level::LevelData;
+    use crate::world::{Dimension, World};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dimension_paths() {
+        let world = World::new("world");
+
+        assert_eq!(
+            world.dimension_path(&Dimension::Overworld),
+            Path::new("world")
+        );
+        assert_eq!(
+            world.dimension_path(&Dimension::Nether),
+            Path::new("world/DIM-1")
+        );
+        assert_eq!(
+            world.dimension_path(&Dimension::from_id("mymod:mining")),
+            Path::new("world/dimensions/mymod/mining")
+        );
+        assert_eq!(Dimension::from_id("minecraft:the_end"), Dimension::End);
+    }
+
+    #[test]
+    fn test_level_data_fallback() {
+        let folder = TempDir::new().unwrap();
+        let world = World::new(folder.path());
+
+        world.write_level_data(&LevelData::new("First")).unwrap();
+        world.write_level_data(&LevelData::new("Second")).unwrap();
+        assert_eq!(world.level_data().unwrap().level_name, "Second");
+
+        fs::write(folder.path().join("level.dat"), b"corrupted").unwrap();
+        assert_eq!(world.level_data().unwrap().level_name, "First");
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let folder = TempDir::new().unwrap();
+        let path = folder.path();
+
+        fs::create_dir_all(path.join("region")).unwrap();
+        fs::create_dir_all(path.join("DIM1/region")).unwrap();
+        fs::create_dir_all(path.join("DIM-1")).unwrap();
+        fs::create_dir_all(path.join("dimensions/mymod/mining/region")).unwrap();
+        fs::create_dir_all(path.join("dimensions/mymod/empty")).unwrap();
+        fs::create_dir_all(path.join("dimensions/mymod/caves/deep/poi")).unwrap();
+
+        let world = World::new(path);
+
+        assert_eq!(
+            world.dimensions().unwrap(),
+            vec![
+                Dimension::Overworld,
+                Dimension::End,
+                Dimension::from_id("mymod:caves/deep"),
+                Dimension::from_id("mymod:mining"),
+            ]
+        );
+        assert_eq!(
+            world.dimension_path(&Dimension::from_id("mymod:caves/deep")),
+            path.join("dimensions/mymod/caves/deep")
+        );
+    }
+}